@@ -0,0 +1,530 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::signal::unix::{SignalKind, signal};
+
+use crate::tor::{
+    self, ConfigYaml, ControlClient, ControlEvent, Descriptor, ExpandedSecretKey,
+    HiddenServiceSecretKey, Hostname, IntroductionPoint, PublicKey,
+};
+
+/// Backend descriptors which have not been fetched again for this long are
+/// considered gone.
+const DESCRIPTOR_MAX_AGE: Duration = Duration::from_hours(1);
+
+/// Frontend descriptors are published again after this long, even when their
+/// introduction points have not changed.
+const REPUBLISH_PERIOD: Duration = Duration::from_hours(1);
+
+/*
+ * ============================================================================
+ * Config
+ * ============================================================================
+ */
+pub struct Config {
+    /// onionbalance `config.yaml` of the Onion Services.
+    pub config: PathBuf,
+
+    /// Tor control port address.
+    pub control_port: String,
+
+    /// Period between fetches of the backend descriptors.
+    pub fetch_descriptor_frequency: Duration,
+
+    /// Period between checks whether the frontend descriptors must be
+    /// published.
+    pub publish_descriptor_check_frequency: Duration,
+}
+
+/*
+ * ============================================================================
+ * State
+ * ============================================================================
+ */
+struct Service {
+    backends: Vec<Backend>,
+    hostname: Hostname,
+    published: Option<Published>,
+    secret_key: ExpandedSecretKey,
+}
+
+struct Backend {
+    fetched: Option<(Instant, Vec<IntroductionPoint>)>,
+    hostname: Hostname,
+    public_key: PublicKey,
+}
+
+struct Published {
+    at: Instant,
+    introduction_points: Vec<IntroductionPoint>,
+    time_period: u64,
+}
+
+impl Service {
+    /// The introduction points of the backends, interleaved so that every
+    /// backend is represented when they exceed the limit of the descriptor.
+    fn introduction_points(&self) -> Vec<IntroductionPoint> {
+        let fetched = self
+            .backends
+            .iter()
+            .filter_map(|backend| backend.fetched.as_ref())
+            .filter(|(at, _)| at.elapsed() < DESCRIPTOR_MAX_AGE)
+            .map(|(_, introduction_points)| introduction_points)
+            .collect::<Vec<_>>();
+
+        let longest = fetched.iter().map(|f| f.len()).max().unwrap_or_default();
+
+        (0..longest)
+            .flat_map(|index| fetched.iter().filter_map(move |f| f.get(index)))
+            .cloned()
+            .collect()
+    }
+
+    fn must_publish(&self, introduction_points: &[IntroductionPoint], time_period: u64) -> bool {
+        self.published.as_ref().is_none_or(|published| {
+            published.time_period != time_period
+                || published.introduction_points != introduction_points
+                || published.at.elapsed() >= REPUBLISH_PERIOD
+        })
+    }
+}
+
+/*
+ * ============================================================================
+ * Balancer
+ * ============================================================================
+ */
+/// Publishes the descriptors of the Onion Services, combining the
+/// introduction points of their backends.
+///
+/// The backend descriptors are fetched through the control port of Tor, and
+/// the frontend descriptors are signed with the keys of the Onion Services and
+/// uploaded through it, replacing the onionbalance process.
+///
+/// The config is loaded again on SIGHUP, so that a change of the backends does
+/// not restart the balancer.
+///
+/// # Errors
+///
+/// Returns an error if the config or keys cannot be loaded, or the control
+/// port cannot be used.
+pub async fn run(config: &Config) -> Result<(), tor::Error> {
+    let mut services = load(&config.config)?;
+
+    let mut client = ControlClient::connect_and_authenticate(&config.control_port).await?;
+    let mut events = client
+        .events()
+        .ok_or_else(|| tor::Error::Io(std::io::Error::other("events already taken")))?;
+    client.set_events(&["HS_DESC_CONTENT"]).await?;
+
+    tracing::info!(
+        services = services.len(),
+        control_port = config.control_port,
+        "publishing descriptors"
+    );
+
+    let mut fetch = tokio::time::interval(config.fetch_descriptor_frequency);
+    let mut publish = tokio::time::interval(config.publish_descriptor_check_frequency);
+    let mut hangup = signal(SignalKind::hangup())?;
+
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => match load(&config.config) {
+                Ok(loaded) => {
+                    services = reload(services, loaded);
+                    tracing::info!(services = services.len(), "reloaded config");
+                    fetch.reset_immediately();
+                }
+                Err(error) => tracing::warn!(%error, "failed to reload config"),
+            },
+            _ = fetch.tick() => fetch_descriptors(&mut client, &services).await?,
+            _ = publish.tick() => {
+                publish_descriptors(&mut client, &mut services, SystemTime::now()).await?;
+            }
+            event = events.recv() => match event {
+                Some(event) => receive_descriptor(&mut services, &event),
+                None => return Err(tor::Error::Io(std::io::ErrorKind::UnexpectedEof.into())),
+            },
+        }
+    }
+}
+
+fn load(path: &Path) -> Result<Vec<Service>, tor::Error> {
+    let config: ConfigYaml = serde_saphyr::from_str(&std::fs::read_to_string(path)?)
+        .map_err(|e| tor::Error::ParseError(format!("malformed config: {e}")))?;
+
+    config
+        .services
+        .iter()
+        .map(|service| {
+            let secret_key = ExpandedSecretKey::try_from(&HiddenServiceSecretKey::try_from(
+                &std::fs::read(&service.key)?,
+            )?)?;
+
+            let backends = service
+                .instances
+                .iter()
+                .map(|instance| {
+                    let hostname = Hostname::try_from(instance.address.as_bytes())?;
+                    Ok(Backend {
+                        fetched: None,
+                        public_key: PublicKey::try_from(&hostname)?,
+                        hostname,
+                    })
+                })
+                .collect::<Result<_, tor::Error>>()?;
+
+            Ok(Service {
+                backends,
+                hostname: Hostname::from(&PublicKey::from(&secret_key)),
+                published: None,
+                secret_key,
+            })
+        })
+        .collect()
+}
+
+/// Carries the fetched backend descriptors and the published frontend
+/// descriptors over to the reloaded services, so that the backends kept by the
+/// reload are not dropped from the frontend descriptors until fetched again.
+fn reload(services: Vec<Service>, mut loaded: Vec<Service>) -> Vec<Service> {
+    let mut fetched = HashMap::new();
+    let mut published = HashMap::new();
+
+    for service in services {
+        for backend in service.backends {
+            if let Some(backend_fetched) = backend.fetched {
+                fetched.insert(backend.hostname.to_string(), backend_fetched);
+            }
+        }
+        published.insert(service.hostname.to_string(), service.published);
+    }
+
+    for service in &mut loaded {
+        service.published = published.remove(&service.hostname.to_string()).flatten();
+        for backend in &mut service.backends {
+            backend.fetched = fetched.get(&backend.hostname.to_string()).cloned();
+        }
+    }
+
+    loaded
+}
+
+async fn fetch_descriptors(
+    client: &mut ControlClient,
+    services: &[Service],
+) -> Result<(), tor::Error> {
+    for backend in services.iter().flat_map(|service| &service.backends) {
+        match client.hs_fetch(&backend.hostname).await {
+            Ok(()) => {}
+            Err(tor::Error::ControlReply { status, message }) => {
+                tracing::warn!(hostname =% backend.hostname, status, message, "fetch failed");
+            }
+            Err(error) => return Err(error),
+        }
+    }
+
+    Ok(())
+}
+
+/// `HS_DESC_CONTENT <address> <descriptor id> <hsdir>`, carrying the
+/// descriptor of a backend, or nothing when it could not be fetched.
+fn receive_descriptor(services: &mut [Service], event: &ControlEvent) {
+    if event.keyword() != "HS_DESC_CONTENT" {
+        return;
+    }
+
+    let Some(address) = event.reply().message().split_whitespace().nth(1) else {
+        return;
+    };
+
+    let Some(data) = event
+        .reply()
+        .lines
+        .first()
+        .and_then(|f| f.data.as_deref())
+        .filter(|f| !f.is_empty())
+    else {
+        return;
+    };
+
+    let hostname = format!("{address}.onion");
+    for backend in services
+        .iter_mut()
+        .flat_map(|service| &mut service.backends)
+        .filter(|backend| backend.hostname.to_string() == hostname)
+    {
+        match Descriptor::from(data.to_string()).introduction_points(&backend.public_key) {
+            Ok(introduction_points) => {
+                tracing::debug!(
+                    hostname,
+                    introduction_points = introduction_points.len(),
+                    "descriptor fetched"
+                );
+                backend.fetched = Some((Instant::now(), introduction_points));
+            }
+            Err(error) => tracing::warn!(hostname, %error, "descriptor rejected"),
+        }
+    }
+}
+
+/// Publishes the descriptors of the current and next time period, as clients
+/// may use either around the rotation.
+async fn publish_descriptors(
+    client: &mut ControlClient,
+    services: &mut [Service],
+    now: SystemTime,
+) -> Result<(), tor::Error> {
+    let time_period = tor::time_period(now);
+    let revision_counter = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+    for service in services {
+        let introduction_points = service.introduction_points();
+
+        if introduction_points.is_empty() {
+            tracing::debug!(hostname =% service.hostname, "no introduction points");
+            continue;
+        }
+
+        if !service.must_publish(&introduction_points, time_period) {
+            continue;
+        }
+
+        for time_period in [time_period, time_period + 1] {
+            let descriptor = Descriptor::builder()
+                .introduction_points(introduction_points.clone())
+                .revision_counter(revision_counter)
+                .time_period(time_period)
+                .build(&service.secret_key);
+
+            match client.hs_post(&service.hostname, &descriptor).await {
+                Ok(()) => {}
+                Err(tor::Error::ControlReply { status, message }) => {
+                    tracing::warn!(hostname =% service.hostname, status, message, "publish failed");
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        tracing::info!(
+            hostname =% service.hostname,
+            introduction_points = introduction_points.len(),
+            "descriptor published"
+        );
+
+        service.published = Some(Published {
+            at: Instant::now(),
+            introduction_points,
+            time_period,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    use crate::tor::{ConfigYamlService, ConfigYamlServiceInstance};
+
+    use super::*;
+
+    fn introduction_point(index: u8) -> IntroductionPoint {
+        IntroductionPoint {
+            auth_key: PublicKey::from(&ExpandedSecretKey::generate()).to_bytes(),
+            enc_key: "ntor AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=".to_string(),
+            enc_key_ed25519: PublicKey::from(&ExpandedSecretKey::generate()).to_bytes(),
+            link_specifiers: format!("AQYKAAAB{index}ID"),
+            onion_key: "ntor AgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgI=".to_string(),
+        }
+    }
+
+    #[test]
+    fn interleaved_introduction_points() {
+        let service = Service {
+            backends: [vec![1, 2, 3], vec![4], vec![]]
+                .into_iter()
+                .map(|indexes| {
+                    let public_key = PublicKey::from(&ExpandedSecretKey::generate());
+                    Backend {
+                        fetched: Some((
+                            Instant::now(),
+                            indexes.into_iter().map(introduction_point).collect(),
+                        )),
+                        hostname: Hostname::from(&public_key),
+                        public_key,
+                    }
+                })
+                .collect(),
+            hostname: Hostname::new("example.onion".to_string()),
+            published: None,
+            secret_key: ExpandedSecretKey::generate(),
+        };
+
+        assert_eq!(
+            vec!["AQYKAAAB1ID", "AQYKAAAB4ID", "AQYKAAAB2ID", "AQYKAAAB3ID"],
+            service
+                .introduction_points()
+                .iter()
+                .map(|f| f.link_specifiers.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn reload() {
+        fn backend(index: u8) -> Backend {
+            let public_key = PublicKey::from(&ExpandedSecretKey::generate());
+            Backend {
+                fetched: Some((Instant::now(), vec![introduction_point(index)])),
+                hostname: Hostname::from(&public_key),
+                public_key,
+            }
+        }
+
+        fn loaded(backend: &Backend) -> Backend {
+            let hostname = Hostname::try_from(backend.hostname.to_string().as_bytes()).unwrap();
+            Backend {
+                fetched: None,
+                public_key: PublicKey::try_from(&hostname).unwrap(),
+                hostname,
+            }
+        }
+
+        fn service(backends: Vec<Backend>) -> Service {
+            Service {
+                backends,
+                hostname: Hostname::new("example.onion".to_string()),
+                published: None,
+                secret_key: ExpandedSecretKey::generate(),
+            }
+        }
+
+        let (kept, removed, added) = (backend(1), backend(2), backend(3));
+        let loaded = vec![service(vec![loaded(&kept), loaded(&added)])];
+        let mut services = vec![service(vec![kept, removed])];
+        services[0].published = Some(Published {
+            at: Instant::now(),
+            introduction_points: services[0].introduction_points(),
+            time_period: 1,
+        });
+
+        let services = super::reload(services, loaded);
+
+        assert_eq!(
+            vec!["AQYKAAAB1ID"],
+            services[0]
+                .introduction_points()
+                .iter()
+                .map(|f| f.link_specifiers.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some(1),
+            services[0].published.as_ref().map(|f| f.time_period)
+        );
+    }
+
+    #[tokio::test]
+    async fn publish() {
+        let directory =
+            std::env::temp_dir().join(format!("tor-operator-balancer-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let frontend = ExpandedSecretKey::generate();
+        let key = directory.join("hs_ed25519_secret_key");
+        std::fs::write(&key, Vec::from(&HiddenServiceSecretKey::from(&frontend))).unwrap();
+
+        let backend = ExpandedSecretKey::generate();
+        let backend_hostname = Hostname::from(&PublicKey::from(&backend));
+        let introduction_points = (1..=3).map(introduction_point).collect::<Vec<_>>();
+        let backend_descriptor = Descriptor::builder()
+            .introduction_points(introduction_points.clone())
+            .build(&backend);
+
+        let config = directory.join("config.yaml");
+        std::fs::write(
+            &config,
+            ConfigYaml {
+                services: vec![ConfigYamlService {
+                    instances: vec![ConfigYamlServiceInstance {
+                        address: backend_hostname.to_string(),
+                        name: "backend".into(),
+                    }],
+                    key: key.to_string_lossy().into(),
+                }],
+            }
+            .to_string(),
+        )
+        .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let control_port = listener.local_addr().unwrap().to_string();
+        let (posted_tx, mut posted_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let reply = if line == "PROTOCOLINFO 1" {
+                    "250-PROTOCOLINFO 1\r\n250-AUTH METHODS=NULL\r\n250 OK\r\n".to_string()
+                } else if let Some(address) = line.strip_prefix("HSFETCH ") {
+                    format!(
+                        "250 OK\r\n650+HS_DESC_CONTENT {address} descid $F00\r\n{}.\r\n650 OK\r\n",
+                        backend_descriptor.to_string().replace('\n', "\r\n")
+                    )
+                } else if let Some(address) = line.strip_prefix("+HSPOST HSADDRESS=") {
+                    let mut descriptor = String::new();
+                    while let Some(line) = lines.next_line().await.unwrap() {
+                        if line == "." {
+                            break;
+                        }
+                        descriptor.push_str(&line);
+                        descriptor.push('\n');
+                    }
+                    let _ = posted_tx.send((address.to_string(), descriptor));
+                    "250 OK\r\n".to_string()
+                } else {
+                    "250 OK\r\n".to_string()
+                };
+                writer.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+
+        let balancer = tokio::spawn(async move {
+            run(&Config {
+                config,
+                control_port,
+                fetch_descriptor_frequency: Duration::from_hours(1),
+                publish_descriptor_check_frequency: Duration::from_millis(100),
+            })
+            .await
+        });
+
+        let (address, descriptor) = posted_rx.recv().await.unwrap();
+        balancer.abort();
+
+        let public_key = PublicKey::from(&frontend);
+        assert_eq!(
+            Hostname::from(&public_key).to_string(),
+            format!("{address}.onion")
+        );
+        assert_eq!(
+            introduction_points,
+            Descriptor::from(descriptor)
+                .introduction_points(&public_key)
+                .unwrap()
+        );
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
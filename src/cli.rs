@@ -176,11 +176,29 @@ pub enum CliCommands {
     /// Custom Resource Definition
     Crd(CrdArgs),
 
+    /// Container Entrypoint
+    Entrypoint(EntrypointArgs),
+
+    /// Maintenance Fallback
+    Fallback(FallbackArgs),
+
+    /// Circuit-aware Gateway
+    Gateway(GatewayArgs),
+
     /// Markdown
     Markdown(MarkdownArgs),
 
+    /// Onion Balance
+    OnionBalance(OnionBalanceArgs),
+
     /// Onion Key
     OnionKey(OnionKeyArgs),
+
+    /// Probe
+    Probe(ProbeArgs),
+
+    /// TLS Terminator
+    Tls(TlsArgs),
 }
 
 /*
@@ -202,6 +220,10 @@ pub enum ControllerCommands {
 
 #[derive(Args, Debug)]
 pub struct ControllerRunArgs {
+    /// Publish the `OnionBalance` descriptors with onion-balance run of the Tor image, instead of the Onion Balance image
+    #[arg(long, env)]
+    pub onion_balance_native: bool,
+
     /// Onion Balance image pull policy
     #[arg(long, env, default_value = "IfNotPresent")]
     pub onion_balance_image_pull_policy: String,
@@ -218,7 +240,7 @@ pub struct ControllerRunArgs {
     #[arg(
         long,
         env,
-        default_value = "ghcr.io/agabani/tor-operator:onion-balance-0.2.4.0"
+        default_value = "ghcr.io/agabani/tor-operator:onion-balance-0.2.4.1"
     )]
     onion_balance_image_uri: String,
 
@@ -246,9 +268,21 @@ pub struct ControllerRunArgs {
     #[arg(
         long,
         env,
-        default_value = "ghcr.io/agabani/tor-operator:tor-0.4.9.11.0"
+        default_value = "ghcr.io/agabani/tor-operator:tor-0.4.9.11.1"
     )]
     tor_image_uri: String,
+
+    /// Certificate of the admission webhook, which is served when set
+    #[arg(long, env, requires = "webhook_key")]
+    pub webhook_certificate: Option<PathBuf>,
+
+    /// Private key of the admission webhook
+    #[arg(long, env, requires = "webhook_certificate")]
+    pub webhook_key: Option<PathBuf>,
+
+    /// Port the admission webhook binds to
+    #[arg(long, env, default_value_t = 8443)]
+    pub webhook_port: u16,
 }
 
 impl ControllerRunArgs {
@@ -325,6 +359,219 @@ pub enum CrdGenerateArgsFormat {
     Yaml,
 }
 
+/*
+ * ============================================================================
+ * Entrypoint
+ * ============================================================================
+ */
+#[derive(Args, Debug)]
+pub struct EntrypointArgs {
+    #[command(subcommand)]
+    pub command: EntrypointCommands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum EntrypointCommands {
+    /// Prepare the container filesystem and run a command
+    Run(EntrypointRunArgs),
+}
+
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Args, Debug)]
+pub struct EntrypointRunArgs {
+    /// Add a port to an ephemeral Onion Service created over the control port
+    #[arg(long, value_name = "VIRTPORT,TARGET")]
+    pub add_onion_port: Vec<String>,
+
+    /// Name of the Config Map annotated with the hostname of the ephemeral Onion Service
+    #[arg(long)]
+    pub config_map_name: Option<String>,
+
+    /// Namespace of the Config Map annotated with the hostname of the ephemeral Onion Service
+    #[arg(long, env = "POD_NAMESPACE")]
+    pub config_map_namespace: Option<String>,
+
+    /// Tor control port address
+    #[arg(long, default_value = "127.0.0.1:6666")]
+    pub control_port: String,
+
+    /// Copy a file, or the files of a directory, from source to destination
+    #[arg(long, value_name = "SOURCE:DESTINATION", value_parser = parse_source_destination)]
+    pub copy: Vec<(String, String)>,
+
+    /// Create a directory with mode 700
+    #[arg(long, value_name = "DIRECTORY")]
+    pub directory: Vec<String>,
+
+    /// Forward SIGTERM to the command as SIGINT
+    #[arg(long)]
+    pub interrupt_on_terminate: bool,
+
+    /// Only run the command while holding the Lease
+    #[arg(long, requires = "leader_election_lease")]
+    pub leader_election_exclusive: bool,
+
+    /// Identity of the leader election candidate
+    #[arg(long, env = "POD_NAME")]
+    pub leader_election_identity: Option<String>,
+
+    /// Only run the Onion Services while holding the Lease
+    #[arg(
+        long,
+        value_name = "LEASE",
+        requires_all = ["leader_election_identity", "leader_election_namespace"]
+    )]
+    pub leader_election_lease: Option<String>,
+
+    /// Namespace of the Lease
+    #[arg(long, env = "POD_NAMESPACE")]
+    pub leader_election_namespace: Option<String>,
+
+    /// Record the time of the descriptors uploaded over the control port on the Lease
+    #[arg(long, requires = "leader_election_lease")]
+    pub leader_election_record_uploads: bool,
+
+    /// Re-render the templates and send SIGHUP to the command when a template source changes
+    #[arg(long)]
+    pub reload: bool,
+
+    /// Render a template from source to destination
+    #[arg(long, value_name = "SOURCE:DESTINATION", value_parser = parse_source_destination)]
+    pub template: Vec<(String, String)>,
+
+    /// Command to run
+    #[arg(last = true, required = true)]
+    pub command: Vec<String>,
+}
+
+fn parse_source_destination(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once(':')
+        .map(|(source, destination)| (source.to_string(), destination.to_string()))
+        .ok_or_else(|| format!("expected SOURCE:DESTINATION, found {value}"))
+}
+
+/*
+ * ============================================================================
+ * Fallback
+ * ============================================================================
+ */
+#[derive(Args, Debug)]
+pub struct FallbackArgs {
+    #[command(subcommand)]
+    pub command: FallbackCommands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum FallbackCommands {
+    /// Forward the connections of Tor to their targets, serving a maintenance response while they are unavailable
+    Run(FallbackRunArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct FallbackRunArgs {
+    /// Namespace of the Services whose endpoints are watched
+    #[arg(long, env = "POD_NAMESPACE")]
+    pub namespace: String,
+
+    /// Accept connections from Tor on an address and forward them to a target while the Service has ready endpoints
+    #[arg(
+        long,
+        required = true,
+        value_name = "listen=LISTEN,target=TARGET,service=SERVICE[,status=STATUS][,content-type=CONTENT_TYPE][,body-file=BODY_FILE]",
+        value_parser = parse_fallback_route
+    )]
+    pub route: Vec<FallbackRunArgsRoute>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FallbackRunArgsRoute {
+    pub body_file: Option<PathBuf>,
+    pub content_type: Option<String>,
+    pub listen: String,
+    pub service: String,
+    pub status: Option<u16>,
+    pub target: String,
+}
+
+fn parse_fallback_route(value: &str) -> Result<FallbackRunArgsRoute, String> {
+    let mut body_file = None;
+    let mut content_type = None;
+    let mut listen = None;
+    let mut service = None;
+    let mut status = None;
+    let mut target = None;
+
+    for pair in value.split(',') {
+        let (key, value) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("expected KEY=VALUE, found {pair}"))?;
+        match key {
+            "body-file" => body_file = Some(value.into()),
+            "content-type" => content_type = Some(value.to_string()),
+            "listen" => listen = Some(value.to_string()),
+            "service" => service = Some(value.to_string()),
+            "status" => {
+                status = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid status {value}"))?,
+                );
+            }
+            "target" => target = Some(value.to_string()),
+            _ => return Err(format!("unexpected key {key}")),
+        }
+    }
+
+    Ok(FallbackRunArgsRoute {
+        body_file,
+        content_type,
+        listen: listen.ok_or("missing listen")?,
+        service: service.ok_or("missing service")?,
+        status,
+        target: target.ok_or("missing target")?,
+    })
+}
+
+/*
+ * ============================================================================
+ * Gateway
+ * ============================================================================
+ */
+#[derive(Args, Debug)]
+pub struct GatewayArgs {
+    #[command(subcommand)]
+    pub command: GatewayCommands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum GatewayCommands {
+    /// Forward the connections of Tor to their targets, enforcing per-circuit limits
+    Run(GatewayRunArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct GatewayRunArgs {
+    /// Maximum number of concurrent connections per circuit
+    #[arg(long, env)]
+    pub connection_limit: Option<usize>,
+
+    /// Maximum number of new connections per circuit per minute
+    #[arg(long, env)]
+    pub rate_limit: Option<u32>,
+
+    /// Accept PROXY protocol connections from Tor on an address and forward them to a target
+    #[arg(long, required = true, value_name = "LISTEN,TARGET", value_parser = parse_listen_target)]
+    pub route: Vec<(String, String)>,
+}
+
+fn parse_listen_target(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once(',')
+        .map(|(listen, target)| (listen.to_string(), target.to_string()))
+        .ok_or_else(|| format!("expected LISTEN,TARGET, found {value}"))
+}
+
 /*
  * ============================================================================
  * Markdown
@@ -350,6 +597,50 @@ pub struct MarkdownGenerateArgs {
     pub output: Option<PathBuf>,
 }
 
+/*
+ * ============================================================================
+ * Onion Balance
+ * ============================================================================
+ */
+#[derive(Args, Debug)]
+pub struct OnionBalanceArgs {
+    #[command(subcommand)]
+    pub command: OnionBalanceCommands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum OnionBalanceCommands {
+    /// Publish the descriptors of the Onion Services, combining the introduction points of their backends
+    Run(OnionBalanceRunArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct OnionBalanceRunArgs {
+    /// onionbalance config.yaml of the Onion Services
+    #[arg(long, env, value_hint = clap::ValueHint::FilePath)]
+    pub config: PathBuf,
+
+    /// Tor control port address
+    #[arg(long, env, default_value = "127.0.0.1:6666")]
+    pub control_port: String,
+
+    /// Seconds between fetches of the backend descriptors
+    #[arg(
+        long,
+        env = "ONIONBALANCE_FETCH_DESCRIPTOR_FREQUENCY",
+        default_value_t = 600
+    )]
+    pub fetch_descriptor_frequency: u64,
+
+    /// Seconds between checks whether the descriptors must be published
+    #[arg(
+        long,
+        env = "ONIONBALANCE_PUBLISH_DESCRIPTOR_CHECK_FREQUENCY",
+        default_value_t = 300
+    )]
+    pub publish_descriptor_check_frequency: u64,
+}
+
 /*
  * ============================================================================
  * Onion Address
@@ -373,3 +664,86 @@ pub struct OnionKeyGenerateArgs {
     #[arg(long, env, value_hint = clap::ValueHint::DirPath)]
     pub output: Option<PathBuf>,
 }
+
+/*
+ * ============================================================================
+ * Probe
+ * ============================================================================
+ */
+#[derive(Args, Debug)]
+pub struct ProbeArgs {
+    #[command(subcommand)]
+    pub command: ProbeCommands,
+
+    /// Tor control port address
+    #[arg(long, env, default_value = "127.0.0.1:6666")]
+    pub control_port: String,
+
+    /// Tor control port authentication cookie file (Default: advertised by Tor)
+    #[arg(long, env, value_hint = clap::ValueHint::FilePath)]
+    pub control_cookie_file: Option<PathBuf>,
+
+    /// Tor control port password
+    #[arg(long, env)]
+    pub control_password: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ProbeCommands {
+    /// Check Tor is responsive and has bootstrapped
+    Liveness,
+
+    /// Check Tor has bootstrapped, established a circuit and published the Onion Service descriptor
+    Readiness(ProbeReadinessArgs),
+
+    /// Check Tor has bootstrapped
+    Startup,
+}
+
+#[derive(Args, Debug)]
+pub struct ProbeReadinessArgs {
+    /// Onion Service hostname files whose descriptors must be published
+    #[arg(
+        long = "hostname-file",
+        env = "HOSTNAME_FILE",
+        value_delimiter = ',',
+        value_hint = clap::ValueHint::FilePath
+    )]
+    pub hostname_files: Vec<PathBuf>,
+}
+
+/*
+ * ============================================================================
+ * TLS
+ * ============================================================================
+ */
+#[derive(Args, Debug)]
+pub struct TlsArgs {
+    #[command(subcommand)]
+    pub command: TlsCommands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TlsCommands {
+    /// Terminate TLS connections and forward them to a target
+    Run(TlsRunArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct TlsRunArgs {
+    /// PEM encoded certificate chain, reloaded when it changes
+    #[arg(long, env, value_hint = clap::ValueHint::FilePath)]
+    pub certificate: PathBuf,
+
+    /// PEM encoded private key, reloaded when it changes
+    #[arg(long, env, value_hint = clap::ValueHint::FilePath)]
+    pub key: PathBuf,
+
+    /// Address to accept TLS connections on
+    #[arg(long, env)]
+    pub listen: String,
+
+    /// Target to forward the decrypted connections to
+    #[arg(long, env)]
+    pub target: String,
+}
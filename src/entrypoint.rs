@@ -0,0 +1,1019 @@
+use std::{
+    os::unix::{fs::PermissionsExt as _, process::ExitStatusExt as _},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use k8s_openapi::{
+    api::{coordination::v1::Lease, core::v1::ConfigMap},
+    apimachinery::pkg::apis::meta::v1::MicroTime,
+    jiff::Timestamp,
+};
+use rand::{Rng as _, SeedableRng as _};
+use tokio::{
+    process::Command,
+    signal::unix::{SignalKind, signal},
+};
+
+use crate::{
+    kubernetes::{TOR_AGABANI_CO_UK_HOSTNAME_KEY, TOR_AGABANI_CO_UK_PUBLISHED_AT_KEY},
+    tor::{self, ControlClient},
+};
+
+const ADD_ONION_RETRY_PERIOD: Duration = Duration::from_secs(1);
+
+const CONTROL_PORT_RETRY_PERIOD: Duration = Duration::from_secs(1);
+
+const LEASE_DURATION_SECONDS: i32 = 15;
+
+const LEASE_RETRY_PERIOD: Duration = Duration::from_secs(2);
+
+const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Minimum time between two records of an uploaded descriptor, as every
+/// publication uploads to several directories.
+const UPLOAD_RECORD_PERIOD: Duration = Duration::from_secs(30);
+
+/// Options of the Onion Services, left out of the templates while on standby.
+const STANDBY_OPTION_PREFIX: &str = "HiddenService";
+
+const TOR_TMP_DIR: &str = "TOR_TMP_DIR";
+
+/*
+ * ============================================================================
+ * Config
+ * ============================================================================
+ */
+pub struct Config {
+    /// Program and arguments of the child process.
+    pub command: Vec<String>,
+
+    /// Files, or the files of directories, to copy from source to destination.
+    pub copies: Vec<(String, String)>,
+
+    /// Directories to create with mode 700.
+    pub directories: Vec<String>,
+
+    /// Onion Service to create with `ADD_ONION` once the child process runs.
+    pub ephemeral: Option<Ephemeral>,
+
+    /// Forward SIGTERM to the child process as SIGINT, so that Tor waits
+    /// `ShutdownWaitLength` before exiting.
+    pub interrupt_on_terminate: bool,
+
+    /// Only run the Onion Services while holding the Lease.
+    pub leader_election: Option<LeaderElection>,
+
+    /// Re-render the templates and send SIGHUP to the child process when a
+    /// template source changes.
+    pub reload: bool,
+
+    /// Templates to render from source to destination.
+    pub templates: Vec<(String, String)>,
+}
+
+#[derive(Clone)]
+pub struct Ephemeral {
+    /// Tor control port address.
+    pub control_port: String,
+
+    /// Name and namespace of the Config Map to annotate with the hostname.
+    pub config_map: Option<(String, String)>,
+
+    /// Ports of the Onion Service, as `VIRTPORT,TARGET`.
+    pub ports: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct LeaderElection {
+    /// Only run the child process while holding the Lease, instead of keeping
+    /// it running without the Onion Services on standby.
+    pub exclusive: bool,
+
+    /// Identity of the candidate, usually the name of the Pod.
+    pub identity: String,
+
+    /// Name of the Lease.
+    pub lease: String,
+
+    /// Namespace of the Lease.
+    pub namespace: String,
+
+    /// Tor control port watched for uploaded descriptors, whose time is
+    /// recorded on the Lease while holding it.
+    pub record_uploads: Option<String>,
+}
+
+/*
+ * ============================================================================
+ * Entrypoint
+ * ============================================================================
+ */
+/// Prepares the container filesystem, then runs the child process until it
+/// exits, forwarding signals to it.
+///
+/// Every path, template and argument has `$VAR` and `${VAR}` substituted from
+/// the environment. `TOR_TMP_DIR` defaults to a new temporary directory.
+///
+/// With leader election, the templates are rendered without the `HiddenService`
+/// options until the Lease is acquired, so that standbys keep Tor bootstrapped
+/// without publishing the Onion Services. With exclusive leader election, the
+/// child process is only started once the Lease is acquired, and stopped when
+/// it is lost.
+///
+/// Returns the exit code of the child process, or `128 + signal` if it was
+/// terminated by a signal.
+///
+/// # Errors
+///
+/// Returns an error if the filesystem cannot be prepared or the child process
+/// cannot be run.
+pub async fn run(config: &Config) -> std::io::Result<i32> {
+    let tmp_dir = match std::env::var_os(TOR_TMP_DIR) {
+        Some(tmp_dir) => PathBuf::from(tmp_dir),
+        None => create_tmp_dir()?,
+    };
+    tracing::info!(tmp_dir = %tmp_dir.display(), "using temporary directory");
+
+    let tmp_dir = tmp_dir.to_string_lossy().to_string();
+    let lookup = {
+        let tmp_dir = tmp_dir.clone();
+        move |name: &str| {
+            if name == TOR_TMP_DIR {
+                Some(tmp_dir.clone())
+            } else {
+                std::env::var(name).ok()
+            }
+        }
+    };
+
+    if let Some(leader_election) = config.leader_election.as_ref().filter(|f| f.exclusive) {
+        acquire(leader_election).await?;
+    }
+
+    let standby = Arc::new(AtomicBool::new(
+        config
+            .leader_election
+            .as_ref()
+            .is_some_and(|f| !f.exclusive),
+    ));
+
+    prepare(config, &lookup, standby.load(Ordering::SeqCst))?;
+
+    let command = config
+        .command
+        .iter()
+        .map(|arg| render(arg, &lookup))
+        .collect::<Vec<_>>();
+
+    let templates = config
+        .templates
+        .iter()
+        .map(|(source, destination)| (render(source, &lookup), render(destination, &lookup)))
+        .collect::<Vec<_>>();
+
+    execute(
+        &command,
+        &[(TOR_TMP_DIR, &tmp_dir)],
+        config.interrupt_on_terminate,
+        |pid| {
+            let mut tasks = Vec::new();
+            if let Some(ephemeral) = &config.ephemeral {
+                tasks.push(tokio::spawn(add_onion(ephemeral.clone())));
+            }
+            if let Some(leader_election) = &config.leader_election {
+                tasks.push(tokio::spawn(elect_leader(
+                    leader_election.clone(),
+                    templates.clone(),
+                    lookup.clone(),
+                    standby.clone(),
+                    pid,
+                )));
+                if let Some(control_port) = &leader_election.record_uploads {
+                    tasks.push(tokio::spawn(record_uploads(
+                        leader_election.clone(),
+                        control_port.clone(),
+                        standby.clone(),
+                    )));
+                }
+            }
+            if config.reload {
+                tasks.push(tokio::spawn(watch_templates(
+                    templates,
+                    lookup,
+                    standby,
+                    pid,
+                    RELOAD_INTERVAL,
+                )));
+            }
+            tasks
+        },
+    )
+    .await
+}
+
+async fn execute(
+    command: &[String],
+    envs: &[(&str, &str)],
+    interrupt_on_terminate: bool,
+    spawn: impl FnOnce(i32) -> Vec<tokio::task::JoinHandle<()>>,
+) -> std::io::Result<i32> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| std::io::Error::other("missing command"))?;
+
+    tracing::info!(program, "starting child process");
+
+    let mut child = Command::new(program)
+        .args(args)
+        .envs(envs.iter().copied())
+        .spawn()?;
+
+    let pid = child
+        .id()
+        .and_then(|pid| i32::try_from(pid).ok())
+        .ok_or_else(|| std::io::Error::other("child process has no pid"))?;
+
+    let forwarders = [
+        SignalKind::hangup(),
+        SignalKind::interrupt(),
+        SignalKind::quit(),
+        SignalKind::terminate(),
+        SignalKind::user_defined1(),
+        SignalKind::user_defined2(),
+    ]
+    .into_iter()
+    .map(|kind| {
+        let forwarded = if interrupt_on_terminate && kind == SignalKind::terminate() {
+            SignalKind::interrupt()
+        } else {
+            kind
+        };
+        forward_signal(kind, forwarded, pid)
+    })
+    .collect::<std::io::Result<Vec<_>>>()?;
+
+    let tasks = spawn(pid);
+
+    let status = child.wait().await;
+
+    for task in forwarders.into_iter().chain(tasks) {
+        task.abort();
+    }
+
+    let status = status?;
+    tracing::info!(%status, "child process exited");
+
+    Ok(status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or_default()))
+}
+
+fn create_tmp_dir() -> std::io::Result<PathBuf> {
+    let mut csprng =
+        rand::rngs::StdRng::try_from_rng(&mut rand::rngs::SysRng).map_err(std::io::Error::other)?;
+
+    let tmp_dir = std::env::temp_dir().join(format!("tmp.{:016x}.tor", csprng.next_u64()));
+
+    std::fs::create_dir(&tmp_dir)?;
+    std::fs::set_permissions(&tmp_dir, std::fs::Permissions::from_mode(0o700))?;
+
+    Ok(tmp_dir)
+}
+
+fn forward_signal(
+    kind: SignalKind,
+    forwarded: SignalKind,
+    pid: i32,
+) -> std::io::Result<tokio::task::JoinHandle<()>> {
+    let mut signals = signal(kind)?;
+
+    Ok(tokio::spawn(async move {
+        while signals.recv().await.is_some() {
+            tracing::info!(
+                signal = kind.as_raw_value(),
+                forwarded = forwarded.as_raw_value(),
+                "forwarding signal"
+            );
+            // SAFETY: `kill` has no memory safety preconditions.
+            unsafe { libc::kill(pid, forwarded.as_raw_value()) };
+        }
+    }))
+}
+
+async fn watch_templates(
+    templates: Vec<(String, String)>,
+    lookup: impl Fn(&str) -> Option<String>,
+    standby: Arc<AtomicBool>,
+    pid: i32,
+    period: Duration,
+) {
+    let mut contents = templates
+        .iter()
+        .map(|(source, _)| std::fs::read_to_string(source).ok())
+        .collect::<Vec<_>>();
+
+    let mut interval = tokio::time::interval(period);
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        let mut changed = false;
+
+        for ((source, destination), content) in templates.iter().zip(&mut contents) {
+            let Ok(current) = std::fs::read_to_string(source) else {
+                continue;
+            };
+            if content.as_ref() == Some(&current) {
+                continue;
+            }
+
+            tracing::info!(source, "template changed");
+            let rendered = render_template(&current, &lookup, standby.load(Ordering::SeqCst));
+            *content = Some(current);
+
+            // an unchanged rendering does not need a reload
+            if std::fs::read_to_string(destination).ok().as_ref() == Some(&rendered) {
+                continue;
+            }
+
+            match write_file(Path::new(destination), rendered.as_bytes()) {
+                Ok(()) => changed = true,
+                Err(error) => tracing::warn!(%error, destination, "failed to render template"),
+            }
+        }
+
+        if changed {
+            tracing::info!("reloading child process");
+            // SAFETY: `kill` has no memory safety preconditions.
+            unsafe { libc::kill(pid, libc::SIGHUP) };
+        }
+    }
+}
+
+/*
+ * ============================================================================
+ * Ephemeral
+ * ============================================================================
+ */
+async fn add_onion(ephemeral: Ephemeral) {
+    let hostname = loop {
+        match try_add_onion(&ephemeral).await {
+            Ok(service_id) => break format!("{service_id}.onion"),
+            Err(error) => {
+                tracing::debug!(%error, "failed to add onion service");
+                tokio::time::sleep(ADD_ONION_RETRY_PERIOD).await;
+            }
+        }
+    };
+    tracing::info!(hostname, "added ephemeral onion service");
+
+    let Some((name, namespace)) = &ephemeral.config_map else {
+        return;
+    };
+
+    while let Err(error) = annotate_config_map(name, namespace, &hostname).await {
+        tracing::warn!(%error, config_map = name, "failed to annotate config map");
+        tokio::time::sleep(ADD_ONION_RETRY_PERIOD).await;
+    }
+}
+
+async fn try_add_onion(ephemeral: &Ephemeral) -> Result<String, tor::Error> {
+    let mut client = ControlClient::connect_and_authenticate(&ephemeral.control_port).await?;
+
+    client
+        .add_onion(
+            &ephemeral
+                .ports
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>(),
+        )
+        .await
+}
+
+async fn annotate_config_map(name: &str, namespace: &str, hostname: &str) -> kube::Result<()> {
+    let api = kube::Api::<ConfigMap>::namespaced(kube::Client::try_default().await?, namespace);
+
+    api.patch(
+        name,
+        &kube::api::PatchParams::default(),
+        &kube::api::Patch::Merge(serde_json::json!({
+            "metadata": {
+                "annotations": {
+                    TOR_AGABANI_CO_UK_HOSTNAME_KEY: hostname,
+                },
+            },
+        })),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/*
+ * ============================================================================
+ * Leader Election
+ * ============================================================================
+ */
+async fn elect_leader(
+    leader_election: LeaderElection,
+    templates: Vec<(String, String)>,
+    lookup: impl Fn(&str) -> Option<String>,
+    standby: Arc<AtomicBool>,
+    pid: i32,
+) {
+    let api = match kube::Client::try_default().await {
+        Ok(client) => kube::Api::<Lease>::namespaced(client, &leader_election.namespace),
+        Err(error) => {
+            tracing::error!(%error, "failed to create kubernetes client");
+            return;
+        }
+    };
+
+    let mut renewed: Option<(Instant, Duration)> = None;
+
+    loop {
+        // The Lease is renewed no earlier than the request is sent.
+        let attempted = Instant::now();
+
+        let (leader, period) =
+            match acquire_or_renew(&api, &leader_election.lease, &leader_election.identity).await {
+                Ok((leader, duration)) => {
+                    renewed = leader.then_some((attempted, duration));
+                    (leader, duration / 3)
+                }
+                Err(error) => {
+                    tracing::warn!(%error, lease = leader_election.lease, "failed to renew lease");
+                    // Leadership is given up at the renew deadline, before the
+                    // Lease could be acquired by another candidate.
+                    let leader = renewed.is_some_and(|(instant, duration)| {
+                        instant.elapsed() < renew_deadline(duration)
+                    });
+                    if !leader {
+                        renewed = None;
+                    }
+                    (leader, LEASE_RETRY_PERIOD)
+                }
+            };
+
+        if leader == standby.load(Ordering::SeqCst) {
+            tracing::info!(leader, lease = leader_election.lease, "leadership changed");
+            standby.store(!leader, Ordering::SeqCst);
+
+            if leader_election.exclusive {
+                // The child process only runs while holding the Lease, so it
+                // is stopped and the container restarts as a candidate.
+                // SAFETY: `kill` has no memory safety preconditions.
+                unsafe { libc::kill(pid, libc::SIGTERM) };
+                return;
+            }
+
+            for (source, destination) in &templates {
+                let rendered = std::fs::read_to_string(source)
+                    .map(|template| render_template(&template, &lookup, !leader))
+                    .and_then(|rendered| write_file(Path::new(destination), rendered.as_bytes()));
+                if let Err(error) = rendered {
+                    tracing::warn!(%error, destination, "failed to render template");
+                }
+            }
+
+            // SAFETY: `kill` has no memory safety preconditions.
+            unsafe { libc::kill(pid, libc::SIGHUP) };
+        }
+
+        tokio::time::sleep(period).await;
+    }
+}
+
+/// Time a leader keeps leadership without renewing the Lease, leaving a third
+/// of the Lease for the child process to step down before it expires.
+fn renew_deadline(duration: Duration) -> Duration {
+    duration * 2 / 3
+}
+
+/// Waits until the candidate holds the Lease.
+async fn acquire(leader_election: &LeaderElection) -> std::io::Result<()> {
+    let client = kube::Client::try_default()
+        .await
+        .map_err(std::io::Error::other)?;
+    let api = kube::Api::<Lease>::namespaced(client, &leader_election.namespace);
+
+    tracing::info!(lease = leader_election.lease, "waiting for leadership");
+
+    loop {
+        let period = match acquire_or_renew(&api, &leader_election.lease, &leader_election.identity)
+            .await
+        {
+            Ok((true, _)) => break,
+            Ok((false, duration)) => duration / 3,
+            Err(error) => {
+                tracing::warn!(%error, lease = leader_election.lease, "failed to acquire lease");
+                LEASE_RETRY_PERIOD
+            }
+        };
+
+        tokio::time::sleep(period).await;
+    }
+
+    tracing::info!(lease = leader_election.lease, "leadership acquired");
+
+    Ok(())
+}
+
+/// Returns whether the candidate holds the Lease, and the duration of the
+/// Lease.
+async fn acquire_or_renew(
+    api: &kube::Api<Lease>,
+    name: &str,
+    identity: &str,
+) -> kube::Result<(bool, Duration)> {
+    let mut lease = api.get(name).await?;
+    let spec = lease.spec.get_or_insert_with(Default::default);
+
+    let seconds = spec
+        .lease_duration_seconds
+        .unwrap_or(LEASE_DURATION_SECONDS);
+    let duration = Duration::from_secs(u64::try_from(seconds).unwrap_or_default());
+
+    let now = Timestamp::now();
+    let holder = spec
+        .holder_identity
+        .clone()
+        .filter(|holder| !holder.is_empty());
+
+    if holder.as_deref() != Some(identity) {
+        let expired = spec.renew_time.as_ref().is_none_or(|renew_time| {
+            now.duration_since(renew_time.0).as_secs() >= i64::from(seconds)
+        });
+        if holder.is_some() && !expired {
+            return Ok((false, duration));
+        }
+        if holder.is_some() {
+            spec.lease_transitions = Some(spec.lease_transitions.unwrap_or_default() + 1);
+        }
+        spec.acquire_time = Some(MicroTime(now));
+        spec.holder_identity = Some(identity.to_string());
+    }
+    spec.renew_time = Some(MicroTime(now));
+
+    api.replace(name, &kube::api::PostParams::default(), &lease)
+        .await?;
+
+    Ok((true, duration))
+}
+
+/// Records the time of the descriptors uploaded by Tor on the Lease, while
+/// holding it.
+async fn record_uploads(
+    leader_election: LeaderElection,
+    control_port: String,
+    standby: Arc<AtomicBool>,
+) {
+    let api = match kube::Client::try_default().await {
+        Ok(client) => kube::Api::<Lease>::namespaced(client, &leader_election.namespace),
+        Err(error) => {
+            tracing::error!(%error, "failed to create kubernetes client");
+            return;
+        }
+    };
+
+    loop {
+        if let Err(error) =
+            try_record_uploads(&api, &leader_election.lease, &control_port, &standby).await
+        {
+            tracing::debug!(%error, control_port, "failed to watch descriptor uploads");
+        }
+        tokio::time::sleep(CONTROL_PORT_RETRY_PERIOD).await;
+    }
+}
+
+async fn try_record_uploads(
+    api: &kube::Api<Lease>,
+    lease: &str,
+    control_port: &str,
+    standby: &AtomicBool,
+) -> Result<(), tor::Error> {
+    let mut client = ControlClient::connect_and_authenticate(control_port).await?;
+    let mut events = client.events().expect("events are only taken once");
+    client.set_events(&["HS_DESC"]).await?;
+
+    let mut recorded: Option<Instant> = None;
+
+    while let Some(event) = events.recv().await {
+        if !is_uploaded(event.reply().message()) || standby.load(Ordering::SeqCst) {
+            continue;
+        }
+        if recorded.is_some_and(|instant| instant.elapsed() < UPLOAD_RECORD_PERIOD) {
+            continue;
+        }
+
+        match annotate_lease(api, lease, Timestamp::now()).await {
+            Ok(()) => recorded = Some(Instant::now()),
+            Err(error) => tracing::warn!(%error, lease, "failed to record descriptor upload"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the event reports a descriptor uploaded to a directory, e.g.
+/// `HS_DESC UPLOADED <address> UNKNOWN <directory>`.
+fn is_uploaded(message: &str) -> bool {
+    let mut words = message.split_whitespace();
+    words.next() == Some("HS_DESC") && words.next() == Some("UPLOADED")
+}
+
+async fn annotate_lease(
+    api: &kube::Api<Lease>,
+    name: &str,
+    published_at: Timestamp,
+) -> kube::Result<()> {
+    api.patch(
+        name,
+        &kube::api::PatchParams::default(),
+        &kube::api::Patch::Merge(serde_json::json!({
+            "metadata": {
+                "annotations": {
+                    TOR_AGABANI_CO_UK_PUBLISHED_AT_KEY: published_at.to_string(),
+                },
+            },
+        })),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/*
+ * ============================================================================
+ * Filesystem
+ * ============================================================================
+ */
+fn prepare(
+    config: &Config,
+    lookup: &impl Fn(&str) -> Option<String>,
+    standby: bool,
+) -> std::io::Result<()> {
+    for directory in &config.directories {
+        create_dir(Path::new(&render(directory, lookup)))?;
+    }
+
+    for (source, destination) in &config.copies {
+        copy(
+            Path::new(&render(source, lookup)),
+            Path::new(&render(destination, lookup)),
+        )?;
+    }
+
+    for (source, destination) in &config.templates {
+        let template = std::fs::read_to_string(render(source, lookup))?;
+        write_file(
+            Path::new(&render(destination, lookup)),
+            render_template(&template, lookup, standby).as_bytes(),
+        )?;
+    }
+
+    Ok(())
+}
+
+fn create_dir(path: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o700))
+}
+
+/// Follows symbolic links and, like `cp -L <source>/*`, skips hidden entries
+/// such as the `..data` link of projected volumes.
+fn copy(source: &Path, destination: &Path) -> std::io::Result<()> {
+    if !std::fs::metadata(source)?.is_dir() {
+        return write_file(destination, &std::fs::read(source)?);
+    }
+
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if std::fs::metadata(entry.path())?.is_file() {
+            write_file(
+                &destination.join(entry.file_name()),
+                &std::fs::read(entry.path())?,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_file(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, contents)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+}
+
+/*
+ * ============================================================================
+ * Template
+ * ============================================================================
+ */
+fn render_template(
+    template: &str,
+    lookup: &impl Fn(&str) -> Option<String>,
+    standby: bool,
+) -> String {
+    let rendered = render(template, lookup);
+
+    if !standby {
+        return rendered;
+    }
+
+    rendered
+        .lines()
+        .filter(|line| !line.trim_start().starts_with(STANDBY_OPTION_PREFIX))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Substitutes `$VAR` and `${VAR}` like `envsubst`, undefined variables
+/// becoming empty.
+fn render(template: &str, lookup: &impl Fn(&str) -> Option<String>) -> String {
+    fn is_name(c: char) -> bool {
+        c.is_ascii_alphanumeric() || c == '_'
+    }
+
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(index) = rest.find('$') {
+        output.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+
+        let (name, remainder) = if let Some(braced) = rest.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) if braced[..end].chars().all(is_name) => {
+                    (&braced[..end], &braced[end + 1..])
+                }
+                _ => ("", rest),
+            }
+        } else {
+            let end = rest.find(|c| !is_name(c)).unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            output.push('$');
+        } else {
+            output.push_str(&lookup(name).unwrap_or_default());
+            rest = remainder;
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "TOR_TMP_DIR" => Some("/tmp/tmp.tor".to_string()),
+            "PORT" => Some("80".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn lease_renew_deadline() {
+        let duration = Duration::from_secs(u64::try_from(LEASE_DURATION_SECONDS).unwrap());
+
+        assert_eq!(Duration::from_secs(10), renew_deadline(duration));
+        assert!(renew_deadline(duration) > duration / 3 + LEASE_RETRY_PERIOD);
+    }
+
+    #[test]
+    fn render_variables() {
+        assert_eq!(
+            "DataDirectory /tmp/tmp.tor/home/.tor\nHiddenServicePort 80 example:80",
+            render(
+                "DataDirectory ${TOR_TMP_DIR}/home/.tor\nHiddenServicePort $PORT example:$PORT",
+                &lookup
+            )
+        );
+        assert_eq!("[]", render("[$UNDEFINED${UNDEFINED}]", &lookup));
+        assert_eq!("$ $1 ${ ${-} 5$", render("$ $1 ${ ${-} 5$", &lookup));
+    }
+
+    #[test]
+    fn render_standby() {
+        let template = "DataDirectory ${TOR_TMP_DIR}/home/.tor\nHiddenServiceDir /hs\nHiddenServicePort $PORT example:$PORT";
+
+        assert_eq!(
+            "DataDirectory /tmp/tmp.tor/home/.tor\nHiddenServiceDir /hs\nHiddenServicePort 80 example:80",
+            render_template(template, &lookup, false)
+        );
+        assert_eq!(
+            "DataDirectory /tmp/tmp.tor/home/.tor",
+            render_template(template, &lookup, true)
+        );
+    }
+
+    #[test]
+    fn uploaded() {
+        assert!(is_uploaded(
+            "HS_DESC UPLOADED hostname UNKNOWN $0000000000000000000000000000000000000000"
+        ));
+        assert!(!is_uploaded(
+            "HS_DESC UPLOAD hostname UNKNOWN $0000000000000000000000000000000000000000 descriptor"
+        ));
+        assert!(!is_uploaded("HS_DESC_CONTENT UPLOADED hostname"));
+    }
+
+    #[test]
+    fn prepare_filesystem() {
+        let root =
+            std::env::temp_dir().join(format!("tor-operator-entrypoint-{}", std::process::id()));
+        let secrets = root.join("etc/secrets");
+        let data = secrets.join("..2026_01_01");
+        std::fs::create_dir_all(&data).unwrap();
+        std::fs::write(data.join("hostname"), "hostname.onion").unwrap();
+        std::os::unix::fs::symlink("..2026_01_01", secrets.join("..data")).unwrap();
+        std::os::unix::fs::symlink("..data/hostname", secrets.join("hostname")).unwrap();
+        std::fs::write(root.join("torrc"), "DataDirectory ${TOR_TMP_DIR}/home/.tor").unwrap();
+
+        let root_str = root.to_string_lossy();
+        let config = Config {
+            command: Vec::new(),
+            copies: vec![(
+                format!("{root_str}/etc/secrets"),
+                format!("{root_str}/out/hidden_service"),
+            )],
+            directories: vec![
+                format!("{root_str}/out/hidden_service"),
+                format!("{root_str}/out/home/.tor"),
+            ],
+            ephemeral: None,
+            interrupt_on_terminate: false,
+            leader_election: None,
+            reload: false,
+            templates: vec![(format!("{root_str}/torrc"), format!("{root_str}/out/torrc"))],
+        };
+
+        prepare(&config, &lookup, false).unwrap();
+
+        let mode = |path: &str| {
+            std::fs::metadata(root.join(path))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777
+        };
+
+        assert_eq!(0o700, mode("out/hidden_service"));
+        assert_eq!(0o700, mode("out/home/.tor"));
+        assert_eq!(0o600, mode("out/hidden_service/hostname"));
+        assert_eq!(
+            vec!["hostname".to_string()],
+            std::fs::read_dir(root.join("out/hidden_service"))
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "DataDirectory /tmp/tmp.tor/home/.tor",
+            std::fs::read_to_string(root.join("out/torrc")).unwrap()
+        );
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn exit_status() {
+        let command = |script: &str| vec!["sh".to_string(), "-c".to_string(), script.to_string()];
+
+        assert_eq!(
+            0,
+            execute(&command("exit 0"), &[], false, |_| Vec::new())
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            3,
+            execute(&command("exit 3"), &[], false, |_| Vec::new())
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            4,
+            execute(
+                &command("exit $TOR_TMP_DIR"),
+                &[(TOR_TMP_DIR, "4")],
+                false,
+                |_| { Vec::new() }
+            )
+            .await
+            .unwrap()
+        );
+        assert_eq!(
+            128 + 15,
+            execute(&command("kill -TERM $$"), &[], false, |_| Vec::new())
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn reload() {
+        let root = std::env::temp_dir().join(format!(
+            "tor-operator-entrypoint-reload-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&root).unwrap();
+        let source = root.join("torrc").to_string_lossy().to_string();
+        let destination = root.join("out/torrc").to_string_lossy().to_string();
+        std::fs::write(&source, "HiddenServicePort ${PORT} example:80").unwrap();
+        write_file(Path::new(&destination), b"HiddenServicePort 80 example:80").unwrap();
+
+        let command = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            "trap 'exit 7' HUP; while true; do sleep 0.01; done".to_string(),
+        ];
+
+        let templates = vec![(source.clone(), destination.clone())];
+        let code = execute(&command, &[], false, |pid| {
+            let source = source.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                std::fs::write(source, "HiddenServicePort ${PORT} example:8080").unwrap();
+            });
+            vec![tokio::spawn(watch_templates(
+                templates,
+                lookup,
+                Arc::new(AtomicBool::new(false)),
+                pid,
+                Duration::from_millis(50),
+            ))]
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(7, code);
+        assert_eq!(
+            "HiddenServicePort 80 example:8080",
+            std::fs::read_to_string(&destination).unwrap()
+        );
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reload_unchanged() {
+        let root = std::env::temp_dir().join(format!(
+            "tor-operator-entrypoint-reload-unchanged-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&root).unwrap();
+        let source = root.join("torrc").to_string_lossy().to_string();
+        let destination = root.join("out/torrc").to_string_lossy().to_string();
+        std::fs::write(&source, "HiddenServicePort 80 example:80").unwrap();
+        write_file(Path::new(&destination), b"HiddenServicePort 80 example:80").unwrap();
+
+        let command = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            "trap 'exit 7' HUP; i=0; while [ $i -lt 50 ]; do sleep 0.01; i=$((i+1)); done"
+                .to_string(),
+        ];
+
+        let templates = vec![(source.clone(), destination.clone())];
+        let code = execute(&command, &[], false, |pid| {
+            let source = source.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                std::fs::write(source, "HiddenServicePort ${PORT} example:80").unwrap();
+            });
+            vec![tokio::spawn(watch_templates(
+                templates,
+                lookup,
+                Arc::new(AtomicBool::new(false)),
+                pid,
+                Duration::from_millis(50),
+            ))]
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(0, code);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,270 @@
+use std::{
+    convert::Infallible,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use axum::{
+    body::Body,
+    http::{HeaderValue, Response, StatusCode, header},
+};
+use futures::StreamExt as _;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use k8s_openapi::api::discovery::v1::EndpointSlice;
+use kube::runtime::{WatchStreamExt as _, reflector, watcher};
+use tokio::net::{TcpListener, TcpStream};
+
+const DEFAULT_BODY: &str = "<!DOCTYPE html>
+<html>
+<head><title>Service Unavailable</title></head>
+<body><h1>Service Unavailable</h1><p>This service is undergoing maintenance. Please try again later.</p></body>
+</html>
+";
+
+const DEFAULT_CONTENT_TYPE: &str = "text/html; charset=utf-8";
+
+const DEFAULT_STATUS: u16 = 503;
+
+/*
+ * ============================================================================
+ * Config
+ * ============================================================================
+ */
+pub struct Config {
+    /// Namespace of the Services whose `EndpointSlices` are watched.
+    pub namespace: String,
+
+    pub routes: Vec<Route>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Route {
+    /// File holding the body of the fallback response, read on every response.
+    pub body_file: Option<PathBuf>,
+
+    /// Content type of the fallback response.
+    pub content_type: Option<String>,
+
+    /// Address to accept connections from Tor on.
+    pub listen: String,
+
+    /// Service whose `EndpointSlices` decide whether the target is available.
+    pub service: String,
+
+    /// HTTP status of the fallback response.
+    pub status: Option<u16>,
+
+    /// Target to forward the connections to while it is available.
+    pub target: String,
+}
+
+/*
+ * ============================================================================
+ * Fallback
+ * ============================================================================
+ */
+/// Forwards the connections of Tor to their targets, and answers them with a
+/// maintenance response while a target has no ready endpoints or refuses the
+/// connection.
+///
+/// Availability follows the `EndpointSlices` of the target Service, so traffic
+/// switches back to the target as soon as it has ready endpoints again.
+///
+/// # Errors
+///
+/// Returns an error if the Kubernetes client cannot be created, or a listener
+/// cannot be bound or stops accepting connections.
+pub async fn run(config: &Config) -> std::io::Result<()> {
+    let client = kube::Client::try_default()
+        .await
+        .map_err(std::io::Error::other)?;
+
+    let mut tasks = tokio::task::JoinSet::new();
+
+    for route in &config.routes {
+        let listener = TcpListener::bind(&route.listen).await?;
+        tracing::info!(
+            listen = route.listen,
+            target = route.target,
+            service = route.service,
+            "accepting connections"
+        );
+
+        // The target is assumed available until the endpoints are known.
+        let available = Arc::new(AtomicBool::new(true));
+
+        tokio::spawn(watch_endpoints(
+            kube::Api::namespaced(client.clone(), &config.namespace),
+            route.service.clone(),
+            available.clone(),
+        ));
+
+        let route = Arc::new(route.clone());
+        tasks.spawn(async move {
+            loop {
+                let (inbound, _) = listener.accept().await?;
+                let route = route.clone();
+                let available = available.load(Ordering::Relaxed);
+                tokio::spawn(async move {
+                    if let Err(error) = forward(inbound, &route, available).await {
+                        tracing::warn!(target = route.target, %error, "connection failed");
+                    }
+                });
+            }
+        });
+    }
+
+    match tasks.join_next().await {
+        Some(result) => result.map_err(std::io::Error::other)?,
+        None => Ok(()),
+    }
+}
+
+async fn watch_endpoints(
+    api: kube::Api<EndpointSlice>,
+    service: String,
+    available: Arc<AtomicBool>,
+) {
+    let (reader, writer) = reflector::store();
+    let config =
+        watcher::Config::default().labels(&format!("kubernetes.io/service-name={service}"));
+    let mut stream = reflector(writer, watcher(api, config))
+        .default_backoff()
+        .boxed();
+
+    while let Some(event) = stream.next().await {
+        if let Err(error) = event {
+            tracing::warn!(service, %error, "watching endpoints failed");
+            continue;
+        }
+
+        let ready = has_ready_endpoints(&reader.state());
+        if available.swap(ready, Ordering::Relaxed) != ready {
+            tracing::info!(service, available = ready, "availability changed");
+        }
+    }
+}
+
+/// Endpoints without a ready condition are treated as ready, as the
+/// `EndpointSlice` API specifies.
+fn has_ready_endpoints(endpoint_slices: &[Arc<EndpointSlice>]) -> bool {
+    endpoint_slices
+        .iter()
+        .flat_map(|endpoint_slice| &endpoint_slice.endpoints)
+        .any(|endpoint| {
+            endpoint
+                .conditions
+                .as_ref()
+                .and_then(|f| f.ready)
+                .unwrap_or(true)
+        })
+}
+
+async fn forward(inbound: TcpStream, route: &Route, available: bool) -> std::io::Result<()> {
+    if available {
+        match TcpStream::connect(&route.target).await {
+            Ok(mut outbound) => {
+                let mut inbound = inbound;
+                tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await?;
+                return Ok(());
+            }
+            Err(error) => {
+                tracing::debug!(target = route.target, %error, "target unavailable");
+            }
+        }
+    }
+
+    hyper::server::conn::http1::Builder::new()
+        .serve_connection(
+            TokioIo::new(inbound),
+            service_fn(|_request| async { Ok::<_, Infallible>(fallback_response(route).await) }),
+        )
+        .await
+        .map_err(std::io::Error::other)
+}
+
+async fn fallback_response(route: &Route) -> Response<Body> {
+    let body = match &route.body_file {
+        Some(body_file) => tokio::fs::read(body_file).await.unwrap_or_else(|error| {
+            tracing::warn!(%error, "reading fallback body failed");
+            DEFAULT_BODY.into()
+        }),
+        None => DEFAULT_BODY.into(),
+    };
+
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = StatusCode::from_u16(route.status.unwrap_or(DEFAULT_STATUS))
+        .unwrap_or(StatusCode::SERVICE_UNAVAILABLE);
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        route
+            .content_type
+            .as_deref()
+            .and_then(|f| HeaderValue::from_str(f).ok())
+            .unwrap_or(HeaderValue::from_static(DEFAULT_CONTENT_TYPE)),
+    );
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::discovery::v1::{Endpoint, EndpointConditions};
+
+    use super::*;
+
+    fn endpoint_slice(ready: &[Option<bool>]) -> Arc<EndpointSlice> {
+        Arc::new(EndpointSlice {
+            endpoints: ready
+                .iter()
+                .map(|ready| Endpoint {
+                    conditions: Some(EndpointConditions {
+                        ready: *ready,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn ready_endpoints() {
+        assert!(!has_ready_endpoints(&[]));
+        assert!(!has_ready_endpoints(&[endpoint_slice(&[])]));
+        assert!(!has_ready_endpoints(&[endpoint_slice(&[Some(false)])]));
+        assert!(has_ready_endpoints(&[
+            endpoint_slice(&[Some(false)]),
+            endpoint_slice(&[None]),
+        ]));
+        assert!(has_ready_endpoints(&[endpoint_slice(&[Some(true)])]));
+    }
+
+    #[tokio::test]
+    async fn response() {
+        let response = fallback_response(&Route {
+            body_file: None,
+            content_type: None,
+            listen: "127.0.0.1:0".into(),
+            service: "example".into(),
+            status: None,
+            target: "example:80".into(),
+        })
+        .await;
+
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, response.status());
+        assert_eq!(
+            Some(DEFAULT_CONTENT_TYPE),
+            response
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|f| f.to_str().ok())
+        );
+    }
+}
@@ -0,0 +1,504 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt as _},
+    net::{TcpListener, TcpStream},
+};
+
+/// Prefix of the source address Tor uses to export the circuit ID.
+const CIRCUIT_ID_PREFIX: [u16; 4] = [0xfc00, 0xdead, 0xbeef, 0x4dad];
+
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum length of a PROXY protocol v1 line, including the CRLF.
+const PROXY_V1_MAX_LENGTH: usize = 107;
+
+const PROXY_V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+const RATE_LIMIT_WINDOW: Duration = Duration::from_mins(1);
+
+/*
+ * ============================================================================
+ * Config
+ * ============================================================================
+ */
+pub struct Config {
+    /// Maximum number of concurrent connections per circuit.
+    pub connection_limit: Option<usize>,
+
+    /// Maximum number of new connections per circuit per minute.
+    pub rate_limit: Option<u32>,
+
+    /// Addresses to accept connections from Tor on, and the targets to forward
+    /// them to.
+    pub routes: Vec<(String, String)>,
+}
+
+/*
+ * ============================================================================
+ * Gateway
+ * ============================================================================
+ */
+/// Accepts the connections Tor makes to the Onion Service targets, reads the
+/// circuit ID from their PROXY protocol header and forwards them to the real
+/// targets, enforcing the per-circuit limits.
+///
+/// Every connection is logged with its circuit ID once closed.
+///
+/// # Errors
+///
+/// Returns an error if a listener cannot be bound or stops accepting
+/// connections.
+pub async fn run(config: &Config) -> std::io::Result<()> {
+    let limiter = Arc::new(Limiter::new(config.connection_limit, config.rate_limit));
+
+    let mut tasks = tokio::task::JoinSet::new();
+
+    for (listen, target) in &config.routes {
+        let listener = TcpListener::bind(listen).await?;
+        tracing::info!(listen, target, "accepting connections");
+
+        let target = target.clone();
+        let limiter = limiter.clone();
+        tasks.spawn(async move {
+            loop {
+                let (inbound, _) = listener.accept().await?;
+                let target = target.clone();
+                let limiter = limiter.clone();
+                tokio::spawn(async move {
+                    if let Err(error) = forward(inbound, &target, &limiter).await {
+                        tracing::warn!(target, %error, "connection failed");
+                    }
+                });
+            }
+        });
+    }
+
+    match tasks.join_next().await {
+        Some(result) => result.map_err(std::io::Error::other)?,
+        None => Ok(()),
+    }
+}
+
+async fn forward(
+    mut inbound: TcpStream,
+    target: &str,
+    limiter: &Arc<Limiter>,
+) -> std::io::Result<()> {
+    let header = tokio::time::timeout(HEADER_TIMEOUT, read_proxy_header(&mut inbound))
+        .await
+        .map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::TimedOut, "PROXY header timed out")
+        })??;
+    let circuit_id = header.circuit_id();
+
+    let _permit = match circuit_id.map(|circuit_id| limiter.acquire(circuit_id, Instant::now())) {
+        Some(Err(limit)) => {
+            tracing::info!(
+                circuit_id,
+                target,
+                rejected = limit.as_str(),
+                "connection rejected"
+            );
+            return Ok(());
+        }
+        Some(Ok(permit)) => Some(permit),
+        None => None,
+    };
+
+    let start = Instant::now();
+    let mut outbound = TcpStream::connect(target).await?;
+    let (bytes_received, bytes_sent) =
+        tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await?;
+
+    tracing::info!(
+        circuit_id,
+        target,
+        bytes_received,
+        bytes_sent,
+        duration_ms = u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX),
+        "connection closed"
+    );
+
+    Ok(())
+}
+
+/*
+ * ============================================================================
+ * PROXY protocol
+ * ============================================================================
+ */
+#[derive(Debug, PartialEq)]
+struct ProxyHeader {
+    source: Option<SocketAddr>,
+}
+
+impl ProxyHeader {
+    /// The circuit ID Tor encodes in the last 32 bits of the source address
+    /// when `HiddenServiceExportCircuitID haproxy` is set.
+    fn circuit_id(&self) -> Option<u32> {
+        let Some(SocketAddr::V6(source)) = self.source else {
+            return None;
+        };
+        let segments = source.ip().segments();
+        (segments[..4] == CIRCUIT_ID_PREFIX)
+            .then(|| (u32::from(segments[6]) << 16) | u32::from(segments[7]))
+    }
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the PROXY protocol header, in the version 1 text format sent by Tor,
+/// or in the version 2 binary format.
+async fn read_proxy_header(reader: &mut (impl AsyncRead + Unpin)) -> std::io::Result<ProxyHeader> {
+    let mut first = [0; 1];
+    reader.read_exact(&mut first).await?;
+
+    match first[0] {
+        b'P' => read_proxy_v1_header(reader).await,
+        b'\r' => read_proxy_v2_header(reader).await,
+        _ => Err(invalid("missing PROXY protocol signature")),
+    }
+}
+
+/// Reads the rest of a version 1 line, such as
+/// `PROXY TCP6 fc00:dead:beef:4dad::12:3456 ::1 13398 80\r\n`.
+async fn read_proxy_v1_header(
+    reader: &mut (impl AsyncRead + Unpin),
+) -> std::io::Result<ProxyHeader> {
+    let mut line = vec![b'P'];
+
+    // Read a byte at a time, so that none of the payload is consumed.
+    while !line.ends_with(b"\r\n") {
+        if line.len() == PROXY_V1_MAX_LENGTH {
+            return Err(invalid("PROXY protocol v1 line too long"));
+        }
+        let mut byte = [0; 1];
+        reader.read_exact(&mut byte).await?;
+        line.push(byte[0]);
+    }
+
+    let line = std::str::from_utf8(&line[..line.len() - 2])
+        .map_err(|_| invalid("malformed PROXY protocol v1 line"))?;
+    let mut fields = line.split(' ');
+
+    if fields.next() != Some("PROXY") {
+        return Err(invalid("missing PROXY protocol signature"));
+    }
+
+    let source = match fields.next() {
+        // The addresses are unknown, and may be omitted.
+        Some("UNKNOWN") => None,
+        Some(protocol @ ("TCP4" | "TCP6")) => {
+            let fields = fields.collect::<Vec<_>>();
+            let [source, _, source_port, _] = fields[..] else {
+                return Err(invalid("malformed PROXY protocol v1 line"));
+            };
+            let ip = source
+                .parse::<IpAddr>()
+                .ok()
+                .filter(|ip| ip.is_ipv4() == (protocol == "TCP4"))
+                .ok_or_else(|| invalid("malformed PROXY protocol v1 address"))?;
+            let port = source_port
+                .parse::<u16>()
+                .map_err(|_| invalid("malformed PROXY protocol v1 port"))?;
+            Some(SocketAddr::new(ip, port))
+        }
+        _ => return Err(invalid("unsupported PROXY protocol v1 protocol")),
+    };
+
+    Ok(ProxyHeader { source })
+}
+
+/// Reads the rest of a version 2 header.
+async fn read_proxy_v2_header(
+    reader: &mut (impl AsyncRead + Unpin),
+) -> std::io::Result<ProxyHeader> {
+    let mut preamble = [0; 16];
+    preamble[0] = b'\r';
+    reader.read_exact(&mut preamble[1..]).await?;
+
+    if preamble[..12] != PROXY_V2_SIGNATURE {
+        return Err(invalid("missing PROXY protocol v2 signature"));
+    }
+    if preamble[12] >> 4 != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+
+    let mut addresses = vec![0; usize::from(u16::from_be_bytes([preamble[14], preamble[15]]))];
+    reader.read_exact(&mut addresses).await?;
+
+    // LOCAL connections carry no addresses.
+    let command = preamble[12] & 0x0f;
+    if command == 0 {
+        return Ok(ProxyHeader { source: None });
+    }
+
+    let source = match preamble[13] >> 4 {
+        // AF_INET
+        1 if addresses.len() >= 12 => {
+            let ip = Ipv4Addr::from(<[u8; 4]>::try_from(&addresses[..4]).expect("length checked"));
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            Some(SocketAddr::new(IpAddr::V4(ip), port))
+        }
+        // AF_INET6
+        2 if addresses.len() >= 36 => {
+            let ip =
+                Ipv6Addr::from(<[u8; 16]>::try_from(&addresses[..16]).expect("length checked"));
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            Some(SocketAddr::new(IpAddr::V6(ip), port))
+        }
+        // AF_UNSPEC, AF_UNIX
+        0 | 3 => None,
+        _ => return Err(invalid("malformed PROXY protocol v2 addresses")),
+    };
+
+    Ok(ProxyHeader { source })
+}
+
+/*
+ * ============================================================================
+ * Limiter
+ * ============================================================================
+ */
+struct Limiter {
+    circuits: Mutex<HashMap<u32, Circuit>>,
+    connection_limit: Option<usize>,
+    rate_limit: Option<u32>,
+}
+
+struct Circuit {
+    connections: usize,
+    window_connections: u32,
+    window_start: Instant,
+}
+
+#[derive(Debug, PartialEq)]
+enum Limit {
+    Connection,
+    Rate,
+}
+
+impl Limit {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Limit::Connection => "connection limit",
+            Limit::Rate => "rate limit",
+        }
+    }
+}
+
+struct Permit {
+    circuit_id: u32,
+    limiter: Arc<Limiter>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.limiter.release(self.circuit_id);
+    }
+}
+
+impl Limiter {
+    fn new(connection_limit: Option<usize>, rate_limit: Option<u32>) -> Self {
+        Self {
+            circuits: Mutex::new(HashMap::new()),
+            connection_limit,
+            rate_limit,
+        }
+    }
+
+    fn acquire(self: &Arc<Self>, circuit_id: u32, now: Instant) -> Result<Permit, Limit> {
+        let mut circuits = self
+            .circuits
+            .lock()
+            .expect("limiter lock is never poisoned");
+
+        // Forget the circuits that have no connections left to limit.
+        circuits.retain(|_, circuit| {
+            circuit.connections > 0 || now.duration_since(circuit.window_start) < RATE_LIMIT_WINDOW
+        });
+
+        let circuit = circuits.entry(circuit_id).or_insert(Circuit {
+            connections: 0,
+            window_connections: 0,
+            window_start: now,
+        });
+
+        if now.duration_since(circuit.window_start) >= RATE_LIMIT_WINDOW {
+            circuit.window_connections = 0;
+            circuit.window_start = now;
+        }
+
+        if self
+            .connection_limit
+            .is_some_and(|limit| circuit.connections >= limit)
+        {
+            return Err(Limit::Connection);
+        }
+        if self
+            .rate_limit
+            .is_some_and(|limit| circuit.window_connections >= limit)
+        {
+            return Err(Limit::Rate);
+        }
+
+        circuit.connections += 1;
+        circuit.window_connections += 1;
+
+        Ok(Permit {
+            circuit_id,
+            limiter: self.clone(),
+        })
+    }
+
+    fn release(&self, circuit_id: u32) {
+        let mut circuits = self
+            .circuits
+            .lock()
+            .expect("limiter lock is never poisoned");
+        if let Some(circuit) = circuits.get_mut(&circuit_id) {
+            circuit.connections = circuit.connections.saturating_sub(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut header = PROXY_V2_SIGNATURE.to_vec();
+        header.push(0x20 | command);
+        header.push(family);
+        header.extend(u16::try_from(addresses.len()).unwrap().to_be_bytes());
+        header.extend(addresses);
+        header
+    }
+
+    #[tokio::test]
+    async fn proxy_header_circuit_id() {
+        let mut addresses = Vec::new();
+        addresses.extend(
+            "fc00:dead:beef:4dad::12:3456"
+                .parse::<Ipv6Addr>()
+                .unwrap()
+                .octets(),
+        );
+        addresses.extend(Ipv6Addr::LOCALHOST.octets());
+        addresses.extend(80_u16.to_be_bytes());
+        addresses.extend(80_u16.to_be_bytes());
+
+        let header = read_proxy_header(&mut header(0x01, 0x21, &addresses).as_slice())
+            .await
+            .unwrap();
+
+        assert_eq!(Some(0x0012_3456), header.circuit_id());
+    }
+
+    #[tokio::test]
+    async fn proxy_header_ipv4() {
+        let addresses = [127, 0, 0, 1, 127, 0, 0, 1, 0x1f, 0x90, 0, 80];
+
+        let header = read_proxy_header(&mut header(0x01, 0x11, &addresses).as_slice())
+            .await
+            .unwrap();
+
+        assert_eq!(Some("127.0.0.1:8080".parse().unwrap()), header.source);
+        assert_eq!(None, header.circuit_id());
+    }
+
+    #[tokio::test]
+    async fn proxy_header_local() {
+        let header = read_proxy_header(&mut header(0x00, 0x00, &[]).as_slice())
+            .await
+            .unwrap();
+
+        assert_eq!(ProxyHeader { source: None }, header);
+    }
+
+    #[tokio::test]
+    async fn proxy_header_invalid() {
+        for header in [
+            b"GET / HTTP/1.1\r\n".as_slice(),
+            b"PROXY TCP4 ::1 127.0.0.1 1 2\r\n",
+            b"PROXY TCP6 ::1 ::1 65536 80\r\n",
+            b"PROXY UDP4 127.0.0.1 127.0.0.1 1 2\r\n",
+            b"\r\n\r\n\0\r\nQUIT\r\x21\x00\x00\x00",
+            &[b'P'; PROXY_V1_MAX_LENGTH + 1],
+        ] {
+            let error = read_proxy_header(&mut &header[..]).await.unwrap_err();
+
+            assert_eq!(std::io::ErrorKind::InvalidData, error.kind());
+        }
+    }
+
+    #[tokio::test]
+    async fn proxy_header_tor() {
+        // The line Tor sends with `HiddenServiceExportCircuitID haproxy`.
+        let mut reader =
+            b"PROXY TCP6 fc00:dead:beef:4dad::12:3456 ::1 13398 80\r\nGET / HTTP/1.1\r\n"
+                .as_slice();
+
+        let header = read_proxy_header(&mut reader).await.unwrap();
+
+        assert_eq!(
+            Some("[fc00:dead:beef:4dad::12:3456]:13398".parse().unwrap()),
+            header.source
+        );
+        assert_eq!(Some(0x0012_3456), header.circuit_id());
+        assert_eq!(b"GET / HTTP/1.1\r\n", reader);
+    }
+
+    #[tokio::test]
+    async fn proxy_header_v1() {
+        let header =
+            read_proxy_header(&mut b"PROXY TCP4 127.0.0.1 127.0.0.1 8080 80\r\n".as_slice())
+                .await
+                .unwrap();
+
+        assert_eq!(Some("127.0.0.1:8080".parse().unwrap()), header.source);
+        assert_eq!(None, header.circuit_id());
+
+        let header = read_proxy_header(&mut b"PROXY UNKNOWN\r\n".as_slice())
+            .await
+            .unwrap();
+
+        assert_eq!(ProxyHeader { source: None }, header);
+    }
+
+    #[test]
+    fn limiter_connection_limit() {
+        let limiter = Arc::new(Limiter::new(Some(2), None));
+        let now = Instant::now();
+
+        let first = limiter.acquire(1, now).unwrap();
+        let _second = limiter.acquire(1, now).unwrap();
+
+        assert_eq!(Some(Limit::Connection), limiter.acquire(1, now).err());
+        assert!(limiter.acquire(2, now).is_ok());
+
+        drop(first);
+
+        assert!(limiter.acquire(1, now).is_ok());
+    }
+
+    #[test]
+    fn limiter_rate_limit() {
+        let limiter = Arc::new(Limiter::new(None, Some(2)));
+        let now = Instant::now();
+
+        drop(limiter.acquire(1, now).unwrap());
+        drop(limiter.acquire(1, now).unwrap());
+
+        assert_eq!(Some(Limit::Rate), limiter.acquire(1, now).err());
+        assert!(limiter.acquire(1, now + RATE_LIMIT_WINDOW).is_ok());
+    }
+}
@@ -1,6 +1,9 @@
 use std::collections::HashMap;
 
-use kube::core::ObjectList;
+use kube::{
+    api::ListParams,
+    core::{ObjectList, Selector},
+};
 
 use crate::{Error, Result, metrics::Metrics};
 
@@ -56,7 +59,7 @@ where
             resource.r#ref = %format!("{}.{}.{}/{}", R::kind(&()), R::version(&()), R::group(&()), resource.name_any())
         )
     )]
-    async fn delete<O>(&self, object: &O, resource: R) -> Result<()>
+    pub async fn delete<O>(&self, object: &O, resource: R) -> Result<()>
     where
         O: Object,
     {
@@ -84,7 +87,7 @@ where
             resource.r#ref = %format!("{}.{}.{}/{}", R::kind(&()), R::version(&()), R::group(&()), object.name_any())
         )
     )]
-    async fn list<O>(&self, object: &O) -> Result<ObjectList<R>>
+    pub async fn list<O>(&self, object: &O) -> Result<ObjectList<R>>
     where
         O: Object + Resource,
     {
@@ -92,6 +95,15 @@ where
         Ok(self.api.list(&object.try_owned_list_params()?).await?)
     }
 
+    #[tracing::instrument(skip_all)]
+    pub async fn list_selected(&self, selector: &Selector) -> Result<ObjectList<R>> {
+        self.metrics.kubernetes_api_usage_count::<R>("list");
+        Ok(self
+            .api
+            .list(&ListParams::default().labels_from(selector))
+            .await?)
+    }
+
     #[tracing::instrument(
         skip_all,
         fields(
@@ -0,0 +1,40 @@
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// The subset of the cert-manager `Certificate` managed by the Tor Operator.
+#[allow(clippy::module_name_repetitions)]
+#[derive(CustomResource, JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[kube(
+    group = "cert-manager.io",
+    kind = "Certificate",
+    namespaced,
+    version = "v1"
+)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateSpec {
+    pub dns_names: Vec<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<String>,
+
+    pub issuer_ref: CertificateIssuerRef,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renew_before: Option<String>,
+
+    pub secret_name: String,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CertificateIssuerRef {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+
+    pub name: String,
+}
@@ -11,7 +11,10 @@ impl ConditionsExt for Vec<Condition> {
             .map(|s| match other.iter().find(|o| s.type_ == o.type_) {
                 Some(o) => {
                     if s.status == o.status && s.reason == o.reason && s.message == o.message {
-                        s.clone()
+                        Condition {
+                            observed_generation: o.observed_generation,
+                            ..s.clone()
+                        }
                     } else {
                         o.clone()
                     }
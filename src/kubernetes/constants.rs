@@ -14,6 +14,16 @@ pub const POD_SECURITY_CONTEXT_RUN_AS_USER: i64 = 10001;
 
 pub const TOR_AGABANI_CO_UK: &str = "tor.agabani.co.uk";
 
+pub const TOR_AGABANI_CO_UK_HOSTNAME_KEY: &str = "tor.agabani.co.uk/hostname";
+
+pub const TOR_AGABANI_CO_UK_NOT_AFTER_KEY: &str = "tor.agabani.co.uk/not-after";
+
+pub const TOR_AGABANI_CO_UK_ONION_SERVICE_KEY: &str = "tor.agabani.co.uk/onion-service";
+
 pub const TOR_AGABANI_CO_UK_OWNED_BY_KEY: &str = "tor.agabani.co.uk/owned-by";
 
 pub const TOR_AGABANI_CO_UK_PART_OF_KEY: &str = "tor.agabani.co.uk/part-of";
+
+pub const TOR_AGABANI_CO_UK_PUBLISHED_AT_KEY: &str = "tor.agabani.co.uk/published-at";
+
+pub const TOR_AGABANI_CO_UK_TRACK_KEY: &str = "tor.agabani.co.uk/track";
@@ -0,0 +1,11 @@
+use k8s_openapi::api::core::v1::{Capabilities, SecurityContext};
+
+pub fn container_security_context() -> SecurityContext {
+    SecurityContext {
+        capabilities: Some(Capabilities {
+            drop: Some(vec!["ALL".to_string()]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
@@ -1,36 +1,51 @@
 mod annotation;
 mod annotations;
 mod api;
+mod certificate;
 mod conditions_ext;
 mod constants;
+mod container_security_context;
 mod context;
 mod error_backoff;
 mod error_policy;
 mod labels;
 mod object;
+mod pod_disruption_budget;
 mod pod_security_context;
+mod probe;
 mod resource;
 mod resource_name;
 mod resource_namespace;
 mod resource_uid;
 mod selector_labels;
+mod service_monitor;
 mod subset;
 mod tor;
 
 pub use annotation::Annotation;
 pub use annotations::Annotations;
 pub use api::Api;
+pub use certificate::{Certificate, CertificateIssuerRef, CertificateSpec};
 pub use conditions_ext::ConditionsExt;
+pub use constants::{
+    TOR_AGABANI_CO_UK_HOSTNAME_KEY, TOR_AGABANI_CO_UK_NOT_AFTER_KEY,
+    TOR_AGABANI_CO_UK_ONION_SERVICE_KEY, TOR_AGABANI_CO_UK_PUBLISHED_AT_KEY,
+    TOR_AGABANI_CO_UK_TRACK_KEY,
+};
+pub use container_security_context::container_security_context;
 pub use context::Context;
 pub use error_backoff::ErrorBackoff;
 pub use error_policy::error_policy;
 pub use labels::Labels;
 pub use object::Object;
+pub use pod_disruption_budget::{PodDisruptionBudget, PodDisruptionBudgetExt};
 pub use pod_security_context::pod_security_context;
+pub use probe::{liveness_probe, readiness_probe, startup_probe};
 pub use resource::Resource;
 pub use resource_name::ResourceName;
 pub use resource_namespace::ResourceNamespace;
 pub use resource_uid::ResourceUid;
 pub use selector_labels::SelectorLabels;
+pub use service_monitor::{ServiceMonitor, ServiceMonitorEndpoint, ServiceMonitorSpec};
 pub use subset::Subset;
-pub use tor::Torrc;
+pub use tor::{TorMetrics, TorMetricsExt, Torrc, TorrcReloadStrategy};
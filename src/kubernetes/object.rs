@@ -110,6 +110,20 @@ pub trait Object: kube::ResourceExt<DynamicType = ()> {
         ))
     }
 
+    fn try_part_of_selector_labels<O: Object>(&self) -> Result<SelectorLabels>
+    where
+        Self: Resource,
+    {
+        Ok(BTreeMap::from([
+            (
+                APP_KUBERNETES_IO_COMPONENT_KEY.into(),
+                O::APP_KUBERNETES_IO_COMPONENT_VALUE.into(),
+            ),
+            (TOR_AGABANI_CO_UK_PART_OF_KEY.into(), self.try_uid()?.into()),
+        ])
+        .into())
+    }
+
     fn try_selector_labels(&self) -> Result<SelectorLabels>
     where
         Self: Resource,
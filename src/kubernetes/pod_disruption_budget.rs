@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{Annotations, Labels, ResourceName};
+
+/// Pod Disruption Budget settings.
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PodDisruptionBudget {
+    /// Annotations is an unstructured key value map stored with a resource that may be set by external tools to store and retrieve arbitrary metadata. They are not queryable and should be preserved when modifying objects. More info: <http://kubernetes.io/docs/user-guide/annotations>
+    pub annotations: Option<BTreeMap<String, String>>,
+
+    /// Map of string keys and values that can be used to organize and categorize (scope and select) objects. May match selectors of replication controllers and services. More info: <http://kubernetes.io/docs/user-guide/labels>
+    pub labels: Option<BTreeMap<String, String>>,
+
+    /// An eviction is allowed if at most "maxUnavailable" pods selected by "selector" are unavailable after the eviction, i.e. even in absence of the evicted pod. For example, one can prevent all voluntary evictions by specifying 0. This is a mutually exclusive setting with "minAvailable".
+    ///
+    /// Default: 1, unless `minAvailable` is set
+    pub max_unavailable: Option<IntOrString>,
+
+    /// An eviction is allowed if at least "minAvailable" pods selected by "selector" will still be available after the eviction, i.e. even in the absence of the evicted pod.  So for example you can prevent all voluntary evictions by specifying "100%".
+    ///
+    /// Default: nil / none / null / undefined.
+    pub min_available: Option<IntOrString>,
+
+    /// Name of the Pod Disruption Budget.
+    ///
+    /// Default: name of the resource
+    pub name: Option<String>,
+}
+
+impl PodDisruptionBudget {
+    #[must_use]
+    pub fn annotations(&self) -> Option<Annotations> {
+        self.annotations.clone().map(Into::into)
+    }
+
+    #[must_use]
+    pub fn labels(&self) -> Option<Labels> {
+        self.labels.clone().map(Into::into)
+    }
+
+    #[must_use]
+    pub fn max_unavailable(&self) -> Option<IntOrString> {
+        self.max_unavailable
+            .clone()
+            .or_else(|| self.min_available.is_none().then_some(IntOrString::Int(1)))
+    }
+}
+
+/// Accessors of the Pod Disruption Budget settings of a resource.
+pub trait PodDisruptionBudgetExt: kube::ResourceExt {
+    fn pod_disruption_budget(&self) -> Option<&PodDisruptionBudget>;
+
+    #[must_use]
+    fn pod_disruption_budget_annotations(&self) -> Option<Annotations> {
+        self.pod_disruption_budget()
+            .and_then(PodDisruptionBudget::annotations)
+    }
+
+    #[must_use]
+    fn pod_disruption_budget_enabled(&self) -> bool {
+        self.pod_disruption_budget().is_some()
+    }
+
+    #[must_use]
+    fn pod_disruption_budget_labels(&self) -> Option<Labels> {
+        self.pod_disruption_budget()
+            .and_then(PodDisruptionBudget::labels)
+    }
+
+    #[must_use]
+    fn pod_disruption_budget_max_unavailable(&self) -> Option<IntOrString> {
+        self.pod_disruption_budget()
+            .and_then(PodDisruptionBudget::max_unavailable)
+    }
+
+    #[must_use]
+    fn pod_disruption_budget_min_available(&self) -> Option<IntOrString> {
+        self.pod_disruption_budget()
+            .and_then(|f| f.min_available.clone())
+    }
+
+    #[must_use]
+    fn pod_disruption_budget_name(&self) -> ResourceName {
+        self.pod_disruption_budget()
+            .and_then(|f| f.name.as_ref())
+            .map_or_else(|| self.name_any().into(), Into::into)
+    }
+}
@@ -0,0 +1,33 @@
+use k8s_openapi::api::core::v1::{ExecAction, Probe};
+
+fn probe(probe: &str, args: &[&str], period_seconds: i32, failure_threshold: i32) -> Probe {
+    Probe {
+        exec: Some(ExecAction {
+            command: Some(
+                ["tor-operator", "probe", probe]
+                    .iter()
+                    .chain(args)
+                    .map(ToString::to_string)
+                    .collect(),
+            ),
+        }),
+        failure_threshold: Some(failure_threshold),
+        period_seconds: Some(period_seconds),
+        success_threshold: Some(1),
+        timeout_seconds: Some(5),
+        ..Default::default()
+    }
+}
+
+pub fn liveness_probe(args: &[&str]) -> Probe {
+    probe("liveness", args, 10, 3)
+}
+
+pub fn readiness_probe(args: &[&str]) -> Probe {
+    probe("readiness", args, 10, 3)
+}
+
+/// Allows tor up to 5 minutes to bootstrap before the liveness probe takes over.
+pub fn startup_probe(args: &[&str]) -> Probe {
+    probe("startup", args, 5, 60)
+}
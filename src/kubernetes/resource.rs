@@ -47,6 +47,14 @@ pub trait Resource: kube::ResourceExt<DynamicType = ()> {
     }
 }
 
+impl Resource for super::Certificate {
+    type Spec = super::CertificateSpec;
+
+    fn spec(&self) -> &Self::Spec {
+        &self.spec
+    }
+}
+
 impl Resource for k8s_openapi::api::core::v1::ConfigMap {
     type Spec = std::collections::BTreeMap<String, String>;
 
@@ -77,6 +85,16 @@ impl Resource for k8s_openapi::api::autoscaling::v2::HorizontalPodAutoscaler {
     }
 }
 
+impl Resource for k8s_openapi::api::policy::v1::PodDisruptionBudget {
+    type Spec = k8s_openapi::api::policy::v1::PodDisruptionBudgetSpec;
+
+    fn spec(&self) -> &Self::Spec {
+        self.spec
+            .as_ref()
+            .expect("spec is always set on managed PodDisruptionBudgets")
+    }
+}
+
 impl Resource for k8s_openapi::api::core::v1::Secret {
     type Spec = std::collections::BTreeMap<String, k8s_openapi::ByteString>;
 
@@ -96,3 +114,63 @@ impl Resource for k8s_openapi::api::core::v1::Service {
             .expect("spec is always set on managed Services")
     }
 }
+
+impl Resource for k8s_openapi::api::apps::v1::StatefulSet {
+    type Spec = k8s_openapi::api::apps::v1::StatefulSetSpec;
+
+    fn spec(&self) -> &Self::Spec {
+        self.spec
+            .as_ref()
+            .expect("spec is always set on managed StatefulSets")
+    }
+}
+
+impl Resource for super::ServiceMonitor {
+    type Spec = super::ServiceMonitorSpec;
+
+    fn spec(&self) -> &Self::Spec {
+        &self.spec
+    }
+}
+
+impl Resource for k8s_openapi::api::coordination::v1::Lease {
+    type Spec = k8s_openapi::api::coordination::v1::LeaseSpec;
+
+    fn spec(&self) -> &Self::Spec {
+        self.spec
+            .as_ref()
+            .expect("spec is always set on managed Leases")
+    }
+}
+
+impl Resource for k8s_openapi::api::core::v1::ServiceAccount {
+    type Spec = ();
+
+    fn spec(&self) -> &Self::Spec {
+        &()
+    }
+}
+
+impl Resource for k8s_openapi::api::rbac::v1::Role {
+    type Spec = Option<Vec<k8s_openapi::api::rbac::v1::PolicyRule>>;
+
+    fn spec(&self) -> &Self::Spec {
+        &self.rules
+    }
+}
+
+impl Resource for k8s_openapi::api::rbac::v1::RoleBinding {
+    type Spec = Self;
+
+    fn spec(&self) -> &Self::Spec {
+        self
+    }
+}
+
+impl Resource for k8s_openapi::api::core::v1::Pod {
+    type Spec = k8s_openapi::api::core::v1::PodSpec;
+
+    fn spec(&self) -> &Self::Spec {
+        self.spec.as_ref().expect("spec is always set on Pods")
+    }
+}
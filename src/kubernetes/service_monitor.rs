@@ -0,0 +1,31 @@
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+use kube::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// The subset of the Prometheus Operator `ServiceMonitor` managed by the Tor
+/// Operator.
+#[allow(clippy::module_name_repetitions)]
+#[derive(CustomResource, JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[kube(
+    group = "monitoring.coreos.com",
+    kind = "ServiceMonitor",
+    namespaced,
+    version = "v1"
+)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceMonitorSpec {
+    pub endpoints: Vec<ServiceMonitorEndpoint>,
+
+    pub selector: LabelSelector,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceMonitorEndpoint {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval: Option<String>,
+
+    pub port: String,
+}
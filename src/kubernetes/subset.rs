@@ -53,6 +53,17 @@ impl Subset for k8s_openapi::api::apps::v1::DeploymentSpec {
             return false;
         }
 
+        // The API server defaults the strategy when none is set.
+        match (&self.strategy, &superset.strategy) {
+            (None, _) => {}
+            (Some(_), None) => return false,
+            (Some(subset), Some(superset)) => {
+                if !subset.is_subset(superset) {
+                    return false;
+                }
+            }
+        }
+
         if self.template.metadata != superset.template.metadata {
             return false;
         }
@@ -72,8 +83,168 @@ impl Subset for k8s_openapi::api::apps::v1::DeploymentSpec {
     }
 }
 
+impl Subset for k8s_openapi::api::apps::v1::DeploymentStrategy {
+    fn is_subset(&self, superset: &Self) -> bool {
+        /* The API server defaults `rollingUpdate`, `maxSurge` and
+         * `maxUnavailable` of a `RollingUpdate` strategy when they are not set.
+         */
+        if self.type_.is_some() && self.type_ != superset.type_ {
+            return false;
+        }
+
+        match (&self.rolling_update, &superset.rolling_update) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(subset), Some(superset)) => {
+                (subset.max_surge.is_none() || subset.max_surge == superset.max_surge)
+                    && (subset.max_unavailable.is_none()
+                        || subset.max_unavailable == superset.max_unavailable)
+            }
+        }
+    }
+}
+
+impl Subset for k8s_openapi::api::policy::v1::PodDisruptionBudgetSpec {
+    fn is_subset(&self, superset: &Self) -> bool {
+        self.max_unavailable == superset.max_unavailable
+            && self.min_available == superset.min_available
+            && self.selector == superset.selector
+    }
+}
+
 impl Subset for k8s_openapi::api::core::v1::ServiceSpec {
     fn is_subset(&self, superset: &Self) -> bool {
         self.ports == superset.ports && self.type_ == superset.type_
     }
 }
+
+impl Subset for k8s_openapi::api::apps::v1::StatefulSetSpec {
+    fn is_subset(&self, superset: &Self) -> bool {
+        if self.persistent_volume_claim_retention_policy
+            != superset.persistent_volume_claim_retention_policy
+        {
+            return false;
+        }
+
+        if self.replicas != superset.replicas {
+            return false;
+        }
+
+        if self.selector != superset.selector {
+            return false;
+        }
+
+        if self.template.metadata != superset.template.metadata {
+            return false;
+        }
+
+        if self.template.spec != superset.template.spec {
+            return false;
+        }
+
+        /* `volume_claim_templates` are not compared as they cannot be updated
+         * and are auto populated by the statefulsets controller.
+         */
+
+        true
+    }
+}
+
+impl Subset for super::CertificateSpec {
+    fn is_subset(&self, superset: &Self) -> bool {
+        self == superset
+    }
+}
+
+impl Subset for super::ServiceMonitorSpec {
+    fn is_subset(&self, superset: &Self) -> bool {
+        self == superset
+    }
+}
+
+impl Subset for () {
+    fn is_subset(&self, _superset: &Self) -> bool {
+        true
+    }
+}
+
+impl Subset for k8s_openapi::api::coordination::v1::LeaseSpec {
+    fn is_subset(&self, superset: &Self) -> bool {
+        /* The remaining fields are owned by the leader election candidates. */
+        self.lease_duration_seconds == superset.lease_duration_seconds
+    }
+}
+
+impl Subset for Option<Vec<k8s_openapi::api::rbac::v1::PolicyRule>> {
+    fn is_subset(&self, superset: &Self) -> bool {
+        self == superset
+    }
+}
+
+impl Subset for k8s_openapi::api::rbac::v1::RoleBinding {
+    fn is_subset(&self, superset: &Self) -> bool {
+        self.role_ref == superset.role_ref && self.subjects == superset.subjects
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::{
+        api::apps::v1::{DeploymentSpec, DeploymentStrategy, RollingUpdateDeployment},
+        apimachinery::pkg::util::intstr::IntOrString,
+    };
+
+    use super::*;
+
+    fn deployment_spec(strategy: Option<DeploymentStrategy>) -> DeploymentSpec {
+        DeploymentSpec {
+            strategy,
+            ..Default::default()
+        }
+    }
+
+    fn rolling_update(
+        max_surge: Option<IntOrString>,
+        max_unavailable: Option<IntOrString>,
+    ) -> DeploymentStrategy {
+        DeploymentStrategy {
+            rolling_update: Some(RollingUpdateDeployment {
+                max_surge,
+                max_unavailable,
+            }),
+            type_: Some("RollingUpdate".into()),
+        }
+    }
+
+    #[test]
+    fn deployment_strategy() {
+        let defaulted = deployment_spec(Some(rolling_update(
+            Some(IntOrString::String("25%".into())),
+            Some(IntOrString::Int(0)),
+        )));
+
+        assert!(deployment_spec(None).is_subset(&defaulted));
+        assert!(
+            deployment_spec(Some(rolling_update(None, Some(IntOrString::Int(0)))))
+                .is_subset(&defaulted)
+        );
+        assert!(
+            deployment_spec(Some(DeploymentStrategy {
+                rolling_update: None,
+                type_: Some("RollingUpdate".into()),
+            }))
+            .is_subset(&defaulted)
+        );
+        assert!(
+            !deployment_spec(Some(rolling_update(None, Some(IntOrString::Int(1)))))
+                .is_subset(&defaulted)
+        );
+        assert!(
+            !deployment_spec(Some(DeploymentStrategy {
+                rolling_update: None,
+                type_: Some("Recreate".into()),
+            }))
+            .is_subset(&defaulted)
+        );
+    }
+}
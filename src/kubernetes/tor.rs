@@ -1,11 +1,207 @@
+use std::collections::BTreeMap;
+
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use super::{Annotations, Labels, ResourceName};
+
 /// Tor torrc settings.
 #[allow(clippy::module_name_repetitions)]
 #[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct Torrc {
+    /// Strategy used to apply a change of the torrc file.
+    ///
+    /// Default: Restart
+    pub reload_strategy: Option<TorrcReloadStrategy>,
+
+    /// Seconds Tor waits for connections to close once asked to shut down.
+    ///
+    /// The Pods are given this long, plus a few seconds, to terminate.
+    ///
+    /// Default: 10
+    pub shutdown_wait_length_seconds: Option<i64>,
+
     /// The template to be prepended to the torrc file.
     pub template: Option<String>,
 }
+
+/// Strategy used to apply a change of the torrc file.
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq)]
+pub enum TorrcReloadStrategy {
+    /// Restart the Pods.
+    #[default]
+    Restart,
+
+    /// Send SIGHUP to Tor, restarting the Pods only for options Tor cannot change while running.
+    Signal,
+}
+
+/// Tor metrics settings.
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TorMetrics {
+    /// Entrance policies of the `MetricsPort`, in the form of Tor exit policies.
+    ///
+    /// When set, the `MetricsPort` listens on every interface, so that the
+    /// metrics are exposed to the networks accepted, e.g. `accept 10.0.0.0/8`
+    /// for the Pods of the cluster. Otherwise it only listens on the loopback,
+    /// and the metrics are only exposed within the Pod. `service` and
+    /// `serviceMonitor` require a policy accepting Prometheus.
+    ///
+    /// Default: `accept 127.0.0.1`
+    pub policy: Option<Vec<String>>,
+
+    /// Port the Prometheus metrics are served on.
+    ///
+    /// Default: 9035
+    pub port: Option<i32>,
+
+    /// Service settings.
+    ///
+    /// The Service is created when either `service` or `serviceMonitor` is set.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub service: Option<TorMetricsService>,
+
+    /// Service Monitor settings. Requires the Prometheus Operator, and a
+    /// `policy` accepting Prometheus.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub service_monitor: Option<TorMetricsServiceMonitor>,
+}
+
+/// Tor metrics Service settings.
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TorMetricsService {
+    /// Annotations is an unstructured key value map stored with a resource that may be set by external tools to store and retrieve arbitrary metadata. They are not queryable and should be preserved when modifying objects. More info: <http://kubernetes.io/docs/user-guide/annotations>
+    pub annotations: Option<BTreeMap<String, String>>,
+
+    /// Map of string keys and values that can be used to organize and categorize (scope and select) objects. May match selectors of replication controllers and services. More info: <http://kubernetes.io/docs/user-guide/labels>
+    pub labels: Option<BTreeMap<String, String>>,
+
+    /// Name of the Service.
+    ///
+    /// Default: name of the resource suffixed with `-metrics`
+    pub name: Option<String>,
+}
+
+/// Tor metrics Service Monitor settings.
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TorMetricsServiceMonitor {
+    /// Annotations is an unstructured key value map stored with a resource that may be set by external tools to store and retrieve arbitrary metadata. They are not queryable and should be preserved when modifying objects. More info: <http://kubernetes.io/docs/user-guide/annotations>
+    pub annotations: Option<BTreeMap<String, String>>,
+
+    /// Interval at which Prometheus scrapes the metrics.
+    ///
+    /// Default: the global scrape interval of Prometheus
+    pub interval: Option<String>,
+
+    /// Map of string keys and values that can be used to organize and categorize (scope and select) objects. May match selectors of replication controllers and services. More info: <http://kubernetes.io/docs/user-guide/labels>
+    pub labels: Option<BTreeMap<String, String>>,
+
+    /// Name of the Service Monitor.
+    ///
+    /// Default: name of the resource suffixed with `-metrics`
+    pub name: Option<String>,
+}
+
+impl TorMetrics {
+    /// Address the `MetricsPort` listens on, only exposed beyond the Pod when a
+    /// policy is set.
+    #[must_use]
+    pub fn listen(&self) -> String {
+        if self.policy.is_some() {
+            format!("0.0.0.0:{}", self.port())
+        } else {
+            format!("127.0.0.1:{}", self.port())
+        }
+    }
+
+    #[must_use]
+    pub fn policy(&self) -> Vec<String> {
+        self.policy
+            .clone()
+            .unwrap_or_else(|| vec!["accept 127.0.0.1".into()])
+    }
+
+    #[must_use]
+    pub fn port(&self) -> i32 {
+        self.port.unwrap_or(9035)
+    }
+
+    #[must_use]
+    pub fn service_enabled(&self) -> bool {
+        self.service.is_some() || self.service_monitor.is_some()
+    }
+}
+
+/// Accessors of the Tor metrics settings of a resource.
+pub trait TorMetricsExt: kube::ResourceExt {
+    fn metrics(&self) -> Option<&TorMetrics>;
+
+    #[must_use]
+    fn metrics_service_annotations(&self) -> Option<Annotations> {
+        self.metrics()
+            .and_then(|f| f.service.as_ref())
+            .and_then(|f| f.annotations.as_ref())
+            .cloned()
+            .map(Into::into)
+    }
+
+    #[must_use]
+    fn metrics_service_labels(&self) -> Option<Labels> {
+        self.metrics()
+            .and_then(|f| f.service.as_ref())
+            .and_then(|f| f.labels.as_ref())
+            .cloned()
+            .map(Into::into)
+    }
+
+    #[must_use]
+    fn metrics_service_name(&self) -> ResourceName {
+        self.metrics()
+            .and_then(|f| f.service.as_ref())
+            .and_then(|f| f.name.as_ref())
+            .map_or_else(|| format!("{}-metrics", self.name_any()).into(), Into::into)
+    }
+
+    #[must_use]
+    fn metrics_service_monitor_annotations(&self) -> Option<Annotations> {
+        self.metrics()
+            .and_then(|f| f.service_monitor.as_ref())
+            .and_then(|f| f.annotations.as_ref())
+            .cloned()
+            .map(Into::into)
+    }
+
+    #[must_use]
+    fn metrics_service_monitor_interval(&self) -> Option<String> {
+        self.metrics()
+            .and_then(|f| f.service_monitor.as_ref())
+            .and_then(|f| f.interval.clone())
+    }
+
+    #[must_use]
+    fn metrics_service_monitor_labels(&self) -> Option<Labels> {
+        self.metrics()
+            .and_then(|f| f.service_monitor.as_ref())
+            .and_then(|f| f.labels.as_ref())
+            .cloned()
+            .map(Into::into)
+    }
+
+    #[must_use]
+    fn metrics_service_monitor_name(&self) -> ResourceName {
+        self.metrics()
+            .and_then(|f| f.service_monitor.as_ref())
+            .and_then(|f| f.name.as_ref())
+            .map_or_else(|| format!("{}-metrics", self.name_any()).into(), Into::into)
+    }
+}
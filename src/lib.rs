@@ -1,17 +1,25 @@
 #![warn(clippy::pedantic)]
 
+pub mod balancer;
 pub mod cli;
 mod collections;
+pub mod entrypoint;
+pub mod fallback;
+pub mod gateway;
 pub mod http_server;
 mod kubernetes;
 pub mod metrics;
 pub mod onion_balance;
 pub mod onion_key;
 pub mod onion_service;
+pub mod onion_service_group;
 pub mod otel;
+pub mod probe;
+pub mod tls;
 pub mod tor;
 pub mod tor_ingress;
 pub mod tor_proxy;
+pub mod webhook;
 
 /*
  * ============================================================================
@@ -20,7 +28,9 @@ pub mod tor_proxy;
  */
 #[derive(Debug)]
 pub enum Error {
+    Certificate(rcgen::Error),
     Kube(kube::Error),
+    LabelSelector(kube::core::ParseExpressionError),
     MissingConfiguration(&'static str),
     MissingObjectKey(&'static str),
     OtlpExporter(opentelemetry_otlp::ExporterBuildError),
@@ -29,12 +39,24 @@ pub enum Error {
 
 impl std::error::Error for Error {}
 
+impl From<rcgen::Error> for Error {
+    fn from(e: rcgen::Error) -> Self {
+        Self::Certificate(e)
+    }
+}
+
 impl From<kube::Error> for Error {
     fn from(e: kube::Error) -> Self {
         Self::Kube(e)
     }
 }
 
+impl From<kube::core::ParseExpressionError> for Error {
+    fn from(e: kube::core::ParseExpressionError) -> Self {
+        Self::LabelSelector(e)
+    }
+}
+
 impl From<opentelemetry_otlp::ExporterBuildError> for Error {
     fn from(e: opentelemetry_otlp::ExporterBuildError) -> Self {
         Self::OtlpExporter(e)
@@ -44,7 +66,9 @@ impl From<opentelemetry_otlp::ExporterBuildError> for Error {
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Self::Certificate(e) => write!(f, "certificate error: {e}"),
             Self::Kube(e) => write!(f, "kubernetes error: {e}"),
+            Self::LabelSelector(e) => write!(f, "label selector error: {e}"),
             Self::MissingConfiguration(msg) => write!(f, "missing configuration: {msg}"),
             Self::MissingObjectKey(key) => write!(f, "missing object key: {key}"),
             Self::OtlpExporter(e) => write!(f, "OTLP exporter error: {e}"),
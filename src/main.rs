@@ -1,18 +1,23 @@
 #![warn(clippy::pedantic)]
 
-use std::{borrow::Cow, fs::File, io::Write};
+use std::{borrow::Cow, fs::File, io::Write, time::Duration};
 
 use tor_operator::{
+    balancer,
     cli::{
         CliArgs, CliCommands, ControllerArgs, ControllerCommands, ControllerRunArgs, CrdArgs,
-        CrdCommands, CrdGenerateArgs, CrdGenerateArgsFormat, MarkdownArgs, MarkdownCommands,
-        MarkdownGenerateArgs, OnionKeyArgs, OnionKeyCommands, OnionKeyGenerateArgs, parse,
+        CrdCommands, CrdGenerateArgs, CrdGenerateArgsFormat, EntrypointArgs, EntrypointCommands,
+        EntrypointRunArgs, FallbackArgs, FallbackCommands, FallbackRunArgs, GatewayArgs,
+        GatewayCommands, GatewayRunArgs, MarkdownArgs, MarkdownCommands, MarkdownGenerateArgs,
+        OnionBalanceArgs, OnionBalanceCommands, OnionBalanceRunArgs, OnionKeyArgs,
+        OnionKeyCommands, OnionKeyGenerateArgs, ProbeArgs, ProbeCommands, TlsArgs, TlsCommands,
+        TlsRunArgs, parse,
     },
-    http_server,
+    entrypoint, fallback, gateway, http_server,
     metrics::Metrics,
-    onion_balance, onion_key, onion_service, otel,
+    onion_balance, onion_key, onion_service, onion_service_group, otel, probe, tls,
     tor::{ExpandedSecretKey, HiddenServicePublicKey, HiddenServiceSecretKey, Hostname, PublicKey},
-    tor_ingress, tor_proxy,
+    tor_ingress, tor_proxy, webhook,
 };
 
 #[tokio::main]
@@ -31,14 +36,34 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>
         CliCommands::Crd(crd) => match &crd.command {
             CrdCommands::Generate(generate) => crd_generate(cli, crd, generate)?,
         },
+        CliCommands::Entrypoint(entrypoint) => match &entrypoint.command {
+            EntrypointCommands::Run(run) => {
+                let code = entrypoint_run(cli, entrypoint, run).await?;
+                provider.shutdown()?;
+                std::process::exit(code);
+            }
+        },
+        CliCommands::Fallback(fallback) => match &fallback.command {
+            FallbackCommands::Run(run) => fallback_run(cli, fallback, run).await?,
+        },
+        CliCommands::Gateway(gateway) => match &gateway.command {
+            GatewayCommands::Run(run) => gateway_run(cli, gateway, run).await?,
+        },
         CliCommands::Markdown(markdown) => match &markdown.command {
             MarkdownCommands::Generate(help) => markdown_generate(cli, markdown, help)?,
         },
+        CliCommands::OnionBalance(onion_balance) => match &onion_balance.command {
+            OnionBalanceCommands::Run(run) => onion_balance_run(cli, onion_balance, run).await?,
+        },
         CliCommands::OnionKey(onion_address) => match &onion_address.command {
             OnionKeyCommands::Generate(generate) => {
                 onion_key_generate(cli, onion_address, generate)?;
             }
         },
+        CliCommands::Probe(probe) => probe_run(cli, probe).await?,
+        CliCommands::Tls(tls) => match &tls.command {
+            TlsCommands::Run(run) => tls_run(cli, tls, run).await?,
+        },
     }
 
     provider.shutdown()
@@ -55,6 +80,7 @@ async fn controller_run(
     let client = kube::Client::try_default().await?;
 
     let onion_balance_config = onion_balance::Config {
+        native: run.onion_balance_native,
         onion_balance_image: onion_balance::ImageConfig {
             pull_policy: run.onion_balance_image_pull_policy.clone(),
             uri: run.onion_balance_image_uri(),
@@ -74,6 +100,13 @@ async fn controller_run(
         },
     };
 
+    let onion_service_group_config = onion_service_group::Config {
+        tor_image: onion_service_group::ImageConfig {
+            pull_policy: run.tor_image_pull_policy.clone(),
+            uri: run.tor_image_uri(),
+        },
+    };
+
     let tor_ingress_config = tor_ingress::Config {};
 
     let tor_proxy_config = tor_proxy::Config {
@@ -83,13 +116,35 @@ async fn controller_run(
         },
     };
 
+    let webhook_config = match (&run.webhook_certificate, &run.webhook_key) {
+        (Some(certificate), Some(key)) => Some(webhook::Config {
+            addr: format!("{}:{}", run.host, run.webhook_port).parse()?,
+            certificate: certificate.clone(),
+            key: key.clone(),
+            onion_service: onion_service::Config {
+                tor_image: onion_service::ImageConfig {
+                    pull_policy: run.tor_image_pull_policy.clone(),
+                    uri: run.tor_image_uri(),
+                },
+            },
+        }),
+        _ => None,
+    };
+
     let metrics = Metrics::new(meter_provider);
 
     tokio::select! {
         result = http_server::run(addr) => { result?; },
+        result = async {
+            match webhook_config {
+                Some(webhook_config) => webhook::run(client.clone(), webhook_config).await,
+                None => std::future::pending().await,
+            }
+        } => { result?; },
         () = onion_balance::run_controller(client.clone(), onion_balance_config, metrics.clone()) => {},
         () = onion_key::run_controller(client.clone(), onion_key_config, metrics.clone()) => {},
         () = onion_service::run_controller(client.clone(),onion_service_config, metrics.clone()) => {},
+        () = onion_service_group::run_controller(client.clone(), onion_service_group_config, metrics.clone()) => {},
         () = tor_ingress::run_controller(client.clone(), tor_ingress_config, metrics.clone()) => {},
         () = tor_proxy::run_controller(client.clone(), tor_proxy_config, metrics.clone()) => {},
     }
@@ -138,6 +193,10 @@ fn crd_generate(
             "onionservice",
             onion_service::generate_custom_resource_definition(),
         ),
+        (
+            "onionservicegroup",
+            onion_service_group::generate_custom_resource_definition(),
+        ),
         (
             "toringress",
             tor_ingress::generate_custom_resource_definition(),
@@ -173,6 +232,85 @@ fn crd_generate(
     Ok(())
 }
 
+async fn entrypoint_run(
+    _cli: &CliArgs,
+    _entrypoint: &EntrypointArgs,
+    run: &EntrypointRunArgs,
+) -> Result<i32, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    let config = entrypoint::Config {
+        command: run.command.clone(),
+        copies: run.copy.clone(),
+        directories: run.directory.clone(),
+        ephemeral: (!run.add_onion_port.is_empty()).then(|| entrypoint::Ephemeral {
+            config_map: run
+                .config_map_name
+                .clone()
+                .zip(run.config_map_namespace.clone()),
+            control_port: run.control_port.clone(),
+            ports: run.add_onion_port.clone(),
+        }),
+        interrupt_on_terminate: run.interrupt_on_terminate,
+        leader_election: match (
+            &run.leader_election_identity,
+            &run.leader_election_lease,
+            &run.leader_election_namespace,
+        ) {
+            (Some(identity), Some(lease), Some(namespace)) => Some(entrypoint::LeaderElection {
+                exclusive: run.leader_election_exclusive,
+                identity: identity.clone(),
+                lease: lease.clone(),
+                namespace: namespace.clone(),
+                record_uploads: run
+                    .leader_election_record_uploads
+                    .then(|| run.control_port.clone()),
+            }),
+            _ => None,
+        },
+        reload: run.reload,
+        templates: run.template.clone(),
+    };
+
+    Ok(entrypoint::run(&config).await?)
+}
+
+async fn fallback_run(
+    _cli: &CliArgs,
+    _fallback: &FallbackArgs,
+    run: &FallbackRunArgs,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    let config = fallback::Config {
+        namespace: run.namespace.clone(),
+        routes: run
+            .route
+            .iter()
+            .map(|route| fallback::Route {
+                body_file: route.body_file.clone(),
+                content_type: route.content_type.clone(),
+                listen: route.listen.clone(),
+                service: route.service.clone(),
+                status: route.status,
+                target: route.target.clone(),
+            })
+            .collect(),
+    };
+
+    Ok(fallback::run(&config).await?)
+}
+
+async fn gateway_run(
+    _cli: &CliArgs,
+    _gateway: &GatewayArgs,
+    run: &GatewayRunArgs,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    let config = gateway::Config {
+        connection_limit: run.connection_limit,
+        rate_limit: run.rate_limit,
+        routes: run.route.clone(),
+    };
+
+    Ok(gateway::run(&config).await?)
+}
+
 fn markdown_generate(
     _cli: &CliArgs,
     _markdown: &MarkdownArgs,
@@ -187,6 +325,23 @@ fn markdown_generate(
     Ok(())
 }
 
+async fn onion_balance_run(
+    _cli: &CliArgs,
+    _onion_balance: &OnionBalanceArgs,
+    run: &OnionBalanceRunArgs,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    let config = balancer::Config {
+        config: run.config.clone(),
+        control_port: run.control_port.clone(),
+        fetch_descriptor_frequency: Duration::from_secs(run.fetch_descriptor_frequency),
+        publish_descriptor_check_frequency: Duration::from_secs(
+            run.publish_descriptor_check_frequency,
+        ),
+    };
+
+    Ok(balancer::run(&config).await?)
+}
+
 fn onion_key_generate(
     _cli: &CliArgs,
     _onion_key: &OnionKeyArgs,
@@ -214,3 +369,39 @@ fn onion_key_generate(
 
     Ok(())
 }
+
+async fn probe_run(
+    _cli: &CliArgs,
+    probe: &ProbeArgs,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    let config = probe::Config {
+        control_cookie_file: probe.control_cookie_file.clone(),
+        control_password: probe.control_password.clone(),
+        control_port: probe.control_port.clone(),
+    };
+
+    match &probe.command {
+        ProbeCommands::Liveness => probe::liveness(&config).await?,
+        ProbeCommands::Readiness(readiness) => {
+            probe::readiness(&config, &readiness.hostname_files).await?;
+        }
+        ProbeCommands::Startup => probe::startup(&config).await?,
+    }
+
+    Ok(())
+}
+
+async fn tls_run(
+    _cli: &CliArgs,
+    _tls: &TlsArgs,
+    run: &TlsRunArgs,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    let config = tls::Config {
+        certificate: run.certificate.clone(),
+        key: run.key.clone(),
+        listen: run.listen.clone(),
+        target: run.target.clone(),
+    };
+
+    Ok(tls::run(&config).await?)
+}
@@ -61,7 +61,9 @@ impl Metrics {
 
     pub fn reconcile_failure(&self, controller: &'static str, error: &Error) {
         let error = match error {
+            Error::Certificate(_) => "certificate",
             Error::Kube(_) => "kube",
+            Error::LabelSelector(_) => "label selector",
             Error::MissingConfiguration(_) => "missing configuration",
             Error::MissingObjectKey(_) => "missing object key",
             Error::OtlpExporter(_) => "otlp exporter",
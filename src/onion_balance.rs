@@ -1,23 +1,36 @@
-use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    time::Duration,
+};
 
 use futures::StreamExt;
 use k8s_openapi::{
     api::{
-        apps::v1::{Deployment, DeploymentSpec},
+        apps::v1::{Deployment, DeploymentSpec, DeploymentStrategy, StatefulSet},
+        coordination::v1::{Lease, LeaseSpec},
         core::v1::{
-            Affinity, Capabilities, ConfigMap, ConfigMapVolumeSource, Container, ExecAction,
-            KeyToPath, LocalObjectReference, PodSecurityContext, PodSpec, PodTemplateSpec, Probe,
-            SecretVolumeSource, SecurityContext, Toleration, TopologySpreadConstraint, Volume,
+            Affinity, ConfigMap, ConfigMapVolumeSource, Container, EnvVarSource, KeyToPath,
+            LocalObjectReference, ObjectFieldSelector, PodSecurityContext, PodSpec,
+            PodTemplateSpec, Service, ServiceAccount, ServicePort, ServiceSpec, Toleration,
+            TopologySpreadConstraint, Volume,
         },
+        policy::v1::{PodDisruptionBudget, PodDisruptionBudgetSpec},
+        rbac::v1::{PolicyRule, Role, RoleBinding, RoleRef, Subject},
     },
     apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition,
-    apimachinery::pkg::apis::meta::v1::{Condition, LabelSelector, Time},
+    apimachinery::pkg::{
+        apis::meta::v1::{Condition, LabelSelector, Time},
+        util::intstr::IntOrString,
+    },
     jiff::Timestamp,
 };
 use kube::{
-    Client, CustomResource, CustomResourceExt, Resource,
-    core::ObjectMeta,
-    runtime::{Controller, controller::Action, watcher::Config as WatcherConfig},
+    Client, CustomResource, CustomResourceExt, Resource, ResourceExt,
+    core::{ObjectMeta, Selector, SelectorExt},
+    runtime::{
+        Controller, controller::Action, reflector::ObjectRef, watcher::Config as WatcherConfig,
+    },
 };
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -27,12 +40,17 @@ use crate::{
     collections::vec_get_or_insert,
     kubernetes::{
         self, Annotations, Api, ConditionsExt, ErrorBackoff, Labels, Object,
-        Resource as KubernetesResource, ResourceName, SelectorLabels, Subset,
-        Torrc as KubernetesTorrc, error_policy, pod_security_context,
+        PodDisruptionBudget as KubernetesPodDisruptionBudget, PodDisruptionBudgetExt,
+        Resource as KubernetesResource, ResourceName, SelectorLabels, ServiceMonitor,
+        ServiceMonitorEndpoint, ServiceMonitorSpec, Subset, TOR_AGABANI_CO_UK_PUBLISHED_AT_KEY,
+        TorMetrics, TorMetricsExt, Torrc as KubernetesTorrc, TorrcReloadStrategy,
+        container_security_context, error_policy, liveness_probe, pod_security_context,
+        readiness_probe, startup_probe,
     },
     metrics::Metrics,
     onion_key::OnionKey,
-    tor::{ConfigYaml, ConfigYamlService, ConfigYamlServiceInstance, Torrc},
+    onion_service::OnionService,
+    tor::{ConfigYaml, ConfigYamlService, ConfigYamlServiceInstance, Hostname, Torrc},
 };
 
 /*
@@ -59,6 +77,7 @@ use crate::{
     printcolumn = r#"{"name":"Hostname", "type":"string", "description":"The hostname of the OnionBalance", "jsonPath":".status.hostname"}"#,
     printcolumn = r#"{"name":"OnionServices", "type":"number", "description":"The hostname of OnionServices", "jsonPath":".status.onionServices"}"#,
     printcolumn = r#"{"name":"State", "type":"string", "description":"Human readable description of state", "jsonPath":".status.summary.Initialized"}"#,
+    printcolumn = r#"{"name":"Publisher", "type":"string", "description":"The pod publishing the descriptors", "jsonPath":".status.highAvailability.publisher", "priority":1}"#,
     printcolumn = r#"{"name":"Age", "type":"date", "jsonPath":".metadata.creationTimestamp"}"#,
     status = "OnionBalanceStatus",
     version = "v1"
@@ -71,13 +90,61 @@ pub struct OnionBalanceSpec {
     /// Deployment settings.
     pub deployment: Option<OnionBalanceSpecDeployment>,
 
+    /// High availability settings.
+    ///
+    /// When set, the `OnionBalance` runs several replicas that elect a
+    /// publisher through a Lease. Only the publisher runs onionbalance, the
+    /// others keep Tor bootstrapped, ready to take over.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub high_availability: Option<OnionBalanceSpecHighAvailability>,
+
+    /// Tor metrics settings.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub metrics: Option<TorMetrics>,
+
+    /// onionbalance settings.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub onion_balance: Option<OnionBalanceSpecOnionBalance>,
+
     /// `OnionKey` settings.
     pub onion_key: OnionBalanceSpecOnionKey,
 
+    /// Label selector of the `OnionServices` part of the `OnionBalance` load
+    /// balancing, in the namespace of the `OnionBalance`.
+    ///
+    /// The hostnames are taken from the status of the selected `OnionServices`,
+    /// so that backends are added and removed as they come and go.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub onion_service_selector: Option<LabelSelector>,
+
     /// `OnionService` part of the `OnionBalance` load balancing.
+    #[serde(default)]
     pub onion_services: Vec<OnionBalanceSpecOnionService>,
 
+    /// Pod Disruption Budget settings.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub pod_disruption_budget: Option<KubernetesPodDisruptionBudget>,
+
+    /// Additional services of the `OnionBalance`, each with its own `OnionKey`
+    /// and `OnionServices`.
+    ///
+    /// The services are load balanced by the same onionbalance process as the
+    /// service of `onionKey`.
+    ///
+    /// Default: nil / none / null / undefined.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<OnionBalanceSpecService>,
+
     /// Tor torrc settings.
+    ///
+    /// The reload strategy does not apply to a change of the backends, which
+    /// never restarts the Pods. The native balancer reloads its config in
+    /// place, while onionbalance picks it up the next time it starts.
     pub torrc: Option<KubernetesTorrc>,
 }
 
@@ -130,6 +197,11 @@ pub struct OnionBalanceSpecDeployment {
     /// `SecurityContext` holds pod-level security attributes and common container settings. Optional: Defaults to empty.  See type description for default values of each field.
     pub security_context: Option<PodSecurityContext>,
 
+    /// The deployment strategy to use to replace existing pods with new ones.
+    ///
+    /// Default: Recreate, unless `highAvailability` is set
+    pub strategy: Option<DeploymentStrategy>,
+
     /// If specified, the pod's tolerations.
     pub tolerations: Option<Vec<Toleration>>,
 
@@ -140,6 +212,73 @@ pub struct OnionBalanceSpecDeployment {
     pub volumes: Option<Vec<Volume>>,
 }
 
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionBalanceSpecHighAvailability {
+    /// Duration a replica waits for an unrenewed Lease before taking over
+    /// publishing.
+    ///
+    /// A leader which cannot renew the Lease stops publishing after two thirds
+    /// of this duration, before another replica can take over.
+    ///
+    /// Default: 15
+    pub lease_duration_seconds: Option<i32>,
+
+    /// Number of replicas, one publisher and the rest standbys.
+    #[schemars(range(min = 1))]
+    pub replicas: i32,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionBalanceSpecOnionBalance {
+    /// Seconds between fetches of the descriptors of the `OnionServices`.
+    ///
+    /// Default: 600
+    #[schemars(range(min = 1))]
+    pub fetch_descriptor_frequency_seconds: Option<i32>,
+
+    /// Verbosity of the onionbalance logs.
+    ///
+    /// Default: Info
+    pub log_level: Option<OnionBalanceSpecOnionBalanceLogLevel>,
+
+    /// Seconds between checks of whether the descriptors of the services need
+    /// to be published.
+    ///
+    /// Default: 300
+    #[schemars(range(min = 1))]
+    pub publish_descriptor_check_frequency_seconds: Option<i32>,
+}
+
+/// Verbosity of the onionbalance logs.
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq)]
+pub enum OnionBalanceSpecOnionBalanceLogLevel {
+    Debug,
+    #[default]
+    Info,
+    Warning,
+    Error,
+    Critical,
+}
+
+impl OnionBalanceSpecOnionBalanceLogLevel {
+    /// Value of the `-v` option of onionbalance.
+    #[must_use]
+    pub fn as_arg(self) -> &'static str {
+        match self {
+            Self::Debug => "debug",
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Error => "error",
+            Self::Critical => "critical",
+        }
+    }
+}
+
 #[allow(clippy::module_name_repetitions)]
 #[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
@@ -153,7 +292,15 @@ pub struct OnionBalanceSpecOnionKey {
 #[serde(rename_all = "camelCase")]
 pub struct OnionBalanceSpecOnionService {
     /// `OnionKey` reference of the `OnionService`.
-    pub onion_key: OnionBalanceSpecOnionServiceOnionKey,
+    ///
+    /// One of `onionKey` and `onionServiceRef` is required.
+    pub onion_key: Option<OnionBalanceSpecOnionServiceOnionKey>,
+
+    /// Reference to an `OnionService` in the namespace of the `OnionBalance`,
+    /// whose hostname is taken from its status.
+    ///
+    /// One of `onionKey` and `onionServiceRef` is required.
+    pub onion_service_ref: Option<OnionBalanceSpecOnionServiceRef>,
 }
 
 #[allow(clippy::module_name_repetitions)]
@@ -166,12 +313,46 @@ pub struct OnionBalanceSpecOnionServiceOnionKey {
     pub hostname: String,
 }
 
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionBalanceSpecOnionServiceRef {
+    /// Name of the `OnionService`.
+    pub name: String,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionBalanceSpecService {
+    /// `OnionKey` settings.
+    pub onion_key: OnionBalanceSpecOnionKey,
+
+    /// Label selector of the `OnionServices` part of the service load
+    /// balancing, in the namespace of the `OnionBalance`.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub onion_service_selector: Option<LabelSelector>,
+
+    /// `OnionService` part of the service load balancing.
+    #[serde(default)]
+    pub onion_services: Vec<OnionBalanceSpecOnionService>,
+}
+
 #[allow(clippy::module_name_repetitions)]
 #[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct OnionBalanceStatus {
+    /// `OnionServices` considered for the `OnionBalance` load balancing.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub backends: Vec<OnionBalanceStatusBackend>,
+
     /// Represents the latest available observations of a deployment's current state.
     ///
+    /// ### `BackendMisconfigured`
+    ///
+    /// `Configured`, `Misconfigured`
+    ///
     /// ### Initialized
     ///
     /// `Initialized`
@@ -182,17 +363,82 @@ pub struct OnionBalanceStatus {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub conditions: Vec<Condition>,
 
+    /// High availability observations.
+    ///
+    /// Only populated when high availability is enabled.
+    pub high_availability: Option<OnionBalanceStatusHighAvailability>,
+
     /// `OnionKey` hostname.
     ///
     /// The hostname is only populated once `state` is "running".
     pub hostname: Option<String>,
 
-    /// Number of `OnionServices`.
+    /// Number of `OnionServices` part of the `OnionBalance` load balancing.
     pub onion_services: i32,
 
     /// Represents the latest available observations of a deployment's current state.
     #[serde(default)]
     pub summary: BTreeMap<String, String>,
+
+    /// Additional services of the `OnionBalance`, in the order of `services`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<OnionBalanceStatusService>,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionBalanceStatusBackend {
+    /// Hostname of the `OnionService`.
+    pub hostname: Option<String>,
+
+    /// Name of the `OnionService`.
+    pub name: Option<String>,
+
+    /// Whether the `OnionService` is part of the `OnionBalance` load balancing.
+    pub ready: bool,
+
+    /// Reason the `OnionService` is excluded from the `OnionBalance` load
+    /// balancing.
+    ///
+    /// `OnionServiceNotFound`, `HostnameNotFound`, `DeploymentNotFound`,
+    /// `DeploymentUnavailable`, `Misconfigured`, `StatefulSetNotFound`,
+    /// `StatefulSetUnavailable`
+    pub reason: Option<String>,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionBalanceStatusHighAvailability {
+    /// Number of times publishing moved from one pod to another.
+    pub failovers: i32,
+
+    /// Time the publisher last uploaded a descriptor.
+    pub published_at: Option<Time>,
+
+    /// Name of the pod publishing the descriptors.
+    pub publisher: Option<String>,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionBalanceStatusService {
+    /// `OnionServices` considered for the service load balancing.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub backends: Vec<OnionBalanceStatusBackend>,
+
+    /// `OnionKey` hostname.
+    ///
+    /// The hostname is only populated once every `OnionKey` is ready.
+    pub hostname: Option<String>,
+
+    /// Name of the `OnionKey`.
+    pub onion_key: String,
+
+    /// Number of `OnionServices` part of the service load balancing.
+    pub onion_services: i32,
 }
 
 impl OnionBalance {
@@ -319,6 +565,26 @@ impl OnionBalance {
         )
     }
 
+    #[must_use]
+    pub fn deployment_strategy(&self) -> Option<DeploymentStrategy> {
+        self.spec
+            .deployment
+            .as_ref()
+            .and_then(|f| f.strategy.as_ref())
+            .cloned()
+            .or_else(|| {
+                (!self.high_availability_enabled()).then(|| DeploymentStrategy {
+                    type_: Some("Recreate".into()),
+                    ..Default::default()
+                })
+            })
+    }
+
+    #[must_use]
+    pub fn deployment_termination_grace_period_seconds(&self) -> i64 {
+        self.torrc_shutdown_wait_length_seconds() + 5
+    }
+
     #[must_use]
     pub fn deployment_tolerations(&self) -> Option<Vec<Toleration>> {
         self.spec
@@ -347,11 +613,143 @@ impl OnionBalance {
             .unwrap_or_default()
     }
 
+    #[must_use]
+    pub fn high_availability_enabled(&self) -> bool {
+        self.spec.high_availability.is_some()
+    }
+
+    #[must_use]
+    pub fn high_availability_lease_duration_seconds(&self) -> i32 {
+        self.spec
+            .high_availability
+            .as_ref()
+            .and_then(|f| f.lease_duration_seconds)
+            .unwrap_or(15)
+    }
+
+    #[must_use]
+    pub fn high_availability_replicas(&self) -> i32 {
+        self.spec
+            .high_availability
+            .as_ref()
+            .map_or(1, |f| f.replicas)
+    }
+
+    #[must_use]
+    pub fn lease_name(&self) -> ResourceName {
+        self.default_name()
+    }
+
+    /// Environment variables of onionbalance, which reads its timing
+    /// parameters from the environment.
+    #[must_use]
+    pub fn onion_balance_env(&self) -> Vec<(&'static str, i32)> {
+        let Some(onion_balance) = self.spec.onion_balance.as_ref() else {
+            return Vec::new();
+        };
+
+        [
+            (
+                "ONIONBALANCE_FETCH_DESCRIPTOR_FREQUENCY",
+                onion_balance.fetch_descriptor_frequency_seconds,
+            ),
+            (
+                "ONIONBALANCE_PUBLISH_DESCRIPTOR_CHECK_FREQUENCY",
+                onion_balance.publish_descriptor_check_frequency_seconds,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|value| (name, value)))
+        .collect()
+    }
+
+    #[must_use]
+    pub fn onion_balance_log_level(&self) -> OnionBalanceSpecOnionBalanceLogLevel {
+        self.spec
+            .onion_balance
+            .as_ref()
+            .and_then(|f| f.log_level)
+            .unwrap_or_default()
+    }
+
     #[must_use]
     pub fn onion_key_name(&self) -> ResourceName {
         ResourceName::from(&self.spec.onion_key.name)
     }
 
+    /// Whether the `OnionService` is referenced or selected as a backend.
+    #[must_use]
+    pub fn selects_onion_service(&self, onion_service: &OnionService) -> bool {
+        if self.namespace() != onion_service.namespace() {
+            return false;
+        }
+
+        let name = onion_service.name_any();
+
+        self.services().iter().any(|service| {
+            service
+                .onion_services
+                .iter()
+                .filter_map(|f| f.onion_service_ref.as_ref())
+                .any(|f| f.name == name)
+                || service
+                    .onion_service_selector
+                    .as_ref()
+                    .and_then(|f| Selector::try_from(f.clone()).ok())
+                    .is_some_and(|f| f.matches(onion_service.labels()))
+        })
+    }
+
+    /// The service of `onionKey`, followed by the additional services.
+    #[must_use]
+    pub fn services(&self) -> Vec<OnionBalanceSpecService> {
+        std::iter::once(OnionBalanceSpecService {
+            onion_key: self.spec.onion_key.clone(),
+            onion_service_selector: self.spec.onion_service_selector.clone(),
+            onion_services: self.spec.onion_services.clone(),
+        })
+        .chain(self.spec.services.iter().cloned())
+        .collect()
+    }
+
+    #[must_use]
+    pub fn role_binding_name(&self) -> ResourceName {
+        self.default_name()
+    }
+
+    #[must_use]
+    pub fn role_name(&self) -> ResourceName {
+        self.default_name()
+    }
+
+    #[must_use]
+    pub fn service_account_enabled(&self) -> bool {
+        self.high_availability_enabled()
+    }
+
+    #[must_use]
+    pub fn service_account_name(&self) -> ResourceName {
+        self.default_name()
+    }
+
+    #[must_use]
+    pub fn torrc_reload_strategy(&self) -> TorrcReloadStrategy {
+        self.spec
+            .torrc
+            .as_ref()
+            .and_then(|f| f.reload_strategy)
+            .unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn torrc_shutdown_wait_length_seconds(&self) -> i64 {
+        self.spec
+            .torrc
+            .as_ref()
+            .and_then(|f| f.shutdown_wait_length_seconds)
+            .unwrap_or(10)
+    }
+
     #[must_use]
     pub fn torrc_template(&self) -> Option<&str> {
         self.spec
@@ -375,6 +773,18 @@ impl KubernetesResource for OnionBalance {
     }
 }
 
+impl TorMetricsExt for OnionBalance {
+    fn metrics(&self) -> Option<&TorMetrics> {
+        self.spec.metrics.as_ref()
+    }
+}
+
+impl PodDisruptionBudgetExt for OnionBalance {
+    fn pod_disruption_budget(&self) -> Option<&KubernetesPodDisruptionBudget> {
+        self.spec.pod_disruption_budget.as_ref()
+    }
+}
+
 impl Object for OnionBalance {
     const APP_KUBERNETES_IO_COMPONENT_VALUE: &'static str = "onion-balance";
 
@@ -402,6 +812,9 @@ pub fn generate_custom_resource_definition() -> CustomResourceDefinition {
  * ============================================================================
  */
 pub struct Config {
+    /// Publishes the descriptors with `tor-operator onion-balance run` of the
+    /// Tor image, instead of onionbalance of the Onion Balance image.
+    pub native: bool,
     pub onion_balance_image: ImageConfig,
     pub tor_image: ImageConfig,
 }
@@ -420,31 +833,56 @@ pub async fn run_controller(client: Client, config: Config, metrics: Metrics) {
     metrics.kubernetes_api_usage_count::<OnionBalance>("watch");
     metrics.kubernetes_api_usage_count::<ConfigMap>("watch");
     metrics.kubernetes_api_usage_count::<Deployment>("watch");
-    Controller::new(
+    metrics.kubernetes_api_usage_count::<PodDisruptionBudget>("watch");
+    metrics.kubernetes_api_usage_count::<Service>("watch");
+    metrics.kubernetes_api_usage_count::<OnionService>("watch");
+    let controller = Controller::new(
         kube::Api::<OnionBalance>::all(client.clone()),
         WatcherConfig::default(),
-    )
-    .owns(
-        kube::Api::<ConfigMap>::all(client.clone()),
-        WatcherConfig::default(),
-    )
-    .owns(
-        kube::Api::<Deployment>::all(client.clone()),
-        WatcherConfig::default(),
-    )
-    .shutdown_on_signal()
-    .run(
-        reconciler,
-        error_policy,
-        Arc::new(Context {
-            client,
-            config,
-            error_backoff: ErrorBackoff::default(),
-            metrics,
-        }),
-    )
-    .for_each(|_| async {})
-    .await;
+    );
+    let store = controller.store();
+    controller
+        .owns(
+            kube::Api::<ConfigMap>::all(client.clone()),
+            WatcherConfig::default(),
+        )
+        .owns(
+            kube::Api::<Deployment>::all(client.clone()),
+            WatcherConfig::default(),
+        )
+        .owns(
+            kube::Api::<PodDisruptionBudget>::all(client.clone()),
+            WatcherConfig::default(),
+        )
+        .owns(
+            kube::Api::<Service>::all(client.clone()),
+            WatcherConfig::default(),
+        )
+        .watches(
+            kube::Api::<OnionService>::all(client.clone()),
+            WatcherConfig::default(),
+            move |onion_service| {
+                store
+                    .state()
+                    .into_iter()
+                    .filter(|object| object.selects_onion_service(&onion_service))
+                    .map(|object| ObjectRef::from_obj(object.as_ref()))
+                    .collect::<Vec<_>>()
+            },
+        )
+        .shutdown_on_signal()
+        .run(
+            reconciler,
+            error_policy,
+            Arc::new(Context {
+                client,
+                config,
+                error_backoff: ErrorBackoff::default(),
+                metrics,
+            }),
+        )
+        .for_each(|_| async {})
+        .await;
 }
 
 /*
@@ -475,25 +913,25 @@ impl kubernetes::Context for Context {
  * ============================================================================
  */
 enum State {
-    OnionKeyNotFound,
-    OnionKeyHostnameNotFound,
-    Initialized(Box<OnionKey>),
+    OnionKeyNotFound(ResourceName),
+    OnionKeyHostnameNotFound(ResourceName),
+    Initialized(Vec<OnionKey>),
 }
 
 impl State {
     fn conditions(&self, generation: Option<i64>) -> Vec<Condition> {
         match self {
-            State::OnionKeyNotFound => vec![Condition {
+            State::OnionKeyNotFound(name) => vec![Condition {
                 last_transition_time: Time(Timestamp::now()),
-                message: "The OnionKey was not found.".into(),
+                message: format!("The OnionKey {name} was not found."),
                 observed_generation: generation,
                 reason: "NotFound".into(),
                 status: "False".into(),
                 type_: "OnionKey".into(),
             }],
-            State::OnionKeyHostnameNotFound => vec![Condition {
+            State::OnionKeyHostnameNotFound(name) => vec![Condition {
                 last_transition_time: Time(Timestamp::now()),
-                message: "The OnionKey does not have a hostname.".into(),
+                message: format!("The OnionKey {name} does not have a hostname."),
                 observed_generation: generation,
                 reason: "HostnameNotFound".into(),
                 status: "False".into(),
@@ -502,7 +940,7 @@ impl State {
             State::Initialized(_) => vec![
                 Condition {
                     last_transition_time: Time(Timestamp::now()),
-                    message: "The OnionKey is ready.".into(),
+                    message: "The OnionKeys are ready.".into(),
                     observed_generation: generation,
                     reason: "Ready".into(),
                     status: "True".into(),
@@ -526,6 +964,7 @@ impl State {
  * Reconciler
  * ============================================================================
  */
+#[allow(clippy::too_many_lines)]
 #[tracing::instrument(skip_all)]
 async fn reconciler(object: Arc<OnionBalance>, ctx: Arc<Context>) -> Result<Action> {
     let _timer = ctx
@@ -535,27 +974,60 @@ async fn reconciler(object: Arc<OnionBalance>, ctx: Arc<Context>) -> Result<Acti
 
     let namespace = object.try_namespace()?;
 
-    let config_yaml = generate_config_yaml(&object);
-    let torrc = generate_torrc(&object);
-
-    let labels = object.try_labels()?;
-    let selector_labels = object.try_selector_labels()?;
+    let services = object.services();
 
     // OnionKey
-    let state = reconcile_onion_key(
+    let state = reconcile_onion_keys(
         &Api::new(
             kube::Api::namespaced(ctx.client.clone(), &namespace),
             ctx.metrics.clone(),
         ),
-        &object,
+        &services,
     )
     .await?;
 
-    if let State::Initialized(onion_key) = &state {
-        let annotations = Annotations::new()
-            .add(&config_yaml)
-            .add_opt(onion_key.hostname().as_ref())
-            .add(&torrc);
+    // OnionService
+    let mut backends = Vec::with_capacity(services.len());
+    for (index, service) in services.iter().enumerate() {
+        let frontend = match &state {
+            State::Initialized(onion_keys) => onion_keys.get(index).and_then(OnionKey::hostname),
+            _ => None,
+        };
+
+        backends.push(
+            reconcile_onion_services(
+                &Api::new(
+                    kube::Api::namespaced(ctx.client.clone(), &namespace),
+                    ctx.metrics.clone(),
+                ),
+                &Api::new(
+                    kube::Api::namespaced(ctx.client.clone(), &namespace),
+                    ctx.metrics.clone(),
+                ),
+                &Api::new(
+                    kube::Api::namespaced(ctx.client.clone(), &namespace),
+                    ctx.metrics.clone(),
+                ),
+                service,
+                frontend.as_ref(),
+            )
+            .await?,
+        );
+    }
+
+    let config_yaml = generate_config_yaml(&backends);
+    let torrc = generate_torrc(&object);
+
+    let labels = object.try_labels()?;
+    let selector_labels = object.try_selector_labels()?;
+
+    let mut lease = None;
+
+    if let State::Initialized(onion_keys) = &state {
+        let hostname = onion_keys.first().and_then(OnionKey::hostname);
+
+        let (annotations, template_annotations) =
+            generate_annotations(&object, &config_yaml, hostname.as_ref(), &torrc);
 
         // ConfigMap
         reconcile_config_map(
@@ -571,53 +1043,312 @@ async fn reconciler(object: Arc<OnionBalance>, ctx: Arc<Context>) -> Result<Acti
         )
         .await?;
 
-        // Deployment
-        reconcile_deployment(
+        // ServiceAccount
+        reconcile_service_account(
             &Api::new(
                 kube::Api::namespaced(ctx.client.clone(), &namespace),
                 ctx.metrics.clone(),
             ),
-            &ctx.config,
             &object,
             &annotations,
             &labels,
-            &selector_labels,
-            onion_key,
         )
         .await?;
-    }
-
-    // OnionBalance
-    reconcile_onion_balance(
-        &Api::new(
-            kube::Api::namespaced(ctx.client.clone(), &namespace),
-            ctx.metrics.clone(),
-        ),
-        &object,
-        &state,
-    )
-    .await?;
-
-    ctx.error_backoff.reset(object.as_ref());
 
-    tracing::info!("reconciled");
+        // Role
+        reconcile_role(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &object,
+            &annotations,
+            &labels,
+        )
+        .await?;
 
-    match state {
-        State::Initialized(_) => Ok(Action::requeue(Duration::from_hours(1))),
+        // RoleBinding
+        reconcile_role_binding(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &object,
+            &annotations,
+            &labels,
+        )
+        .await?;
+
+        // Lease
+        lease = reconcile_lease(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &object,
+            &annotations,
+            &labels,
+        )
+        .await?;
+
+        // Deployment
+        reconcile_deployment(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &ctx.config,
+            &object,
+            &annotations,
+            &template_annotations,
+            &labels,
+            &selector_labels,
+            onion_keys,
+        )
+        .await?;
+
+        // PodDisruptionBudget
+        reconcile_pod_disruption_budget(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &object,
+            &annotations,
+            &labels,
+            &selector_labels,
+        )
+        .await?;
+
+        // Service
+        reconcile_service(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &object,
+            &annotations,
+            &labels,
+            &selector_labels,
+        )
+        .await?;
+
+        // ServiceMonitor
+        reconcile_service_monitor(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &object,
+            &annotations,
+            &labels,
+            &selector_labels,
+        )
+        .await?;
+    }
+
+    // OnionBalance
+    reconcile_onion_balance(
+        &Api::new(
+            kube::Api::namespaced(ctx.client.clone(), &namespace),
+            ctx.metrics.clone(),
+        ),
+        &object,
+        &services,
+        &state,
+        backends,
+        lease.as_ref(),
+    )
+    .await?;
+
+    ctx.error_backoff.reset(object.as_ref());
+
+    tracing::info!("reconciled");
+
+    match state {
+        // Backend health and the Lease are not watched, so they are picked up
+        // by polling.
+        State::Initialized(_) => Ok(Action::requeue(Duration::from_mins(1))),
         _ => Ok(Action::requeue(Duration::from_secs(5))),
     }
 }
 
-async fn reconcile_onion_key(api: &Api<OnionKey>, object: &OnionBalance) -> Result<State> {
-    let Some(onion_key) = api.get_opt(&object.onion_key_name()).await? else {
-        return Ok(State::OnionKeyNotFound);
+async fn reconcile_onion_keys(
+    api: &Api<OnionKey>,
+    services: &[OnionBalanceSpecService],
+) -> Result<State> {
+    let mut onion_keys = Vec::with_capacity(services.len());
+
+    for service in services {
+        let name = ResourceName::from(&service.onion_key.name);
+
+        let Some(onion_key) = api.get_opt(&name).await? else {
+            return Ok(State::OnionKeyNotFound(name));
+        };
+
+        if onion_key.hostname().is_none() {
+            return Ok(State::OnionKeyHostnameNotFound(name));
+        }
+
+        onion_keys.push(onion_key);
+    }
+
+    Ok(State::Initialized(onion_keys))
+}
+
+/// Returns the backends of the load balancing, in the order they are listed
+/// followed by the selected ones.
+///
+/// A backend is only ready once the workload of its `OnionService` is
+/// available, which requires the readiness probe of Tor to have seen the
+/// descriptor published. Hostnames that do not belong to an `OnionService` in
+/// the namespace cannot be checked and are always ready.
+///
+/// Once the hostname of the frontend is known, an `OnionService` is only ready
+/// when its `onionBalance` points back at it, as Tor otherwise rejects the
+/// introductions made on behalf of the frontend.
+async fn reconcile_onion_services(
+    api: &Api<OnionService>,
+    deployment_api: &Api<Deployment>,
+    stateful_set_api: &Api<StatefulSet>,
+    service: &OnionBalanceSpecService,
+    frontend: Option<&Hostname>,
+) -> Result<Vec<OnionBalanceStatusBackend>> {
+    // Hostname, name and OnionService of every backend.
+    let mut candidates: Vec<(Option<String>, Option<String>, Option<OnionService>)> = Vec::new();
+
+    let hostnames = service
+        .onion_services
+        .iter()
+        .filter_map(|f| f.onion_key.as_ref())
+        .collect::<Vec<_>>();
+    // Only listed when there are hostnames to look the OnionServices up by.
+    let namespace_onion_services = if hostnames.is_empty() {
+        Vec::new()
+    } else {
+        api.list_selected(&Selector::default()).await?.items
     };
 
-    if onion_key.hostname().is_none() {
-        return Ok(State::OnionKeyHostnameNotFound);
+    for onion_service in &service.onion_services {
+        if let Some(onion_key) = &onion_service.onion_key {
+            let onion_service = namespace_onion_services
+                .iter()
+                .find(|f| status_hostname(f) == Some(&onion_key.hostname));
+            candidates.push((
+                Some(onion_key.hostname.clone()),
+                onion_service.map(ResourceExt::name_any),
+                onion_service.cloned(),
+            ));
+        }
+
+        if let Some(onion_service_ref) = &onion_service.onion_service_ref {
+            let onion_service = api
+                .get_opt(&ResourceName::from(&onion_service_ref.name))
+                .await?;
+            candidates.push((
+                onion_service.as_ref().and_then(status_hostname).cloned(),
+                Some(onion_service_ref.name.clone()),
+                onion_service,
+            ));
+        }
+    }
+
+    if let Some(selector) = &service.onion_service_selector {
+        let mut selected = api
+            .list_selected(&Selector::try_from(selector.clone())?)
+            .await?
+            .items;
+        selected.sort_by_key(ResourceExt::name_any);
+        candidates.extend(selected.into_iter().map(|onion_service| {
+            (
+                status_hostname(&onion_service).cloned(),
+                Some(onion_service.name_any()),
+                Some(onion_service),
+            )
+        }));
+    }
+
+    let mut backends: Vec<OnionBalanceStatusBackend> = Vec::with_capacity(candidates.len());
+
+    for (hostname, name, onion_service) in candidates {
+        if hostname.is_some() && backends.iter().any(|f| f.hostname == hostname) {
+            continue;
+        }
+
+        let reason = match &onion_service {
+            None if hostname.is_none() => Some("OnionServiceNotFound"),
+            None => None,
+            Some(_) if hostname.is_none() => Some("HostnameNotFound"),
+            Some(onion_service) if !belongs_to(onion_service, frontend) => Some("Misconfigured"),
+            Some(onion_service) => {
+                let name = onion_service.deployment_name();
+                let (deployment, stateful_set) = if onion_service.persistence_enabled() {
+                    (None, stateful_set_api.get_opt(&name).await?)
+                } else if onion_service.injection_enabled() {
+                    (None, None)
+                } else {
+                    (deployment_api.get_opt(&name).await?, None)
+                };
+                backend_reason(onion_service, deployment.as_ref(), stateful_set.as_ref())
+            }
+        };
+
+        backends.push(OnionBalanceStatusBackend {
+            hostname,
+            name,
+            ready: reason.is_none(),
+            reason: reason.map(Into::into),
+        });
+    }
+
+    Ok(backends)
+}
+
+fn status_hostname(onion_service: &OnionService) -> Option<&String> {
+    onion_service.status.as_ref()?.hostname.as_ref()
+}
+
+/// Whether the `OnionService` is an instance of the frontend, which is assumed
+/// while the hostname of the frontend is unknown.
+fn belongs_to(onion_service: &OnionService, frontend: Option<&Hostname>) -> bool {
+    frontend.is_none_or(|frontend| {
+        onion_service.onion_balance_onion_key_hostname().as_ref() == Some(frontend)
+    })
+}
+
+/// Returns the reason the backend of the `OnionService` is excluded, if any.
+fn backend_reason(
+    onion_service: &OnionService,
+    deployment: Option<&Deployment>,
+    stateful_set: Option<&StatefulSet>,
+) -> Option<&'static str> {
+    if onion_service.injection_enabled() {
+        return None;
     }
 
-    Ok(State::Initialized(Box::new(onion_key)))
+    if onion_service.persistence_enabled() {
+        let Some(stateful_set) = stateful_set else {
+            return Some("StatefulSetNotFound");
+        };
+        let ready_replicas = stateful_set
+            .status
+            .as_ref()
+            .and_then(|f| f.ready_replicas)
+            .unwrap_or_default();
+        return (ready_replicas < 1).then_some("StatefulSetUnavailable");
+    }
+
+    let Some(deployment) = deployment else {
+        return Some("DeploymentNotFound");
+    };
+    let available = deployment
+        .status
+        .as_ref()
+        .and_then(|f| f.conditions.as_ref())
+        .into_iter()
+        .flatten()
+        .any(|f| f.type_ == "Available" && f.status == "True");
+    (!available).then_some("DeploymentUnavailable")
 }
 
 async fn reconcile_config_map(
@@ -640,14 +1371,16 @@ async fn reconcile_config_map(
     .map(|_| ())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn reconcile_deployment(
     api: &Api<Deployment>,
     config: &Config,
     object: &OnionBalance,
     annotations: &Annotations,
+    template_annotations: &Annotations,
     labels: &Labels,
     selector_labels: &SelectorLabels,
-    onion_key: &OnionKey,
+    onion_keys: &[OnionKey],
 ) -> Result<()> {
     api.sync(
         object,
@@ -657,9 +1390,10 @@ async fn reconcile_deployment(
                 object,
                 config,
                 annotations,
+                template_annotations,
                 labels,
                 selector_labels,
-                onion_key,
+                onion_keys,
             )?,
         )]
         .into(),
@@ -668,16 +1402,132 @@ async fn reconcile_deployment(
     .map(|_| ())
 }
 
+async fn reconcile_lease(
+    api: &Api<Lease>,
+    object: &OnionBalance,
+    annotations: &Annotations,
+    labels: &Labels,
+) -> Result<Option<Lease>> {
+    let resources: HashMap<(), _> = generate_lease(object, annotations, labels)?
+        .map(|lease| [((), lease)].into())
+        .unwrap_or_default();
+
+    api.sync(object, resources)
+        .await
+        .map(|mut leases| leases.remove(&()))
+}
+
+async fn reconcile_pod_disruption_budget(
+    api: &Api<PodDisruptionBudget>,
+    object: &OnionBalance,
+    annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+) -> Result<()> {
+    let resources: HashMap<(), _> =
+        generate_pod_disruption_budget(object, annotations, labels, selector_labels)?
+            .map(|pod_disruption_budget| [((), pod_disruption_budget)].into())
+            .unwrap_or_default();
+
+    api.sync(object, resources).await.map(|_| ())
+}
+
+async fn reconcile_role(
+    api: &Api<Role>,
+    object: &OnionBalance,
+    annotations: &Annotations,
+    labels: &Labels,
+) -> Result<()> {
+    let resources: HashMap<(), _> = generate_role(object, annotations, labels)?
+        .map(|role| [((), role)].into())
+        .unwrap_or_default();
+
+    api.sync(object, resources).await.map(|_| ())
+}
+
+async fn reconcile_role_binding(
+    api: &Api<RoleBinding>,
+    object: &OnionBalance,
+    annotations: &Annotations,
+    labels: &Labels,
+) -> Result<()> {
+    let resources: HashMap<(), _> = generate_role_binding(object, annotations, labels)?
+        .map(|role_binding| [((), role_binding)].into())
+        .unwrap_or_default();
+
+    api.sync(object, resources).await.map(|_| ())
+}
+
+async fn reconcile_service(
+    api: &Api<Service>,
+    object: &OnionBalance,
+    annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+) -> Result<()> {
+    let resources: HashMap<(), _> =
+        generate_metrics_service(object, annotations, labels, selector_labels)?
+            .map(|service| [((), service)].into())
+            .unwrap_or_default();
+
+    api.sync(object, resources).await.map(|_| ())
+}
+
+async fn reconcile_service_account(
+    api: &Api<ServiceAccount>,
+    object: &OnionBalance,
+    annotations: &Annotations,
+    labels: &Labels,
+) -> Result<()> {
+    let resources: HashMap<(), _> = generate_service_account(object, annotations, labels)?
+        .map(|service_account| [((), service_account)].into())
+        .unwrap_or_default();
+
+    api.sync(object, resources).await.map(|_| ())
+}
+
+async fn reconcile_service_monitor(
+    api: &Api<ServiceMonitor>,
+    object: &OnionBalance,
+    annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+) -> Result<()> {
+    let resources: HashMap<(), _> =
+        generate_service_monitor(object, annotations, labels, selector_labels)?
+            .map(|service_monitor| [((), service_monitor)].into())
+            .unwrap_or_default();
+    let enabled = !resources.is_empty();
+
+    match api.sync(object, resources).await {
+        Ok(_) => Ok(()),
+        // The Prometheus Operator is optional, so there is nothing to clean up
+        // when its custom resource definitions are not installed.
+        Err(Error::Kube(kube::Error::Api(status))) if !enabled && status.is_not_found() => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
 async fn reconcile_onion_balance(
     api: &Api<OnionBalance>,
     object: &OnionBalance,
+    services: &[OnionBalanceSpecService],
     state: &State,
+    backends: Vec<Vec<OnionBalanceStatusBackend>>,
+    lease: Option<&Lease>,
 ) -> Result<()> {
-    let conditions = object
+    let mut conditions = object
         .status_conditions()
         .unwrap_or(&Vec::new())
         .merge_from(&state.conditions(object.meta().generation));
 
+    if let State::Initialized(_) = state {
+        conditions = conditions.merge_from(&vec![generate_backend_misconfigured_condition(
+            &backends,
+            object.meta().generation,
+        )]);
+    }
+
     let summary = conditions
         .iter()
         .fold(BTreeMap::new(), |mut summary, condition| {
@@ -685,49 +1535,185 @@ async fn reconcile_onion_balance(
             summary
         });
 
-    api.update_status(
-        object,
-        OnionBalanceStatus {
-            conditions,
-            hostname: if let State::Initialized(onion_key) = state {
-                onion_key.hostname().as_ref().map(ToString::to_string)
+    let mut services = services
+        .iter()
+        .zip(backends)
+        .enumerate()
+        .map(|(index, (service, backends))| OnionBalanceStatusService {
+            hostname: if let State::Initialized(onion_keys) = state {
+                onion_keys
+                    .get(index)
+                    .and_then(OnionKey::hostname)
+                    .map(|f| f.to_string())
             } else {
                 None
             },
-            onion_services: i32::try_from(object.spec.onion_services.len())
+            onion_key: service.onion_key.name.clone(),
+            onion_services: i32::try_from(backends.iter().filter(|f| f.ready).count())
                 .expect("onion_services count cannot realistically exceed i32::MAX"),
+            backends,
+        })
+        .collect::<Vec<_>>();
+
+    // The service of `onionKey` is reported at the top level.
+    let service = services.remove(0);
+
+    api.update_status(
+        object,
+        OnionBalanceStatus {
+            backends: service.backends,
+            conditions,
+            high_availability: lease.and_then(|lease| {
+                let spec = lease.spec.as_ref()?;
+                Some(OnionBalanceStatusHighAvailability {
+                    failovers: spec.lease_transitions.unwrap_or_default(),
+                    published_at: lease
+                        .annotations()
+                        .get(TOR_AGABANI_CO_UK_PUBLISHED_AT_KEY)
+                        .and_then(|f| f.parse().ok())
+                        .map(Time),
+                    publisher: spec.holder_identity.clone(),
+                })
+            }),
+            hostname: service.hostname,
+            onion_services: service.onion_services,
+            services,
             summary,
         },
     )
     .await
 }
 
-#[allow(unused_variables)]
+fn generate_backend_misconfigured_condition(
+    backends: &[Vec<OnionBalanceStatusBackend>],
+    generation: Option<i64>,
+) -> Condition {
+    let names = backends
+        .iter()
+        .flatten()
+        .filter(|f| f.reason.as_deref() == Some("Misconfigured"))
+        .filter_map(|f| f.name.as_deref())
+        .collect::<Vec<_>>();
+
+    if names.is_empty() {
+        Condition {
+            last_transition_time: Time(Timestamp::now()),
+            message: "The OnionServices are instances of the OnionBalance.".into(),
+            observed_generation: generation,
+            reason: "Configured".into(),
+            status: "False".into(),
+            type_: "BackendMisconfigured".into(),
+        }
+    } else {
+        Condition {
+            last_transition_time: Time(Timestamp::now()),
+            message: format!(
+                "The OnionServices {} are not instances of the OnionBalance.",
+                names.join(", ")
+            ),
+            observed_generation: generation,
+            reason: "Misconfigured".into(),
+            status: "True".into(),
+            type_: "BackendMisconfigured".into(),
+        }
+    }
+}
+
+/// Annotations of the resources, and of the Pod template, restarting the Pods
+/// when they change.
+///
+/// The backends are left out of the Pod template, so that the frontend keeps
+/// publishing while the backends are unstable. A change of the backends is
+/// only written to the Config Map.
+fn generate_annotations(
+    object: &OnionBalance,
+    config_yaml: &ConfigYaml,
+    hostname: Option<&Hostname>,
+    torrc: &Torrc,
+) -> (Annotations, Annotations) {
+    let annotations = Annotations::new()
+        .add(config_yaml)
+        .add_opt(hostname)
+        .add(torrc);
+
+    let template_annotations = match object.torrc_reload_strategy() {
+        TorrcReloadStrategy::Restart => Annotations::new().add_opt(hostname).add(torrc),
+        TorrcReloadStrategy::Signal => Annotations::new().add_opt(hostname).add(&torrc.restart()),
+    };
+
+    (annotations, template_annotations)
+}
+
 fn generate_torrc(object: &OnionBalance) -> Torrc {
     let mut torrc = Torrc::builder();
     if let Some(template) = object.torrc_template() {
         torrc = torrc.template(template);
     }
-    torrc
+    torrc = torrc
         .data_dir("${TOR_TMP_DIR}/home/.tor")
         .control_port("127.0.0.1:6666")
-        .build()
+        .shutdown_wait_length(object.torrc_shutdown_wait_length_seconds());
+    if let Some(metrics) = object.metrics() {
+        torrc = torrc.metrics_port(&metrics.listen());
+        torrc = metrics
+            .policy()
+            .iter()
+            .fold(torrc, |torrc, policy| torrc.metrics_port_policy(policy));
+    }
+    torrc.build()
 }
 
-fn generate_config_yaml(object: &OnionBalance) -> ConfigYaml {
+/// Generates the onionbalance config of the services, given their backends.
+///
+/// Instances are named after their `OnionService`, when known, so that the
+/// onionbalance logs can be traced back to it.
+fn generate_config_yaml(backends: &[Vec<OnionBalanceStatusBackend>]) -> ConfigYaml {
     ConfigYaml {
-        services: vec![ConfigYamlService {
-            instances: object
-                .spec
-                .onion_services
-                .iter()
-                .map(|service| ConfigYamlServiceInstance {
-                    address: service.onion_key.hostname.clone(),
-                    name: service.onion_key.hostname.clone(),
-                })
-                .collect(),
-            key: "${TOR_TMP_DIR}/var/lib/tor/hidden_service/hs_ed25519_secret_key".into(),
-        }],
+        services: backends
+            .iter()
+            .enumerate()
+            .map(|(index, backends)| ConfigYamlService {
+                instances: backends
+                    .iter()
+                    .filter(|f| f.ready)
+                    .filter_map(|f| {
+                        f.hostname
+                            .as_ref()
+                            .map(|hostname| ConfigYamlServiceInstance {
+                                address: hostname.clone(),
+                                name: f.name.clone().unwrap_or_else(|| hostname.clone()),
+                            })
+                    })
+                    .collect(),
+                key: format!("{}/hs_ed25519_secret_key", hidden_service_dir(index)),
+            })
+            .collect(),
+    }
+}
+
+/// The key of the service of `onionKey` keeps the paths it had before
+/// additional services were supported.
+fn hidden_service_dir(index: usize) -> String {
+    if index == 0 {
+        "${TOR_TMP_DIR}/var/lib/tor/hidden_service".into()
+    } else {
+        format!("${{TOR_TMP_DIR}}/var/lib/tor/hidden_service_{index}")
+    }
+}
+
+fn secrets_volume_name(index: usize) -> String {
+    if index == 0 {
+        "etc-secrets".into()
+    } else {
+        format!("etc-secrets-{index}")
+    }
+}
+
+fn secrets_mount_path(index: usize) -> String {
+    if index == 0 {
+        "/etc/secrets".into()
+    } else {
+        format!("/etc/secrets-{index}")
     }
 }
 
@@ -772,9 +1758,10 @@ fn generate_deployment(
     object: &OnionBalance,
     config: &Config,
     annotations: &Annotations,
+    template_annotations: &Annotations,
     labels: &Labels,
     selector_labels: &SelectorLabels,
-    onion_key: &OnionKey,
+    onion_keys: &[OnionKey],
 ) -> Result<Deployment> {
     Ok(Deployment {
         metadata: ObjectMeta {
@@ -799,15 +1786,16 @@ fn generate_deployment(
             ..Default::default()
         },
         spec: Some(DeploymentSpec {
-            replicas: Some(1),
+            replicas: Some(object.high_availability_replicas()),
             selector: LabelSelector {
                 match_labels: Some(selector_labels.into()),
                 ..Default::default()
             },
+            strategy: object.deployment_strategy(),
             template: PodTemplateSpec {
                 metadata: Some(ObjectMeta {
                     annotations: Some(
-                        annotations
+                        template_annotations
                             .clone()
                             .append_reverse(object.deployment_annotations())
                             .into(),
@@ -827,9 +1815,15 @@ fn generate_deployment(
                     init_containers: Some(generate_deployment_init_containers(object)),
                     node_selector: object.deployment_node_selector(),
                     security_context: Some(object.deployment_security_context()),
+                    service_account_name: object
+                        .service_account_enabled()
+                        .then(|| object.service_account_name().into()),
+                    termination_grace_period_seconds: Some(
+                        object.deployment_termination_grace_period_seconds(),
+                    ),
                     tolerations: object.deployment_tolerations(),
                     topology_spread_constraints: object.deployment_topology_spread_constraints(),
-                    volumes: Some(generate_deployment_volumes(object, onion_key)),
+                    volumes: Some(generate_deployment_volumes(object, onion_keys)),
                     ..Default::default()
                 }),
             },
@@ -839,37 +1833,130 @@ fn generate_deployment(
     })
 }
 
+#[allow(clippy::too_many_lines)]
 fn generate_deployment_containers(object: &OnionBalance, config: &Config) -> Vec<Container> {
     let mut containers = object.deployment_containers();
 
+    let services = object.services().len();
+
     {
         let container = vec_get_or_insert(&mut containers, |f| f.name == "onionbalance");
         container.name = "onionbalance".to_string();
-        container.args= Some(vec![
-            "-c".into(),
-            [
-                "export TOR_TMP_DIR=${TOR_TMP_DIR:-$(mktemp -d --suffix=.tor -p /tmp)}",
-                // hidden_service
-                "mkdir -p $TOR_TMP_DIR/var/lib/tor/hidden_service",
-                "chmod 700 $TOR_TMP_DIR/var/lib/tor/hidden_service",
-                "cp -L /etc/secrets/* $TOR_TMP_DIR/var/lib/tor/hidden_service",
-                // config.yaml
-                "mkdir -p $TOR_TMP_DIR/usr/local/etc/onionbalance",
-                "envsubst < /etc/configs/config.yaml > $TOR_TMP_DIR/usr/local/etc/onionbalance/config.yaml",
-                // executable
-                "onionbalance -v info -c $TOR_TMP_DIR/usr/local/etc/onionbalance/config.yaml -p 6666"]
-            .join(" && "),
-        ]);
-        container.command = Some(vec!["/bin/bash".into()]);
-        container.image = Some(config.onion_balance_image.uri.clone());
-        container.image_pull_policy = Some(config.onion_balance_image.pull_policy.clone());
+        container.args = Some({
+            let mut args: Vec<String> = vec!["entrypoint".into(), "run".into()];
+
+            // hidden_service
+            for index in 0..services {
+                let hidden_service_dir = hidden_service_dir(index);
+                args.extend([
+                    "--directory".into(),
+                    hidden_service_dir.clone(),
+                    "--copy".into(),
+                    format!("{}:{hidden_service_dir}", secrets_mount_path(index)),
+                ]);
+            }
+
+            // leader election
+            if object.high_availability_enabled() {
+                args.extend([
+                    "--leader-election-lease".into(),
+                    object.lease_name().to_string(),
+                    "--leader-election-exclusive".into(),
+                    "--leader-election-record-uploads".into(),
+                ]);
+            }
+
+            // config.yaml
+            args.extend([
+                "--template".into(),
+                "/etc/configs/config.yaml:${TOR_TMP_DIR}/usr/local/etc/onionbalance/config.yaml"
+                    .into(),
+            ]);
+
+            // reload, which only the native balancer does in place
+            if config.native {
+                args.push("--reload".into());
+            }
+
+            // executable
+            if config.native {
+                args.extend(
+                    [
+                        "--",
+                        "tor-operator",
+                        "onion-balance",
+                        "run",
+                        "--config",
+                        "${TOR_TMP_DIR}/usr/local/etc/onionbalance/config.yaml",
+                        "--control-port",
+                        "127.0.0.1:6666",
+                    ]
+                    .into_iter()
+                    .map(Into::into),
+                );
+            } else {
+                args.extend(
+                    [
+                        "--",
+                        "onionbalance",
+                        "-v",
+                        object.onion_balance_log_level().as_arg(),
+                        "-c",
+                        "${TOR_TMP_DIR}/usr/local/etc/onionbalance/config.yaml",
+                        "-p",
+                        "6666",
+                    ]
+                    .into_iter()
+                    .map(Into::into),
+                );
+            }
+
+            args
+        });
+        container.command = Some(vec!["tor-operator".into()]);
+        let image = if config.native {
+            &config.tor_image
+        } else {
+            &config.onion_balance_image
+        };
+        container.image = Some(image.uri.clone());
+        container.image_pull_policy = Some(image.pull_policy.clone());
+
+        let onion_balance_env = object.onion_balance_env();
+        if !onion_balance_env.is_empty() {
+            let env = container.env.get_or_insert_with(Default::default);
+            for (name, value) in onion_balance_env {
+                let env_var = vec_get_or_insert(env, |f| f.name == name);
+                env_var.name = name.to_string();
+                env_var.value = Some(value.to_string());
+            }
+        }
+
+        if object.service_account_enabled() {
+            let env = container.env.get_or_insert_with(Default::default);
+            for (name, field_path) in [
+                ("POD_NAME", "metadata.name"),
+                ("POD_NAMESPACE", "metadata.namespace"),
+            ] {
+                let env_var = vec_get_or_insert(env, |f| f.name == name);
+                env_var.name = name.to_string();
+                env_var.value_from = Some(EnvVarSource {
+                    field_ref: Some(ObjectFieldSelector {
+                        field_path: field_path.into(),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                });
+            }
+        }
 
         let volume_mounts = container.volume_mounts.get_or_insert_with(Default::default);
 
-        {
-            let volume_mount = vec_get_or_insert(volume_mounts, |f| f.name == "etc-secrets");
-            volume_mount.name = "etc-secrets".to_string();
-            volume_mount.mount_path = "/etc/secrets".into();
+        for index in 0..services {
+            let name = secrets_volume_name(index);
+            let volume_mount = vec_get_or_insert(volume_mounts, |f| f.name == name);
+            volume_mount.name = name;
+            volume_mount.mount_path = secrets_mount_path(index);
             volume_mount.read_only = Some(true);
         }
 
@@ -885,56 +1972,56 @@ fn generate_deployment_containers(object: &OnionBalance, config: &Config) -> Vec
     {
         let container = vec_get_or_insert(&mut containers, |f| f.name == "tor");
         container.name = "tor".to_string();
-        container.args = Some(vec![
-            "-c".into(),
-            [
-                "export TOR_TMP_DIR=${TOR_TMP_DIR:-$(mktemp -d --suffix=.tor -p /tmp)}",
-                // hidden_service
-                "mkdir -p $TOR_TMP_DIR/var/lib/tor/hidden_service",
-                "chmod 700 $TOR_TMP_DIR/var/lib/tor/hidden_service",
-                "cp -L /etc/secrets/* $TOR_TMP_DIR/var/lib/tor/hidden_service",
-                // torrc
-                "mkdir -p $TOR_TMP_DIR/usr/local/etc/tor",
-                "envsubst < /etc/configs/torrc > $TOR_TMP_DIR/usr/local/etc/tor/torrc",
-                // data directory
-                "mkdir -p $TOR_TMP_DIR/home/.tor",
-                "chmod 700 $TOR_TMP_DIR/home/.tor",
-                // executable
-                "tor -f $TOR_TMP_DIR/usr/local/etc/tor/torrc",
-            ]
-            .join(" && "),
-        ]);
-        container.command = Some(vec!["/bin/bash".into()]);
+        container.args = Some({
+            let mut args = vec!["entrypoint", "run"];
+
+            // hidden_service
+            args.extend(["--directory", "${TOR_TMP_DIR}/var/lib/tor/hidden_service"]);
+            args.extend([
+                "--copy",
+                "/etc/secrets:${TOR_TMP_DIR}/var/lib/tor/hidden_service",
+            ]);
+
+            // torrc
+            args.extend([
+                "--template",
+                "/etc/configs/torrc:${TOR_TMP_DIR}/usr/local/etc/tor/torrc",
+            ]);
+
+            // data directory
+            args.extend(["--directory", "${TOR_TMP_DIR}/home/.tor"]);
+
+            // reload
+            if object.torrc_reload_strategy() == TorrcReloadStrategy::Signal {
+                args.push("--reload");
+            }
+
+            // shutdown
+            args.push("--interrupt-on-terminate");
+
+            // executable
+            args.extend(["--", "tor", "-f", "${TOR_TMP_DIR}/usr/local/etc/tor/torrc"]);
+
+            args.into_iter().map(Into::into).collect()
+        });
+        container.command = Some(vec!["tor-operator".into()]);
         container.image = Some(config.tor_image.uri.clone());
         container.image_pull_policy = Some(config.tor_image.pull_policy.clone());
-        container.liveness_probe = Some(Probe {
-            exec: Some(ExecAction {
-                command: Some(vec![
-                    "/bin/bash".to_string(),
-                    "-c".to_string(),
-                    "echo > /dev/tcp/127.0.0.1/9050".to_string(),
-                ]),
-            }),
-            failure_threshold: Some(3),
-            period_seconds: Some(10),
-            success_threshold: Some(1),
-            timeout_seconds: Some(1),
-            ..Default::default()
-        });
-        container.readiness_probe = Some(Probe {
-            exec: Some(ExecAction {
-                command: Some(vec![
-                    "/bin/bash".to_string(),
-                    "-c".to_string(),
-                    "echo > /dev/tcp/127.0.0.1/9050".to_string(),
-                ]),
-            }),
-            failure_threshold: Some(3),
-            period_seconds: Some(10),
-            success_threshold: Some(1),
-            timeout_seconds: Some(1),
-            ..Default::default()
-        });
+
+        if let Some(metrics) = object.metrics() {
+            let ports = container.ports.get_or_insert_with(Default::default);
+            let port = vec_get_or_insert(ports, |f| match &f.name {
+                Some(name) => name == "metrics",
+                None => false,
+            });
+            port.name = Some("metrics".to_string());
+            port.container_port = metrics.port();
+            port.protocol = Some("TCP".to_string());
+        }
+
+        container.liveness_probe = Some(liveness_probe(&[]));
+        container.readiness_probe = Some(readiness_probe(&[]));
+        container.startup_probe = Some(startup_probe(&[]));
 
         let volume_mounts = container.volume_mounts.get_or_insert_with(Default::default);
 
@@ -954,13 +2041,7 @@ fn generate_deployment_containers(object: &OnionBalance, config: &Config) -> Vec
     }
 
     for container in &mut containers {
-        container.security_context = Some(SecurityContext {
-            capabilities: Some(Capabilities {
-                drop: Some(vec!["ALL".to_string()]),
-                ..Default::default()
-            }),
-            ..Default::default()
-        });
+        container.security_context = Some(container_security_context());
     }
 
     containers
@@ -970,47 +2051,21 @@ fn generate_deployment_init_containers(object: &OnionBalance) -> Vec<Container>
     let mut containers = object.deployment_init_containers();
 
     for container in &mut containers {
-        container.security_context = Some(SecurityContext {
-            capabilities: Some(Capabilities {
-                drop: Some(vec!["ALL".to_string()]),
-                ..Default::default()
-            }),
-            ..Default::default()
-        });
+        container.security_context = Some(container_security_context());
     }
 
     containers
 }
 
 #[must_use]
-pub fn generate_deployment_volumes(object: &OnionBalance, onion_key: &OnionKey) -> Vec<Volume> {
+pub fn generate_deployment_volumes(object: &OnionBalance, onion_keys: &[OnionKey]) -> Vec<Volume> {
     let mut volumes = object.deployment_volumes();
 
-    {
-        let volume = vec_get_or_insert(&mut volumes, |f| f.name == "etc-secrets");
-        volume.name = "etc-secrets".to_string();
-        volume.secret = Some(SecretVolumeSource {
-            default_mode: Some(0o400),
-            items: Some(vec![
-                KeyToPath {
-                    key: "hostname".into(),
-                    mode: Some(0o400),
-                    path: "hostname".into(),
-                },
-                KeyToPath {
-                    key: "hs_ed25519_public_key".into(),
-                    mode: Some(0o400),
-                    path: "hs_ed25519_public_key".into(),
-                },
-                KeyToPath {
-                    key: "hs_ed25519_secret_key".into(),
-                    mode: Some(0o400),
-                    path: "hs_ed25519_secret_key".into(),
-                },
-            ]),
-            optional: Some(false),
-            secret_name: Some(onion_key.secret_name().into()),
-        });
+    for (index, onion_key) in onion_keys.iter().enumerate() {
+        let name = secrets_volume_name(index);
+        let volume = vec_get_or_insert(&mut volumes, |f| f.name == name);
+        volume.name = name;
+        volume.secret = Some(onion_key.secret_volume_source());
     }
 
     {
@@ -1046,37 +2101,377 @@ pub fn generate_deployment_volumes(object: &OnionBalance, onion_key: &OnionKey)
     volumes
 }
 
+fn generate_lease(
+    object: &OnionBalance,
+    annotations: &Annotations,
+    labels: &Labels,
+) -> Result<Option<Lease>> {
+    if !object.high_availability_enabled() {
+        return Ok(None);
+    }
+
+    Ok(Some(Lease {
+        metadata: ObjectMeta {
+            name: Some(object.lease_name().into()),
+            annotations: Some(annotations.clone().into()),
+            labels: Some(labels.clone().into()),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        spec: Some(LeaseSpec {
+            lease_duration_seconds: Some(object.high_availability_lease_duration_seconds()),
+            ..Default::default()
+        }),
+    }))
+}
+
+fn generate_pod_disruption_budget(
+    object: &OnionBalance,
+    annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+) -> Result<Option<PodDisruptionBudget>> {
+    if !object.pod_disruption_budget_enabled() {
+        return Ok(None);
+    }
+
+    Ok(Some(PodDisruptionBudget {
+        metadata: ObjectMeta {
+            name: Some(object.pod_disruption_budget_name().into()),
+            annotations: Some(
+                annotations
+                    .clone()
+                    .append_reverse(object.pod_disruption_budget_annotations())
+                    .into(),
+            ),
+            labels: Some(
+                labels
+                    .clone()
+                    .append_reverse(object.pod_disruption_budget_labels())
+                    .into(),
+            ),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        spec: Some(PodDisruptionBudgetSpec {
+            max_unavailable: object.pod_disruption_budget_max_unavailable(),
+            min_available: object.pod_disruption_budget_min_available(),
+            selector: Some(LabelSelector {
+                match_labels: Some(selector_labels.into()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }))
+}
+
+fn generate_role(
+    object: &OnionBalance,
+    annotations: &Annotations,
+    labels: &Labels,
+) -> Result<Option<Role>> {
+    if !object.service_account_enabled() {
+        return Ok(None);
+    }
+
+    Ok(Some(Role {
+        metadata: ObjectMeta {
+            name: Some(object.role_name().into()),
+            annotations: Some(annotations.clone().into()),
+            labels: Some(labels.clone().into()),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        rules: Some(vec![PolicyRule {
+            api_groups: Some(vec!["coordination.k8s.io".into()]),
+            resource_names: Some(vec![object.lease_name().into()]),
+            resources: Some(vec!["leases".into()]),
+            // patch records the time of the uploaded descriptors.
+            verbs: vec!["get".into(), "patch".into(), "update".into()],
+            ..Default::default()
+        }]),
+    }))
+}
+
+fn generate_role_binding(
+    object: &OnionBalance,
+    annotations: &Annotations,
+    labels: &Labels,
+) -> Result<Option<RoleBinding>> {
+    if !object.service_account_enabled() {
+        return Ok(None);
+    }
+
+    Ok(Some(RoleBinding {
+        metadata: ObjectMeta {
+            name: Some(object.role_binding_name().into()),
+            annotations: Some(annotations.clone().into()),
+            labels: Some(labels.clone().into()),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        role_ref: RoleRef {
+            api_group: "rbac.authorization.k8s.io".into(),
+            kind: "Role".into(),
+            name: object.role_name().into(),
+        },
+        subjects: Some(vec![Subject {
+            kind: "ServiceAccount".into(),
+            name: object.service_account_name().into(),
+            namespace: Some(object.try_namespace()?.into()),
+            ..Default::default()
+        }]),
+    }))
+}
+
+fn generate_service_account(
+    object: &OnionBalance,
+    annotations: &Annotations,
+    labels: &Labels,
+) -> Result<Option<ServiceAccount>> {
+    if !object.service_account_enabled() {
+        return Ok(None);
+    }
+
+    Ok(Some(ServiceAccount {
+        metadata: ObjectMeta {
+            name: Some(object.service_account_name().into()),
+            annotations: Some(annotations.clone().into()),
+            labels: Some(labels.clone().into()),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        ..Default::default()
+    }))
+}
+
+fn generate_metrics_service(
+    object: &OnionBalance,
+    annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+) -> Result<Option<Service>> {
+    let Some(metrics) = object.metrics().filter(|f| f.service_enabled()) else {
+        return Ok(None);
+    };
+
+    Ok(Some(Service {
+        metadata: ObjectMeta {
+            name: Some(object.metrics_service_name().into()),
+            annotations: Some(
+                annotations
+                    .clone()
+                    .append_reverse(object.metrics_service_annotations())
+                    .into(),
+            ),
+            labels: Some(
+                labels
+                    .clone()
+                    .append_reverse(object.metrics_service_labels())
+                    .into(),
+            ),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        spec: Some(ServiceSpec {
+            ports: Some(vec![ServicePort {
+                name: Some("metrics".to_string()),
+                port: metrics.port(),
+                protocol: Some("TCP".to_string()),
+                target_port: Some(IntOrString::String("metrics".to_string())),
+                ..Default::default()
+            }]),
+            selector: Some(selector_labels.into()),
+            type_: Some("ClusterIP".to_string()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }))
+}
+
+fn generate_service_monitor(
+    object: &OnionBalance,
+    annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+) -> Result<Option<ServiceMonitor>> {
+    if object
+        .metrics()
+        .and_then(|f| f.service_monitor.as_ref())
+        .is_none()
+    {
+        return Ok(None);
+    }
+
+    Ok(Some(ServiceMonitor {
+        metadata: ObjectMeta {
+            name: Some(object.metrics_service_monitor_name().into()),
+            annotations: Some(
+                annotations
+                    .clone()
+                    .append_reverse(object.metrics_service_monitor_annotations())
+                    .into(),
+            ),
+            labels: Some(
+                labels
+                    .clone()
+                    .append_reverse(object.metrics_service_monitor_labels())
+                    .into(),
+            ),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        spec: ServiceMonitorSpec {
+            endpoints: vec![ServiceMonitorEndpoint {
+                interval: object.metrics_service_monitor_interval(),
+                port: "metrics".to_string(),
+            }],
+            selector: LabelSelector {
+                match_labels: Some(selector_labels.into()),
+                ..Default::default()
+            },
+        },
+    }))
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::onion_service::{
+        OnionServiceSpec, OnionServiceSpecOnionBalance, OnionServiceSpecOnionBalanceOnionKey,
+    };
+
     use super::*;
 
+    fn backends(hostnames: &[&str]) -> Vec<OnionBalanceStatusBackend> {
+        hostnames
+            .iter()
+            .map(|hostname| OnionBalanceStatusBackend {
+                hostname: Some((*hostname).into()),
+                name: None,
+                ready: true,
+                reason: None,
+            })
+            .collect()
+    }
+
     #[test]
-    fn config() {
-        let object = OnionBalance {
-            spec: OnionBalanceSpec {
-                onion_services: vec![
-                    OnionBalanceSpecOnionService {
-                        onion_key: OnionBalanceSpecOnionServiceOnionKey {
-                            hostname: "hostname1.onion".into(),
-                        },
-                    },
-                    OnionBalanceSpecOnionService {
-                        onion_key: OnionBalanceSpecOnionServiceOnionKey {
-                            hostname: "hostname2.onion".into(),
-                        },
-                    },
-                    OnionBalanceSpecOnionService {
-                        onion_key: OnionBalanceSpecOnionServiceOnionKey {
-                            hostname: "hostname3.onion".into(),
-                        },
+    fn template_annotations() {
+        let mut object = OnionBalance::default();
+        let torrc = generate_torrc(&object);
+        let healthy = generate_config_yaml(&[backends(&["a.onion", "b.onion"])]);
+        let unhealthy = generate_config_yaml(&[backends(&["a.onion"])]);
+
+        for reload_strategy in [TorrcReloadStrategy::Restart, TorrcReloadStrategy::Signal] {
+            object.spec.torrc = Some(KubernetesTorrc {
+                reload_strategy: Some(reload_strategy),
+                ..Default::default()
+            });
+
+            let (healthy_annotations, healthy_template_annotations) =
+                generate_annotations(&object, &healthy, None, &torrc);
+            let (unhealthy_annotations, unhealthy_template_annotations) =
+                generate_annotations(&object, &unhealthy, None, &torrc);
+
+            assert_ne!(*healthy_annotations, *unhealthy_annotations);
+            assert_eq!(
+                *healthy_template_annotations,
+                *unhealthy_template_annotations
+            );
+        }
+    }
+
+    #[test]
+    fn backend_misconfigured() {
+        let frontend = Hostname::new("frontend.onion".into());
+
+        let onion_service = |hostname: Option<&str>| OnionService {
+            spec: OnionServiceSpec {
+                onion_balance: hostname.map(|hostname| OnionServiceSpecOnionBalance {
+                    onion_key: OnionServiceSpecOnionBalanceOnionKey {
+                        hostname: hostname.into(),
                     },
-                ],
+                }),
                 ..Default::default()
             },
             ..Default::default()
         };
 
-        let config_yaml = generate_config_yaml(&object);
+        assert!(belongs_to(&onion_service(None), None));
+        assert!(belongs_to(
+            &onion_service(Some("frontend.onion")),
+            Some(&frontend)
+        ));
+        assert!(!belongs_to(&onion_service(None), Some(&frontend)));
+        assert!(!belongs_to(
+            &onion_service(Some("other.onion")),
+            Some(&frontend)
+        ));
+
+        let condition = generate_backend_misconfigured_condition(&[backends(&["a.onion"])], None);
+        assert_eq!("False", condition.status);
+
+        let condition = generate_backend_misconfigured_condition(
+            &[vec![
+                OnionBalanceStatusBackend {
+                    hostname: Some("a.onion".into()),
+                    name: Some("a".into()),
+                    ready: false,
+                    reason: Some("Misconfigured".into()),
+                },
+                OnionBalanceStatusBackend {
+                    hostname: Some("b.onion".into()),
+                    name: Some("b".into()),
+                    ready: false,
+                    reason: Some("DeploymentUnavailable".into()),
+                },
+            ]],
+            None,
+        );
+        assert_eq!("True", condition.status);
+        assert_eq!(
+            "The OnionServices a are not instances of the OnionBalance.",
+            condition.message
+        );
+    }
+
+    #[test]
+    fn config() {
+        let object = OnionBalance::default();
+
+        let config_yaml = generate_config_yaml(&[backends(&[
+            "hostname1.onion",
+            "hostname2.onion",
+            "hostname3.onion",
+        ])]);
 
         assert_eq!(
             r"services:
@@ -1096,8 +2491,301 @@ mod tests {
 
         assert_eq!(
             r"DataDirectory ${TOR_TMP_DIR}/home/.tor
-ControlPort 127.0.0.1:6666",
+ControlPort 127.0.0.1:6666
+ShutdownWaitLength 10",
             torrc.to_string()
         );
     }
+
+    #[test]
+    fn config_services() {
+        let config_yaml = generate_config_yaml(&[
+            backends(&["hostname1.onion"]),
+            backends(&["hostname2.onion"]),
+            backends(&[]),
+        ]);
+
+        assert_eq!(
+            r"services:
+- instances:
+  - address: hostname1.onion
+    name: hostname1.onion
+  key: ${TOR_TMP_DIR}/var/lib/tor/hidden_service/hs_ed25519_secret_key
+- instances:
+  - address: hostname2.onion
+    name: hostname2.onion
+  key: ${TOR_TMP_DIR}/var/lib/tor/hidden_service_1/hs_ed25519_secret_key
+- instances: []
+  key: ${TOR_TMP_DIR}/var/lib/tor/hidden_service_2/hs_ed25519_secret_key
+",
+            config_yaml.to_string()
+        );
+    }
+
+    #[test]
+    fn config_instances() {
+        let config_yaml = generate_config_yaml(&[vec![
+            OnionBalanceStatusBackend {
+                hostname: Some("hostname1.onion".into()),
+                name: Some("onion-service-1".into()),
+                ready: true,
+                reason: None,
+            },
+            OnionBalanceStatusBackend {
+                hostname: Some("hostname2.onion".into()),
+                name: Some("onion-service-2".into()),
+                ready: false,
+                reason: Some("DeploymentUnavailable".into()),
+            },
+            OnionBalanceStatusBackend {
+                hostname: None,
+                name: Some("onion-service-3".into()),
+                ready: false,
+                reason: Some("HostnameNotFound".into()),
+            },
+        ]]);
+
+        assert_eq!(
+            r"services:
+- instances:
+  - address: hostname1.onion
+    name: onion-service-1
+  key: ${TOR_TMP_DIR}/var/lib/tor/hidden_service/hs_ed25519_secret_key
+",
+            config_yaml.to_string()
+        );
+    }
+
+    #[test]
+    fn high_availability() {
+        let object = &OnionBalance {
+            metadata: ObjectMeta {
+                name: Some("example".into()),
+                ..Default::default()
+            },
+            spec: OnionBalanceSpec {
+                high_availability: Some(OnionBalanceSpecHighAvailability {
+                    lease_duration_seconds: None,
+                    replicas: 2,
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert_eq!(2, object.high_availability_replicas());
+        assert_eq!(15, object.high_availability_lease_duration_seconds());
+        assert_eq!(None, object.deployment_strategy());
+        assert_eq!(
+            Some("Recreate"),
+            OnionBalance::default()
+                .deployment_strategy()
+                .and_then(|f| f.type_)
+                .as_deref()
+        );
+
+        let containers = generate_deployment_containers(
+            object,
+            &Config {
+                native: false,
+                onion_balance_image: ImageConfig {
+                    pull_policy: "IfNotPresent".into(),
+                    uri: "onion-balance".into(),
+                },
+                tor_image: ImageConfig {
+                    pull_policy: "IfNotPresent".into(),
+                    uri: "tor".into(),
+                },
+            },
+        );
+        let onion_balance = containers
+            .iter()
+            .find(|f| f.name == "onionbalance")
+            .unwrap();
+        let args = onion_balance.args.as_ref().unwrap();
+
+        assert!(
+            args.windows(2)
+                .any(|f| f == ["--leader-election-lease", "example"])
+        );
+        assert!(args.iter().any(|f| f == "--leader-election-exclusive"));
+        assert!(args.iter().any(|f| f == "--leader-election-record-uploads"));
+        assert!(
+            onion_balance
+                .env
+                .as_ref()
+                .unwrap()
+                .iter()
+                .any(|f| f.name == "POD_NAME")
+        );
+    }
+
+    #[test]
+    fn onion_balance() {
+        let config = Config {
+            native: false,
+            onion_balance_image: ImageConfig {
+                pull_policy: "IfNotPresent".into(),
+                uri: "onion-balance".into(),
+            },
+            tor_image: ImageConfig {
+                pull_policy: "IfNotPresent".into(),
+                uri: "tor".into(),
+            },
+        };
+
+        let container = |object: &OnionBalance| {
+            generate_deployment_containers(object, &config)
+                .into_iter()
+                .find(|f| f.name == "onionbalance")
+                .unwrap()
+        };
+
+        let container_default = container(&OnionBalance::default());
+        let args = container_default.args.unwrap();
+        assert!(args.windows(2).any(|f| f == ["-v", "info"]));
+        assert_eq!(None, container_default.env);
+
+        let container_configured = container(&OnionBalance {
+            spec: OnionBalanceSpec {
+                onion_balance: Some(OnionBalanceSpecOnionBalance {
+                    fetch_descriptor_frequency_seconds: Some(60),
+                    log_level: Some(OnionBalanceSpecOnionBalanceLogLevel::Debug),
+                    publish_descriptor_check_frequency_seconds: None,
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let args = container_configured.args.unwrap();
+        assert!(args.windows(2).any(|f| f == ["-v", "debug"]));
+        assert_eq!(
+            vec![(
+                "ONIONBALANCE_FETCH_DESCRIPTOR_FREQUENCY".to_string(),
+                Some("60".to_string())
+            )],
+            container_configured
+                .env
+                .unwrap()
+                .into_iter()
+                .map(|f| (f.name, f.value))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn onion_balance_native() {
+        let container = generate_deployment_containers(
+            &OnionBalance::default(),
+            &Config {
+                native: true,
+                onion_balance_image: ImageConfig {
+                    pull_policy: "IfNotPresent".into(),
+                    uri: "onion-balance".into(),
+                },
+                tor_image: ImageConfig {
+                    pull_policy: "IfNotPresent".into(),
+                    uri: "tor".into(),
+                },
+            },
+        )
+        .into_iter()
+        .find(|f| f.name == "onionbalance")
+        .unwrap();
+
+        assert_eq!(Some("tor"), container.image.as_deref());
+        let args = container.args.unwrap();
+        assert!(
+            args.windows(4)
+                .any(|f| f == ["--", "tor-operator", "onion-balance", "run"])
+        );
+        assert!(!args.iter().any(|f| f == "onionbalance"));
+    }
+
+    #[test]
+    fn selects_onion_service() {
+        let object = OnionBalance {
+            metadata: ObjectMeta {
+                name: Some("example".into()),
+                namespace: Some("default".into()),
+                ..Default::default()
+            },
+            spec: OnionBalanceSpec {
+                onion_service_selector: Some(LabelSelector {
+                    match_labels: Some(BTreeMap::from([("app".into(), "example".into())])),
+                    ..Default::default()
+                }),
+                onion_services: vec![OnionBalanceSpecOnionService {
+                    onion_key: None,
+                    onion_service_ref: Some(OnionBalanceSpecOnionServiceRef {
+                        name: "referenced".into(),
+                    }),
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let onion_service = |name: &str, namespace: &str, labels: &[(&str, &str)]| OnionService {
+            metadata: ObjectMeta {
+                name: Some(name.into()),
+                namespace: Some(namespace.into()),
+                labels: Some(
+                    labels
+                        .iter()
+                        .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+                        .collect(),
+                ),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert!(object.selects_onion_service(&onion_service("referenced", "default", &[])));
+        assert!(object.selects_onion_service(&onion_service(
+            "selected",
+            "default",
+            &[("app", "example")]
+        )));
+        assert!(!object.selects_onion_service(&onion_service("other", "default", &[])));
+        assert!(!object.selects_onion_service(&onion_service(
+            "selected",
+            "other",
+            &[("app", "example")]
+        )));
+    }
+
+    #[test]
+    fn backend() {
+        let onion_service = OnionService::default();
+
+        let deployment = |status: &str| Deployment {
+            status: Some(k8s_openapi::api::apps::v1::DeploymentStatus {
+                conditions: Some(vec![k8s_openapi::api::apps::v1::DeploymentCondition {
+                    status: status.into(),
+                    type_: "Available".into(),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Some("DeploymentNotFound"),
+            backend_reason(&onion_service, None, None)
+        );
+        assert_eq!(
+            Some("DeploymentUnavailable"),
+            backend_reason(&onion_service, Some(&Deployment::default()), None)
+        );
+        assert_eq!(
+            Some("DeploymentUnavailable"),
+            backend_reason(&onion_service, Some(&deployment("False")), None)
+        );
+        assert_eq!(
+            None,
+            backend_reason(&onion_service, Some(&deployment("True")), None)
+        );
+    }
 }
@@ -3,7 +3,7 @@ use std::{collections::BTreeMap, sync::Arc, time::Duration};
 use futures::StreamExt;
 use k8s_openapi::{
     ByteString,
-    api::core::v1::Secret,
+    api::core::v1::{KeyToPath, Secret, SecretVolumeSource},
     apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition,
     apimachinery::pkg::apis::meta::v1::{Condition, Time},
     jiff::Timestamp,
@@ -179,6 +179,26 @@ impl OnionKey {
         ResourceName::from(&self.spec.secret.name)
     }
 
+    /// Volume source mounting the `OnionKey` Secret as a Tor hidden service
+    /// directory.
+    #[must_use]
+    pub fn secret_volume_source(&self) -> SecretVolumeSource {
+        SecretVolumeSource {
+            default_mode: Some(0o400),
+            items: Some(
+                ["hostname", "hs_ed25519_public_key", "hs_ed25519_secret_key"]
+                    .map(|key| KeyToPath {
+                        key: key.into(),
+                        mode: Some(0o400),
+                        path: key.into(),
+                    })
+                    .into(),
+            ),
+            optional: Some(false),
+            secret_name: Some(self.secret_name().into()),
+        }
+    }
+
     #[must_use]
     pub fn status_conditions(&self) -> Option<&Vec<Condition>> {
         self.status.as_ref().map(|f| f.conditions.as_ref())
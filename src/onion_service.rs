@@ -1,24 +1,44 @@
-use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    time::Duration,
+};
 
 use futures::StreamExt;
 use k8s_openapi::{
+    ByteString,
     api::{
-        apps::v1::{Deployment, DeploymentSpec},
+        apps::v1::{
+            Deployment, DeploymentSpec, DeploymentStrategy, StatefulSet,
+            StatefulSetPersistentVolumeClaimRetentionPolicy, StatefulSetSpec,
+        },
+        coordination::v1::{Lease, LeaseSpec},
         core::v1::{
-            Affinity, Capabilities, ConfigMap, ConfigMapVolumeSource, Container, ExecAction,
-            KeyToPath, LocalObjectReference, PodSecurityContext, PodSpec, PodTemplateSpec, Probe,
-            SecretVolumeSource, SecurityContext, Toleration, TopologySpreadConstraint, Volume,
+            Affinity, ConfigMap, ConfigMapVolumeSource, Container, EnvVarSource, KeyToPath,
+            LocalObjectReference, ObjectFieldSelector, PersistentVolumeClaim,
+            PersistentVolumeClaimSpec, Pod, PodSecurityContext, PodSpec, PodTemplateSpec,
+            ResourceRequirements, Secret, SecretVolumeSource, Service, ServiceAccount, ServicePort,
+            ServiceSpec, Toleration, TopologySpreadConstraint, Volume, VolumeMount,
+            VolumeResourceRequirements,
         },
+        policy::v1::{PodDisruptionBudget, PodDisruptionBudgetSpec},
+        rbac::v1::{PolicyRule, Role, RoleBinding, RoleRef, Subject},
     },
     apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition,
-    apimachinery::pkg::apis::meta::v1::{Condition, LabelSelector, Time},
-    jiff::Timestamp,
+    apimachinery::pkg::{
+        api::resource::Quantity,
+        apis::meta::v1::{Condition, LabelSelector, Time},
+        util::intstr::IntOrString,
+    },
+    jiff::{SignedDuration, Timestamp, tz::TimeZone},
 };
 use kube::{
     Client, CustomResource, CustomResourceExt, Resource,
+    api::DeleteParams,
     core::ObjectMeta,
     runtime::{Controller, controller::Action, watcher::Config as WatcherConfig},
 };
+use rcgen::{CertificateParams, DnType, ExtendedKeyUsagePurpose, Issuer, KeyPair, date_time_ymd};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -26,15 +46,35 @@ use crate::{
     Error, Result,
     collections::vec_get_or_insert,
     kubernetes::{
-        self, Annotations, Api, ConditionsExt, ErrorBackoff, Labels, Object,
-        Resource as KubernetesResource, ResourceName, SelectorLabels, Subset,
-        Torrc as KubernetesTorrc, error_policy, pod_security_context,
+        self, Annotations, Api, Certificate, CertificateIssuerRef, CertificateSpec, ConditionsExt,
+        ErrorBackoff, Labels, Object, PodDisruptionBudget as KubernetesPodDisruptionBudget,
+        PodDisruptionBudgetExt, Resource as KubernetesResource, ResourceName, SelectorLabels,
+        ServiceMonitor, ServiceMonitorEndpoint, ServiceMonitorSpec, Subset,
+        TOR_AGABANI_CO_UK_HOSTNAME_KEY, TOR_AGABANI_CO_UK_NOT_AFTER_KEY, TorMetrics, TorMetricsExt,
+        Torrc as KubernetesTorrc, TorrcReloadStrategy, container_security_context, error_policy,
+        liveness_probe, pod_security_context, readiness_probe, startup_probe,
     },
     metrics::Metrics,
     onion_key::OnionKey,
     tor::{Hostname, OBConfig, Torrc},
 };
 
+/// First port the fallback accepts the connections of Tor on.
+const FALLBACK_PORT: usize = 19000;
+
+/// First port the gateway accepts the connections of Tor on.
+const GATEWAY_PORT: usize = 17000;
+
+/// Lifetime of the certificates issued by the Tor Operator.
+const TLS_CERTIFICATE_DURATION: SignedDuration = SignedDuration::from_hours(90 * 24);
+
+/// How long before expiry the certificates issued by the Tor Operator are
+/// renewed.
+const TLS_CERTIFICATE_RENEW_BEFORE: SignedDuration = SignedDuration::from_hours(30 * 24);
+
+/// Address the TLS terminator accepts the connections of Tor on.
+const TLS_LISTEN: &str = "127.0.0.1:18443";
+
 /*
  * ============================================================================
  * Custom Resource Definition
@@ -58,6 +98,7 @@ use crate::{
     printcolumn = r#"{"name":"Hostname", "type":"string", "description":"The hostname of the OnionService", "jsonPath":".status.hostname"}"#,
     printcolumn = r#"{"name":"OnionBalance Hostname", "type":"string", "description":"The hostname of the OnionBalance", "jsonPath":".spec.onionBalance.onionKey.hostname"}"#,
     printcolumn = r#"{"name":"State", "type":"string", "description":"Human readable description of state", "jsonPath":".status.summary.Initialized"}"#,
+    printcolumn = r#"{"name":"Leader", "type":"string", "description":"The pod running the Onion Service", "jsonPath":".status.highAvailability.leader", "priority":1}"#,
     printcolumn = r#"{"name":"Age", "type":"date", "jsonPath":".metadata.creationTimestamp"}"#,
     status = "OnionServiceStatus",
     version = "v1"
@@ -68,21 +109,122 @@ pub struct OnionServiceSpec {
     pub config_map: Option<OnionServiceSpecConfigMap>,
 
     /// Deployment settings.
+    ///
+    /// The settings apply to the Stateful Set when persistence is enabled.
     pub deployment: Option<OnionServiceSpecDeployment>,
 
+    /// Ephemeral Onion Service.
+    ///
+    /// When true, the key is generated in memory by Tor and loaded with
+    /// `ADD_ONION`, never being stored in a Secret. The hostname changes every
+    /// time Tor restarts.
+    ///
+    /// Ephemeral Onion Services do not support `onionBalance`, `onionKey` and
+    /// `highAvailability`, and are reported as misconfigured when they are set.
+    ///
+    /// Default: false
+    pub ephemeral: Option<bool>,
+
+    /// Export the circuit ID of each connection to the targets, in a PROXY
+    /// protocol header, so that they can tell Tor clients apart.
+    ///
+    /// Not supported by ephemeral Onion Services. Without `gateway`, `tls`
+    /// and port `fallback` cannot read the PROXY protocol header, and are
+    /// reported as misconfigured when they are set.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub export_circuit_id: Option<OnionServiceSpecExportCircuitId>,
+
+    /// Gateway settings.
+    ///
+    /// When set, a gateway sidecar receives the connections of Tor, enforces
+    /// per-circuit connection and rate limits, logs every connection with its
+    /// circuit ID and forwards it to the target without the PROXY protocol
+    /// header. Implies `exportCircuitId: Haproxy`.
+    ///
+    /// Not supported by ephemeral Onion Services.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub gateway: Option<OnionServiceSpecGateway>,
+
+    /// High availability settings.
+    ///
+    /// When set, the `OnionService` runs several replicas that elect a leader
+    /// through a Lease. Only the leader runs the Onion Service, the standbys
+    /// keep Tor bootstrapped, ready to take over.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub high_availability: Option<OnionServiceSpecHighAvailability>,
+
+    /// Injection settings.
+    ///
+    /// When set, no Deployment is created. Instead Tor is injected as a
+    /// sidecar into the Pods labelled, or annotated, with
+    /// `tor.agabani.co.uk/onion-service: <name>`, so that the targets can be
+    /// reached without a cluster network hop, for example over a Unix socket
+    /// such as `unix:/run/app.sock`. The gateway and TLS sidecars are injected
+    /// alongside Tor, and only forward to TCP targets.
+    ///
+    /// Not supported by ephemeral Onion Services, and not supported together
+    /// with `highAvailability`, `persistence` and `fallback`.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub injection: Option<OnionServiceSpecInjection>,
+
+    /// Tor metrics settings.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub metrics: Option<TorMetrics>,
+
     /// `OnionBalance` the `OnionService` belongs to.
     ///
     /// Default: nil / none / null / undefined.
     pub onion_balance: Option<OnionServiceSpecOnionBalance>,
 
     /// `OnionKey` settings.
-    pub onion_key: OnionServiceSpecOnionKey,
+    ///
+    /// Required unless `ephemeral` is true.
+    pub onion_key: Option<OnionServiceSpecOnionKey>,
+
+    /// Persistence settings.
+    ///
+    /// When set, the `OnionService` runs as a Stateful Set that keeps the Tor
+    /// data directory on a Persistent Volume Claim, preserving guard state and
+    /// the cached consensus across restarts.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub persistence: Option<OnionServiceSpecPersistence>,
+
+    /// Pod Disruption Budget settings.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub pod_disruption_budget: Option<KubernetesPodDisruptionBudget>,
 
     /// Onion Service Hidden Service ports.
     pub ports: Vec<OnionServiceSpecHiddenServicePort>,
 
+    /// TLS settings.
+    ///
+    /// When set, a certificate is issued for the hostname of the Onion Service
+    /// and a TLS terminating sidecar accepts the connections to virtual port
+    /// 443, forwarding them to the plaintext target. The certificate is
+    /// renewed before it expires.
+    ///
+    /// Not supported by ephemeral Onion Services.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub tls: Option<OnionServiceSpecTls>,
+
     /// Tor torrc settings.
     pub torrc: Option<KubernetesTorrc>,
+
+    /// Time to live, after which the `OnionService` is deleted.
+    ///
+    /// Example: 72h, 1h30m
+    ///
+    /// Default: nil / none / null / undefined.
+    #[schemars(regex(pattern = r"^([0-9]+[hms])+$"))]
+    pub ttl: Option<String>,
 }
 
 #[allow(clippy::module_name_repetitions)]
@@ -112,6 +254,9 @@ pub struct OnionServiceSpecDeployment {
     pub annotations: Option<BTreeMap<String, String>>,
 
     /// Containers of the Deployment.
+    ///
+    /// The `tor` container runs the Tor image of the operator, unless it sets
+    /// its own image.
     pub containers: Option<Vec<Container>>,
 
     /// `ImagePullSecrets` is an optional list of references to secrets in the same namespace to use for pulling any of the images used by this `PodSpec`. If specified, these secrets will be passed to individual puller implementations for them to use. More info: <https://kubernetes.io/docs/concepts/containers/images#specifying-imagepullsecrets-on-a-pod>
@@ -123,7 +268,7 @@ pub struct OnionServiceSpecDeployment {
     /// Map of string keys and values that can be used to organize and categorize (scope and select) objects. May match selectors of replication controllers and services. More info: <http://kubernetes.io/docs/user-guide/labels>
     pub labels: Option<BTreeMap<String, String>>,
 
-    /// Name of the Deployment.
+    /// Name of the Deployment, or of the Stateful Set when persistence is enabled.
     ///
     /// Default: name of the `OnionService`
     pub name: Option<String>,
@@ -134,6 +279,11 @@ pub struct OnionServiceSpecDeployment {
     /// `SecurityContext` holds pod-level security attributes and common container settings. Optional: Defaults to empty.  See type description for default values of each field.
     pub security_context: Option<PodSecurityContext>,
 
+    /// The deployment strategy to use to replace existing pods with new ones.
+    ///
+    /// Default: Recreate, unless `highAvailability` or `ephemeral` is set
+    pub strategy: Option<DeploymentStrategy>,
+
     /// If specified, the pod's tolerations.
     pub tolerations: Option<Vec<Toleration>>,
 
@@ -144,6 +294,65 @@ pub struct OnionServiceSpecDeployment {
     pub volumes: Option<Vec<Volume>>,
 }
 
+/// Protocol used to export the circuit ID.
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum OnionServiceSpecExportCircuitId {
+    /// `HAProxy` PROXY protocol version 1, a text line such as
+    /// `PROXY TCP6 fc00:dead:beef:4dad::12:3456 ::1 13398 80`, where the last
+    /// 32 bits of the source address are the circuit ID.
+    Haproxy,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionServiceSpecGateway {
+    /// Maximum number of concurrent connections per circuit.
+    ///
+    /// Default: unlimited
+    #[schemars(range(min = 1))]
+    pub connection_limit: Option<i32>,
+
+    /// Maximum number of new connections per circuit per minute.
+    ///
+    /// Default: unlimited
+    #[schemars(range(min = 1))]
+    pub rate_limit: Option<i32>,
+
+    /// Resources of the container.
+    pub resources: Option<ResourceRequirements>,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionServiceSpecHighAvailability {
+    /// Duration a standby waits for an unrenewed Lease before taking over.
+    ///
+    /// A leader which cannot renew the Lease steps down after two thirds of
+    /// this duration, before a standby can take over.
+    ///
+    /// Default: 15
+    pub lease_duration_seconds: Option<i32>,
+
+    /// Number of replicas, one leader and the rest standbys.
+    #[schemars(range(min = 1))]
+    pub replicas: i32,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionServiceSpecInjection {
+    /// Resources of the injected Tor container.
+    pub resources: Option<ResourceRequirements>,
+
+    /// Volume mounts of the injected Tor container, such as the volume of the
+    /// Pod holding the Unix sockets of the targets.
+    pub volume_mounts: Option<Vec<VolumeMount>>,
+}
+
 #[allow(clippy::module_name_repetitions)]
 #[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
@@ -170,13 +379,50 @@ pub struct OnionServiceSpecOnionKey {
     pub name: String,
 }
 
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionServiceSpecPersistence {
+    /// `accessModes` contains the desired access modes the volume should have. More info: <https://kubernetes.io/docs/concepts/storage/persistent-volumes#access-modes-1>
+    ///
+    /// Default: `ReadWriteOnce`
+    pub access_modes: Option<Vec<String>>,
+
+    /// Annotations is an unstructured key value map stored with a resource that may be set by external tools to store and retrieve arbitrary metadata. They are not queryable and should be preserved when modifying objects. More info: <http://kubernetes.io/docs/user-guide/annotations>
+    pub annotations: Option<BTreeMap<String, String>>,
+
+    /// Map of string keys and values that can be used to organize and categorize (scope and select) objects. May match selectors of replication controllers and services. More info: <http://kubernetes.io/docs/user-guide/labels>
+    pub labels: Option<BTreeMap<String, String>>,
+
+    /// Storage requested for the Tor data directory.
+    ///
+    /// Default: 256Mi
+    pub size: Option<Quantity>,
+
+    /// `storageClassName` is the name of the `StorageClass` required by the claim. More info: <https://kubernetes.io/docs/concepts/storage/persistent-volumes#class-1>
+    ///
+    /// Default: the default `StorageClass` of the cluster
+    pub storage_class_name: Option<String>,
+}
+
 #[allow(clippy::module_name_repetitions)]
 #[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct OnionServiceSpecHiddenServicePort {
+    /// Maintenance fallback settings.
+    ///
+    /// When set, the connections are answered with a maintenance response
+    /// while the Service of the target has no ready endpoints.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub fallback: Option<OnionServiceSpecHiddenServicePortFallback>,
+
     /// The target any incoming traffic will be redirect to.
     ///
     /// Example: example.default.svc.cluster.local:80
+    ///
+    /// Unix sockets are targeted as `unix:/run/app.sock`, when Tor is
+    /// injected into the Pod.
     pub target: String,
 
     /// The virtual port that the Onion Service will be using.
@@ -185,6 +431,109 @@ pub struct OnionServiceSpecHiddenServicePort {
     pub virtport: i32,
 }
 
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionServiceSpecHiddenServicePortFallback {
+    /// Body of the maintenance response.
+    ///
+    /// Default: a built-in maintenance page
+    pub body: Option<String>,
+
+    /// Content type of the maintenance response.
+    ///
+    /// Default: text/html; charset=utf-8
+    pub content_type: Option<String>,
+
+    /// Name of the Service whose `EndpointSlices` decide whether the target is
+    /// available. The Service must be in the namespace of the `OnionService`.
+    ///
+    /// Default: first label of the target host
+    pub service: Option<String>,
+
+    /// HTTP status of the maintenance response.
+    ///
+    /// Default: 503
+    pub status: Option<i32>,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionServiceSpecTls {
+    /// Name of the Secret holding the CA certificate (`tls.crt`) and private
+    /// key (`tls.key`) the certificate is signed with.
+    ///
+    /// Default: nil / none / null / undefined, the certificate is self-signed.
+    pub ca_secret_name: Option<String>,
+
+    /// Issue the certificate through a cert-manager `Certificate` instead.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub cert_manager: Option<OnionServiceSpecTlsCertManager>,
+
+    /// Resources of the container.
+    pub resources: Option<ResourceRequirements>,
+
+    /// Name of the Secret the certificate is stored in.
+    ///
+    /// Default: name of the `OnionService` suffixed with `-tls`
+    pub secret_name: Option<String>,
+
+    /// The plaintext target the connections to virtual port 443 will be
+    /// forwarded to.
+    ///
+    /// Example: example.default.svc.cluster.local:80
+    pub target: String,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionServiceSpecTlsCertManager {
+    /// Annotations is an unstructured key value map stored with a resource that may be set by external tools to store and retrieve arbitrary metadata. They are not queryable and should be preserved when modifying objects. More info: <http://kubernetes.io/docs/user-guide/annotations>
+    pub annotations: Option<BTreeMap<String, String>>,
+
+    /// Requested lifetime of the certificate.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub duration: Option<String>,
+
+    /// Reference to the issuer of the certificate.
+    pub issuer_ref: OnionServiceSpecTlsCertManagerIssuerRef,
+
+    /// Map of string keys and values that can be used to organize and categorize (scope and select) objects. May match selectors of replication controllers and services. More info: <http://kubernetes.io/docs/user-guide/labels>
+    pub labels: Option<BTreeMap<String, String>>,
+
+    /// Name of the Certificate.
+    ///
+    /// Default: name of the `OnionService`
+    pub name: Option<String>,
+
+    /// How long before expiry the certificate is renewed.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub renew_before: Option<String>,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionServiceSpecTlsCertManagerIssuerRef {
+    /// Group of the issuer.
+    ///
+    /// Default: cert-manager.io
+    pub group: Option<String>,
+
+    /// Kind of the issuer.
+    ///
+    /// Default: Issuer
+    pub kind: Option<String>,
+
+    /// Name of the issuer.
+    pub name: String,
+}
+
 #[allow(clippy::module_name_repetitions)]
 #[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
@@ -195,13 +544,26 @@ pub struct OnionServiceStatus {
     ///
     /// `Initialized`
     ///
+    /// ### Misconfigured
+    ///
+    /// `Configured`, `EphemeralUnsupported`, `ExportCircuitIdUnsupported`,
+    /// `TtlMalformed`
+    ///
     /// ### `OnionKey`
     ///
     /// `NotFound`, `HostnameNotFound`, `Ready`
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub conditions: Vec<Condition>,
 
-    /// `OnionKey` hostname.
+    /// Time the `OnionService` is deleted, when `ttl` is set.
+    pub expires_at: Option<Time>,
+
+    /// High availability observations.
+    ///
+    /// Only populated when high availability is enabled.
+    pub high_availability: Option<OnionServiceStatusHighAvailability>,
+
+    /// `OnionKey` hostname, or the hostname of the ephemeral Onion Service.
     ///
     /// The hostname is only populated once `state` is "running".
     pub hostname: Option<String>,
@@ -211,6 +573,17 @@ pub struct OnionServiceStatus {
     pub summary: BTreeMap<String, String>,
 }
 
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionServiceStatusHighAvailability {
+    /// Number of times leadership moved from one pod to another.
+    pub failovers: i32,
+
+    /// Name of the pod running the Onion Service.
+    pub leader: Option<String>,
+}
+
 impl OnionService {
     #[must_use]
     fn default_name(&self) -> ResourceName {
@@ -335,6 +708,28 @@ impl OnionService {
         )
     }
 
+    #[must_use]
+    pub fn deployment_strategy(&self) -> Option<DeploymentStrategy> {
+        self.spec
+            .deployment
+            .as_ref()
+            .and_then(|f| f.strategy.as_ref())
+            .cloned()
+            .or_else(|| {
+                (!self.high_availability_enabled() && !self.ephemeral()).then(|| {
+                    DeploymentStrategy {
+                        type_: Some("Recreate".into()),
+                        ..Default::default()
+                    }
+                })
+            })
+    }
+
+    #[must_use]
+    pub fn deployment_termination_grace_period_seconds(&self) -> i64 {
+        self.torrc_shutdown_wait_length_seconds() + 5
+    }
+
     #[must_use]
     pub fn deployment_tolerations(&self) -> Option<Vec<Toleration>> {
         self.spec
@@ -364,86 +759,474 @@ impl OnionService {
     }
 
     #[must_use]
-    pub fn onion_balanced(&self) -> bool {
-        self.spec.onion_balance.is_some()
+    pub fn ephemeral(&self) -> bool {
+        self.spec.ephemeral.unwrap_or_default()
     }
 
     #[must_use]
-    pub fn onion_balance_onion_key_hostname(&self) -> Option<Hostname> {
-        self.spec
-            .onion_balance
-            .as_ref()
-            .map(|onion_balance| onion_balance.onion_key.hostname.clone())
-            .map(Hostname::new)
+    pub fn expires_at(&self) -> Option<Timestamp> {
+        let creation_timestamp = self.meta().creation_timestamp.as_ref()?;
+        creation_timestamp.0.checked_add(self.ttl()?).ok()
     }
 
     #[must_use]
-    pub fn onion_key_name(&self) -> ResourceName {
-        ResourceName::from(&self.spec.onion_key.name)
+    pub fn export_circuit_id(&self) -> Option<OnionServiceSpecExportCircuitId> {
+        if self.ephemeral() {
+            return None;
+        }
+        self.spec.export_circuit_id.or_else(|| {
+            self.gateway_enabled()
+                .then_some(OnionServiceSpecExportCircuitId::Haproxy)
+        })
     }
 
     #[must_use]
-    pub fn ports(&self) -> &[OnionServiceSpecHiddenServicePort] {
-        &self.spec.ports
+    pub fn fallback_enabled(&self) -> bool {
+        self.ports().iter().any(|port| port.fallback.is_some()) && !self.injection_enabled()
     }
 
+    /// Address the fallback accepts the connections of Tor for a port on.
     #[must_use]
-    pub fn torrc_template(&self) -> Option<&str> {
-        self.spec
-            .torrc
-            .as_ref()
-            .and_then(|f| f.template.as_ref())
-            .map(String::as_str)
+    pub fn fallback_listen(&self, index: usize) -> String {
+        format!("127.0.0.1:{}", FALLBACK_PORT + index)
     }
 
     #[must_use]
-    pub fn status_conditions(&self) -> Option<&Vec<Condition>> {
-        self.status.as_ref().map(|f| f.conditions.as_ref())
+    pub fn gateway_connection_limit(&self) -> Option<i32> {
+        self.spec.gateway.as_ref().and_then(|f| f.connection_limit)
     }
-}
 
-impl KubernetesResource for OnionService {
-    type Spec = OnionServiceSpec;
+    #[must_use]
+    pub fn gateway_enabled(&self) -> bool {
+        self.spec.gateway.is_some() && !self.ephemeral()
+    }
 
-    fn spec(&self) -> &Self::Spec {
-        &self.spec
+    /// Address the gateway accepts the connections of Tor for a port on.
+    #[must_use]
+    pub fn gateway_listen(&self, index: usize) -> String {
+        format!("127.0.0.1:{}", GATEWAY_PORT + index)
     }
-}
 
-impl Object for OnionService {
-    const APP_KUBERNETES_IO_COMPONENT_VALUE: &'static str = "onion-service";
+    #[must_use]
+    pub fn gateway_rate_limit(&self) -> Option<i32> {
+        self.spec.gateway.as_ref().and_then(|f| f.rate_limit)
+    }
 
-    type Status = OnionServiceStatus;
+    #[must_use]
+    pub fn gateway_resources(&self) -> Option<ResourceRequirements> {
+        self.spec
+            .gateway
+            .as_ref()
+            .and_then(|f| f.resources.as_ref())
+            .cloned()
+    }
 
-    fn status(&self) -> Option<&Self::Status> {
-        self.status.as_ref()
+    /// The ports of the Onion Service, including the TLS port, with the
+    /// fallback in place of the targets it guards.
+    #[must_use]
+    pub fn hidden_service_ports(&self) -> Vec<OnionServiceSpecHiddenServicePort> {
+        let mut ports = self
+            .ports()
+            .iter()
+            .enumerate()
+            .map(|(index, port)| OnionServiceSpecHiddenServicePort {
+                fallback: None,
+                target: if port.fallback.is_some() && self.fallback_enabled() {
+                    self.fallback_listen(index)
+                } else {
+                    port.target.clone()
+                },
+                virtport: port.virtport,
+            })
+            .collect::<Vec<_>>();
+        if self.tls_enabled() {
+            ports.push(OnionServiceSpecHiddenServicePort {
+                fallback: None,
+                target: TLS_LISTEN.into(),
+                virtport: 443,
+            });
+        }
+        ports
     }
-}
 
-impl Subset for OnionServiceSpec {
-    fn is_subset(&self, superset: &Self) -> bool {
-        self == superset
+    #[must_use]
+    pub fn high_availability_enabled(&self) -> bool {
+        self.spec.high_availability.is_some() && !self.injection_enabled()
     }
-}
 
-#[must_use]
-pub fn generate_custom_resource_definition() -> CustomResourceDefinition {
-    OnionService::crd()
-}
+    #[must_use]
+    pub fn high_availability_lease_duration_seconds(&self) -> i32 {
+        self.spec
+            .high_availability
+            .as_ref()
+            .and_then(|f| f.lease_duration_seconds)
+            .unwrap_or(15)
+    }
 
-/*
- * ============================================================================
- * Config
- * ============================================================================
- */
-pub struct Config {
-    pub tor_image: ImageConfig,
-}
+    #[must_use]
+    pub fn high_availability_replicas(&self) -> i32 {
+        self.spec
+            .high_availability
+            .as_ref()
+            .map_or(1, |f| f.replicas)
+    }
 
-pub struct ImageConfig {
-    pub pull_policy: String,
-    pub uri: String,
-}
+    #[must_use]
+    pub fn injection_enabled(&self) -> bool {
+        self.spec.injection.is_some() && !self.ephemeral()
+    }
+
+    #[must_use]
+    pub fn injection_resources(&self) -> Option<ResourceRequirements> {
+        self.spec
+            .injection
+            .as_ref()
+            .and_then(|f| f.resources.as_ref())
+            .cloned()
+    }
+
+    #[must_use]
+    pub fn injection_volume_mounts(&self) -> Vec<VolumeMount> {
+        self.spec
+            .injection
+            .as_ref()
+            .and_then(|f| f.volume_mounts.as_ref())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn lease_name(&self) -> ResourceName {
+        self.default_name()
+    }
+
+    #[must_use]
+    pub fn onion_balanced(&self) -> bool {
+        self.spec.onion_balance.is_some()
+    }
+
+    #[must_use]
+    pub fn onion_balance_onion_key_hostname(&self) -> Option<Hostname> {
+        self.spec
+            .onion_balance
+            .as_ref()
+            .map(|onion_balance| onion_balance.onion_key.hostname.clone())
+            .map(Hostname::new)
+    }
+
+    #[must_use]
+    pub fn onion_key_name(&self) -> Option<ResourceName> {
+        self.spec
+            .onion_key
+            .as_ref()
+            .map(|f| ResourceName::from(&f.name))
+    }
+
+    #[must_use]
+    pub fn persistence_access_modes(&self) -> Vec<String> {
+        self.spec
+            .persistence
+            .as_ref()
+            .and_then(|f| f.access_modes.as_ref())
+            .cloned()
+            .unwrap_or_else(|| vec!["ReadWriteOnce".into()])
+    }
+
+    #[must_use]
+    pub fn persistence_annotations(&self) -> Option<Annotations> {
+        self.spec
+            .persistence
+            .as_ref()
+            .and_then(|f| f.annotations.as_ref())
+            .cloned()
+            .map(Into::into)
+    }
+
+    #[must_use]
+    pub fn persistence_enabled(&self) -> bool {
+        self.spec.persistence.is_some() && !self.injection_enabled()
+    }
+
+    #[must_use]
+    pub fn persistence_labels(&self) -> Option<Labels> {
+        self.spec
+            .persistence
+            .as_ref()
+            .and_then(|f| f.labels.as_ref())
+            .cloned()
+            .map(Into::into)
+    }
+
+    #[must_use]
+    pub fn persistence_size(&self) -> Quantity {
+        self.spec
+            .persistence
+            .as_ref()
+            .and_then(|f| f.size.as_ref())
+            .cloned()
+            .unwrap_or_else(|| Quantity("256Mi".into()))
+    }
+
+    #[must_use]
+    pub fn persistence_storage_class_name(&self) -> Option<String> {
+        self.spec
+            .persistence
+            .as_ref()
+            .and_then(|f| f.storage_class_name.clone())
+    }
+
+    #[must_use]
+    pub fn ports(&self) -> &[OnionServiceSpecHiddenServicePort] {
+        &self.spec.ports
+    }
+
+    #[must_use]
+    pub fn role_binding_name(&self) -> ResourceName {
+        self.default_name()
+    }
+
+    #[must_use]
+    pub fn role_name(&self) -> ResourceName {
+        self.default_name()
+    }
+
+    #[must_use]
+    pub fn service_account_enabled(&self) -> bool {
+        self.high_availability_enabled() || self.ephemeral() || self.fallback_enabled()
+    }
+
+    #[must_use]
+    pub fn service_account_name(&self) -> ResourceName {
+        self.default_name()
+    }
+
+    #[must_use]
+    pub fn tls_ca_secret_name(&self) -> Option<ResourceName> {
+        self.spec
+            .tls
+            .as_ref()
+            .and_then(|f| f.ca_secret_name.as_ref())
+            .map(Into::into)
+    }
+
+    #[must_use]
+    pub fn tls_certificate_annotations(&self) -> Option<Annotations> {
+        self.spec
+            .tls
+            .as_ref()
+            .and_then(|f| f.cert_manager.as_ref())
+            .and_then(|f| f.annotations.as_ref())
+            .cloned()
+            .map(Into::into)
+    }
+
+    #[must_use]
+    pub fn tls_certificate_duration(&self) -> Option<String> {
+        self.spec
+            .tls
+            .as_ref()
+            .and_then(|f| f.cert_manager.as_ref())
+            .and_then(|f| f.duration.clone())
+    }
+
+    /// Whether the certificate is issued by cert-manager.
+    #[must_use]
+    pub fn tls_certificate_enabled(&self) -> bool {
+        self.tls_enabled()
+            && self
+                .spec
+                .tls
+                .as_ref()
+                .is_some_and(|f| f.cert_manager.is_some())
+    }
+
+    #[must_use]
+    pub fn tls_certificate_issuer_ref(&self) -> Option<CertificateIssuerRef> {
+        self.spec
+            .tls
+            .as_ref()
+            .and_then(|f| f.cert_manager.as_ref())
+            .map(|f| CertificateIssuerRef {
+                group: f.issuer_ref.group.clone(),
+                kind: f.issuer_ref.kind.clone(),
+                name: f.issuer_ref.name.clone(),
+            })
+    }
+
+    #[must_use]
+    pub fn tls_certificate_labels(&self) -> Option<Labels> {
+        self.spec
+            .tls
+            .as_ref()
+            .and_then(|f| f.cert_manager.as_ref())
+            .and_then(|f| f.labels.as_ref())
+            .cloned()
+            .map(Into::into)
+    }
+
+    #[must_use]
+    pub fn tls_certificate_name(&self) -> ResourceName {
+        self.spec
+            .tls
+            .as_ref()
+            .and_then(|f| f.cert_manager.as_ref())
+            .and_then(|f| f.name.as_ref())
+            .map_or_else(|| self.default_name(), Into::into)
+    }
+
+    #[must_use]
+    pub fn tls_certificate_renew_before(&self) -> Option<String> {
+        self.spec
+            .tls
+            .as_ref()
+            .and_then(|f| f.cert_manager.as_ref())
+            .and_then(|f| f.renew_before.clone())
+    }
+
+    #[must_use]
+    pub fn tls_enabled(&self) -> bool {
+        self.spec.tls.is_some() && !self.ephemeral()
+    }
+
+    #[must_use]
+    pub fn tls_resources(&self) -> Option<ResourceRequirements> {
+        self.spec
+            .tls
+            .as_ref()
+            .and_then(|f| f.resources.as_ref())
+            .cloned()
+    }
+
+    #[must_use]
+    pub fn tls_secret_name(&self) -> ResourceName {
+        self.spec
+            .tls
+            .as_ref()
+            .and_then(|f| f.secret_name.as_ref())
+            .map_or_else(|| format!("{}-tls", self.default_name()).into(), Into::into)
+    }
+
+    #[must_use]
+    pub fn tls_target(&self) -> Option<&str> {
+        self.spec.tls.as_ref().map(|f| f.target.as_str())
+    }
+
+    #[must_use]
+    pub fn torrc_reload_strategy(&self) -> TorrcReloadStrategy {
+        self.spec
+            .torrc
+            .as_ref()
+            .and_then(|f| f.reload_strategy)
+            .unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn torrc_shutdown_wait_length_seconds(&self) -> i64 {
+        self.spec
+            .torrc
+            .as_ref()
+            .and_then(|f| f.shutdown_wait_length_seconds)
+            .unwrap_or(10)
+    }
+
+    #[must_use]
+    pub fn torrc_template(&self) -> Option<&str> {
+        self.spec
+            .torrc
+            .as_ref()
+            .and_then(|f| f.template.as_ref())
+            .map(String::as_str)
+    }
+
+    #[must_use]
+    pub fn ttl(&self) -> Option<SignedDuration> {
+        self.spec.ttl.as_ref().and_then(|ttl| ttl.parse().ok())
+    }
+
+    #[must_use]
+    pub fn status_conditions(&self) -> Option<&Vec<Condition>> {
+        self.status.as_ref().map(|f| f.conditions.as_ref())
+    }
+
+    /// Whether the `OnionService` is initialized with its latest settings.
+    #[must_use]
+    pub fn status_initialized(&self) -> bool {
+        self.status_conditions()
+            .into_iter()
+            .flatten()
+            .any(|condition| {
+                condition.type_ == "Initialized"
+                    && condition.status == "True"
+                    && condition.observed_generation.is_some()
+                    && condition.observed_generation == self.metadata.generation
+            })
+    }
+}
+
+impl KubernetesResource for OnionService {
+    type Spec = OnionServiceSpec;
+
+    fn spec(&self) -> &Self::Spec {
+        &self.spec
+    }
+}
+
+impl TorMetricsExt for OnionService {
+    fn metrics(&self) -> Option<&TorMetrics> {
+        self.spec.metrics.as_ref()
+    }
+}
+
+impl PodDisruptionBudgetExt for OnionService {
+    fn pod_disruption_budget(&self) -> Option<&KubernetesPodDisruptionBudget> {
+        self.spec.pod_disruption_budget.as_ref()
+    }
+
+    fn pod_disruption_budget_enabled(&self) -> bool {
+        self.spec.pod_disruption_budget.is_some() && !self.injection_enabled()
+    }
+}
+
+impl Object for OnionService {
+    const APP_KUBERNETES_IO_COMPONENT_VALUE: &'static str = "onion-service";
+
+    type Status = OnionServiceStatus;
+
+    /// Dependents are deleted first, so that a Deployment or Stateful Set
+    /// being replaced is only gone once its Pods are.
+    fn delete_params(&self) -> DeleteParams {
+        DeleteParams::foreground()
+    }
+
+    fn status(&self) -> Option<&Self::Status> {
+        self.status.as_ref()
+    }
+}
+
+impl Subset for OnionServiceSpec {
+    fn is_subset(&self, superset: &Self) -> bool {
+        self == superset
+    }
+}
+
+#[must_use]
+pub fn generate_custom_resource_definition() -> CustomResourceDefinition {
+    OnionService::crd()
+}
+
+/*
+ * ============================================================================
+ * Config
+ * ============================================================================
+ */
+pub struct Config {
+    pub tor_image: ImageConfig,
+}
+
+pub struct ImageConfig {
+    pub pull_policy: String,
+    pub uri: String,
+}
 
 /*
  * ============================================================================
@@ -454,6 +1237,9 @@ pub async fn run_controller(client: Client, config: Config, metrics: Metrics) {
     metrics.kubernetes_api_usage_count::<OnionService>("watch");
     metrics.kubernetes_api_usage_count::<ConfigMap>("watch");
     metrics.kubernetes_api_usage_count::<Deployment>("watch");
+    metrics.kubernetes_api_usage_count::<PodDisruptionBudget>("watch");
+    metrics.kubernetes_api_usage_count::<Service>("watch");
+    metrics.kubernetes_api_usage_count::<StatefulSet>("watch");
     Controller::new(
         kube::Api::<OnionService>::all(client.clone()),
         WatcherConfig::default(),
@@ -466,6 +1252,18 @@ pub async fn run_controller(client: Client, config: Config, metrics: Metrics) {
         kube::Api::<Deployment>::all(client.clone()),
         WatcherConfig::default(),
     )
+    .owns(
+        kube::Api::<PodDisruptionBudget>::all(client.clone()),
+        WatcherConfig::default(),
+    )
+    .owns(
+        kube::Api::<Service>::all(client.clone()),
+        WatcherConfig::default(),
+    )
+    .owns(
+        kube::Api::<StatefulSet>::all(client.clone()),
+        WatcherConfig::default(),
+    )
     .shutdown_on_signal()
     .run(
         reconciler,
@@ -509,14 +1307,27 @@ impl kubernetes::Context for Context {
  * ============================================================================
  */
 enum State {
+    Misconfigured {
+        reason: &'static str,
+        message: String,
+    },
     OnionKeyNotFound,
     OnionKeyHostnameNotFound,
-    Initialized(Box<OnionKey>),
+    /// The `OnionKey`, absent for ephemeral Onion Services.
+    Initialized(Option<Box<OnionKey>>),
 }
 
 impl State {
     fn conditions(&self, generation: Option<i64>) -> Vec<Condition> {
-        match self {
+        let mut conditions = match self {
+            State::Misconfigured { reason, message } => vec![Condition {
+                last_transition_time: Time(Timestamp::now()),
+                message: message.clone(),
+                observed_generation: generation,
+                reason: (*reason).into(),
+                status: "True".into(),
+                type_: "Misconfigured".into(),
+            }],
             State::OnionKeyNotFound => vec![Condition {
                 last_transition_time: Time(Timestamp::now()),
                 message: "The OnionKey was not found.".into(),
@@ -551,15 +1362,29 @@ impl State {
                     type_: "Initialized".into(),
                 },
             ],
-        }
-    }
-}
+        };
+
+        if !matches!(self, State::Misconfigured { .. }) {
+            conditions.push(Condition {
+                last_transition_time: Time(Timestamp::now()),
+                message: "The OnionService is configured.".into(),
+                observed_generation: generation,
+                reason: "Configured".into(),
+                status: "False".into(),
+                type_: "Misconfigured".into(),
+            });
+        }
+
+        conditions
+    }
+}
 
 /*
  * ============================================================================
  * Reconciler
  * ============================================================================
  */
+#[allow(clippy::too_many_lines)]
 #[tracing::instrument(skip_all)]
 async fn reconciler(object: Arc<OnionService>, ctx: Arc<Context>) -> Result<Action> {
     let _timer = ctx
@@ -569,6 +1394,21 @@ async fn reconciler(object: Arc<OnionService>, ctx: Arc<Context>) -> Result<Acti
 
     let namespace = object.try_namespace()?;
 
+    // TTL
+    if object
+        .expires_at()
+        .is_some_and(|expires_at| expires_at <= Timestamp::now())
+    {
+        tracing::info!("expired");
+        Api::new(
+            kube::Api::<OnionService>::namespaced(ctx.client.clone(), &namespace),
+            ctx.metrics.clone(),
+        )
+        .delete(object.as_ref(), object.as_ref().clone())
+        .await?;
+        return Ok(Action::await_change());
+    }
+
     let ob_config = generate_ob_config(&object);
     let torrc = generate_torrc(&object);
 
@@ -585,14 +1425,29 @@ async fn reconciler(object: Arc<OnionService>, ctx: Arc<Context>) -> Result<Acti
     )
     .await?;
 
+    let mut lease = None;
+    let mut ephemeral_hostname = None;
+    let mut replacing = false;
+
     if let State::Initialized(onion_key) = &state {
+        let onion_key = onion_key.as_deref();
+        let hostname = onion_key.and_then(OnionKey::hostname);
+
         let annotations = Annotations::new()
-            .add_opt(onion_key.hostname().as_ref())
+            .add_opt(hostname.as_ref())
             .add_opt(ob_config.as_ref())
             .add(&torrc);
 
+        let template_annotations = match object.torrc_reload_strategy() {
+            TorrcReloadStrategy::Restart => annotations.clone(),
+            TorrcReloadStrategy::Signal => Annotations::new()
+                .add_opt(hostname.as_ref())
+                .add_opt(ob_config.as_ref())
+                .add(&torrc.restart()),
+        };
+
         // ConfigMap
-        reconcile_config_map(
+        ephemeral_hostname = reconcile_config_map(
             &Api::new(
                 kube::Api::namespaced(ctx.client.clone(), &namespace),
                 ctx.metrics.clone(),
@@ -605,18 +1460,196 @@ async fn reconciler(object: Arc<OnionService>, ctx: Arc<Context>) -> Result<Acti
         )
         .await?;
 
-        // Deployment
-        reconcile_deployment(
+        // ServiceAccount
+        reconcile_service_account(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &object,
+            &annotations,
+            &labels,
+        )
+        .await?;
+
+        // Role
+        reconcile_role(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &object,
+            &annotations,
+            &labels,
+        )
+        .await?;
+
+        // RoleBinding
+        reconcile_role_binding(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &object,
+            &annotations,
+            &labels,
+        )
+        .await?;
+
+        // Lease
+        lease = reconcile_lease(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &object,
+            &annotations,
+            &labels,
+        )
+        .await?;
+
+        // Certificate
+        reconcile_certificate(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &object,
+            &annotations,
+            &labels,
+            hostname.as_ref(),
+        )
+        .await?;
+
+        // Secret
+        reconcile_tls_secret(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &object,
+            &annotations,
+            &labels,
+            hostname.as_ref(),
+        )
+        .await?;
+
+        let deployment_api = Api::new(
+            kube::Api::namespaced(ctx.client.clone(), &namespace),
+            ctx.metrics.clone(),
+        );
+        let stateful_set_api = Api::new(
+            kube::Api::namespaced(ctx.client.clone(), &namespace),
+            ctx.metrics.clone(),
+        );
+
+        // The workload being replaced is removed first, and its replacement is
+        // only created once its Pods are gone, so that the Deployment and the
+        // Stateful Set never run the same Onion Service side by side.
+        if object.persistence_enabled() {
+            // Deployment
+            reconcile_deployment(
+                &deployment_api,
+                &ctx.config,
+                &object,
+                &annotations,
+                &template_annotations,
+                &labels,
+                &selector_labels,
+                onion_key,
+            )
+            .await?;
+
+            replacing = deployment_api
+                .get_opt(&object.deployment_name())
+                .await?
+                .is_some();
+
+            // StatefulSet
+            if !replacing {
+                reconcile_stateful_set(
+                    &stateful_set_api,
+                    &ctx.config,
+                    &object,
+                    &annotations,
+                    &template_annotations,
+                    &labels,
+                    &selector_labels,
+                    onion_key,
+                )
+                .await?;
+            }
+        } else {
+            // StatefulSet
+            reconcile_stateful_set(
+                &stateful_set_api,
+                &ctx.config,
+                &object,
+                &annotations,
+                &template_annotations,
+                &labels,
+                &selector_labels,
+                onion_key,
+            )
+            .await?;
+
+            replacing = stateful_set_api
+                .get_opt(&object.deployment_name())
+                .await?
+                .is_some();
+
+            // Deployment
+            if !replacing {
+                reconcile_deployment(
+                    &deployment_api,
+                    &ctx.config,
+                    &object,
+                    &annotations,
+                    &template_annotations,
+                    &labels,
+                    &selector_labels,
+                    onion_key,
+                )
+                .await?;
+            }
+        }
+
+        // PodDisruptionBudget
+        reconcile_pod_disruption_budget(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &object,
+            &annotations,
+            &labels,
+            &selector_labels,
+        )
+        .await?;
+
+        // Service
+        reconcile_service(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &object,
+            &annotations,
+            &labels,
+            &selector_labels,
+        )
+        .await?;
+
+        // ServiceMonitor
+        reconcile_service_monitor(
             &Api::new(
                 kube::Api::namespaced(ctx.client.clone(), &namespace),
                 ctx.metrics.clone(),
             ),
-            &ctx.config,
             &object,
             &annotations,
             &labels,
             &selector_labels,
-            onion_key,
         )
         .await?;
     }
@@ -629,6 +1662,8 @@ async fn reconciler(object: Arc<OnionService>, ctx: Arc<Context>) -> Result<Acti
         ),
         &object,
         &state,
+        lease.as_ref(),
+        ephemeral_hostname.as_deref(),
     )
     .await?;
 
@@ -636,14 +1671,38 @@ async fn reconciler(object: Arc<OnionService>, ctx: Arc<Context>) -> Result<Acti
 
     tracing::info!("reconciled");
 
-    match state {
-        State::Initialized(_) => Ok(Action::requeue(Duration::from_hours(1))),
-        _ => Ok(Action::requeue(Duration::from_secs(5))),
-    }
+    let requeue = match state {
+        State::Initialized(_) if replacing => Duration::from_secs(5),
+        // The Lease is not owned, so leadership changes are picked up by polling.
+        State::Initialized(_) if object.high_availability_enabled() => Duration::from_mins(1),
+        State::Initialized(_) => Duration::from_hours(1),
+        _ => Duration::from_secs(5),
+    };
+
+    Ok(Action::requeue(object.expires_at().map_or(
+        requeue,
+        |expires_at| {
+            Duration::try_from(expires_at.duration_since(Timestamp::now()))
+                .unwrap_or_default()
+                .min(requeue)
+        },
+    )))
 }
 
 async fn reconcile_onion_key(api: &Api<OnionKey>, object: &OnionService) -> Result<State> {
-    let Some(onion_key) = api.get_opt(&object.onion_key_name()).await? else {
+    if let Some(state) = generate_misconfigured_state(object) {
+        return Ok(state);
+    }
+
+    if object.ephemeral() {
+        return Ok(State::Initialized(None));
+    }
+
+    let Some(onion_key_name) = object.onion_key_name() else {
+        return Ok(State::OnionKeyNotFound);
+    };
+
+    let Some(onion_key) = api.get_opt(&onion_key_name).await? else {
         return Ok(State::OnionKeyNotFound);
     };
 
@@ -651,9 +1710,31 @@ async fn reconcile_onion_key(api: &Api<OnionKey>, object: &OnionService) -> Resu
         return Ok(State::OnionKeyHostnameNotFound);
     }
 
-    Ok(State::Initialized(Box::new(onion_key)))
+    Ok(State::Initialized(Some(Box::new(onion_key))))
+}
+
+async fn reconcile_certificate(
+    api: &Api<Certificate>,
+    object: &OnionService,
+    annotations: &Annotations,
+    labels: &Labels,
+    hostname: Option<&Hostname>,
+) -> Result<()> {
+    let resources: HashMap<(), _> = generate_certificate(object, annotations, labels, hostname)?
+        .map(|certificate| [((), certificate)].into())
+        .unwrap_or_default();
+    let enabled = !resources.is_empty();
+
+    match api.sync(object, resources).await {
+        Ok(_) => Ok(()),
+        // cert-manager is optional, so there is nothing to clean up when its
+        // custom resource definitions are not installed.
+        Err(Error::Kube(kube::Error::Api(status))) if !enabled && status.is_not_found() => Ok(()),
+        Err(error) => Err(error),
+    }
 }
 
+/// Returns the hostname the ephemeral Onion Service annotated the Config Map with.
 async fn reconcile_config_map(
     api: &Api<ConfigMap>,
     object: &OnionService,
@@ -661,51 +1742,228 @@ async fn reconcile_config_map(
     labels: &Labels,
     torrc: &Torrc,
     ob_config: Option<&OBConfig>,
-) -> Result<()> {
-    api.sync(
-        object,
-        [(
-            (),
-            generate_config_map(object, annotations, labels, ob_config, torrc)?,
-        )]
-        .into(),
-    )
-    .await
-    .map(|_| ())
+) -> Result<Option<String>> {
+    let mut config_maps = api
+        .sync(
+            object,
+            [(
+                (),
+                generate_config_map(object, annotations, labels, ob_config, torrc)?,
+            )]
+            .into(),
+        )
+        .await?;
+
+    // The sidecar of an ephemeral Onion Service annotates the Config Map with
+    // its hostname, as the Role only allows it to patch the Config Map.
+    Ok(config_maps
+        .remove(&())
+        .filter(|_| object.ephemeral())
+        .and_then(|config_map| {
+            config_map
+                .metadata
+                .annotations?
+                .remove(TOR_AGABANI_CO_UK_HOSTNAME_KEY)
+        }))
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn reconcile_deployment(
     api: &Api<Deployment>,
     config: &Config,
     object: &OnionService,
     annotations: &Annotations,
+    template_annotations: &Annotations,
     labels: &Labels,
     selector_labels: &SelectorLabels,
-    onion_key: &OnionKey,
+    onion_key: Option<&OnionKey>,
 ) -> Result<()> {
-    api.sync(
+    let resources: HashMap<(), _> = generate_deployment(
         object,
-        [(
-            (),
-            generate_deployment(
-                object,
-                config,
-                annotations,
-                labels,
-                selector_labels,
-                onion_key,
-            )?,
-        )]
-        .into(),
-    )
-    .await
-    .map(|_| ())
+        config,
+        annotations,
+        template_annotations,
+        labels,
+        selector_labels,
+        onion_key,
+    )?
+    .map(|deployment| [((), deployment)].into())
+    .unwrap_or_default();
+
+    api.sync(object, resources).await.map(|_| ())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn reconcile_stateful_set(
+    api: &Api<StatefulSet>,
+    config: &Config,
+    object: &OnionService,
+    annotations: &Annotations,
+    template_annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+    onion_key: Option<&OnionKey>,
+) -> Result<()> {
+    let resources: HashMap<(), _> = generate_stateful_set(
+        object,
+        config,
+        annotations,
+        template_annotations,
+        labels,
+        selector_labels,
+        onion_key,
+    )?
+    .map(|stateful_set| [((), stateful_set)].into())
+    .unwrap_or_default();
+
+    api.sync(object, resources).await.map(|_| ())
+}
+
+async fn reconcile_lease(
+    api: &Api<Lease>,
+    object: &OnionService,
+    annotations: &Annotations,
+    labels: &Labels,
+) -> Result<Option<Lease>> {
+    let resources: HashMap<(), _> = generate_lease(object, annotations, labels)?
+        .map(|lease| [((), lease)].into())
+        .unwrap_or_default();
+
+    api.sync(object, resources)
+        .await
+        .map(|mut leases| leases.remove(&()))
+}
+
+async fn reconcile_pod_disruption_budget(
+    api: &Api<PodDisruptionBudget>,
+    object: &OnionService,
+    annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+) -> Result<()> {
+    let resources: HashMap<(), _> =
+        generate_pod_disruption_budget(object, annotations, labels, selector_labels)?
+            .map(|pod_disruption_budget| [((), pod_disruption_budget)].into())
+            .unwrap_or_default();
+
+    api.sync(object, resources).await.map(|_| ())
+}
+
+async fn reconcile_role(
+    api: &Api<Role>,
+    object: &OnionService,
+    annotations: &Annotations,
+    labels: &Labels,
+) -> Result<()> {
+    let resources: HashMap<(), _> = generate_role(object, annotations, labels)?
+        .map(|role| [((), role)].into())
+        .unwrap_or_default();
+
+    api.sync(object, resources).await.map(|_| ())
+}
+
+async fn reconcile_role_binding(
+    api: &Api<RoleBinding>,
+    object: &OnionService,
+    annotations: &Annotations,
+    labels: &Labels,
+) -> Result<()> {
+    let resources: HashMap<(), _> = generate_role_binding(object, annotations, labels)?
+        .map(|role_binding| [((), role_binding)].into())
+        .unwrap_or_default();
+
+    api.sync(object, resources).await.map(|_| ())
+}
+
+async fn reconcile_service(
+    api: &Api<Service>,
+    object: &OnionService,
+    annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+) -> Result<()> {
+    let resources: HashMap<(), _> =
+        generate_metrics_service(object, annotations, labels, selector_labels)?
+            .map(|service| [((), service)].into())
+            .unwrap_or_default();
+
+    api.sync(object, resources).await.map(|_| ())
+}
+
+async fn reconcile_service_account(
+    api: &Api<ServiceAccount>,
+    object: &OnionService,
+    annotations: &Annotations,
+    labels: &Labels,
+) -> Result<()> {
+    let resources: HashMap<(), _> = generate_service_account(object, annotations, labels)?
+        .map(|service_account| [((), service_account)].into())
+        .unwrap_or_default();
+
+    api.sync(object, resources).await.map(|_| ())
+}
+
+async fn reconcile_service_monitor(
+    api: &Api<ServiceMonitor>,
+    object: &OnionService,
+    annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+) -> Result<()> {
+    let resources: HashMap<(), _> =
+        generate_service_monitor(object, annotations, labels, selector_labels)?
+            .map(|service_monitor| [((), service_monitor)].into())
+            .unwrap_or_default();
+    let enabled = !resources.is_empty();
+
+    match api.sync(object, resources).await {
+        Ok(_) => Ok(()),
+        // The Prometheus Operator is optional, so there is nothing to clean up
+        // when its custom resource definitions are not installed.
+        Err(Error::Kube(kube::Error::Api(status))) if !enabled && status.is_not_found() => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+async fn reconcile_tls_secret(
+    api: &Api<Secret>,
+    object: &OnionService,
+    annotations: &Annotations,
+    labels: &Labels,
+    hostname: Option<&Hostname>,
+) -> Result<()> {
+    let (secret, ca) = if object.tls_enabled() && !object.tls_certificate_enabled() {
+        let secret = api.get_opt(&object.tls_secret_name()).await?;
+        let ca = match object.tls_ca_secret_name() {
+            Some(ca_secret_name) => api.get_opt(&ca_secret_name).await?,
+            None => None,
+        };
+        (secret, ca)
+    } else {
+        (None, None)
+    };
+
+    let resources: HashMap<(), _> = generate_tls_secret(
+        object,
+        annotations,
+        labels,
+        hostname,
+        secret.as_ref(),
+        ca.as_ref(),
+        Timestamp::now(),
+    )?
+    .map(|secret| [((), secret)].into())
+    .unwrap_or_default();
+
+    api.sync(object, resources).await.map(|_| ())
 }
 
 async fn reconcile_onion_service(
     api: &Api<OnionService>,
     object: &OnionService,
     state: &State,
+    lease: Option<&Lease>,
+    ephemeral_hostname: Option<&str>,
 ) -> Result<()> {
     let conditions = object
         .status_conditions()
@@ -723,10 +1981,19 @@ async fn reconcile_onion_service(
         object,
         OnionServiceStatus {
             conditions,
-            hostname: if let State::Initialized(onion_key) = state {
-                onion_key.hostname().as_ref().map(ToString::to_string)
-            } else {
-                None
+            expires_at: object.expires_at().map(Time),
+            high_availability: lease.and_then(|lease| lease.spec.as_ref()).map(|spec| {
+                OnionServiceStatusHighAvailability {
+                    failovers: spec.lease_transitions.unwrap_or_default(),
+                    leader: spec.holder_identity.clone(),
+                }
+            }),
+            hostname: match state {
+                State::Initialized(Some(onion_key)) => {
+                    onion_key.hostname().as_ref().map(ToString::to_string)
+                }
+                State::Initialized(None) => ephemeral_hostname.map(ToString::to_string),
+                _ => None,
             },
             summary,
         },
@@ -734,6 +2001,65 @@ async fn reconcile_onion_service(
     .await
 }
 
+/// Rejects settings the schema cannot: a `ttl` that matches the pattern but
+/// does not parse, and the settings ephemeral Onion Services do not support.
+fn generate_misconfigured_state(object: &OnionService) -> Option<State> {
+    if let Some(Err(error)) = object
+        .spec
+        .ttl
+        .as_ref()
+        .map(|ttl| ttl.parse::<SignedDuration>())
+    {
+        return Some(State::Misconfigured {
+            reason: "TtlMalformed",
+            message: format!("The ttl is malformed: {error}."),
+        });
+    }
+
+    if object.ephemeral() {
+        let unsupported = [
+            ("highAvailability", object.spec.high_availability.is_some()),
+            ("onionBalance", object.spec.onion_balance.is_some()),
+            ("onionKey", object.spec.onion_key.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(name, set)| set.then_some(name))
+        .collect::<Vec<_>>();
+
+        if !unsupported.is_empty() {
+            return Some(State::Misconfigured {
+                reason: "EphemeralUnsupported",
+                message: format!(
+                    "Ephemeral Onion Services do not support {}.",
+                    unsupported.join(", ")
+                ),
+            });
+        }
+    }
+
+    if object.export_circuit_id().is_some() && !object.gateway_enabled() {
+        let unsupported = [
+            ("tls", object.tls_enabled()),
+            ("fallback", object.fallback_enabled()),
+        ]
+        .into_iter()
+        .filter_map(|(name, set)| set.then_some(name))
+        .collect::<Vec<_>>();
+
+        if !unsupported.is_empty() {
+            return Some(State::Misconfigured {
+                reason: "ExportCircuitIdUnsupported",
+                message: format!(
+                    "Exporting the circuit ID without the gateway does not support {}.",
+                    unsupported.join(", ")
+                ),
+            });
+        }
+    }
+
+    None
+}
+
 fn generate_ob_config(object: &OnionService) -> Option<OBConfig> {
     object
         .onion_balance_onion_key_hostname()
@@ -746,26 +2072,102 @@ fn generate_torrc(object: &OnionService) -> Torrc {
         torrc = torrc.template(template);
     }
     torrc = torrc
-        .data_dir("${TOR_TMP_DIR}/home/.tor")
-        .hidden_service_dir("${TOR_TMP_DIR}/var/lib/tor/hidden_service");
-    if object.onion_balanced() {
-        torrc = torrc.hidden_service_onion_balance_instance(true);
+        .data_dir(if object.persistence_enabled() {
+            "/var/lib/tor/data"
+        } else {
+            "${TOR_TMP_DIR}/home/.tor"
+        })
+        .control_port("127.0.0.1:6666")
+        .cookie_authentication(true)
+        .shutdown_wait_length(object.torrc_shutdown_wait_length_seconds());
+    // Ephemeral Onion Services are added over the control port instead.
+    if !object.ephemeral() {
+        torrc = torrc.hidden_service_dir("${TOR_TMP_DIR}/var/lib/tor/hidden_service");
+        if object.onion_balanced() {
+            torrc = torrc.hidden_service_onion_balance_instance(true);
+        }
+        if let Some(OnionServiceSpecExportCircuitId::Haproxy) = object.export_circuit_id() {
+            torrc = torrc.hidden_service_export_circuit_id("haproxy");
+        }
+        // The gateway receives the connections in place of the targets.
+        torrc =
+            object
+                .hidden_service_ports()
+                .iter()
+                .enumerate()
+                .fold(torrc, |torrc, (index, port)| {
+                    if object.gateway_enabled() {
+                        torrc.hidden_service_port(port.virtport, &object.gateway_listen(index))
+                    } else {
+                        torrc.hidden_service_port(port.virtport, &port.target)
+                    }
+                });
+    }
+    if let Some(metrics) = object.metrics() {
+        torrc = torrc.metrics_port(&metrics.listen());
+        torrc = metrics
+            .policy()
+            .iter()
+            .fold(torrc, |torrc, policy| torrc.metrics_port_policy(policy));
     }
-    torrc = object.ports().iter().fold(torrc, |torrc, port| {
-        torrc.hidden_service_port(port.virtport, &port.target)
-    });
     torrc.build()
 }
 
-fn generate_config_map(
+fn generate_certificate(
     object: &OnionService,
     annotations: &Annotations,
     labels: &Labels,
-    ob_config: Option<&OBConfig>,
-    torrc: &Torrc,
-) -> Result<ConfigMap> {
-    Ok(ConfigMap {
-        metadata: ObjectMeta {
+    hostname: Option<&Hostname>,
+) -> Result<Option<Certificate>> {
+    let (Some(hostname), Some(issuer_ref)) = (hostname, object.tls_certificate_issuer_ref()) else {
+        return Ok(None);
+    };
+
+    if !object.tls_certificate_enabled() {
+        return Ok(None);
+    }
+
+    Ok(Some(Certificate {
+        metadata: ObjectMeta {
+            name: Some(object.tls_certificate_name().into()),
+            annotations: Some(
+                annotations
+                    .clone()
+                    .append_reverse(object.tls_certificate_annotations())
+                    .into(),
+            ),
+            labels: Some(
+                labels
+                    .clone()
+                    .append_reverse(object.tls_certificate_labels())
+                    .into(),
+            ),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        spec: CertificateSpec {
+            dns_names: vec![hostname.to_string()],
+            duration: object.tls_certificate_duration(),
+            issuer_ref,
+            renew_before: object.tls_certificate_renew_before(),
+            secret_name: object.tls_secret_name().into(),
+        },
+    }))
+}
+
+fn generate_config_map(
+    object: &OnionService,
+    annotations: &Annotations,
+    labels: &Labels,
+    ob_config: Option<&OBConfig>,
+    torrc: &Torrc,
+) -> Result<ConfigMap> {
+    Ok(ConfigMap {
+        metadata: ObjectMeta {
             name: Some(object.config_map_name().into()),
             annotations: Some(
                 annotations
@@ -791,22 +2193,31 @@ fn generate_config_map(
             if let Some(ob_config) = ob_config {
                 data.insert("ob_config".into(), ob_config.to_string());
             }
+            for (index, port) in object.ports().iter().enumerate() {
+                if let Some(body) = port.fallback.as_ref().and_then(|f| f.body.as_ref()) {
+                    data.insert(format!("fallback_{index}"), body.clone());
+                }
+            }
             data
         }),
         ..Default::default()
     })
 }
 
-#[allow(clippy::too_many_lines)]
 fn generate_deployment(
     object: &OnionService,
     config: &Config,
     annotations: &Annotations,
+    template_annotations: &Annotations,
     labels: &Labels,
     selector_labels: &SelectorLabels,
-    onion_key: &OnionKey,
-) -> Result<Deployment> {
-    Ok(Deployment {
+    onion_key: Option<&OnionKey>,
+) -> Result<Option<Deployment>> {
+    if object.persistence_enabled() || object.injection_enabled() {
+        return Ok(None);
+    }
+
+    Ok(Some(Deployment {
         metadata: ObjectMeta {
             name: Some(object.deployment_name().into()),
             annotations: Some(
@@ -829,116 +2240,277 @@ fn generate_deployment(
             ..Default::default()
         },
         spec: Some(DeploymentSpec {
-            replicas: Some(1),
+            replicas: Some(object.high_availability_replicas()),
             selector: LabelSelector {
                 match_labels: Some(selector_labels.into()),
                 ..Default::default()
             },
-            template: PodTemplateSpec {
-                metadata: Some(ObjectMeta {
-                    annotations: Some(
-                        annotations
-                            .clone()
-                            .append_reverse(object.deployment_annotations())
-                            .into(),
-                    ),
+            strategy: object.deployment_strategy(),
+            template: generate_pod_template(
+                object,
+                config,
+                template_annotations,
+                labels,
+                onion_key,
+            ),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }))
+}
+
+fn generate_stateful_set(
+    object: &OnionService,
+    config: &Config,
+    annotations: &Annotations,
+    template_annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+    onion_key: Option<&OnionKey>,
+) -> Result<Option<StatefulSet>> {
+    if !object.persistence_enabled() {
+        return Ok(None);
+    }
+
+    Ok(Some(StatefulSet {
+        metadata: ObjectMeta {
+            name: Some(object.deployment_name().into()),
+            annotations: Some(
+                annotations
+                    .clone()
+                    .append_reverse(object.deployment_annotations())
+                    .into(),
+            ),
+            labels: Some(
+                labels
+                    .clone()
+                    .append_reverse(object.deployment_labels())
+                    .into(),
+            ),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        spec: Some(StatefulSetSpec {
+            persistent_volume_claim_retention_policy: Some(
+                StatefulSetPersistentVolumeClaimRetentionPolicy {
+                    when_deleted: Some("Delete".into()),
+                    when_scaled: Some("Retain".into()),
+                },
+            ),
+            replicas: Some(object.high_availability_replicas()),
+            selector: LabelSelector {
+                match_labels: Some(selector_labels.into()),
+                ..Default::default()
+            },
+            template: generate_pod_template(
+                object,
+                config,
+                template_annotations,
+                labels,
+                onion_key,
+            ),
+            volume_claim_templates: Some(vec![PersistentVolumeClaim {
+                metadata: ObjectMeta {
+                    name: Some("var-lib-tor".into()),
+                    annotations: object.persistence_annotations().map(Into::into),
                     labels: Some(
                         labels
                             .clone()
-                            .append_reverse(object.deployment_labels())
+                            .append_reverse(object.persistence_labels())
                             .into(),
                     ),
                     ..Default::default()
-                }),
-                spec: Some(PodSpec {
-                    affinity: object.deployment_affinity(),
-                    containers: generate_deployment_containers(object, config),
-                    image_pull_secrets: object.deployment_image_pull_secrets(),
-                    init_containers: Some(generate_deployment_init_containers(object)),
-                    node_selector: object.deployment_node_selector(),
-                    security_context: Some(object.deployment_security_context()),
-                    tolerations: object.deployment_tolerations(),
-                    topology_spread_constraints: object.deployment_topology_spread_constraints(),
-                    volumes: Some(generate_deployment_volumes(object, onion_key)),
+                },
+                spec: Some(PersistentVolumeClaimSpec {
+                    access_modes: Some(object.persistence_access_modes()),
+                    resources: Some(VolumeResourceRequirements {
+                        requests: Some(BTreeMap::from([(
+                            "storage".into(),
+                            object.persistence_size(),
+                        )])),
+                        ..Default::default()
+                    }),
+                    storage_class_name: object.persistence_storage_class_name(),
                     ..Default::default()
                 }),
-            },
+                ..Default::default()
+            }]),
             ..Default::default()
         }),
         ..Default::default()
-    })
+    }))
+}
+
+fn generate_pod_template(
+    object: &OnionService,
+    config: &Config,
+    template_annotations: &Annotations,
+    labels: &Labels,
+    onion_key: Option<&OnionKey>,
+) -> PodTemplateSpec {
+    PodTemplateSpec {
+        metadata: Some(ObjectMeta {
+            annotations: Some(
+                template_annotations
+                    .clone()
+                    .append_reverse(object.deployment_annotations())
+                    .into(),
+            ),
+            labels: Some(
+                labels
+                    .clone()
+                    .append_reverse(object.deployment_labels())
+                    .into(),
+            ),
+            ..Default::default()
+        }),
+        spec: Some(PodSpec {
+            affinity: object.deployment_affinity(),
+            containers: generate_deployment_containers(object, config),
+            image_pull_secrets: object.deployment_image_pull_secrets(),
+            init_containers: Some(generate_deployment_init_containers(object)),
+            node_selector: object.deployment_node_selector(),
+            security_context: Some(object.deployment_security_context()),
+            service_account_name: object
+                .service_account_enabled()
+                .then(|| object.service_account_name().into()),
+            termination_grace_period_seconds: Some(
+                object.deployment_termination_grace_period_seconds(),
+            ),
+            tolerations: object.deployment_tolerations(),
+            topology_spread_constraints: object.deployment_topology_spread_constraints(),
+            volumes: Some(generate_deployment_volumes(object, onion_key)),
+            ..Default::default()
+        }),
+    }
 }
 
+#[allow(clippy::too_many_lines)]
 fn generate_deployment_containers(object: &OnionService, config: &Config) -> Vec<Container> {
     let mut containers = object.deployment_containers();
 
     {
         let container = vec_get_or_insert(&mut containers, |f| f.name == "tor");
         container.name = "tor".to_string();
-        container.args = Some(vec![
-            "-c".into(),
-            {
-                let mut commands = vec!["export TOR_TMP_DIR=${TOR_TMP_DIR:-$(mktemp -d --suffix=.tor -p /tmp)}"];
-
-                // hidden_service
-                commands.push("mkdir -p $TOR_TMP_DIR/var/lib/tor/hidden_service");
-                commands.push("chmod 700 $TOR_TMP_DIR/var/lib/tor/hidden_service");
-                commands.push("cp -L /etc/secrets/* $TOR_TMP_DIR/var/lib/tor/hidden_service");
+        container.args = Some({
+            let lease_name = object
+                .high_availability_enabled()
+                .then(|| object.lease_name());
+            let config_map_name = object
+                .ephemeral()
+                .then(|| object.config_map_name().to_string());
+            let add_onion_ports = object
+                .hidden_service_ports()
+                .iter()
+                .map(|port| format!("{},{}", port.virtport, port.target))
+                .collect::<Vec<_>>();
+            let mut args = vec!["entrypoint", "run"];
 
-                // ob_config
-                if object.onion_balanced() {
-                    commands.push("cp -L /etc/configs/ob_config $TOR_TMP_DIR/var/lib/tor/hidden_service/ob_config");
+            // hidden_service
+            if object.ephemeral() {
+                for add_onion_port in &add_onion_ports {
+                    args.extend(["--add-onion-port", add_onion_port]);
+                }
+                if let Some(config_map_name) = &config_map_name {
+                    args.extend(["--config-map-name", config_map_name]);
                 }
+            } else {
+                args.extend(["--directory", "${TOR_TMP_DIR}/var/lib/tor/hidden_service"]);
+                args.extend([
+                    "--copy",
+                    "/etc/secrets:${TOR_TMP_DIR}/var/lib/tor/hidden_service",
+                ]);
+            }
+
+            // ob_config
+            if object.onion_balanced() && !object.ephemeral() {
+                args.extend([
+                    "--copy",
+                    "/etc/configs/ob_config:${TOR_TMP_DIR}/var/lib/tor/hidden_service/ob_config",
+                ]);
+            }
 
-                // torrc
-                commands.push("mkdir -p $TOR_TMP_DIR/usr/local/etc/tor");
-                commands.push("envsubst < /etc/configs/torrc > $TOR_TMP_DIR/usr/local/etc/tor/torrc");
+            // torrc
+            args.extend([
+                "--template",
+                "/etc/configs/torrc:${TOR_TMP_DIR}/usr/local/etc/tor/torrc",
+            ]);
 
-                // data directory
-                commands.push("mkdir -p $TOR_TMP_DIR/home/.tor");
-                commands.push("chmod 700 $TOR_TMP_DIR/home/.tor");
+            // data directory
+            if object.persistence_enabled() {
+                args.extend(["--directory", "/var/lib/tor/data"]);
+            } else {
+                args.extend(["--directory", "${TOR_TMP_DIR}/home/.tor"]);
+            }
 
-                // executable
-                commands.push("tor -f $TOR_TMP_DIR/usr/local/etc/tor/torrc");
-                commands
+            // reload
+            if object.torrc_reload_strategy() == TorrcReloadStrategy::Signal {
+                args.push("--reload");
             }
-            .join(" && "),
-        ]);
-        container.command = Some(vec!["/bin/bash".into()]);
-        container.image = Some(config.tor_image.uri.clone());
-        container.image_pull_policy = Some(config.tor_image.pull_policy.clone());
-        container.liveness_probe = Some(Probe {
-            exec: Some(ExecAction {
-                command: Some(vec![
-                    "/bin/bash".to_string(),
-                    "-c".to_string(),
-                    "echo > /dev/tcp/127.0.0.1/9050".to_string(),
-                ]),
-            }),
-            failure_threshold: Some(3),
-            period_seconds: Some(10),
-            success_threshold: Some(1),
-            timeout_seconds: Some(1),
-            ..Default::default()
+
+            // leader election
+            if let Some(lease_name) = &lease_name {
+                args.extend(["--leader-election-lease", lease_name]);
+            }
+
+            // shutdown
+            args.push("--interrupt-on-terminate");
+
+            // executable
+            args.extend(["--", "tor", "-f", "${TOR_TMP_DIR}/usr/local/etc/tor/torrc"]);
+
+            args.into_iter().map(Into::into).collect()
         });
-        container.readiness_probe = Some(Probe {
-            exec: Some(ExecAction {
-                command: Some(vec![
-                    "/bin/bash".to_string(),
-                    "-c".to_string(),
-                    "echo > /dev/tcp/127.0.0.1/9050".to_string(),
-                ]),
-            }),
-            failure_threshold: Some(3),
-            period_seconds: Some(10),
-            success_threshold: Some(1),
-            timeout_seconds: Some(1),
-            ..Default::default()
+        container.command = Some(vec!["tor-operator".into()]);
+        if container.image.is_none() {
+            container.image = Some(config.tor_image.uri.clone());
+            container.image_pull_policy = Some(config.tor_image.pull_policy.clone());
+        }
+
+        if object.service_account_enabled() {
+            let env = container.env.get_or_insert_with(Default::default);
+            for (name, field_path) in [
+                ("POD_NAME", "metadata.name"),
+                ("POD_NAMESPACE", "metadata.namespace"),
+            ] {
+                let env_var = vec_get_or_insert(env, |f| f.name == name);
+                env_var.name = name.to_string();
+                env_var.value_from = Some(EnvVarSource {
+                    field_ref: Some(ObjectFieldSelector {
+                        field_path: field_path.into(),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                });
+            }
+        }
+
+        if let Some(metrics) = object.metrics() {
+            let ports = container.ports.get_or_insert_with(Default::default);
+            let port = vec_get_or_insert(ports, |f| match &f.name {
+                Some(name) => name == "metrics",
+                None => false,
+            });
+            port.name = Some("metrics".to_string());
+            port.container_port = metrics.port();
+            port.protocol = Some("TCP".to_string());
+        }
+
+        container.liveness_probe = Some(liveness_probe(&[]));
+        container.readiness_probe = Some(if object.ephemeral() {
+            readiness_probe(&[])
+        } else {
+            readiness_probe(&["--hostname-file", "/etc/secrets/hostname"])
         });
+        container.startup_probe = Some(startup_probe(&[]));
 
         let volume_mounts = container.volume_mounts.get_or_insert_with(Default::default);
 
-        {
+        if !object.ephemeral() {
             let volume_mount = vec_get_or_insert(volume_mounts, |f| f.name == "etc-secrets");
             volume_mount.name = "etc-secrets".to_string();
             volume_mount.mount_path = "/etc/secrets".into();
@@ -951,16 +2523,127 @@ fn generate_deployment_containers(object: &OnionService, config: &Config) -> Vec
             volume_mount.mount_path = "/etc/configs".into();
             volume_mount.read_only = Some(true);
         }
+
+        if object.persistence_enabled() {
+            let volume_mount = vec_get_or_insert(volume_mounts, |f| f.name == "var-lib-tor");
+            volume_mount.name = "var-lib-tor".to_string();
+            volume_mount.mount_path = "/var/lib/tor".into();
+        }
     }
 
-    for container in &mut containers {
-        container.security_context = Some(SecurityContext {
-            capabilities: Some(Capabilities {
-                drop: Some(vec!["ALL".to_string()]),
+    if object.fallback_enabled() {
+        let container = vec_get_or_insert(&mut containers, |f| f.name == "fallback");
+        container.name = "fallback".to_string();
+        container.args = Some({
+            let mut args = vec!["fallback".to_string(), "run".to_string()];
+            for (index, port) in object.ports().iter().enumerate() {
+                let Some(fallback) = &port.fallback else {
+                    continue;
+                };
+                let mut route = vec![
+                    format!("listen={}", object.fallback_listen(index)),
+                    format!("target={}", port.target),
+                    format!(
+                        "service={}",
+                        fallback.service.as_deref().unwrap_or_else(|| {
+                            port.target.split([':', '.']).next().unwrap_or_default()
+                        })
+                    ),
+                ];
+                if let Some(status) = fallback.status {
+                    route.push(format!("status={status}"));
+                }
+                if let Some(content_type) = &fallback.content_type {
+                    route.push(format!("content-type={content_type}"));
+                }
+                if fallback.body.is_some() {
+                    route.push(format!("body-file=/etc/configs/fallback_{index}"));
+                }
+                args.extend(["--route".into(), route.join(",")]);
+            }
+            args
+        });
+        container.command = Some(vec!["tor-operator".into()]);
+        container.image = Some(config.tor_image.uri.clone());
+        container.image_pull_policy = Some(config.tor_image.pull_policy.clone());
+
+        let env = container.env.get_or_insert_with(Default::default);
+        let env_var = vec_get_or_insert(env, |f| f.name == "POD_NAMESPACE");
+        env_var.name = "POD_NAMESPACE".to_string();
+        env_var.value_from = Some(EnvVarSource {
+            field_ref: Some(ObjectFieldSelector {
+                field_path: "metadata.namespace".into(),
                 ..Default::default()
             }),
             ..Default::default()
         });
+
+        let volume_mounts = container.volume_mounts.get_or_insert_with(Default::default);
+        let volume_mount = vec_get_or_insert(volume_mounts, |f| f.name == "etc-configs");
+        volume_mount.name = "etc-configs".to_string();
+        volume_mount.mount_path = "/etc/configs".into();
+        volume_mount.read_only = Some(true);
+    }
+
+    if object.gateway_enabled() {
+        let container = vec_get_or_insert(&mut containers, |f| f.name == "gateway");
+        container.name = "gateway".to_string();
+        container.args = Some({
+            let mut args = vec!["gateway".to_string(), "run".to_string()];
+            if let Some(connection_limit) = object.gateway_connection_limit() {
+                args.extend(["--connection-limit".into(), connection_limit.to_string()]);
+            }
+            if let Some(rate_limit) = object.gateway_rate_limit() {
+                args.extend(["--rate-limit".into(), rate_limit.to_string()]);
+            }
+            for (index, port) in object.hidden_service_ports().iter().enumerate() {
+                args.extend([
+                    "--route".into(),
+                    format!("{},{}", object.gateway_listen(index), port.target),
+                ]);
+            }
+            args
+        });
+        container.command = Some(vec!["tor-operator".into()]);
+        container.image = Some(config.tor_image.uri.clone());
+        container.image_pull_policy = Some(config.tor_image.pull_policy.clone());
+        container.resources = object.gateway_resources();
+    }
+
+    if let Some(target) = object.tls_target().filter(|_| object.tls_enabled()) {
+        let container = vec_get_or_insert(&mut containers, |f| f.name == "tls");
+        container.name = "tls".to_string();
+        container.args = Some(
+            [
+                "tls",
+                "run",
+                "--certificate",
+                "/etc/tls/tls.crt",
+                "--key",
+                "/etc/tls/tls.key",
+                "--listen",
+                TLS_LISTEN,
+                "--target",
+                target,
+            ]
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+        );
+        container.command = Some(vec!["tor-operator".into()]);
+        container.image = Some(config.tor_image.uri.clone());
+        container.image_pull_policy = Some(config.tor_image.pull_policy.clone());
+        container.resources = object.tls_resources();
+
+        let volume_mounts = container.volume_mounts.get_or_insert_with(Default::default);
+        let volume_mount = vec_get_or_insert(volume_mounts, |f| f.name == "etc-tls");
+        volume_mount.name = "etc-tls".to_string();
+        volume_mount.mount_path = "/etc/tls".into();
+        volume_mount.read_only = Some(true);
+    }
+
+    for container in &mut containers {
+        container.security_context = Some(container_security_context());
     }
 
     containers
@@ -970,46 +2653,19 @@ fn generate_deployment_init_containers(object: &OnionService) -> Vec<Container>
     let mut containers = object.deployment_init_containers();
 
     for container in &mut containers {
-        container.security_context = Some(SecurityContext {
-            capabilities: Some(Capabilities {
-                drop: Some(vec!["ALL".to_string()]),
-                ..Default::default()
-            }),
-            ..Default::default()
-        });
+        container.security_context = Some(container_security_context());
     }
 
     containers
 }
 
-fn generate_deployment_volumes(object: &OnionService, onion_key: &OnionKey) -> Vec<Volume> {
+fn generate_deployment_volumes(object: &OnionService, onion_key: Option<&OnionKey>) -> Vec<Volume> {
     let mut volumes = object.deployment_volumes();
 
-    {
+    if let Some(onion_key) = onion_key {
         let volume = vec_get_or_insert(&mut volumes, |f| f.name == "etc-secrets");
         volume.name = "etc-secrets".to_string();
-        volume.secret = Some(SecretVolumeSource {
-            default_mode: Some(0o400),
-            items: Some(vec![
-                KeyToPath {
-                    key: "hostname".into(),
-                    mode: Some(0o400),
-                    path: "hostname".into(),
-                },
-                KeyToPath {
-                    key: "hs_ed25519_public_key".into(),
-                    mode: Some(0o400),
-                    path: "hs_ed25519_public_key".into(),
-                },
-                KeyToPath {
-                    key: "hs_ed25519_secret_key".into(),
-                    mode: Some(0o400),
-                    path: "hs_ed25519_secret_key".into(),
-                },
-            ]),
-            optional: Some(false),
-            secret_name: Some(onion_key.secret_name().into()),
-        });
+        volume.secret = Some(onion_key.secret_volume_source());
     }
 
     {
@@ -1030,6 +2686,15 @@ fn generate_deployment_volumes(object: &OnionService, onion_key: &OnionKey) -> V
                         path: "ob_config".into(),
                     });
                 }
+                for (index, port) in object.ports().iter().enumerate() {
+                    if port.fallback.as_ref().is_some_and(|f| f.body.is_some()) {
+                        items.push(KeyToPath {
+                            key: format!("fallback_{index}"),
+                            mode: Some(0o400),
+                            path: format!("fallback_{index}"),
+                        });
+                    }
+                }
                 items
             }),
             name: object.config_map_name().into(),
@@ -1037,62 +2702,589 @@ fn generate_deployment_volumes(object: &OnionService, onion_key: &OnionKey) -> V
         });
     }
 
+    if object.tls_enabled() {
+        let volume = vec_get_or_insert(&mut volumes, |f| f.name == "etc-tls");
+        volume.name = "etc-tls".to_string();
+        volume.secret = Some(SecretVolumeSource {
+            default_mode: Some(0o400),
+            items: Some(vec![
+                KeyToPath {
+                    key: "tls.crt".into(),
+                    mode: Some(0o400),
+                    path: "tls.crt".into(),
+                },
+                KeyToPath {
+                    key: "tls.key".into(),
+                    mode: Some(0o400),
+                    path: "tls.key".into(),
+                },
+            ]),
+            optional: Some(false),
+            secret_name: Some(object.tls_secret_name().into()),
+        });
+    }
+
     volumes
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Injects Tor, and the gateway and TLS sidecars when enabled, into a Pod
+/// labelled, or annotated, with `tor.agabani.co.uk/onion-service`.
+///
+/// The injected containers and volumes are prefixed with `tor-`, so that they
+/// do not clash with those of the Pod.
+///
+/// # Errors
+///
+/// Returns an error if the Pod has a container or volume of the same name as
+/// an injected one, unless the Pod has already been injected.
+pub fn inject_pod(
+    object: &OnionService,
+    config: &Config,
+    onion_key: &OnionKey,
+    mut pod: Pod,
+) -> std::result::Result<Pod, String> {
+    const CONTAINERS: [&str; 3] = ["gateway", "tls", "tor"];
+    const VOLUMES: [&str; 3] = ["etc-configs", "etc-secrets", "etc-tls"];
 
-    #[test]
-    fn config() {
-        let object = &OnionService {
-            spec: OnionServiceSpec {
-                ports: vec![
-                    OnionServiceSpecHiddenServicePort {
-                        target: "example:80".into(),
-                        virtport: 80,
-                    },
-                    OnionServiceSpecHiddenServicePort {
-                        target: "example:443".into(),
-                        virtport: 443,
-                    },
-                ],
-                ..Default::default()
-            },
-            ..Default::default()
-        };
+    fn injected_name(name: &str) -> String {
+        if name == "tor" {
+            name.to_string()
+        } else {
+            format!("tor-{name}")
+        }
+    }
 
-        let ob_config = generate_ob_config(object);
+    let security_context = object.deployment_security_context();
 
-        assert!(ob_config.is_none());
+    // The webhook is invoked again for a Pod it has already injected, when
+    // another webhook changes the Pod.
+    let injected = pod
+        .metadata
+        .annotations
+        .as_ref()
+        .is_some_and(|f| f.contains_key(TOR_AGABANI_CO_UK_HOSTNAME_KEY));
 
-        let torrc = generate_torrc(object);
+    if let (false, Some(spec)) = (injected, &pod.spec) {
+        if let Some(container) = spec
+            .containers
+            .iter()
+            .find(|f| CONTAINERS.map(injected_name).contains(&f.name))
+        {
+            return Err(format!(
+                "the Pod already has a container named {}",
+                container.name
+            ));
+        }
+        if let Some(volume) = spec
+            .volumes
+            .iter()
+            .flatten()
+            .find(|f| VOLUMES.map(injected_name).contains(&f.name))
+        {
+            return Err(format!(
+                "the Pod already has a volume named {}",
+                volume.name
+            ));
+        }
+    }
 
-        assert_eq!(
-            r"DataDirectory ${TOR_TMP_DIR}/home/.tor
-HiddenServiceDir ${TOR_TMP_DIR}/var/lib/tor/hidden_service
-HiddenServicePort 80 example:80
-HiddenServicePort 443 example:443",
-            torrc.to_string()
-        );
+    if let Some(hostname) = onion_key.hostname() {
+        pod.metadata
+            .annotations
+            .get_or_insert_with(Default::default)
+            .insert(TOR_AGABANI_CO_UK_HOSTNAME_KEY.into(), hostname.to_string());
+    }
+
+    let spec = pod.spec.get_or_insert_with(Default::default);
+
+    for mut container in generate_deployment_containers(object, config)
+        .into_iter()
+        .filter(|f| CONTAINERS.contains(&f.name.as_str()))
+    {
+        container.name = injected_name(&container.name);
+
+        for volume_mount in container.volume_mounts.iter_mut().flatten() {
+            if VOLUMES.contains(&volume_mount.name.as_str()) {
+                volume_mount.name = injected_name(&volume_mount.name);
+            }
+        }
+
+        if container.name == "tor" {
+            if let Some(resources) = object.injection_resources() {
+                container.resources = Some(resources);
+            }
+            container
+                .volume_mounts
+                .get_or_insert_with(Default::default)
+                .extend(object.injection_volume_mounts());
+        }
+
+        // The security context of the Pod belongs to the application.
+        if let Some(container_security_context) = &mut container.security_context {
+            container_security_context.run_as_non_root = security_context.run_as_non_root;
+            container_security_context.run_as_user = security_context.run_as_user;
+        }
+
+        let name = container.name.clone();
+        *vec_get_or_insert(&mut spec.containers, |f| f.name == name) = container;
+    }
+
+    let volumes = spec.volumes.get_or_insert_with(Default::default);
+
+    for mut volume in generate_deployment_volumes(object, Some(onion_key))
+        .into_iter()
+        .filter(|f| VOLUMES.contains(&f.name.as_str()))
+    {
+        volume.name = injected_name(&volume.name);
+
+        // Only the injected containers mount the volumes, which are made
+        // readable without relying on the fsGroup of the Pod.
+        let (default_mode, items) = match (&mut volume.config_map, &mut volume.secret) {
+            (Some(config_map), _) => (&mut config_map.default_mode, &mut config_map.items),
+            (None, Some(secret)) => (&mut secret.default_mode, &mut secret.items),
+            (None, None) => continue,
+        };
+        *default_mode = Some(0o444);
+        for item in items.iter_mut().flatten() {
+            item.mode = Some(0o444);
+        }
+
+        let name = volume.name.clone();
+        *vec_get_or_insert(volumes, |f| f.name == name) = volume;
+    }
+
+    Ok(pod)
+}
+
+fn generate_lease(
+    object: &OnionService,
+    annotations: &Annotations,
+    labels: &Labels,
+) -> Result<Option<Lease>> {
+    if !object.high_availability_enabled() {
+        return Ok(None);
+    }
+
+    Ok(Some(Lease {
+        metadata: ObjectMeta {
+            name: Some(object.lease_name().into()),
+            annotations: Some(annotations.clone().into()),
+            labels: Some(labels.clone().into()),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        spec: Some(LeaseSpec {
+            lease_duration_seconds: Some(object.high_availability_lease_duration_seconds()),
+            ..Default::default()
+        }),
+    }))
+}
+
+fn generate_pod_disruption_budget(
+    object: &OnionService,
+    annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+) -> Result<Option<PodDisruptionBudget>> {
+    if !object.pod_disruption_budget_enabled() {
+        return Ok(None);
+    }
+
+    Ok(Some(PodDisruptionBudget {
+        metadata: ObjectMeta {
+            name: Some(object.pod_disruption_budget_name().into()),
+            annotations: Some(
+                annotations
+                    .clone()
+                    .append_reverse(object.pod_disruption_budget_annotations())
+                    .into(),
+            ),
+            labels: Some(
+                labels
+                    .clone()
+                    .append_reverse(object.pod_disruption_budget_labels())
+                    .into(),
+            ),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        spec: Some(PodDisruptionBudgetSpec {
+            max_unavailable: object.pod_disruption_budget_max_unavailable(),
+            min_available: object.pod_disruption_budget_min_available(),
+            selector: Some(LabelSelector {
+                match_labels: Some(selector_labels.into()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }))
+}
+
+fn generate_role(
+    object: &OnionService,
+    annotations: &Annotations,
+    labels: &Labels,
+) -> Result<Option<Role>> {
+    if !object.service_account_enabled() {
+        return Ok(None);
+    }
+
+    Ok(Some(Role {
+        metadata: ObjectMeta {
+            name: Some(object.role_name().into()),
+            annotations: Some(annotations.clone().into()),
+            labels: Some(labels.clone().into()),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        rules: Some({
+            let mut rules = Vec::new();
+            if object.high_availability_enabled() {
+                rules.push(PolicyRule {
+                    api_groups: Some(vec!["coordination.k8s.io".into()]),
+                    resource_names: Some(vec![object.lease_name().into()]),
+                    resources: Some(vec!["leases".into()]),
+                    verbs: vec!["get".into(), "update".into()],
+                    ..Default::default()
+                });
+            }
+            if object.ephemeral() {
+                rules.push(PolicyRule {
+                    api_groups: Some(vec![String::new()]),
+                    resource_names: Some(vec![object.config_map_name().into()]),
+                    resources: Some(vec!["configmaps".into()]),
+                    verbs: vec!["patch".into()],
+                    ..Default::default()
+                });
+            }
+            if object.fallback_enabled() {
+                rules.push(PolicyRule {
+                    api_groups: Some(vec!["discovery.k8s.io".into()]),
+                    resources: Some(vec!["endpointslices".into()]),
+                    verbs: vec!["get".into(), "list".into(), "watch".into()],
+                    ..Default::default()
+                });
+            }
+            rules
+        }),
+    }))
+}
+
+fn generate_role_binding(
+    object: &OnionService,
+    annotations: &Annotations,
+    labels: &Labels,
+) -> Result<Option<RoleBinding>> {
+    if !object.service_account_enabled() {
+        return Ok(None);
+    }
+
+    Ok(Some(RoleBinding {
+        metadata: ObjectMeta {
+            name: Some(object.role_binding_name().into()),
+            annotations: Some(annotations.clone().into()),
+            labels: Some(labels.clone().into()),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        role_ref: RoleRef {
+            api_group: "rbac.authorization.k8s.io".into(),
+            kind: "Role".into(),
+            name: object.role_name().into(),
+        },
+        subjects: Some(vec![Subject {
+            kind: "ServiceAccount".into(),
+            name: object.service_account_name().into(),
+            namespace: Some(object.try_namespace()?.into()),
+            ..Default::default()
+        }]),
+    }))
+}
+
+fn generate_service_account(
+    object: &OnionService,
+    annotations: &Annotations,
+    labels: &Labels,
+) -> Result<Option<ServiceAccount>> {
+    if !object.service_account_enabled() {
+        return Ok(None);
+    }
+
+    Ok(Some(ServiceAccount {
+        metadata: ObjectMeta {
+            name: Some(object.service_account_name().into()),
+            annotations: Some(annotations.clone().into()),
+            labels: Some(labels.clone().into()),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        ..Default::default()
+    }))
+}
+
+fn generate_metrics_service(
+    object: &OnionService,
+    annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+) -> Result<Option<Service>> {
+    let Some(metrics) = object.metrics().filter(|f| f.service_enabled()) else {
+        return Ok(None);
+    };
+
+    Ok(Some(Service {
+        metadata: ObjectMeta {
+            name: Some(object.metrics_service_name().into()),
+            annotations: Some(
+                annotations
+                    .clone()
+                    .append_reverse(object.metrics_service_annotations())
+                    .into(),
+            ),
+            labels: Some(
+                labels
+                    .clone()
+                    .append_reverse(object.metrics_service_labels())
+                    .into(),
+            ),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        spec: Some(ServiceSpec {
+            ports: Some(vec![ServicePort {
+                name: Some("metrics".to_string()),
+                port: metrics.port(),
+                protocol: Some("TCP".to_string()),
+                target_port: Some(IntOrString::String("metrics".to_string())),
+                ..Default::default()
+            }]),
+            selector: Some(selector_labels.into()),
+            type_: Some("ClusterIP".to_string()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }))
+}
+
+fn generate_service_monitor(
+    object: &OnionService,
+    annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+) -> Result<Option<ServiceMonitor>> {
+    if object
+        .metrics()
+        .and_then(|f| f.service_monitor.as_ref())
+        .is_none()
+    {
+        return Ok(None);
+    }
+
+    Ok(Some(ServiceMonitor {
+        metadata: ObjectMeta {
+            name: Some(object.metrics_service_monitor_name().into()),
+            annotations: Some(
+                annotations
+                    .clone()
+                    .append_reverse(object.metrics_service_monitor_annotations())
+                    .into(),
+            ),
+            labels: Some(
+                labels
+                    .clone()
+                    .append_reverse(object.metrics_service_monitor_labels())
+                    .into(),
+            ),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        spec: ServiceMonitorSpec {
+            endpoints: vec![ServiceMonitorEndpoint {
+                interval: object.metrics_service_monitor_interval(),
+                port: "metrics".to_string(),
+            }],
+            selector: LabelSelector {
+                match_labels: Some(selector_labels.into()),
+                ..Default::default()
+            },
+        },
+    }))
+}
+
+/// Issues a certificate for the hostname, signed by the CA when there is
+/// one, or keeps the current one until it is due for renewal.
+fn generate_tls_secret(
+    object: &OnionService,
+    annotations: &Annotations,
+    labels: &Labels,
+    hostname: Option<&Hostname>,
+    secret: Option<&Secret>,
+    ca: Option<&Secret>,
+    now: Timestamp,
+) -> Result<Option<Secret>> {
+    fn data<'a>(secret: &'a Secret, key: &str) -> Option<&'a [u8]> {
+        secret
+            .data
+            .as_ref()
+            .and_then(|data| data.get(key))
+            .map(|value| value.0.as_slice())
+    }
+
+    if !object.tls_enabled() || object.tls_certificate_enabled() {
+        return Ok(None);
+    }
+
+    let Some(hostname) = hostname.map(ToString::to_string) else {
+        return Ok(None);
+    };
+
+    let ca = ca.and_then(|ca| Some((data(ca, "tls.crt")?, data(ca, "tls.key")?)));
+    let ca_missing = object.tls_ca_secret_name().is_some() && ca.is_none();
+    if ca_missing {
+        tracing::warn!("CA Secret not found");
     }
 
+    let current = secret.and_then(|secret| {
+        let annotations = secret.metadata.annotations.as_ref()?;
+        let not_after = annotations
+            .get(TOR_AGABANI_CO_UK_NOT_AFTER_KEY)?
+            .parse::<Timestamp>()
+            .ok()?;
+        let renew = annotations.get(TOR_AGABANI_CO_UK_HOSTNAME_KEY) != Some(&hostname)
+            || not_after <= now + TLS_CERTIFICATE_RENEW_BEFORE
+            || data(secret, "ca.crt") != ca.map(|(certificate, _)| certificate);
+        Some((secret.data.clone().unwrap_or_default(), not_after, renew))
+    });
+
+    let (data, not_after) = match current {
+        // The current certificate is kept while the CA is unavailable.
+        Some((data, not_after, renew)) if !renew || ca_missing => (data, not_after),
+        _ if ca_missing => return Ok(None),
+        _ => {
+            tracing::info!("issuing certificate");
+            issue_certificate(&hostname, ca, now)?
+        }
+    };
+
+    let mut secret_annotations: BTreeMap<String, String> = annotations.clone().into();
+    secret_annotations.insert(TOR_AGABANI_CO_UK_HOSTNAME_KEY.into(), hostname);
+    secret_annotations.insert(
+        TOR_AGABANI_CO_UK_NOT_AFTER_KEY.into(),
+        not_after.to_string(),
+    );
+
+    Ok(Some(Secret {
+        metadata: ObjectMeta {
+            name: Some(object.tls_secret_name().into()),
+            annotations: Some(secret_annotations),
+            labels: Some(labels.into()),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        data: Some(data),
+        type_: Some("kubernetes.io/tls".into()),
+        ..Default::default()
+    }))
+}
+
+/// Returns the certificate and private key, and the expiry of the certificate.
+fn issue_certificate(
+    hostname: &str,
+    ca: Option<(&[u8], &[u8])>,
+    now: Timestamp,
+) -> Result<(BTreeMap<String, ByteString>, Timestamp)> {
+    // Certificate validity has a granularity of days.
+    let not_before = Timestamp::from_second(now.as_second() - now.as_second().rem_euclid(86400))
+        .expect("midnight is within range");
+    let not_after = not_before + TLS_CERTIFICATE_DURATION;
+    let date_time = |timestamp: Timestamp| {
+        let date = timestamp.to_zoned(TimeZone::UTC).date();
+        date_time_ymd(
+            date.year().into(),
+            date.month().unsigned_abs(),
+            date.day().unsigned_abs(),
+        )
+    };
+
+    let mut params = CertificateParams::new(vec![hostname.to_string()])?;
+    params.distinguished_name.push(DnType::CommonName, hostname);
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+    params.not_before = date_time(not_before);
+    params.not_after = date_time(not_after);
+
+    let key_pair = KeyPair::generate()?;
+    let mut data = BTreeMap::new();
+
+    let certificate = match ca {
+        Some((ca_certificate, ca_key)) => {
+            let issuer = Issuer::from_ca_cert_pem(
+                &String::from_utf8_lossy(ca_certificate),
+                KeyPair::from_pem(&String::from_utf8_lossy(ca_key))?,
+            )?;
+            data.insert("ca.crt".into(), ByteString(ca_certificate.to_vec()));
+            params.signed_by(&key_pair, &issuer)?
+        }
+        None => params.self_signed(&key_pair)?,
+    };
+
+    data.insert("tls.crt".into(), ByteString(certificate.pem().into_bytes()));
+    data.insert(
+        "tls.key".into(),
+        ByteString(key_pair.serialize_pem().into_bytes()),
+    );
+
+    Ok((data, not_after))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
     #[test]
-    fn config_onion_balance() {
+    fn config() {
         let object = &OnionService {
             spec: OnionServiceSpec {
-                onion_balance: Some(OnionServiceSpecOnionBalance {
-                    onion_key: OnionServiceSpecOnionBalanceOnionKey {
-                        hostname: "hostname.onion".into(),
-                    },
-                }),
                 ports: vec![
                     OnionServiceSpecHiddenServicePort {
+                        fallback: None,
                         target: "example:80".into(),
                         virtport: 80,
                     },
                     OnionServiceSpecHiddenServicePort {
+                        fallback: None,
                         target: "example:443".into(),
                         virtport: 443,
                     },
@@ -1102,19 +3294,898 @@ HiddenServicePort 443 example:443",
             ..Default::default()
         };
 
-        let ob_config = generate_ob_config(object).unwrap();
+        let ob_config = generate_ob_config(object);
 
-        assert_eq!("MasterOnionAddress hostname.onion", ob_config.to_string());
+        assert!(ob_config.is_none());
 
         let torrc = generate_torrc(object);
 
         assert_eq!(
             r"DataDirectory ${TOR_TMP_DIR}/home/.tor
+ControlPort 127.0.0.1:6666
+CookieAuthentication 1
+ShutdownWaitLength 10
 HiddenServiceDir ${TOR_TMP_DIR}/var/lib/tor/hidden_service
-HiddenServiceOnionbalanceInstance 1
 HiddenServicePort 80 example:80
 HiddenServicePort 443 example:443",
             torrc.to_string()
         );
     }
+
+    #[test]
+    fn config_onion_balance() {
+        let object = &OnionService {
+            spec: OnionServiceSpec {
+                onion_balance: Some(OnionServiceSpecOnionBalance {
+                    onion_key: OnionServiceSpecOnionBalanceOnionKey {
+                        hostname: "hostname.onion".into(),
+                    },
+                }),
+                ports: vec![
+                    OnionServiceSpecHiddenServicePort {
+                        fallback: None,
+                        target: "example:80".into(),
+                        virtport: 80,
+                    },
+                    OnionServiceSpecHiddenServicePort {
+                        fallback: None,
+                        target: "example:443".into(),
+                        virtport: 443,
+                    },
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let ob_config = generate_ob_config(object).unwrap();
+
+        assert_eq!("MasterOnionAddress hostname.onion", ob_config.to_string());
+
+        let torrc = generate_torrc(object);
+
+        assert_eq!(
+            r"DataDirectory ${TOR_TMP_DIR}/home/.tor
+ControlPort 127.0.0.1:6666
+CookieAuthentication 1
+ShutdownWaitLength 10
+HiddenServiceDir ${TOR_TMP_DIR}/var/lib/tor/hidden_service
+HiddenServiceOnionbalanceInstance 1
+HiddenServicePort 80 example:80
+HiddenServicePort 443 example:443",
+            torrc.to_string()
+        );
+    }
+
+    #[test]
+    fn config_persistence() {
+        let object = &OnionService {
+            spec: OnionServiceSpec {
+                persistence: Some(OnionServiceSpecPersistence::default()),
+                ports: vec![OnionServiceSpecHiddenServicePort {
+                    fallback: None,
+                    target: "example:80".into(),
+                    virtport: 80,
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let torrc = generate_torrc(object);
+
+        assert_eq!(
+            r"DataDirectory /var/lib/tor/data
+ControlPort 127.0.0.1:6666
+CookieAuthentication 1
+ShutdownWaitLength 10
+HiddenServiceDir ${TOR_TMP_DIR}/var/lib/tor/hidden_service
+HiddenServicePort 80 example:80",
+            torrc.to_string()
+        );
+
+        let containers = generate_deployment_containers(
+            object,
+            &Config {
+                tor_image: ImageConfig {
+                    pull_policy: "IfNotPresent".into(),
+                    uri: "tor".into(),
+                },
+            },
+        );
+        let tor = containers.iter().find(|f| f.name == "tor").unwrap();
+
+        assert!(
+            tor.args
+                .as_ref()
+                .unwrap()
+                .windows(2)
+                .any(|f| f == ["--directory", "/var/lib/tor/data"])
+        );
+        assert!(
+            tor.volume_mounts
+                .as_ref()
+                .unwrap()
+                .iter()
+                .any(|f| f.name == "var-lib-tor" && f.mount_path == "/var/lib/tor")
+        );
+    }
+
+    #[test]
+    fn high_availability() {
+        let object = &OnionService {
+            metadata: ObjectMeta {
+                name: Some("example".into()),
+                ..Default::default()
+            },
+            spec: OnionServiceSpec {
+                high_availability: Some(OnionServiceSpecHighAvailability {
+                    lease_duration_seconds: None,
+                    replicas: 2,
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert_eq!(2, object.high_availability_replicas());
+        assert_eq!(15, object.high_availability_lease_duration_seconds());
+
+        let containers = generate_deployment_containers(
+            object,
+            &Config {
+                tor_image: ImageConfig {
+                    pull_policy: "IfNotPresent".into(),
+                    uri: "tor".into(),
+                },
+            },
+        );
+        let tor = containers.iter().find(|f| f.name == "tor").unwrap();
+
+        assert!(
+            tor.args
+                .as_ref()
+                .unwrap()
+                .windows(2)
+                .any(|f| f == ["--leader-election-lease", "example"])
+        );
+        assert!(
+            tor.env
+                .as_ref()
+                .unwrap()
+                .iter()
+                .any(|f| f.name == "POD_NAME")
+        );
+    }
+
+    #[test]
+    fn pod_disruption_budget() {
+        let mut object = OnionService {
+            metadata: ObjectMeta {
+                name: Some("example".into()),
+                uid: Some("00000000-0000-0000-0000-000000000000".into()),
+                ..Default::default()
+            },
+            spec: OnionServiceSpec {
+                pod_disruption_budget: Some(KubernetesPodDisruptionBudget::default()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let pod_disruption_budget = generate_pod_disruption_budget(
+            &object,
+            &Annotations::new(),
+            &object.try_labels().unwrap(),
+            &object.try_selector_labels().unwrap(),
+        )
+        .unwrap()
+        .unwrap();
+        let spec = pod_disruption_budget.spec.unwrap();
+
+        assert_eq!(Some("example".into()), pod_disruption_budget.metadata.name);
+        assert_eq!(Some(IntOrString::Int(1)), spec.max_unavailable);
+        assert_eq!(None, spec.min_available);
+        assert_eq!(
+            Some(object.try_selector_labels().unwrap().into()),
+            spec.selector.unwrap().match_labels
+        );
+
+        object.spec.pod_disruption_budget = Some(KubernetesPodDisruptionBudget {
+            min_available: Some(IntOrString::String("50%".into())),
+            ..Default::default()
+        });
+
+        assert_eq!(None, object.pod_disruption_budget_max_unavailable());
+        assert_eq!(
+            Some(IntOrString::String("50%".into())),
+            object.pod_disruption_budget_min_available()
+        );
+
+        object.spec.pod_disruption_budget = None;
+
+        assert!(
+            generate_pod_disruption_budget(
+                &object,
+                &Annotations::new(),
+                &object.try_labels().unwrap(),
+                &object.try_selector_labels().unwrap(),
+            )
+            .unwrap()
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn rollout() {
+        let mut object = OnionService::default();
+
+        assert_eq!(
+            Some("Recreate".into()),
+            object.deployment_strategy().and_then(|f| f.type_)
+        );
+        assert_eq!(15, object.deployment_termination_grace_period_seconds());
+
+        let containers = generate_deployment_containers(
+            &object,
+            &Config {
+                tor_image: ImageConfig {
+                    pull_policy: "IfNotPresent".into(),
+                    uri: "tor".into(),
+                },
+            },
+        );
+        let tor = containers.iter().find(|f| f.name == "tor").unwrap();
+
+        assert!(
+            tor.args
+                .as_ref()
+                .unwrap()
+                .iter()
+                .any(|f| f == "--interrupt-on-terminate")
+        );
+
+        object.spec.high_availability = Some(OnionServiceSpecHighAvailability {
+            lease_duration_seconds: None,
+            replicas: 2,
+        });
+        object.spec.torrc = Some(KubernetesTorrc {
+            shutdown_wait_length_seconds: Some(30),
+            ..Default::default()
+        });
+
+        assert_eq!(None, object.deployment_strategy());
+        assert_eq!(35, object.deployment_termination_grace_period_seconds());
+        assert!(
+            generate_torrc(&object)
+                .to_string()
+                .lines()
+                .any(|f| f == "ShutdownWaitLength 30")
+        );
+    }
+
+    #[test]
+    fn tor_image() {
+        let mut object = OnionService::default();
+        let config = Config {
+            tor_image: ImageConfig {
+                pull_policy: "IfNotPresent".into(),
+                uri: "tor".into(),
+            },
+        };
+
+        let containers = generate_deployment_containers(&object, &config);
+        let tor = containers.iter().find(|f| f.name == "tor").unwrap();
+
+        assert_eq!(Some("tor".into()), tor.image);
+        assert_eq!(Some("IfNotPresent".into()), tor.image_pull_policy);
+
+        object.spec.deployment = Some(OnionServiceSpecDeployment {
+            containers: Some(vec![Container {
+                name: "tor".into(),
+                image: Some("tor:canary".into()),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        });
+
+        let containers = generate_deployment_containers(&object, &config);
+        let tor = containers.iter().find(|f| f.name == "tor").unwrap();
+
+        assert_eq!(Some("tor:canary".into()), tor.image);
+        assert_eq!(None, tor.image_pull_policy);
+    }
+
+    #[test]
+    fn fallback() {
+        let object = &OnionService {
+            metadata: ObjectMeta {
+                name: Some("example".into()),
+                uid: Some("00000000-0000-0000-0000-000000000000".into()),
+                ..Default::default()
+            },
+            spec: OnionServiceSpec {
+                ports: vec![
+                    OnionServiceSpecHiddenServicePort {
+                        fallback: Some(OnionServiceSpecHiddenServicePortFallback {
+                            body: Some("maintenance".into()),
+                            status: Some(502),
+                            ..Default::default()
+                        }),
+                        target: "example.default.svc.cluster.local:80".into(),
+                        virtport: 80,
+                    },
+                    OnionServiceSpecHiddenServicePort {
+                        fallback: None,
+                        target: "example:443".into(),
+                        virtport: 443,
+                    },
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let torrc = generate_torrc(object).to_string();
+
+        assert!(
+            torrc
+                .lines()
+                .any(|f| f == "HiddenServicePort 80 127.0.0.1:19000")
+        );
+        assert!(
+            torrc
+                .lines()
+                .any(|f| f == "HiddenServicePort 443 example:443")
+        );
+
+        let containers = generate_deployment_containers(
+            object,
+            &Config {
+                tor_image: ImageConfig {
+                    pull_policy: "IfNotPresent".into(),
+                    uri: "tor".into(),
+                },
+            },
+        );
+        let fallback = containers.iter().find(|f| f.name == "fallback").unwrap();
+
+        assert_eq!(
+            Some(vec![
+                "fallback".to_string(),
+                "run".into(),
+                "--route".into(),
+                "listen=127.0.0.1:19000,target=example.default.svc.cluster.local:80,service=example,status=502,body-file=/etc/configs/fallback_0".into(),
+            ]),
+            fallback.args
+        );
+
+        let config_map = generate_config_map(
+            object,
+            &Annotations::new(),
+            &Labels::default(),
+            None,
+            &generate_torrc(object),
+        )
+        .unwrap();
+
+        assert_eq!(
+            Some("maintenance"),
+            config_map
+                .data
+                .as_ref()
+                .unwrap()
+                .get("fallback_0")
+                .map(String::as_str)
+        );
+
+        let role = generate_role(object, &Annotations::new(), &Labels::default())
+            .unwrap()
+            .unwrap();
+
+        assert!(role.rules.unwrap().iter().any(|rule| {
+            rule.resources.as_deref() == Some(&["endpointslices".to_string()][..])
+        }));
+    }
+
+    #[test]
+    #[allow(clippy::too_many_lines)]
+    fn injection() {
+        let object = &OnionService {
+            metadata: ObjectMeta {
+                name: Some("example".into()),
+                uid: Some("00000000-0000-0000-0000-000000000000".into()),
+                ..Default::default()
+            },
+            spec: OnionServiceSpec {
+                injection: Some(OnionServiceSpecInjection {
+                    volume_mounts: Some(vec![VolumeMount {
+                        mount_path: "/run".into(),
+                        name: "run".into(),
+                        ..Default::default()
+                    }]),
+                    ..Default::default()
+                }),
+                onion_key: Some(OnionServiceSpecOnionKey {
+                    name: "example".into(),
+                }),
+                ports: vec![OnionServiceSpecHiddenServicePort {
+                    fallback: None,
+                    target: "unix:/run/app.sock".into(),
+                    virtport: 80,
+                }],
+                tls: Some(OnionServiceSpecTls {
+                    target: "127.0.0.1:8080".into(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let config = &Config {
+            tor_image: ImageConfig {
+                pull_policy: "IfNotPresent".into(),
+                uri: "tor".into(),
+            },
+        };
+        let onion_key = &OnionKey {
+            spec: crate::onion_key::OnionKeySpec {
+                secret: crate::onion_key::OnionKeySpecSecret {
+                    name: "example".into(),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            status: Some(crate::onion_key::OnionKeyStatus {
+                hostname: Some("example.onion".into()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        assert!(
+            generate_torrc(object)
+                .to_string()
+                .lines()
+                .any(|f| f == "HiddenServicePort 80 unix:/run/app.sock")
+        );
+
+        assert_eq!(
+            None,
+            generate_deployment(
+                object,
+                config,
+                &Annotations::new(),
+                &Annotations::new(),
+                &Labels::default(),
+                &object.try_selector_labels().unwrap(),
+                Some(onion_key),
+            )
+            .unwrap()
+        );
+
+        let pod = inject_pod(
+            object,
+            config,
+            onion_key,
+            Pod {
+                spec: Some(PodSpec {
+                    containers: vec![Container {
+                        name: "app".into(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            Some("example.onion"),
+            pod.metadata
+                .annotations
+                .as_ref()
+                .and_then(|f| f.get(TOR_AGABANI_CO_UK_HOSTNAME_KEY))
+                .map(String::as_str)
+        );
+
+        let spec = pod.spec.unwrap();
+
+        assert_eq!(
+            vec!["app", "tor", "tor-tls"],
+            spec.containers
+                .iter()
+                .map(|f| f.name.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["tor-etc-secrets", "tor-etc-configs", "tor-etc-tls"],
+            spec.volumes
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|f| f.name.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        let tor = spec.containers.iter().find(|f| f.name == "tor").unwrap();
+
+        assert_eq!(
+            vec!["tor-etc-secrets", "tor-etc-configs", "run"],
+            tor.volume_mounts
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|f| f.name.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some(10001),
+            tor.security_context.as_ref().and_then(|f| f.run_as_user)
+        );
+
+        // Injecting an injected Pod again updates the injected containers.
+        assert_eq!(
+            Ok(["app", "tor", "tor-tls"].map(String::from).to_vec()),
+            inject_pod(
+                object,
+                config,
+                onion_key,
+                Pod {
+                    metadata: pod.metadata.clone(),
+                    spec: Some(spec.clone()),
+                    ..Default::default()
+                }
+            )
+            .map(|pod| {
+                pod.spec
+                    .unwrap()
+                    .containers
+                    .iter()
+                    .map(|f| f.name.clone())
+                    .collect::<Vec<_>>()
+            })
+        );
+
+        // A container of the application is never overwritten.
+        assert_eq!(
+            Some("the Pod already has a container named tor".to_string()),
+            inject_pod(
+                object,
+                config,
+                onion_key,
+                Pod {
+                    spec: Some(PodSpec {
+                        containers: vec![Container {
+                            name: "tor".into(),
+                            ..Default::default()
+                        }],
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            )
+            .err()
+        );
+    }
+
+    #[test]
+    fn gateway() {
+        let object = &OnionService {
+            spec: OnionServiceSpec {
+                gateway: Some(OnionServiceSpecGateway {
+                    connection_limit: Some(4),
+                    rate_limit: None,
+                    resources: None,
+                }),
+                ports: vec![OnionServiceSpecHiddenServicePort {
+                    fallback: None,
+                    target: "example:80".into(),
+                    virtport: 80,
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let torrc = generate_torrc(object).to_string();
+
+        assert!(
+            torrc
+                .lines()
+                .any(|f| f == "HiddenServiceExportCircuitID haproxy")
+        );
+        assert!(
+            torrc
+                .lines()
+                .any(|f| f == "HiddenServicePort 80 127.0.0.1:17000")
+        );
+
+        let containers = generate_deployment_containers(
+            object,
+            &Config {
+                tor_image: ImageConfig {
+                    pull_policy: "IfNotPresent".into(),
+                    uri: "tor".into(),
+                },
+            },
+        );
+        let gateway = containers.iter().find(|f| f.name == "gateway").unwrap();
+
+        assert_eq!(
+            Some(vec![
+                "gateway".to_string(),
+                "run".into(),
+                "--connection-limit".into(),
+                "4".into(),
+                "--route".into(),
+                "127.0.0.1:17000,example:80".into(),
+            ]),
+            gateway.args
+        );
+        assert!(gateway.security_context.is_some());
+    }
+
+    #[test]
+    fn tls() {
+        let object = &OnionService {
+            metadata: ObjectMeta {
+                name: Some("example".into()),
+                uid: Some("00000000-0000-0000-0000-000000000000".into()),
+                ..Default::default()
+            },
+            spec: OnionServiceSpec {
+                tls: Some(OnionServiceSpecTls {
+                    target: "example:80".into(),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let hostname = Hostname::new("example.onion".into());
+        let now: Timestamp = "2026-01-01T12:00:00Z".parse().unwrap();
+
+        assert!(
+            generate_torrc(object)
+                .to_string()
+                .lines()
+                .any(|f| f == "HiddenServicePort 443 127.0.0.1:18443")
+        );
+
+        let containers = generate_deployment_containers(
+            object,
+            &Config {
+                tor_image: ImageConfig {
+                    pull_policy: "IfNotPresent".into(),
+                    uri: "tor".into(),
+                },
+            },
+        );
+        let tls = containers.iter().find(|f| f.name == "tls").unwrap();
+
+        assert!(
+            tls.args
+                .as_ref()
+                .unwrap()
+                .windows(2)
+                .any(|f| f == ["--target", "example:80"])
+        );
+
+        let generate = |secret: Option<&Secret>, ca: Option<&Secret>, now: Timestamp| {
+            generate_tls_secret(
+                object,
+                &Annotations::new(),
+                &Labels::default(),
+                Some(&hostname),
+                secret,
+                ca,
+                now,
+            )
+            .unwrap()
+            .unwrap()
+        };
+
+        let secret = generate(None, None, now);
+
+        assert_eq!(
+            Some("2026-04-01T00:00:00Z"),
+            secret
+                .metadata
+                .annotations
+                .as_ref()
+                .unwrap()
+                .get(TOR_AGABANI_CO_UK_NOT_AFTER_KEY)
+                .map(String::as_str)
+        );
+        assert_eq!(secret.data, generate(Some(&secret), None, now).data);
+        assert_ne!(
+            secret.data,
+            generate(
+                Some(&secret),
+                None,
+                now + SignedDuration::from_hours(61 * 24)
+            )
+            .data
+        );
+
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = Secret {
+            data: Some(BTreeMap::from([
+                (
+                    "tls.crt".into(),
+                    ByteString(ca_params.self_signed(&ca_key).unwrap().pem().into_bytes()),
+                ),
+                (
+                    "tls.key".into(),
+                    ByteString(ca_key.serialize_pem().into_bytes()),
+                ),
+            ])),
+            ..Default::default()
+        };
+
+        let signed = generate(Some(&secret), Some(&ca), now);
+
+        assert_ne!(secret.data, signed.data);
+        assert_eq!(
+            ca.data.as_ref().unwrap().get("tls.crt"),
+            signed.data.as_ref().unwrap().get("ca.crt")
+        );
+    }
+
+    #[test]
+    fn ephemeral() {
+        let object = &OnionService {
+            metadata: ObjectMeta {
+                creation_timestamp: Some(Time("2026-01-01T00:00:00Z".parse().unwrap())),
+                name: Some("example".into()),
+                uid: Some("uid".into()),
+                ..Default::default()
+            },
+            spec: OnionServiceSpec {
+                ephemeral: Some(true),
+                ports: vec![OnionServiceSpecHiddenServicePort {
+                    fallback: None,
+                    target: "example:80".into(),
+                    virtport: 80,
+                }],
+                ttl: Some("1h30m".into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Some("2026-01-01T01:30:00Z".parse().unwrap()),
+            object.expires_at()
+        );
+
+        let torrc = generate_torrc(object);
+
+        assert_eq!(
+            r"DataDirectory ${TOR_TMP_DIR}/home/.tor
+ControlPort 127.0.0.1:6666
+CookieAuthentication 1
+ShutdownWaitLength 10",
+            torrc.to_string()
+        );
+
+        let containers = generate_deployment_containers(
+            object,
+            &Config {
+                tor_image: ImageConfig {
+                    pull_policy: "IfNotPresent".into(),
+                    uri: "tor".into(),
+                },
+            },
+        );
+        let tor = containers.iter().find(|f| f.name == "tor").unwrap();
+
+        assert!(
+            tor.args
+                .as_ref()
+                .unwrap()
+                .windows(2)
+                .any(|f| f == ["--add-onion-port", "80,example:80"])
+        );
+        assert!(
+            tor.args
+                .as_ref()
+                .unwrap()
+                .windows(2)
+                .any(|f| f == ["--config-map-name", "example"])
+        );
+        assert!(
+            !tor.volume_mounts
+                .as_ref()
+                .unwrap()
+                .iter()
+                .any(|f| f.name == "etc-secrets")
+        );
+
+        let role = generate_role(object, &Annotations::new(), &Labels::default())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            Some(vec![PolicyRule {
+                api_groups: Some(vec![String::new()]),
+                resource_names: Some(vec!["example".into()]),
+                resources: Some(vec!["configmaps".into()]),
+                verbs: vec!["patch".into()],
+                ..Default::default()
+            }]),
+            role.rules
+        );
+        assert!(generate_misconfigured_state(object).is_none());
+    }
+
+    #[test]
+    fn misconfigured() {
+        let object = &mut OnionService {
+            spec: OnionServiceSpec {
+                ephemeral: Some(true),
+                onion_key: Some(OnionServiceSpecOnionKey {
+                    name: "example".into(),
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            generate_misconfigured_state(object),
+            Some(State::Misconfigured {
+                reason: "EphemeralUnsupported",
+                message,
+            }) if message == "Ephemeral Onion Services do not support onionKey."
+        ));
+
+        object.spec.ttl = Some("9999999999999999h".into());
+
+        assert!(matches!(
+            generate_misconfigured_state(object),
+            Some(State::Misconfigured {
+                reason: "TtlMalformed",
+                ..
+            })
+        ));
+        assert_eq!(None, object.ttl());
+    }
+
+    #[test]
+    fn misconfigured_export_circuit_id() {
+        let object = &mut OnionService {
+            spec: OnionServiceSpec {
+                export_circuit_id: Some(OnionServiceSpecExportCircuitId::Haproxy),
+                ports: vec![OnionServiceSpecHiddenServicePort {
+                    fallback: Some(OnionServiceSpecHiddenServicePortFallback::default()),
+                    ..Default::default()
+                }],
+                tls: Some(OnionServiceSpecTls::default()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            generate_misconfigured_state(object),
+            Some(State::Misconfigured {
+                reason: "ExportCircuitIdUnsupported",
+                message,
+            }) if message == "Exporting the circuit ID without the gateway does not support tls, fallback."
+        ));
+
+        object.spec.gateway = Some(OnionServiceSpecGateway::default());
+
+        assert!(generate_misconfigured_state(object).is_none());
+
+        object.spec.gateway = None;
+        object.spec.export_circuit_id = None;
+
+        assert!(generate_misconfigured_state(object).is_none());
+    }
 }
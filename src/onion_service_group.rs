@@ -0,0 +1,1439 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+use futures::StreamExt;
+use k8s_openapi::{
+    api::{
+        apps::v1::{Deployment, DeploymentSpec, DeploymentStrategy},
+        core::v1::{
+            Affinity, ConfigMap, ConfigMapVolumeSource, Container, KeyToPath, LocalObjectReference,
+            PodSecurityContext, PodSpec, PodTemplateSpec, Toleration, TopologySpreadConstraint,
+            Volume,
+        },
+    },
+    apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition,
+    apimachinery::pkg::apis::meta::v1::{Condition, LabelSelector, Time},
+    jiff::Timestamp,
+};
+use kube::{
+    Client, CustomResource, CustomResourceExt, Resource,
+    core::ObjectMeta,
+    runtime::{Controller, controller::Action, watcher::Config as WatcherConfig},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, Result,
+    collections::vec_get_or_insert,
+    kubernetes::{
+        self, Annotation, Annotations, Api, ConditionsExt, ErrorBackoff, Labels, Object,
+        Resource as KubernetesResource, ResourceName, SelectorLabels, Subset,
+        Torrc as KubernetesTorrc, TorrcReloadStrategy, container_security_context, error_policy,
+        liveness_probe, pod_security_context, readiness_probe, startup_probe,
+    },
+    metrics::Metrics,
+    onion_key::OnionKey,
+    tor::Torrc,
+};
+
+/*
+ * ============================================================================
+ * Custom Resource Definition
+ * ============================================================================
+ */
+/// # `OnionServiceGroup`
+///
+/// An `OnionServiceGroup` is an abstraction of several Tor Onion Services
+/// sharing a single Tor process.
+///
+/// Each Onion Service has its own `OnionKey` and ports, saving the memory of a
+/// Deployment and Tor process per Onion Service.
+#[allow(clippy::module_name_repetitions)]
+#[derive(CustomResource, JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[kube(
+    derive = "Default",
+    derive = "PartialEq",
+    group = "tor.agabani.co.uk",
+    kind = "OnionServiceGroup",
+    namespaced,
+    printcolumn = r#"{"name":"OnionServices", "type":"number", "description":"The number of OnionServices", "jsonPath":".status.onionServices"}"#,
+    printcolumn = r#"{"name":"State", "type":"string", "description":"Human readable description of state", "jsonPath":".status.summary.Initialized"}"#,
+    printcolumn = r#"{"name":"Age", "type":"date", "jsonPath":".metadata.creationTimestamp"}"#,
+    status = "OnionServiceGroupStatus",
+    version = "v1"
+)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionServiceGroupSpec {
+    /// Config Map settings.
+    pub config_map: Option<OnionServiceGroupSpecConfigMap>,
+
+    /// Deployment settings.
+    pub deployment: Option<OnionServiceGroupSpecDeployment>,
+
+    /// Onion Services run by the Tor process.
+    pub onion_services: Vec<OnionServiceGroupSpecOnionService>,
+
+    /// Tor torrc settings.
+    pub torrc: Option<KubernetesTorrc>,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionServiceGroupSpecConfigMap {
+    /// Annotations is an unstructured key value map stored with a resource that may be set by external tools to store and retrieve arbitrary metadata. They are not queryable and should be preserved when modifying objects. More info: <http://kubernetes.io/docs/user-guide/annotations>
+    pub annotations: Option<BTreeMap<String, String>>,
+
+    /// Map of string keys and values that can be used to organize and categorize (scope and select) objects. May match selectors of replication controllers and services. More info: <http://kubernetes.io/docs/user-guide/labels>
+    pub labels: Option<BTreeMap<String, String>>,
+
+    /// Name of the Config Map.
+    ///
+    /// Default: name of the `OnionServiceGroup`
+    pub name: Option<String>,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionServiceGroupSpecDeployment {
+    /// If specified, the pod's scheduling constraints
+    pub affinity: Option<Affinity>,
+
+    /// Annotations is an unstructured key value map stored with a resource that may be set by external tools to store and retrieve arbitrary metadata. They are not queryable and should be preserved when modifying objects. More info: <http://kubernetes.io/docs/user-guide/annotations>
+    pub annotations: Option<BTreeMap<String, String>>,
+
+    /// Containers of the Deployment.
+    pub containers: Option<Vec<Container>>,
+
+    /// `ImagePullSecrets` is an optional list of references to secrets in the same namespace to use for pulling any of the images used by this `PodSpec`. If specified, these secrets will be passed to individual puller implementations for them to use. More info: <https://kubernetes.io/docs/concepts/containers/images#specifying-imagepullsecrets-on-a-pod>
+    pub image_pull_secrets: Option<Vec<LocalObjectReference>>,
+
+    /// List of initialization containers belonging to the pod. Init containers are executed in order prior to containers being started. If any init container fails, the pod is considered to have failed and is handled according to its restartPolicy. The name for an init container or normal container must be unique among all containers. Init containers may not have Lifecycle actions, Readiness probes, Liveness probes, or Startup probes. The resourceRequirements of an init container are taken into account during scheduling by finding the highest request/limit for each resource type, and then using the max of of that value or the sum of the normal containers. Limits are applied to init containers in a similar fashion. Init containers cannot currently be added or removed. Cannot be updated. More info: <https://kubernetes.io/docs/concepts/workloads/pods/init-containers/>
+    pub init_containers: Option<Vec<Container>>,
+
+    /// Map of string keys and values that can be used to organize and categorize (scope and select) objects. May match selectors of replication controllers and services. More info: <http://kubernetes.io/docs/user-guide/labels>
+    pub labels: Option<BTreeMap<String, String>>,
+
+    /// Name of the Deployment.
+    ///
+    /// Default: name of the `OnionServiceGroup`
+    pub name: Option<String>,
+
+    /// `NodeSelector` is a selector which must be true for the pod to fit on a node. Selector which must match a node's labels for the pod to be scheduled on that node. More info: <https://kubernetes.io/docs/concepts/configuration/assign-pod-node/>
+    pub node_selector: Option<std::collections::BTreeMap<String, String>>,
+
+    /// `SecurityContext` holds pod-level security attributes and common container settings. Optional: Defaults to empty.  See type description for default values of each field.
+    pub security_context: Option<PodSecurityContext>,
+
+    /// The deployment strategy to use to replace existing pods with new ones.
+    ///
+    /// Default: Recreate
+    pub strategy: Option<DeploymentStrategy>,
+
+    /// If specified, the pod's tolerations.
+    pub tolerations: Option<Vec<Toleration>>,
+
+    /// `TopologySpreadConstraints` describes how a group of pods ought to spread across topology domains. Scheduler will schedule pods in a way which abides by the constraints. All topologySpreadConstraints are `ANDed`.
+    pub topology_spread_constraints: Option<Vec<TopologySpreadConstraint>>,
+
+    /// List of volumes that can be mounted by containers belonging to the pod. More info: <https://kubernetes.io/docs/concepts/storage/volumes>
+    pub volumes: Option<Vec<Volume>>,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionServiceGroupSpecOnionService {
+    /// `OnionKey` settings.
+    pub onion_key: OnionServiceGroupSpecOnionServiceOnionKey,
+
+    /// Onion Service Hidden Service ports.
+    pub ports: Vec<OnionServiceGroupSpecHiddenServicePort>,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionServiceGroupSpecOnionServiceOnionKey {
+    /// Name of the `OnionKey`.
+    pub name: String,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionServiceGroupSpecHiddenServicePort {
+    /// The target any incoming traffic will be redirect to.
+    ///
+    /// Example: example.default.svc.cluster.local:80
+    pub target: String,
+
+    /// The virtual port that the Onion Service will be using.
+    ///
+    /// Example: 80
+    pub virtport: i32,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionServiceGroupStatus {
+    /// Represents the latest available observations of a deployment's current state.
+    ///
+    /// ### Initialized
+    ///
+    /// `Initialized`
+    ///
+    /// ### `OnionKey`
+    ///
+    /// `Duplicate`, `NotFound`, `HostnameNotFound`, `Ready`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conditions: Vec<Condition>,
+
+    /// `OnionKey` hostnames, keyed by the name of the `OnionKey`.
+    ///
+    /// The hostnames are only populated once every `OnionKey` is ready.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hostnames: BTreeMap<String, String>,
+
+    /// Onion Services of the `OnionServiceGroup`, in the order of
+    /// `onionServices`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub members: Vec<OnionServiceGroupStatusMember>,
+
+    /// Number of `OnionServices`.
+    pub onion_services: i32,
+
+    /// Represents the latest available observations of a deployment's current state.
+    #[serde(default)]
+    pub summary: BTreeMap<String, String>,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OnionServiceGroupStatusMember {
+    /// Hostname of the `OnionKey`.
+    pub hostname: Option<String>,
+
+    /// Name of the `OnionKey`.
+    pub onion_key: String,
+
+    /// Whether the Onion Service is ready to be run.
+    pub ready: bool,
+
+    /// Reason the Onion Service is not ready to be run.
+    ///
+    /// `Duplicate`, `NotFound`, `HostnameNotFound`
+    pub reason: Option<String>,
+}
+
+impl OnionServiceGroup {
+    #[must_use]
+    fn default_name(&self) -> ResourceName {
+        self.try_name()
+            .expect(".metadata.name is always set on a live resource")
+    }
+
+    #[must_use]
+    pub fn config_map_annotations(&self) -> Option<Annotations> {
+        self.spec
+            .config_map
+            .as_ref()
+            .and_then(|f| f.annotations.as_ref())
+            .cloned()
+            .map(Into::into)
+    }
+
+    #[must_use]
+    pub fn config_map_labels(&self) -> Option<Labels> {
+        self.spec
+            .config_map
+            .as_ref()
+            .and_then(|f| f.labels.as_ref())
+            .cloned()
+            .map(Into::into)
+    }
+
+    #[must_use]
+    pub fn config_map_name(&self) -> ResourceName {
+        self.spec
+            .config_map
+            .as_ref()
+            .and_then(|f| f.name.as_ref())
+            .map_or_else(|| self.default_name(), Into::into)
+    }
+
+    #[must_use]
+    pub fn deployment_affinity(&self) -> Option<Affinity> {
+        self.spec
+            .deployment
+            .as_ref()
+            .and_then(|f| f.affinity.as_ref())
+            .cloned()
+    }
+
+    #[must_use]
+    pub fn deployment_annotations(&self) -> Option<Annotations> {
+        self.spec
+            .deployment
+            .as_ref()
+            .and_then(|f| f.annotations.as_ref())
+            .cloned()
+            .map(Into::into)
+    }
+
+    #[must_use]
+    pub fn deployment_containers(&self) -> Vec<Container> {
+        self.spec
+            .deployment
+            .as_ref()
+            .and_then(|f| f.containers.as_ref())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn deployment_image_pull_secrets(&self) -> Option<Vec<LocalObjectReference>> {
+        self.spec
+            .deployment
+            .as_ref()
+            .and_then(|f| f.image_pull_secrets.as_ref())
+            .cloned()
+    }
+
+    #[must_use]
+    pub fn deployment_init_containers(&self) -> Vec<Container> {
+        self.spec
+            .deployment
+            .as_ref()
+            .and_then(|f| f.init_containers.as_ref())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn deployment_labels(&self) -> Option<Labels> {
+        self.spec
+            .deployment
+            .as_ref()
+            .and_then(|f| f.labels.as_ref())
+            .cloned()
+            .map(Into::into)
+    }
+
+    #[must_use]
+    pub fn deployment_name(&self) -> ResourceName {
+        self.spec
+            .deployment
+            .as_ref()
+            .and_then(|f| f.name.as_ref())
+            .map_or_else(|| self.default_name(), Into::into)
+    }
+
+    #[must_use]
+    pub fn deployment_node_selector(&self) -> Option<BTreeMap<String, String>> {
+        self.spec
+            .deployment
+            .as_ref()
+            .and_then(|f| f.node_selector.as_ref())
+            .cloned()
+    }
+
+    #[must_use]
+    pub fn deployment_security_context(&self) -> PodSecurityContext {
+        pod_security_context(
+            self.spec
+                .deployment
+                .as_ref()
+                .and_then(|f| f.security_context.as_ref())
+                .cloned()
+                .unwrap_or_default(),
+        )
+    }
+
+    #[must_use]
+    pub fn deployment_strategy(&self) -> Option<DeploymentStrategy> {
+        self.spec
+            .deployment
+            .as_ref()
+            .and_then(|f| f.strategy.as_ref())
+            .cloned()
+            .or_else(|| {
+                Some(DeploymentStrategy {
+                    type_: Some("Recreate".into()),
+                    ..Default::default()
+                })
+            })
+    }
+
+    #[must_use]
+    pub fn deployment_termination_grace_period_seconds(&self) -> i64 {
+        self.torrc_shutdown_wait_length_seconds() + 5
+    }
+
+    #[must_use]
+    pub fn deployment_tolerations(&self) -> Option<Vec<Toleration>> {
+        self.spec
+            .deployment
+            .as_ref()
+            .and_then(|f| f.tolerations.as_ref())
+            .cloned()
+    }
+
+    #[must_use]
+    pub fn deployment_topology_spread_constraints(&self) -> Option<Vec<TopologySpreadConstraint>> {
+        self.spec
+            .deployment
+            .as_ref()
+            .and_then(|f| f.topology_spread_constraints.as_ref())
+            .cloned()
+    }
+
+    #[must_use]
+    pub fn deployment_volumes(&self) -> Vec<Volume> {
+        self.spec
+            .deployment
+            .as_ref()
+            .and_then(|f| f.volumes.as_ref())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn onion_services(&self) -> &[OnionServiceGroupSpecOnionService] {
+        &self.spec.onion_services
+    }
+
+    #[must_use]
+    pub fn torrc_reload_strategy(&self) -> TorrcReloadStrategy {
+        self.spec
+            .torrc
+            .as_ref()
+            .and_then(|f| f.reload_strategy)
+            .unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn torrc_shutdown_wait_length_seconds(&self) -> i64 {
+        self.spec
+            .torrc
+            .as_ref()
+            .and_then(|f| f.shutdown_wait_length_seconds)
+            .unwrap_or(10)
+    }
+
+    #[must_use]
+    pub fn torrc_template(&self) -> Option<&str> {
+        self.spec
+            .torrc
+            .as_ref()
+            .and_then(|f| f.template.as_ref())
+            .map(String::as_str)
+    }
+
+    #[must_use]
+    pub fn status_conditions(&self) -> Option<&Vec<Condition>> {
+        self.status.as_ref().map(|f| f.conditions.as_ref())
+    }
+}
+
+impl OnionServiceGroupSpecOnionService {
+    #[must_use]
+    pub fn onion_key_name(&self) -> ResourceName {
+        ResourceName::from(&self.onion_key.name)
+    }
+
+    /// Directory, inside the container, the Onion Service keys are copied to.
+    #[must_use]
+    fn hidden_service_dir(&self) -> String {
+        format!(
+            "${{TOR_TMP_DIR}}/var/lib/tor/hidden_services/{}",
+            self.onion_key.name
+        )
+    }
+
+    /// Directory, inside the container, the `OnionKey` Secret is mounted at.
+    #[must_use]
+    fn secret_dir(&self) -> String {
+        format!("/etc/secrets/{}", self.onion_key.name)
+    }
+}
+
+impl KubernetesResource for OnionServiceGroup {
+    type Spec = OnionServiceGroupSpec;
+
+    fn spec(&self) -> &Self::Spec {
+        &self.spec
+    }
+}
+
+impl Object for OnionServiceGroup {
+    const APP_KUBERNETES_IO_COMPONENT_VALUE: &'static str = "onion-service-group";
+
+    type Status = OnionServiceGroupStatus;
+
+    fn status(&self) -> Option<&Self::Status> {
+        self.status.as_ref()
+    }
+}
+
+impl Subset for OnionServiceGroupSpec {
+    fn is_subset(&self, superset: &Self) -> bool {
+        self == superset
+    }
+}
+
+#[must_use]
+pub fn generate_custom_resource_definition() -> CustomResourceDefinition {
+    OnionServiceGroup::crd()
+}
+
+/// Hostnames of every `OnionKey` of the `OnionServiceGroup`.
+struct Hostnames(String);
+
+impl Annotation<'_> for Hostnames {
+    const NAME: &'static str = "hostnames";
+}
+
+impl<'a> From<&'a Hostnames> for std::borrow::Cow<'a, str> {
+    fn from(value: &'a Hostnames) -> Self {
+        std::borrow::Cow::Borrowed(&value.0)
+    }
+}
+
+/*
+ * ============================================================================
+ * Config
+ * ============================================================================
+ */
+pub struct Config {
+    pub tor_image: ImageConfig,
+}
+
+pub struct ImageConfig {
+    pub pull_policy: String,
+    pub uri: String,
+}
+
+/*
+ * ============================================================================
+ * Controller
+ * ============================================================================
+ */
+pub async fn run_controller(client: Client, config: Config, metrics: Metrics) {
+    metrics.kubernetes_api_usage_count::<OnionServiceGroup>("watch");
+    metrics.kubernetes_api_usage_count::<ConfigMap>("watch");
+    metrics.kubernetes_api_usage_count::<Deployment>("watch");
+    Controller::new(
+        kube::Api::<OnionServiceGroup>::all(client.clone()),
+        WatcherConfig::default(),
+    )
+    .owns(
+        kube::Api::<ConfigMap>::all(client.clone()),
+        WatcherConfig::default(),
+    )
+    .owns(
+        kube::Api::<Deployment>::all(client.clone()),
+        WatcherConfig::default(),
+    )
+    .shutdown_on_signal()
+    .run(
+        reconciler,
+        error_policy,
+        Arc::new(Context {
+            client,
+            config,
+            error_backoff: ErrorBackoff::default(),
+            metrics,
+        }),
+    )
+    .for_each(|_| async {})
+    .await;
+}
+
+/*
+ * ============================================================================
+ * Context
+ * ============================================================================
+ */
+struct Context {
+    client: Client,
+    config: Config,
+    error_backoff: ErrorBackoff,
+    metrics: Metrics,
+}
+
+impl kubernetes::Context for Context {
+    fn error_backoff(&self) -> &ErrorBackoff {
+        &self.error_backoff
+    }
+
+    fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+}
+
+/*
+ * ============================================================================
+ * State
+ * ============================================================================
+ */
+enum State {
+    OnionKeyDuplicate(ResourceName),
+    OnionKeyNotFound(ResourceName),
+    OnionKeyHostnameNotFound(ResourceName),
+    Initialized(Vec<OnionKey>),
+}
+
+impl State {
+    fn conditions(&self, generation: Option<i64>) -> Vec<Condition> {
+        match self {
+            State::OnionKeyDuplicate(name) => vec![Condition {
+                last_transition_time: Time(Timestamp::now()),
+                message: format!("The OnionKey {name} is used by more than one Onion Service."),
+                observed_generation: generation,
+                reason: "Duplicate".into(),
+                status: "False".into(),
+                type_: "OnionKey".into(),
+            }],
+            State::OnionKeyNotFound(name) => vec![Condition {
+                last_transition_time: Time(Timestamp::now()),
+                message: format!("The OnionKey {name} was not found."),
+                observed_generation: generation,
+                reason: "NotFound".into(),
+                status: "False".into(),
+                type_: "OnionKey".into(),
+            }],
+            State::OnionKeyHostnameNotFound(name) => vec![Condition {
+                last_transition_time: Time(Timestamp::now()),
+                message: format!("The OnionKey {name} does not have a hostname."),
+                observed_generation: generation,
+                reason: "HostnameNotFound".into(),
+                status: "False".into(),
+                type_: "OnionKey".into(),
+            }],
+            State::Initialized(_) => vec![
+                Condition {
+                    last_transition_time: Time(Timestamp::now()),
+                    message: "The OnionKeys are ready.".into(),
+                    observed_generation: generation,
+                    reason: "Ready".into(),
+                    status: "True".into(),
+                    type_: "OnionKey".into(),
+                },
+                Condition {
+                    last_transition_time: Time(Timestamp::now()),
+                    message: "The OnionServiceGroup is initialized.".into(),
+                    observed_generation: generation,
+                    reason: "Initialized".into(),
+                    status: "True".into(),
+                    type_: "Initialized".into(),
+                },
+            ],
+        }
+    }
+}
+
+/*
+ * ============================================================================
+ * Reconciler
+ * ============================================================================
+ */
+#[tracing::instrument(skip_all)]
+async fn reconciler(object: Arc<OnionServiceGroup>, ctx: Arc<Context>) -> Result<Action> {
+    let _timer = ctx
+        .metrics
+        .count_and_measure(OnionServiceGroup::APP_KUBERNETES_IO_COMPONENT_VALUE);
+    tracing::info!("reconciling");
+
+    let namespace = object.try_namespace()?;
+
+    let torrc = generate_torrc(&object);
+
+    let labels = object.try_labels()?;
+    let selector_labels = object.try_selector_labels()?;
+
+    // OnionKey
+    let (state, members) = reconcile_onion_keys(
+        &Api::new(
+            kube::Api::namespaced(ctx.client.clone(), &namespace),
+            ctx.metrics.clone(),
+        ),
+        &object,
+    )
+    .await?;
+
+    if let State::Initialized(onion_keys) = &state {
+        let hostnames = generate_hostnames(onion_keys);
+
+        let annotations = Annotations::new().add(&hostnames).add(&torrc);
+
+        let template_annotations = match object.torrc_reload_strategy() {
+            TorrcReloadStrategy::Restart => annotations.clone(),
+            TorrcReloadStrategy::Signal => Annotations::new().add(&hostnames).add(&torrc.restart()),
+        };
+
+        // ConfigMap
+        reconcile_config_map(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &object,
+            &annotations,
+            &labels,
+            &torrc,
+        )
+        .await?;
+
+        // Deployment
+        reconcile_deployment(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &ctx.config,
+            &object,
+            &annotations,
+            &template_annotations,
+            &labels,
+            &selector_labels,
+            onion_keys,
+        )
+        .await?;
+    }
+
+    // OnionServiceGroup
+    reconcile_onion_service_group(
+        &Api::new(
+            kube::Api::namespaced(ctx.client.clone(), &namespace),
+            ctx.metrics.clone(),
+        ),
+        &object,
+        &state,
+        members,
+    )
+    .await?;
+
+    ctx.error_backoff.reset(object.as_ref());
+
+    tracing::info!("reconciled");
+
+    match state {
+        State::Initialized(_) => Ok(Action::requeue(Duration::from_hours(1))),
+        _ => Ok(Action::requeue(Duration::from_secs(5))),
+    }
+}
+
+async fn reconcile_onion_keys(
+    api: &Api<OnionKey>,
+    object: &OnionServiceGroup,
+) -> Result<(State, Vec<OnionServiceGroupStatusMember>)> {
+    let mut onion_keys = Vec::with_capacity(object.onion_services().len());
+
+    for onion_service in object.onion_services() {
+        onion_keys.push(api.get_opt(&onion_service.onion_key_name()).await?);
+    }
+
+    Ok(generate_state(object, onion_keys))
+}
+
+async fn reconcile_config_map(
+    api: &Api<ConfigMap>,
+    object: &OnionServiceGroup,
+    annotations: &Annotations,
+    labels: &Labels,
+    torrc: &Torrc,
+) -> Result<()> {
+    api.sync(
+        object,
+        [((), generate_config_map(object, annotations, labels, torrc)?)].into(),
+    )
+    .await
+    .map(|_| ())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn reconcile_deployment(
+    api: &Api<Deployment>,
+    config: &Config,
+    object: &OnionServiceGroup,
+    annotations: &Annotations,
+    template_annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+    onion_keys: &[OnionKey],
+) -> Result<()> {
+    api.sync(
+        object,
+        [(
+            (),
+            generate_deployment(
+                object,
+                config,
+                annotations,
+                template_annotations,
+                labels,
+                selector_labels,
+                onion_keys,
+            )?,
+        )]
+        .into(),
+    )
+    .await
+    .map(|_| ())
+}
+
+async fn reconcile_onion_service_group(
+    api: &Api<OnionServiceGroup>,
+    object: &OnionServiceGroup,
+    state: &State,
+    members: Vec<OnionServiceGroupStatusMember>,
+) -> Result<()> {
+    let conditions = object
+        .status_conditions()
+        .unwrap_or(&Vec::new())
+        .merge_from(&state.conditions(object.meta().generation));
+
+    let summary = conditions
+        .iter()
+        .fold(BTreeMap::new(), |mut summary, condition| {
+            summary.insert(condition.type_.clone(), condition.reason.clone());
+            summary
+        });
+
+    api.update_status(
+        object,
+        OnionServiceGroupStatus {
+            conditions,
+            hostnames: if let State::Initialized(onion_keys) = state {
+                object
+                    .onion_services()
+                    .iter()
+                    .zip(onion_keys)
+                    .filter_map(|(onion_service, onion_key)| {
+                        onion_key.hostname().map(|hostname| {
+                            (onion_service.onion_key.name.clone(), hostname.to_string())
+                        })
+                    })
+                    .collect()
+            } else {
+                BTreeMap::new()
+            },
+            members,
+            onion_services: i32::try_from(object.onion_services().len())
+                .expect("onion_services count cannot realistically exceed i32::MAX"),
+            summary,
+        },
+    )
+    .await
+}
+
+/// Pairs each Onion Service with its `OnionKey`, rejecting an `OnionKey` used
+/// by more than one Onion Service as they would share a hidden service
+/// directory.
+///
+/// The state reports the first Onion Service that is not ready, while the
+/// members report every Onion Service.
+fn generate_state(
+    object: &OnionServiceGroup,
+    onion_keys: Vec<Option<OnionKey>>,
+) -> (State, Vec<OnionServiceGroupStatusMember>) {
+    let mut names = HashSet::new();
+    let mut state = None;
+    let mut ready = Vec::with_capacity(onion_keys.len());
+    let mut members = Vec::with_capacity(onion_keys.len());
+
+    for (onion_service, onion_key) in object.onion_services().iter().zip(onion_keys) {
+        let name = onion_service.onion_key_name();
+        let hostname = onion_key
+            .as_ref()
+            .and_then(OnionKey::hostname)
+            .map(|hostname| hostname.to_string());
+
+        let (reason, not_ready) = if !names.insert(name.clone()) {
+            ("Duplicate", State::OnionKeyDuplicate(name))
+        } else if onion_key.is_none() {
+            ("NotFound", State::OnionKeyNotFound(name))
+        } else if hostname.is_none() {
+            ("HostnameNotFound", State::OnionKeyHostnameNotFound(name))
+        } else {
+            ready.extend(onion_key);
+            members.push(OnionServiceGroupStatusMember {
+                hostname,
+                onion_key: onion_service.onion_key.name.clone(),
+                ready: true,
+                reason: None,
+            });
+            continue;
+        };
+
+        state.get_or_insert(not_ready);
+        members.push(OnionServiceGroupStatusMember {
+            hostname,
+            onion_key: onion_service.onion_key.name.clone(),
+            ready: false,
+            reason: Some(reason.into()),
+        });
+    }
+
+    (state.unwrap_or(State::Initialized(ready)), members)
+}
+
+fn generate_hostnames(onion_keys: &[OnionKey]) -> Hostnames {
+    Hostnames(
+        onion_keys
+            .iter()
+            .filter_map(OnionKey::hostname)
+            .map(|hostname| hostname.to_string())
+            .collect::<Vec<_>>()
+            .join("\n"),
+    )
+}
+
+fn generate_torrc(object: &OnionServiceGroup) -> Torrc {
+    let mut torrc = Torrc::builder();
+    if let Some(template) = object.torrc_template() {
+        torrc = torrc.template(template);
+    }
+    torrc = torrc
+        .data_dir("${TOR_TMP_DIR}/home/.tor")
+        .control_port("127.0.0.1:6666")
+        .cookie_authentication(true)
+        .shutdown_wait_length(object.torrc_shutdown_wait_length_seconds());
+    for onion_service in object.onion_services() {
+        torrc = torrc.hidden_service_dir(&onion_service.hidden_service_dir());
+        torrc = onion_service.ports.iter().fold(torrc, |torrc, port| {
+            torrc.hidden_service_port(port.virtport, &port.target)
+        });
+    }
+    torrc.build()
+}
+
+fn generate_config_map(
+    object: &OnionServiceGroup,
+    annotations: &Annotations,
+    labels: &Labels,
+    torrc: &Torrc,
+) -> Result<ConfigMap> {
+    Ok(ConfigMap {
+        metadata: ObjectMeta {
+            name: Some(object.config_map_name().into()),
+            annotations: Some(
+                annotations
+                    .clone()
+                    .append_reverse(object.config_map_annotations())
+                    .into(),
+            ),
+            labels: Some(
+                labels
+                    .clone()
+                    .append_reverse(object.config_map_labels())
+                    .into(),
+            ),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        data: Some(BTreeMap::from([("torrc".into(), torrc.to_string())])),
+        ..Default::default()
+    })
+}
+
+fn generate_deployment(
+    object: &OnionServiceGroup,
+    config: &Config,
+    annotations: &Annotations,
+    template_annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+    onion_keys: &[OnionKey],
+) -> Result<Deployment> {
+    Ok(Deployment {
+        metadata: ObjectMeta {
+            name: Some(object.deployment_name().into()),
+            annotations: Some(
+                annotations
+                    .clone()
+                    .append_reverse(object.deployment_annotations())
+                    .into(),
+            ),
+            labels: Some(
+                labels
+                    .clone()
+                    .append_reverse(object.deployment_labels())
+                    .into(),
+            ),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        spec: Some(DeploymentSpec {
+            replicas: Some(1),
+            selector: LabelSelector {
+                match_labels: Some(selector_labels.into()),
+                ..Default::default()
+            },
+            strategy: object.deployment_strategy(),
+            template: PodTemplateSpec {
+                metadata: Some(ObjectMeta {
+                    annotations: Some(
+                        template_annotations
+                            .clone()
+                            .append_reverse(object.deployment_annotations())
+                            .into(),
+                    ),
+                    labels: Some(
+                        labels
+                            .clone()
+                            .append_reverse(object.deployment_labels())
+                            .into(),
+                    ),
+                    ..Default::default()
+                }),
+                spec: Some(PodSpec {
+                    affinity: object.deployment_affinity(),
+                    containers: generate_deployment_containers(object, config),
+                    image_pull_secrets: object.deployment_image_pull_secrets(),
+                    init_containers: Some(generate_deployment_init_containers(object)),
+                    node_selector: object.deployment_node_selector(),
+                    security_context: Some(object.deployment_security_context()),
+                    termination_grace_period_seconds: Some(
+                        object.deployment_termination_grace_period_seconds(),
+                    ),
+                    tolerations: object.deployment_tolerations(),
+                    topology_spread_constraints: object.deployment_topology_spread_constraints(),
+                    volumes: Some(generate_deployment_volumes(object, onion_keys)),
+                    ..Default::default()
+                }),
+            },
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+fn generate_deployment_containers(object: &OnionServiceGroup, config: &Config) -> Vec<Container> {
+    let mut containers = object.deployment_containers();
+
+    {
+        let container = vec_get_or_insert(&mut containers, |f| f.name == "tor");
+        container.name = "tor".to_string();
+        container.args = Some({
+            let mut args: Vec<String> = vec!["entrypoint".into(), "run".into()];
+
+            // hidden_services
+            for onion_service in object.onion_services() {
+                let hidden_service_dir = onion_service.hidden_service_dir();
+                args.extend(["--directory".into(), hidden_service_dir.clone()]);
+                args.extend([
+                    "--copy".into(),
+                    format!("{}:{hidden_service_dir}", onion_service.secret_dir()),
+                ]);
+            }
+
+            // torrc
+            args.extend([
+                "--template".into(),
+                "/etc/configs/torrc:${TOR_TMP_DIR}/usr/local/etc/tor/torrc".into(),
+            ]);
+
+            // data directory
+            args.extend(["--directory".into(), "${TOR_TMP_DIR}/home/.tor".into()]);
+
+            // reload
+            if object.torrc_reload_strategy() == TorrcReloadStrategy::Signal {
+                args.push("--reload".into());
+            }
+
+            // shutdown
+            args.push("--interrupt-on-terminate".into());
+
+            // executable
+            args.extend(
+                ["--", "tor", "-f", "${TOR_TMP_DIR}/usr/local/etc/tor/torrc"].map(Into::into),
+            );
+
+            args
+        });
+        container.command = Some(vec!["tor-operator".into()]);
+        container.image = Some(config.tor_image.uri.clone());
+        container.image_pull_policy = Some(config.tor_image.pull_policy.clone());
+
+        container.liveness_probe = Some(liveness_probe(&[]));
+        container.readiness_probe = Some(readiness_probe(
+            &object
+                .onion_services()
+                .iter()
+                .flat_map(|onion_service| {
+                    [
+                        "--hostname-file".to_string(),
+                        format!("{}/hostname", onion_service.secret_dir()),
+                    ]
+                })
+                .collect::<Vec<_>>()
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>(),
+        ));
+        container.startup_probe = Some(startup_probe(&[]));
+
+        let volume_mounts = container.volume_mounts.get_or_insert_with(Default::default);
+
+        for (index, onion_service) in object.onion_services().iter().enumerate() {
+            let name = format!("etc-secrets-{index}");
+            let volume_mount = vec_get_or_insert(volume_mounts, |f| f.name == name);
+            volume_mount.name = name;
+            volume_mount.mount_path = onion_service.secret_dir();
+            volume_mount.read_only = Some(true);
+        }
+
+        {
+            let volume_mount = vec_get_or_insert(volume_mounts, |f| f.name == "etc-configs");
+            volume_mount.name = "etc-configs".to_string();
+            volume_mount.mount_path = "/etc/configs".into();
+            volume_mount.read_only = Some(true);
+        }
+    }
+
+    for container in &mut containers {
+        container.security_context = Some(container_security_context());
+    }
+
+    containers
+}
+
+fn generate_deployment_init_containers(object: &OnionServiceGroup) -> Vec<Container> {
+    let mut containers = object.deployment_init_containers();
+
+    for container in &mut containers {
+        container.security_context = Some(container_security_context());
+    }
+
+    containers
+}
+
+fn generate_deployment_volumes(object: &OnionServiceGroup, onion_keys: &[OnionKey]) -> Vec<Volume> {
+    let mut volumes = object.deployment_volumes();
+
+    for (index, onion_key) in onion_keys.iter().enumerate() {
+        let name = format!("etc-secrets-{index}");
+        let volume = vec_get_or_insert(&mut volumes, |f| f.name == name);
+        volume.name = name;
+        volume.secret = Some(onion_key.secret_volume_source());
+    }
+
+    {
+        let volume = vec_get_or_insert(&mut volumes, |f| f.name == "etc-configs");
+        volume.name = "etc-configs".to_string();
+        volume.config_map = Some(ConfigMapVolumeSource {
+            default_mode: Some(0o400),
+            items: Some(vec![KeyToPath {
+                key: "torrc".into(),
+                mode: Some(0o400),
+                path: "torrc".into(),
+            }]),
+            name: object.config_map_name().into(),
+            optional: Some(false),
+        });
+    }
+
+    volumes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config() {
+        let object = &OnionServiceGroup {
+            spec: OnionServiceGroupSpec {
+                onion_services: vec![
+                    OnionServiceGroupSpecOnionService {
+                        onion_key: OnionServiceGroupSpecOnionServiceOnionKey {
+                            name: "first".into(),
+                        },
+                        ports: vec![OnionServiceGroupSpecHiddenServicePort {
+                            target: "first:80".into(),
+                            virtport: 80,
+                        }],
+                    },
+                    OnionServiceGroupSpecOnionService {
+                        onion_key: OnionServiceGroupSpecOnionServiceOnionKey {
+                            name: "second".into(),
+                        },
+                        ports: vec![
+                            OnionServiceGroupSpecHiddenServicePort {
+                                target: "second:80".into(),
+                                virtport: 80,
+                            },
+                            OnionServiceGroupSpecHiddenServicePort {
+                                target: "second:443".into(),
+                                virtport: 443,
+                            },
+                        ],
+                    },
+                ],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let torrc = generate_torrc(object);
+
+        assert_eq!(
+            r"DataDirectory ${TOR_TMP_DIR}/home/.tor
+ControlPort 127.0.0.1:6666
+CookieAuthentication 1
+ShutdownWaitLength 10
+HiddenServiceDir ${TOR_TMP_DIR}/var/lib/tor/hidden_services/first
+HiddenServicePort 80 first:80
+HiddenServiceDir ${TOR_TMP_DIR}/var/lib/tor/hidden_services/second
+HiddenServicePort 80 second:80
+HiddenServicePort 443 second:443",
+            torrc.to_string()
+        );
+    }
+
+    fn onion_service_group(names: &[&str]) -> OnionServiceGroup {
+        OnionServiceGroup {
+            metadata: ObjectMeta {
+                name: Some("group".into()),
+                namespace: Some("default".into()),
+                uid: Some("uid".into()),
+                ..Default::default()
+            },
+            spec: OnionServiceGroupSpec {
+                onion_services: names
+                    .iter()
+                    .map(|name| OnionServiceGroupSpecOnionService {
+                        onion_key: OnionServiceGroupSpecOnionServiceOnionKey {
+                            name: (*name).into(),
+                        },
+                        ports: vec![OnionServiceGroupSpecHiddenServicePort {
+                            target: format!("{name}:80"),
+                            virtport: 80,
+                        }],
+                    })
+                    .collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn onion_key(name: &str, hostname: Option<&str>) -> OnionKey {
+        OnionKey {
+            metadata: ObjectMeta {
+                name: Some(name.into()),
+                ..Default::default()
+            },
+            spec: crate::onion_key::OnionKeySpec {
+                secret: crate::onion_key::OnionKeySpecSecret {
+                    name: format!("{name}-secret"),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            status: Some(crate::onion_key::OnionKeyStatus {
+                hostname: hostname.map(Into::into),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn config_map() {
+        let object = &onion_service_group(&["first", "second"]);
+        let torrc = generate_torrc(object);
+
+        let config_map = generate_config_map(
+            object,
+            &Annotations::new().add(&torrc),
+            &object.try_labels().unwrap(),
+            &torrc,
+        )
+        .unwrap();
+
+        assert_eq!(Some("group"), config_map.metadata.name.as_deref());
+        assert_eq!(
+            Some("uid"),
+            config_map
+                .metadata
+                .owner_references
+                .as_ref()
+                .and_then(|f| f.first())
+                .map(|f| f.uid.as_str())
+        );
+        assert_eq!(
+            Some(&torrc.to_string()),
+            config_map.data.as_ref().and_then(|f| f.get("torrc"))
+        );
+    }
+
+    #[test]
+    fn deployment() {
+        let object = &onion_service_group(&["first", "second"]);
+        let onion_keys = [
+            onion_key("first", Some("first.onion")),
+            onion_key("second", Some("second.onion")),
+        ];
+
+        let deployment = generate_deployment(
+            object,
+            &Config {
+                tor_image: ImageConfig {
+                    pull_policy: "IfNotPresent".into(),
+                    uri: "tor:latest".into(),
+                },
+            },
+            &Annotations::new(),
+            &Annotations::new(),
+            &object.try_labels().unwrap(),
+            &object.try_selector_labels().unwrap(),
+            &onion_keys,
+        )
+        .unwrap();
+
+        assert_eq!(Some("group"), deployment.metadata.name.as_deref());
+
+        let pod_spec = deployment
+            .spec
+            .and_then(|f| f.template.spec)
+            .expect("pod spec is generated");
+
+        let tor = pod_spec
+            .containers
+            .iter()
+            .find(|f| f.name == "tor")
+            .expect("tor container is generated");
+        assert_eq!(Some("tor:latest"), tor.image.as_deref());
+        assert_eq!(Some(container_security_context()), tor.security_context);
+
+        let args = tor.args.as_ref().expect("tor args are generated");
+        for name in ["first", "second"] {
+            assert!(args.contains(&format!(
+                "/etc/secrets/{name}:${{TOR_TMP_DIR}}/var/lib/tor/hidden_services/{name}"
+            )));
+        }
+
+        let mounts = tor
+            .volume_mounts
+            .as_ref()
+            .expect("tor volume mounts are generated")
+            .iter()
+            .map(|f| (f.name.as_str(), f.mount_path.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("etc-secrets-0", "/etc/secrets/first"),
+                ("etc-secrets-1", "/etc/secrets/second"),
+                ("etc-configs", "/etc/configs"),
+            ],
+            mounts
+        );
+
+        let volumes = pod_spec.volumes.expect("volumes are generated");
+        assert_eq!(
+            vec![Some("first-secret"), Some("second-secret"), None,],
+            volumes
+                .iter()
+                .map(|f| f.secret.as_ref().and_then(|f| f.secret_name.as_deref()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some("group"),
+            volumes[2].config_map.as_ref().map(|f| f.name.as_str())
+        );
+    }
+
+    #[test]
+    fn duplicate_onion_key() {
+        let object = &onion_service_group(&["first", "second", "first"]);
+
+        let (state, members) = generate_state(
+            object,
+            vec![
+                Some(onion_key("first", Some("first.onion"))),
+                Some(onion_key("second", Some("second.onion"))),
+                Some(onion_key("first", Some("first.onion"))),
+            ],
+        );
+
+        assert!(
+            matches!(state, State::OnionKeyDuplicate(name) if name == ResourceName::from("first".to_string()))
+        );
+        assert_eq!(
+            vec![(true, None), (true, None), (false, Some("Duplicate"))],
+            members
+                .iter()
+                .map(|f| (f.ready, f.reason.as_deref()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn members() {
+        let object = &onion_service_group(&["first", "second", "third"]);
+
+        let (state, members) = generate_state(
+            object,
+            vec![
+                Some(onion_key("first", Some("first.onion"))),
+                None,
+                Some(onion_key("third", None)),
+            ],
+        );
+
+        assert!(
+            matches!(state, State::OnionKeyNotFound(name) if name == ResourceName::from("second".to_string()))
+        );
+        assert_eq!(
+            vec![
+                OnionServiceGroupStatusMember {
+                    hostname: Some("first.onion".into()),
+                    onion_key: "first".into(),
+                    ready: true,
+                    reason: None,
+                },
+                OnionServiceGroupStatusMember {
+                    hostname: None,
+                    onion_key: "second".into(),
+                    ready: false,
+                    reason: Some("NotFound".into()),
+                },
+                OnionServiceGroupStatusMember {
+                    hostname: None,
+                    onion_key: "third".into(),
+                    ready: false,
+                    reason: Some("HostnameNotFound".into()),
+                },
+            ],
+            members
+        );
+
+        let (state, _) = generate_state(
+            object,
+            vec![
+                Some(onion_key("first", Some("first.onion"))),
+                Some(onion_key("second", Some("second.onion"))),
+                Some(onion_key("third", Some("third.onion"))),
+            ],
+        );
+
+        assert!(matches!(state, State::Initialized(onion_keys) if onion_keys.len() == 3));
+    }
+}
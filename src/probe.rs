@@ -0,0 +1,216 @@
+use std::path::PathBuf;
+
+use crate::tor::{self, ControlAuth, ControlClient, Hostname};
+
+/*
+ * ============================================================================
+ * Config
+ * ============================================================================
+ */
+pub struct Config {
+    /// 127.0.0.1:6666
+    pub control_port: String,
+
+    /// Default: the `COOKIEFILE` advertised by tor
+    pub control_cookie_file: Option<PathBuf>,
+
+    pub control_password: Option<String>,
+}
+
+/*
+ * ============================================================================
+ * Error
+ * ============================================================================
+ */
+#[derive(Debug)]
+pub enum Error {
+    CircuitNotEstablished,
+    DescriptorNotUploaded(String),
+    Hostname(tor::Error),
+    Io(std::io::Error),
+    NotBootstrapped(String),
+    Tor(tor::Error),
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::CircuitNotEstablished => write!(f, "circuit not established"),
+            Error::DescriptorNotUploaded(hostname) => {
+                write!(f, "descriptor not uploaded: {hostname}")
+            }
+            Error::Hostname(e) => write!(f, "hostname error: {e}"),
+            Error::Io(e) => write!(f, "io error: {e}"),
+            Error::NotBootstrapped(phase) => write!(f, "not bootstrapped: {phase}"),
+            Error::Tor(e) => write!(f, "tor error: {e}"),
+        }
+    }
+}
+
+impl From<tor::Error> for Error {
+    fn from(e: tor::Error) -> Self {
+        Self::Tor(e)
+    }
+}
+
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/*
+ * ============================================================================
+ * Probes
+ * ============================================================================
+ */
+/// Tor is responsive and has bootstrapped.
+///
+/// # Errors
+///
+/// Returns an error if a check fails.
+pub async fn liveness(config: &Config) -> Result<()> {
+    let mut client = connect(config).await?;
+
+    check_bootstrap(&mut client).await
+}
+
+/// Tor has bootstrapped, established a circuit and, for onion services,
+/// uploaded the descriptor of every hostname.
+///
+/// Standbys, running without a `HiddenServiceDir`, skip the descriptor check.
+///
+/// # Errors
+///
+/// Returns an error if a check fails.
+pub async fn readiness(config: &Config, hostname_files: &[PathBuf]) -> Result<()> {
+    let mut client = connect(config).await?;
+
+    check_bootstrap(&mut client).await?;
+    check_circuit_established(&mut client).await?;
+
+    if !hostname_files.is_empty() && check_hidden_service_configured(&mut client).await? {
+        for hostname_file in hostname_files {
+            let hostname = tokio::fs::read(hostname_file).await.map_err(Error::Io)?;
+            let hostname = Hostname::try_from(&hostname).map_err(Error::Hostname)?;
+            check_descriptor_uploaded(&mut client, &hostname).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Tor has bootstrapped.
+///
+/// # Errors
+///
+/// Returns an error if a check fails.
+pub async fn startup(config: &Config) -> Result<()> {
+    let mut client = connect(config).await?;
+
+    check_bootstrap(&mut client).await
+}
+
+async fn connect(config: &Config) -> Result<ControlClient> {
+    let mut client = ControlClient::connect(&config.control_port).await?;
+
+    let auth = if let Some(password) = &config.control_password {
+        ControlAuth::HashedPassword(password.clone())
+    } else if config.control_cookie_file.is_some() {
+        ControlAuth::Cookie(config.control_cookie_file.clone())
+    } else if client
+        .protocol_info()
+        .await?
+        .auth_methods
+        .iter()
+        .any(|method| method == "NULL")
+    {
+        ControlAuth::Null
+    } else {
+        ControlAuth::Cookie(None)
+    };
+
+    client.authenticate(&auth).await?;
+
+    Ok(client)
+}
+
+/*
+ * ============================================================================
+ * Checks
+ * ============================================================================
+ */
+async fn check_bootstrap(client: &mut ControlClient) -> Result<()> {
+    let phase = client
+        .get_info(&["status/bootstrap-phase"])
+        .await?
+        .remove("status/bootstrap-phase")
+        .unwrap_or_default();
+
+    if bootstrap_progress(&phase) == Some(100) {
+        Ok(())
+    } else {
+        Err(Error::NotBootstrapped(phase))
+    }
+}
+
+async fn check_circuit_established(client: &mut ControlClient) -> Result<()> {
+    let established = client
+        .get_info(&["status/circuit-established"])
+        .await?
+        .remove("status/circuit-established");
+
+    if established.as_deref() == Some("1") {
+        Ok(())
+    } else {
+        Err(Error::CircuitNotEstablished)
+    }
+}
+
+async fn check_hidden_service_configured(client: &mut ControlClient) -> Result<bool> {
+    Ok(client
+        .get_conf(&["HiddenServiceDir"])
+        .await?
+        .into_iter()
+        .any(|(_, value)| value.is_some_and(|value| !value.is_empty())))
+}
+
+async fn check_descriptor_uploaded(client: &mut ControlClient, hostname: &Hostname) -> Result<()> {
+    let hostname = hostname.to_string();
+    let address = hostname.trim_end_matches(".onion");
+
+    match client
+        .get_info(&[&format!("hs/service/desc/id/{address}")])
+        .await
+    {
+        Ok(descriptor) if descriptor.values().any(|d| !d.is_empty()) => Ok(()),
+        Ok(_) | Err(tor::Error::ControlReply { .. }) => Err(Error::DescriptorNotUploaded(hostname)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// `NOTICE BOOTSTRAP PROGRESS=100 TAG=done SUMMARY="Done"`
+fn bootstrap_progress(phase: &str) -> Option<u8> {
+    phase
+        .split_whitespace()
+        .find_map(|part| part.strip_prefix("PROGRESS="))
+        .and_then(|progress| progress.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bootstrap_phase() {
+        assert_eq!(
+            Some(100),
+            bootstrap_progress(r#"NOTICE BOOTSTRAP PROGRESS=100 TAG=done SUMMARY="Done""#)
+        );
+        assert_eq!(
+            Some(14),
+            bootstrap_progress(
+                r#"NOTICE BOOTSTRAP PROGRESS=14 TAG=handshake SUMMARY="Handshaking with a relay""#
+            )
+        );
+        assert_eq!(None, bootstrap_progress(""));
+    }
+}
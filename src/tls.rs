@@ -0,0 +1,268 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        ServerConfig,
+        crypto::{CryptoProvider, aws_lc_rs},
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject as _},
+        server::{ClientHello, ResolvesServerCert},
+        sign::CertifiedKey,
+    },
+    server::TlsStream,
+};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const RELOAD_INTERVAL: Duration = Duration::from_mins(1);
+
+/*
+ * ============================================================================
+ * Config
+ * ============================================================================
+ */
+pub struct Config {
+    /// PEM encoded certificate chain.
+    pub certificate: PathBuf,
+
+    /// PEM encoded private key.
+    pub key: PathBuf,
+
+    /// Address to accept TLS connections on.
+    pub listen: String,
+
+    /// Target to forward the decrypted connections to.
+    pub target: String,
+}
+
+/*
+ * ============================================================================
+ * Terminator
+ * ============================================================================
+ */
+/// Accepts TLS connections, terminates them and forwards the plaintext to the
+/// target.
+///
+/// The certificate and private key are reloaded when their files change, so
+/// that rotated certificates are served without a restart.
+///
+/// # Errors
+///
+/// Returns an error if the certificate cannot be loaded, or the listener
+/// cannot be bound or stops accepting connections.
+pub async fn run(config: &Config) -> std::io::Result<()> {
+    let acceptor = acceptor(config.certificate.clone(), config.key.clone())?;
+
+    let listener = TcpListener::bind(&config.listen).await?;
+    tracing::info!(
+        listen = config.listen,
+        target = config.target,
+        "accepting connections"
+    );
+
+    loop {
+        let (inbound, _) = listener.accept().await?;
+        let acceptor = acceptor.clone();
+        let target = config.target.clone();
+        tokio::spawn(async move {
+            if let Err(error) = terminate(&acceptor, inbound, &target).await {
+                tracing::warn!(target, %error, "connection failed");
+            }
+        });
+    }
+}
+
+/// Creates a TLS acceptor serving the certificate and private key, which are
+/// reloaded in the background when their files change.
+///
+/// # Errors
+///
+/// Returns an error if the certificate cannot be loaded.
+pub(crate) fn acceptor(certificate: PathBuf, key: PathBuf) -> std::io::Result<TlsAcceptor> {
+    let provider = Arc::new(aws_lc_rs::default_provider());
+    let resolver = Arc::new(Resolver::load(certificate, key, &provider)?);
+
+    let server_config = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(std::io::Error::other)?
+        .with_no_client_auth()
+        .with_cert_resolver(resolver.clone());
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+        loop {
+            interval.tick().await;
+            match resolver.reload(&provider) {
+                Ok(true) => tracing::info!("certificate reloaded"),
+                Ok(false) => {}
+                Err(error) => tracing::warn!(%error, "certificate reload failed"),
+            }
+        }
+    });
+
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// Completes the TLS handshake of an inbound connection.
+///
+/// # Errors
+///
+/// Returns an error if the handshake fails or times out.
+pub(crate) async fn accept(
+    acceptor: &TlsAcceptor,
+    inbound: TcpStream,
+) -> std::io::Result<TlsStream<TcpStream>> {
+    tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(inbound))
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "TLS handshake timed out"))?
+}
+
+async fn terminate(
+    acceptor: &TlsAcceptor,
+    inbound: TcpStream,
+    target: &str,
+) -> std::io::Result<()> {
+    let mut inbound = accept(acceptor, inbound).await?;
+
+    let mut outbound = TcpStream::connect(target).await?;
+    tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await?;
+
+    Ok(())
+}
+
+/*
+ * ============================================================================
+ * Resolver
+ * ============================================================================
+ */
+#[derive(Debug)]
+struct Resolver {
+    certificate: PathBuf,
+    key: PathBuf,
+    loaded: RwLock<Loaded>,
+}
+
+#[derive(Debug)]
+struct Loaded {
+    certificate_pem: Vec<u8>,
+    certified_key: Arc<CertifiedKey>,
+    key_pem: Vec<u8>,
+}
+
+impl Resolver {
+    fn load(
+        certificate: PathBuf,
+        key: PathBuf,
+        provider: &CryptoProvider,
+    ) -> std::io::Result<Self> {
+        let loaded = Loaded::read(&certificate, &key, provider)?;
+        Ok(Self {
+            certificate,
+            key,
+            loaded: RwLock::new(loaded),
+        })
+    }
+
+    /// Returns `true` when the certificate or private key changed.
+    fn reload(&self, provider: &CryptoProvider) -> std::io::Result<bool> {
+        let certificate_pem = std::fs::read(&self.certificate)?;
+        let key_pem = std::fs::read(&self.key)?;
+
+        {
+            let loaded = self.loaded.read().expect("resolver lock is never poisoned");
+            if loaded.certificate_pem == certificate_pem && loaded.key_pem == key_pem {
+                return Ok(false);
+            }
+        }
+
+        let loaded = Loaded::parse(certificate_pem, key_pem, provider)?;
+        *self
+            .loaded
+            .write()
+            .expect("resolver lock is never poisoned") = loaded;
+        Ok(true)
+    }
+}
+
+impl ResolvesServerCert for Resolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(
+            self.loaded
+                .read()
+                .expect("resolver lock is never poisoned")
+                .certified_key
+                .clone(),
+        )
+    }
+}
+
+impl Loaded {
+    fn read(certificate: &Path, key: &Path, provider: &CryptoProvider) -> std::io::Result<Self> {
+        Self::parse(std::fs::read(certificate)?, std::fs::read(key)?, provider)
+    }
+
+    fn parse(
+        certificate_pem: Vec<u8>,
+        key_pem: Vec<u8>,
+        provider: &CryptoProvider,
+    ) -> std::io::Result<Self> {
+        fn invalid(error: impl std::fmt::Display) -> std::io::Error {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, error.to_string())
+        }
+
+        let chain = CertificateDer::pem_slice_iter(&certificate_pem)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid)?;
+        if chain.is_empty() {
+            return Err(invalid("no certificate found"));
+        }
+        let key = PrivateKeyDer::from_pem_slice(&key_pem).map_err(invalid)?;
+        let certified_key = CertifiedKey::from_der(chain, key, provider).map_err(invalid)?;
+
+        Ok(Self {
+            certificate_pem,
+            certified_key: Arc::new(certified_key),
+            key_pem,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rcgen::{CertificateParams, KeyPair};
+
+    use super::*;
+
+    fn self_signed(hostname: &str) -> (String, String) {
+        let key_pair = KeyPair::generate().unwrap();
+        let certificate = CertificateParams::new(vec![hostname.to_string()])
+            .unwrap()
+            .self_signed(&key_pair)
+            .unwrap();
+        (certificate.pem(), key_pair.serialize_pem())
+    }
+
+    #[test]
+    fn loaded_parse() {
+        let provider = aws_lc_rs::default_provider();
+        let (certificate, key) = self_signed("example.onion");
+
+        let loaded = Loaded::parse(certificate.into(), key.into(), &provider).unwrap();
+
+        assert_eq!(1, loaded.certified_key.cert.len());
+    }
+
+    #[test]
+    fn loaded_parse_mismatched_key() {
+        let provider = aws_lc_rs::default_provider();
+        let (certificate, _) = self_signed("example.onion");
+        let (_, key) = self_signed("example.onion");
+
+        assert!(Loaded::parse(certificate.into(), key.into(), &provider).is_err());
+    }
+}
@@ -1,6 +1,11 @@
+pub const BLIND_BASEPOINT: &[u8] = b"(15112221349535400772501151409588531511454012693041857206046113283949847762202, 46316835694926478169428394003475163141307993866256225615783033603165251855960)";
+pub const BLIND_HASH_INPUT: &[u8] = b"Derive temporary signing key hash input";
+pub const BLIND_NONCE: &[u8] = b"key-blind";
+pub const BLIND_STRING: &[u8] = b"Derive temporary signing key\0";
 pub const ED25519_V1_PUBLIC_TYPE_0_KEY: &[u8] = b"== ed25519v1-public: type0 ==\0\0\0";
 pub const ED25519_V1_PUBLIC_TYPE_0_LENGTH: usize = 32;
 pub const ED25519_V1_SECRET_TYPE_0_KEY: &[u8] = b"== ed25519v1-secret: type0 ==\0\0\0";
 pub const ED25519_V1_SECRET_TYPE_0_LENGTH: usize = 64;
 pub const ONION_DOMAIN_LENGTH: usize = 56;
+pub const TIME_PERIOD_LENGTH_MINUTES: u64 = 1440;
 pub const VERSION_LENGTH: usize = 32;
@@ -0,0 +1,1039 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use rand::{Rng as _, SeedableRng as _};
+use sha2::{Digest as _, Sha256};
+use tokio::{
+    io::{AsyncBufReadExt as _, AsyncRead, AsyncWriteExt as _, BufReader},
+    net::{TcpStream, ToSocketAddrs, tcp::OwnedWriteHalf},
+    sync::mpsc,
+    task::JoinHandle,
+};
+
+use super::{Descriptor, Error, Hostname, Result};
+
+const SAFE_COOKIE_SERVER_TO_CONTROLLER: &[u8] =
+    b"Tor safe cookie authentication server-to-controller hash";
+
+const SAFE_COOKIE_CONTROLLER_TO_SERVER: &[u8] =
+    b"Tor safe cookie authentication controller-to-server hash";
+
+/*
+ * ============================================================================
+ * Client
+ * ============================================================================
+ */
+/// An async client for tor's control protocol.
+///
+/// Synchronous replies are returned from the command that caused them,
+/// asynchronous `650` replies are delivered through [`ControlClient::events`].
+#[allow(clippy::module_name_repetitions)]
+pub struct ControlClient {
+    events: Option<mpsc::UnboundedReceiver<ControlEvent>>,
+    reader: JoinHandle<()>,
+    replies: mpsc::UnboundedReceiver<Result<ControlReply>>,
+    writer: OwnedWriteHalf,
+}
+
+impl ControlClient {
+    /// Connects to the control port at `addr`, e.g. `127.0.0.1:6666`, without
+    /// authenticating.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the control port cannot be connected to.
+    pub async fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        let (reader, writer) = stream.into_split();
+
+        let (events_tx, events_rx) = mpsc::unbounded_channel();
+        let (replies_tx, replies_rx) = mpsc::unbounded_channel();
+
+        let reader = tokio::spawn(async move {
+            let mut reader = BufReader::new(reader);
+            loop {
+                match read_reply(&mut reader).await {
+                    Ok(reply) if reply.status == 650 => {
+                        let _ = events_tx.send(ControlEvent(reply));
+                    }
+                    Ok(reply) => {
+                        if replies_tx.send(Ok(reply)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(error) => {
+                        let _ = replies_tx.send(Err(error));
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            events: Some(events_rx),
+            reader,
+            replies: replies_rx,
+            writer,
+        })
+    }
+
+    /// Connects to the control port, authenticating without credentials when
+    /// Tor allows it, and with the cookie otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the control port cannot be connected to or
+    /// authentication fails.
+    pub async fn connect_and_authenticate<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        let mut client = Self::connect(addr).await?;
+
+        let auth = if client
+            .protocol_info()
+            .await?
+            .auth_methods
+            .iter()
+            .any(|method| method == "NULL")
+        {
+            ControlAuth::Null
+        } else {
+            ControlAuth::Cookie(None)
+        };
+        client.authenticate(&auth).await?;
+
+        Ok(client)
+    }
+
+    /// Sends a raw command and waits for its reply.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection fails or tor replies with a
+    /// non `2xx` status.
+    pub async fn command(&mut self, command: &str) -> Result<ControlReply> {
+        self.writer
+            .write_all(format!("{command}\r\n").as_bytes())
+            .await?;
+
+        let reply = self
+            .replies
+            .recv()
+            .await
+            .ok_or_else(|| Error::Io(std::io::ErrorKind::UnexpectedEof.into()))??;
+
+        if (200..300).contains(&reply.status) {
+            Ok(reply)
+        } else {
+            Err(Error::ControlReply {
+                status: reply.status,
+                message: reply.message().to_string(),
+            })
+        }
+    }
+
+    /// Takes the receiver of asynchronous events.
+    ///
+    /// Returns `None` if the receiver has already been taken.
+    pub fn events(&mut self) -> Option<mpsc::UnboundedReceiver<ControlEvent>> {
+        self.events.take()
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if the command fails or the reply is malformed.
+    pub async fn protocol_info(&mut self) -> Result<ControlProtocolInfo> {
+        let reply = self.command("PROTOCOLINFO 1").await?;
+
+        let mut protocol_info = ControlProtocolInfo::default();
+
+        for line in &reply.lines {
+            if let Some(auth) = line.text.strip_prefix("AUTH ") {
+                for (key, value) in parse_key_values(auth)? {
+                    match key.as_str() {
+                        "METHODS" => {
+                            protocol_info.auth_methods =
+                                value.split(',').map(ToString::to_string).collect();
+                        }
+                        "COOKIEFILE" => protocol_info.cookie_file = Some(value.into()),
+                        _ => {}
+                    }
+                }
+            } else if let Some(version) = line.text.strip_prefix("VERSION ") {
+                protocol_info.version = parse_key_values(version)?
+                    .into_iter()
+                    .find(|(key, _)| key == "Tor")
+                    .map(|(_, value)| value);
+            }
+        }
+
+        Ok(protocol_info)
+    }
+
+    /// Authenticates the connection.
+    ///
+    /// Cookie authentication prefers `SAFECOOKIE` and falls back to
+    /// `COOKIE`. If no cookie file is given, the one advertised by tor is used.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the cookie cannot be read or tor rejects the
+    /// credentials.
+    pub async fn authenticate(&mut self, auth: &ControlAuth) -> Result<()> {
+        match auth {
+            ControlAuth::Null => {
+                self.command("AUTHENTICATE").await?;
+            }
+            ControlAuth::HashedPassword(password) => {
+                self.command(&format!("AUTHENTICATE {}", quote(password)))
+                    .await?;
+            }
+            ControlAuth::Cookie(cookie_file) => {
+                let protocol_info = self.protocol_info().await?;
+
+                let cookie_file = cookie_file
+                    .clone()
+                    .or(protocol_info.cookie_file)
+                    .ok_or_else(|| Error::Authentication("missing cookie file".to_string()))?;
+
+                let cookie = tokio::fs::read(&cookie_file).await?;
+
+                if protocol_info.auth_methods.iter().any(|m| m == "SAFECOOKIE") {
+                    self.authenticate_safe_cookie(&cookie).await?;
+                } else {
+                    self.command(&format!("AUTHENTICATE {}", hex_encode(&cookie)))
+                        .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn authenticate_safe_cookie(&mut self, cookie: &[u8]) -> Result<()> {
+        let mut csprng = rand::rngs::StdRng::try_from_rng(&mut rand::rngs::SysRng)
+            .map_err(|e| Error::Authentication(e.to_string()))?;
+        let mut client_nonce = [0_u8; 32];
+        csprng.fill_bytes(&mut client_nonce);
+
+        let reply = self
+            .command(&format!(
+                "AUTHCHALLENGE SAFECOOKIE {}",
+                hex_encode(&client_nonce)
+            ))
+            .await?;
+
+        let challenge = reply
+            .message()
+            .strip_prefix("AUTHCHALLENGE ")
+            .ok_or_else(|| Error::ParseError(format!("unexpected reply: {}", reply.message())))?;
+
+        let mut server_hash = None;
+        let mut server_nonce = None;
+        for (key, value) in parse_key_values(challenge)? {
+            match key.as_str() {
+                "SERVERHASH" => server_hash = Some(hex_decode(&value)?),
+                "SERVERNONCE" => server_nonce = Some(hex_decode(&value)?),
+                _ => {}
+            }
+        }
+        let server_hash =
+            server_hash.ok_or_else(|| Error::ParseError("missing SERVERHASH".to_string()))?;
+        let server_nonce =
+            server_nonce.ok_or_else(|| Error::ParseError("missing SERVERNONCE".to_string()))?;
+
+        let message = [cookie, &client_nonce, &server_nonce].concat();
+
+        if hmac_sha256(SAFE_COOKIE_SERVER_TO_CONTROLLER, &message) != server_hash.as_slice() {
+            return Err(Error::Authentication(
+                "server hash does not match cookie".to_string(),
+            ));
+        }
+
+        let client_hash = hmac_sha256(SAFE_COOKIE_CONTROLLER_TO_SERVER, &message);
+
+        self.command(&format!("AUTHENTICATE {}", hex_encode(&client_hash)))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Creates an Onion Service whose key is generated by tor and never
+    /// returned, returning its `ServiceID`.
+    ///
+    /// `Detach` keeps the Onion Service running after the control connection
+    /// is closed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command fails or the reply is malformed.
+    pub async fn add_onion(&mut self, ports: &[&str]) -> Result<String> {
+        let ports = ports
+            .iter()
+            .map(|port| format!("Port={port}"))
+            .collect::<Vec<_>>();
+
+        let reply = self
+            .command(&format!(
+                "ADD_ONION NEW:ED25519-V3 Flags=DiscardPK,Detach {}",
+                ports.join(" ")
+            ))
+            .await?;
+
+        reply
+            .lines
+            .iter()
+            .find_map(|line| line.text.strip_prefix("ServiceID="))
+            .map(ToString::to_string)
+            .ok_or_else(|| Error::ParseError(format!("missing ServiceID: {}", reply.message())))
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if the command fails or the reply is malformed.
+    pub async fn get_info(&mut self, keys: &[&str]) -> Result<BTreeMap<String, String>> {
+        let reply = self.command(&format!("GETINFO {}", keys.join(" "))).await?;
+
+        let mut values = BTreeMap::new();
+        for line in reply.lines {
+            if line.text == "OK" {
+                continue;
+            }
+            let (key, value) = line
+                .text
+                .split_once('=')
+                .ok_or_else(|| Error::ParseError(format!("unexpected line: {}", line.text)))?;
+            values.insert(
+                key.to_string(),
+                line.data.unwrap_or_else(|| value.to_string()),
+            );
+        }
+
+        Ok(values)
+    }
+
+    /// Returns the values of the options, `None` being an unset option.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command fails or the reply is malformed.
+    pub async fn get_conf(&mut self, keys: &[&str]) -> Result<Vec<(String, Option<String>)>> {
+        let reply = self.command(&format!("GETCONF {}", keys.join(" "))).await?;
+
+        reply
+            .lines
+            .into_iter()
+            .filter(|line| line.text != "OK")
+            .map(|line| match line.text.split_once('=') {
+                Some((key, value)) => Ok((key.to_string(), Some(unquote(value)?))),
+                None => Ok((line.text, None)),
+            })
+            .collect()
+    }
+
+    /// Sets the options, `None` resetting an option to its default.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command fails.
+    pub async fn set_conf(&mut self, values: &[(&str, Option<&str>)]) -> Result<()> {
+        let values = values
+            .iter()
+            .map(|(key, value)| match value {
+                Some(value) => format!("{key}={}", quote(value)),
+                None => (*key).to_string(),
+            })
+            .collect::<Vec<_>>();
+
+        self.command(&format!("SETCONF {}", values.join(" ")))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Requests the descriptor of the Onion Service from the hidden service
+    /// directories, which is delivered through the `HS_DESC_CONTENT` event.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command fails.
+    pub async fn hs_fetch(&mut self, hostname: &Hostname) -> Result<()> {
+        let hostname = hostname.to_string();
+        self.command(&format!("HSFETCH {}", hostname.trim_end_matches(".onion")))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Uploads the descriptor of the Onion Service to its responsible hidden
+    /// service directories.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command fails.
+    pub async fn hs_post(&mut self, hostname: &Hostname, descriptor: &Descriptor) -> Result<()> {
+        let hostname = hostname.to_string();
+        let descriptor = descriptor
+            .to_string()
+            .lines()
+            .map(|line| {
+                if line.starts_with('.') {
+                    format!(".{line}")
+                } else {
+                    line.to_string()
+                }
+            })
+            .collect::<Vec<_>>();
+
+        self.command(&format!(
+            "+HSPOST HSADDRESS={}\r\n{}\r\n.",
+            hostname.trim_end_matches(".onion"),
+            descriptor.join("\r\n")
+        ))
+        .await?;
+
+        Ok(())
+    }
+
+    /// Subscribes to the events, replacing any previous subscription.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command fails.
+    pub async fn set_events(&mut self, events: &[&str]) -> Result<()> {
+        self.command(format!("SETEVENTS {}", events.join(" ")).trim_end())
+            .await?;
+
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if the command fails.
+    pub async fn signal(&mut self, signal: ControlSignal) -> Result<()> {
+        self.command(&format!("SIGNAL {signal}")).await?;
+
+        Ok(())
+    }
+}
+
+impl Drop for ControlClient {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/*
+ * ============================================================================
+ * Types
+ * ============================================================================
+ */
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlAuth {
+    /// `AUTHENTICATE` with a cookie file, defaulting to tor's `COOKIEFILE`.
+    Cookie(Option<PathBuf>),
+
+    /// `AUTHENTICATE` with the password of `HashedControlPassword`.
+    HashedPassword(String),
+
+    /// `AUTHENTICATE` without credentials.
+    Null,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlEvent(ControlReply);
+
+impl ControlEvent {
+    /// `HS_DESC`
+    #[must_use]
+    pub fn keyword(&self) -> &str {
+        self.0
+            .message()
+            .split_once(' ')
+            .map_or(self.0.message(), |(keyword, _)| keyword)
+    }
+
+    #[must_use]
+    pub fn reply(&self) -> &ControlReply {
+        &self.0
+    }
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ControlProtocolInfo {
+    pub auth_methods: Vec<String>,
+
+    pub cookie_file: Option<PathBuf>,
+
+    pub version: Option<String>,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlReply {
+    pub lines: Vec<ControlReplyLine>,
+
+    pub status: u16,
+}
+
+impl ControlReply {
+    /// The text of the first line.
+    #[must_use]
+    pub fn message(&self) -> &str {
+        self.lines.first().map_or("", |line| line.text.as_str())
+    }
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlReplyLine {
+    /// The body of a `+` data line.
+    pub data: Option<String>,
+
+    pub text: String,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControlSignal {
+    Active,
+    ClearDnsCache,
+    Debug,
+    Dormant,
+    Dump,
+    Halt,
+    Heartbeat,
+    Newnym,
+    Reload,
+    Shutdown,
+}
+
+impl std::fmt::Display for ControlSignal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let signal = match self {
+            ControlSignal::Active => "ACTIVE",
+            ControlSignal::ClearDnsCache => "CLEARDNSCACHE",
+            ControlSignal::Debug => "DEBUG",
+            ControlSignal::Dormant => "DORMANT",
+            ControlSignal::Dump => "DUMP",
+            ControlSignal::Halt => "HALT",
+            ControlSignal::Heartbeat => "HEARTBEAT",
+            ControlSignal::Newnym => "NEWNYM",
+            ControlSignal::Reload => "RELOAD",
+            ControlSignal::Shutdown => "SHUTDOWN",
+        };
+        write!(f, "{signal}")
+    }
+}
+
+/*
+ * ============================================================================
+ * Protocol
+ * ============================================================================
+ */
+async fn read_reply<R: AsyncRead + Unpin>(reader: &mut BufReader<R>) -> Result<ControlReply> {
+    let mut lines = Vec::new();
+
+    loop {
+        let line = read_line(reader).await?;
+
+        if line.len() < 4 || !line.is_char_boundary(3) || !line.is_char_boundary(4) {
+            return Err(Error::ParseError(format!("malformed reply line: {line}")));
+        }
+
+        let status = line[..3]
+            .parse()
+            .map_err(|_| Error::ParseError(format!("malformed reply status: {line}")))?;
+        let text = line[4..].to_string();
+
+        match &line[3..4] {
+            " " => {
+                lines.push(ControlReplyLine { data: None, text });
+                return Ok(ControlReply { lines, status });
+            }
+            "-" => lines.push(ControlReplyLine { data: None, text }),
+            "+" => {
+                let mut data = Vec::new();
+                loop {
+                    let line = read_line(reader).await?;
+                    if line == "." {
+                        break;
+                    }
+                    data.push(line.strip_prefix('.').unwrap_or(&line).to_string());
+                }
+                lines.push(ControlReplyLine {
+                    data: Some(data.join("\n")),
+                    text,
+                });
+            }
+            _ => return Err(Error::ParseError(format!("malformed reply line: {line}"))),
+        }
+    }
+}
+
+async fn read_line<R: AsyncRead + Unpin>(reader: &mut BufReader<R>) -> Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        return Err(Error::Io(std::io::ErrorKind::UnexpectedEof.into()));
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// `METHODS=COOKIE,SAFECOOKIE COOKIEFILE="/home/.tor/control_auth_cookie"`
+fn parse_key_values(input: &str) -> Result<Vec<(String, String)>> {
+    let mut values = Vec::new();
+    let mut rest = input.trim_start();
+
+    while !rest.is_empty() {
+        let (key, value) = rest
+            .split_once('=')
+            .ok_or_else(|| Error::ParseError(format!("malformed key value: {rest}")))?;
+
+        if value.starts_with('"') {
+            let end = quoted_string_len(value)?;
+            values.push((key.to_string(), unquote(&value[..end])?));
+            rest = value[end..].trim_start();
+        } else {
+            let (value, remainder) = value.split_once(' ').unwrap_or((value, ""));
+            values.push((key.to_string(), value.to_string()));
+            rest = remainder.trim_start();
+        }
+    }
+
+    Ok(values)
+}
+
+fn quoted_string_len(input: &str) -> Result<usize> {
+    let mut escaped = false;
+    for (i, c) in input.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Ok(i + 1),
+            _ => {}
+        }
+    }
+    Err(Error::ParseError(format!("unterminated string: {input}")))
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn unquote(value: &str) -> Result<String> {
+    let Some(inner) = value.strip_prefix('"') else {
+        return Ok(value.to_string());
+    };
+    let inner = inner
+        .strip_suffix('"')
+        .ok_or_else(|| Error::ParseError(format!("unterminated string: {value}")))?;
+
+    let mut output = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => output.push('\n'),
+                Some('r') => output.push('\r'),
+                Some('t') => output.push('\t'),
+                Some(c) => output.push(c),
+                None => return Err(Error::ParseError(format!("dangling escape: {value}"))),
+            }
+        } else {
+            output.push(c);
+        }
+    }
+    Ok(output)
+}
+
+/*
+ * ============================================================================
+ * Cryptography
+ * ============================================================================
+ */
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    const BLOCK_SIZE: usize = 64;
+
+    let mut block = [0_u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let inner = Sha256::new()
+        .chain_update(block.map(|b| b ^ 0x36))
+        .chain_update(message)
+        .finalize();
+
+    Sha256::new()
+        .chain_update(block.map(|b| b ^ 0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+fn hex_encode(data: &[u8]) -> String {
+    use std::fmt::Write as _;
+
+    data.iter().fold(String::new(), |mut output, b| {
+        let _ = write!(output, "{b:02X}");
+        output
+    })
+}
+
+fn hex_decode(data: &str) -> Result<Vec<u8>> {
+    if !data.is_ascii() || !data.len().is_multiple_of(2) {
+        return Err(Error::ParseError(format!("malformed hex: {data}")));
+    }
+    (0..data.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&data[i..i + 2], 16)
+                .map_err(|_| Error::ParseError(format!("malformed hex: {data}")))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use tokio::{
+        io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Serves a single connection, answering each command with the reply
+    /// produced by the handler. The handler returns `None` to hang up.
+    async fn fake_control_port<F>(mut handler: F) -> SocketAddr
+    where
+        F: FnMut(&str) -> Option<String> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let Some(reply) = handler(&line) else {
+                    break;
+                };
+                writer.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+
+        addr
+    }
+
+    #[test]
+    fn hmac_sha256_rfc_4231() {
+        assert_eq!(
+            "B0344C61D8DB38535CA8AFCEAF0BF12B881DC200C9833DA726E9376C2E32CFF7",
+            hex_encode(&hmac_sha256(&[0x0b; 20], b"Hi There"))
+        );
+    }
+
+    #[test]
+    fn key_values() {
+        assert_eq!(
+            vec![
+                ("METHODS".to_string(), "COOKIE,SAFECOOKIE".to_string()),
+                (
+                    "COOKIEFILE".to_string(),
+                    "/home/\"tor\"/control_auth_cookie".to_string()
+                ),
+            ],
+            parse_key_values(
+                r#"METHODS=COOKIE,SAFECOOKIE COOKIEFILE="/home/\"tor\"/control_auth_cookie""#
+            )
+            .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn authenticate_hashed_password() {
+        let addr = fake_control_port(|line| match line {
+            r#"AUTHENTICATE "pass \"word\"""# => Some("250 OK\r\n".to_string()),
+            _ => Some("515 Authentication failed\r\n".to_string()),
+        })
+        .await;
+
+        let mut client = ControlClient::connect(addr).await.unwrap();
+
+        client
+            .authenticate(&ControlAuth::HashedPassword("pass \"word\"".to_string()))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn authenticate_safe_cookie() {
+        let cookie = [7_u8; 32];
+        let server_nonce = [9_u8; 32];
+
+        let cookie_file = std::env::temp_dir().join(format!(
+            "tor-operator-control-auth-cookie-{}",
+            std::process::id()
+        ));
+        std::fs::write(&cookie_file, cookie).unwrap();
+
+        let protocol_info = format!(
+            "250-PROTOCOLINFO 1\r\n250-AUTH METHODS=COOKIE,SAFECOOKIE COOKIEFILE={}\r\n250-VERSION Tor=\"0.4.9.11\"\r\n250 OK\r\n",
+            quote(&cookie_file.to_string_lossy())
+        );
+
+        let mut client_nonce = Vec::new();
+        let addr = fake_control_port(move |line| {
+            if line == "PROTOCOLINFO 1" {
+                Some(protocol_info.clone())
+            } else if let Some(nonce) = line.strip_prefix("AUTHCHALLENGE SAFECOOKIE ") {
+                client_nonce = hex_decode(nonce).unwrap();
+                let message = [&cookie[..], &client_nonce, &server_nonce].concat();
+                Some(format!(
+                    "250 AUTHCHALLENGE SERVERHASH={} SERVERNONCE={}\r\n",
+                    hex_encode(&hmac_sha256(SAFE_COOKIE_SERVER_TO_CONTROLLER, &message)),
+                    hex_encode(&server_nonce)
+                ))
+            } else if let Some(hash) = line.strip_prefix("AUTHENTICATE ") {
+                let message = [&cookie[..], &client_nonce, &server_nonce].concat();
+                let expected = hmac_sha256(SAFE_COOKIE_CONTROLLER_TO_SERVER, &message);
+                if hex_decode(hash).unwrap() == expected {
+                    Some("250 OK\r\n".to_string())
+                } else {
+                    Some("515 Authentication failed\r\n".to_string())
+                }
+            } else {
+                None
+            }
+        })
+        .await;
+
+        let mut client = ControlClient::connect(addr).await.unwrap();
+
+        client
+            .authenticate(&ControlAuth::Cookie(None))
+            .await
+            .unwrap();
+
+        std::fs::remove_file(cookie_file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn add_onion() {
+        let addr = fake_control_port(|line| match line {
+            "ADD_ONION NEW:ED25519-V3 Flags=DiscardPK,Detach Port=80,example:80 Port=443,example:443" => {
+                Some(
+                    "250-ServiceID=pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd\r\n250 OK\r\n"
+                        .to_string(),
+                )
+            }
+            _ => Some("512 Invalid argument\r\n".to_string()),
+        })
+        .await;
+
+        let mut client = ControlClient::connect(addr).await.unwrap();
+
+        assert_eq!(
+            "pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd",
+            client
+                .add_onion(&["80,example:80", "443,example:443"])
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn get_info() {
+        let addr = fake_control_port(|line| match line {
+            "GETINFO version status/bootstrap-phase config-text" => Some(
+                [
+                    "250-version=0.4.9.11",
+                    "250-status/bootstrap-phase=NOTICE BOOTSTRAP PROGRESS=100 TAG=done SUMMARY=\"Done\"",
+                    "250+config-text=",
+                    "ControlPort 127.0.0.1:6666",
+                    "..hidden",
+                    ".",
+                    "250 OK",
+                    "",
+                ]
+                .join("\r\n"),
+            ),
+            _ => None,
+        })
+        .await;
+
+        let mut client = ControlClient::connect(addr).await.unwrap();
+
+        let info = client
+            .get_info(&["version", "status/bootstrap-phase", "config-text"])
+            .await
+            .unwrap();
+
+        assert_eq!("0.4.9.11", info["version"]);
+        assert_eq!(
+            "NOTICE BOOTSTRAP PROGRESS=100 TAG=done SUMMARY=\"Done\"",
+            info["status/bootstrap-phase"]
+        );
+        assert_eq!("ControlPort 127.0.0.1:6666\n.hidden", info["config-text"]);
+    }
+
+    #[tokio::test]
+    async fn get_conf_and_set_conf() {
+        let addr = fake_control_port(|line| match line {
+            "GETCONF SocksPort ControlPort HiddenServiceDir" => Some(
+                "250-SocksPort=0.0.0.0:9050\r\n250-SocksPort=\"127.0.0.1:9150\"\r\n250-ControlPort=127.0.0.1:6666\r\n250 HiddenServiceDir\r\n"
+                    .to_string(),
+            ),
+            r#"SETCONF HiddenServicePort="80 example:80" SocksPort"# => {
+                Some("250 OK\r\n".to_string())
+            }
+            _ => Some("552 Unrecognized option\r\n".to_string()),
+        })
+        .await;
+
+        let mut client = ControlClient::connect(addr).await.unwrap();
+
+        assert_eq!(
+            vec![
+                ("SocksPort".to_string(), Some("0.0.0.0:9050".to_string())),
+                ("SocksPort".to_string(), Some("127.0.0.1:9150".to_string())),
+                (
+                    "ControlPort".to_string(),
+                    Some("127.0.0.1:6666".to_string())
+                ),
+                ("HiddenServiceDir".to_string(), None),
+            ],
+            client
+                .get_conf(&["SocksPort", "ControlPort", "HiddenServiceDir"])
+                .await
+                .unwrap()
+        );
+
+        client
+            .set_conf(&[
+                ("HiddenServicePort", Some("80 example:80")),
+                ("SocksPort", None),
+            ])
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn signal_and_errors() {
+        let addr = fake_control_port(|line| match line {
+            "SIGNAL NEWNYM" => Some("250 OK\r\n".to_string()),
+            _ => Some("552 Unrecognized signal\r\n".to_string()),
+        })
+        .await;
+
+        let mut client = ControlClient::connect(addr).await.unwrap();
+
+        client.signal(ControlSignal::Newnym).await.unwrap();
+
+        match client.signal(ControlSignal::Dormant).await {
+            Err(Error::ControlReply { status, message }) => {
+                assert_eq!(552, status);
+                assert_eq!("Unrecognized signal", message);
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn events() {
+        let addr = fake_control_port(|line| match line {
+            "SETEVENTS HS_DESC" => Some(
+                "250 OK\r\n650 HS_DESC UPLOADED abcdef UNKNOWN $F00 AAAA\r\n650+NS\r\nr relay\r\n.\r\n650 OK\r\n"
+                    .to_string(),
+            ),
+            "GETINFO version" => Some("250-version=0.4.9.11\r\n250 OK\r\n".to_string()),
+            _ => None,
+        })
+        .await;
+
+        let mut client = ControlClient::connect(addr).await.unwrap();
+        let mut events = client.events().unwrap();
+
+        client.set_events(&["HS_DESC"]).await.unwrap();
+
+        let event = events.recv().await.unwrap();
+        assert_eq!("HS_DESC", event.keyword());
+        assert_eq!(
+            "HS_DESC UPLOADED abcdef UNKNOWN $F00 AAAA",
+            event.reply().message()
+        );
+
+        let event = events.recv().await.unwrap();
+        assert_eq!("NS", event.keyword());
+        assert_eq!(Some("r relay"), event.reply().lines[0].data.as_deref());
+
+        assert_eq!(
+            "0.4.9.11",
+            client.get_info(&["version"]).await.unwrap()["version"]
+        );
+    }
+
+    #[tokio::test]
+    async fn hs_fetch_and_hs_post() {
+        let mut posted = Vec::new();
+        let mut posting = false;
+        let addr = fake_control_port(move |line| {
+            if posting {
+                if line == "." {
+                    posting = false;
+                    return Some(if posted == ["hs-descriptor 3", "..hidden"] {
+                        "250 OK\r\n".to_string()
+                    } else {
+                        "554 Invalid descriptor\r\n".to_string()
+                    });
+                }
+                posted.push(line.to_string());
+                return Some(String::new());
+            }
+            match line {
+                "HSFETCH pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd" => {
+                    Some("250 OK\r\n".to_string())
+                }
+                "+HSPOST HSADDRESS=pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd" => {
+                    posting = true;
+                    Some(String::new())
+                }
+                _ => Some("552 Unrecognized command\r\n".to_string()),
+            }
+        })
+        .await;
+
+        let mut client = ControlClient::connect(addr).await.unwrap();
+
+        let hostname = Hostname::new(
+            "pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd.onion".to_string(),
+        );
+
+        client.hs_fetch(&hostname).await.unwrap();
+
+        client
+            .hs_post(
+                &hostname,
+                &Descriptor::from("hs-descriptor 3\n.hidden\n".to_string()),
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn connection_closed() {
+        let addr = fake_control_port(|_| None).await;
+
+        let mut client = ControlClient::connect(addr).await.unwrap();
+
+        assert!(matches!(
+            client.command("GETINFO version").await,
+            Err(Error::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,808 @@
+use std::{
+    fmt::Write as _,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use aws_lc_rs::cipher::{AES_256, EncryptingKey, EncryptionContext, UnboundCipherKey};
+use base64::{
+    Engine as _,
+    alphabet::STANDARD,
+    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig, general_purpose},
+};
+use rand::{Rng as _, SeedableRng as _};
+use sha3::{Digest as _, Sha3_256};
+
+use super::{Error, ExpandedSecretKey, PublicKey, Result, constants::TIME_PERIOD_LENGTH_MINUTES};
+
+/// Tor emits padded base64, but accepts either.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+const CERTIFICATE_LIFETIME_HOURS: u64 = 54;
+
+const CERTIFICATE_TYPE_DESCRIPTOR_SIGNING: u8 = 0x08;
+
+const CERTIFICATE_TYPE_INTRODUCTION_POINT_AUTHENTICATION: u8 = 0x09;
+
+const CERTIFICATE_TYPE_INTRODUCTION_POINT_ENCRYPTION: u8 = 0x0B;
+
+const DESCRIPTOR_LIFETIME_MINUTES: u64 = 180;
+
+const DESCRIPTOR_SIGNATURE_PREFIX: &[u8] = b"Tor onion service descriptor sig v3";
+
+const ENCRYPTED_DATA_CONSTANT: &[u8] = b"hsdir-encrypted-data";
+
+/// Tor rejects descriptors with more introduction points.
+const MAX_INTRODUCTION_POINTS: usize = 20;
+
+const SUPERENCRYPTED_DATA_CONSTANT: &[u8] = b"hsdir-superencrypted-data";
+
+/// The superencrypted layer is padded, so that its length does not reveal the
+/// number of introduction points.
+const SUPERENCRYPTED_PADDING: usize = 10000;
+
+/// Fake clients hiding that client authorization is disabled.
+const FAKE_AUTH_CLIENTS: usize = 16;
+
+const ROTATION_OFFSET_MINUTES: u64 = 720;
+
+/*
+ * ============================================================================
+ * Descriptor
+ * ============================================================================
+ */
+/// A v3 Onion Service descriptor, as uploaded to and fetched from the hidden
+/// service directories.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Descriptor(String);
+
+impl Descriptor {
+    #[must_use]
+    pub fn builder() -> DescriptorBuilder {
+        DescriptorBuilder {
+            introduction_points: Vec::new(),
+            revision_counter: 0,
+            time_period: time_period(SystemTime::now()),
+        }
+    }
+
+    /// Verifies the descriptor of the Onion Service and decrypts its
+    /// introduction points.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the descriptor is malformed, its signatures do not
+    /// verify, or it cannot be decrypted with the public key.
+    pub fn introduction_points(&self, public_key: &PublicKey) -> Result<Vec<IntroductionPoint>> {
+        let document = items(&self.0)?;
+
+        let certificate = item_object(&document, "descriptor-signing-key-cert")?;
+        let certificate = Certificate::try_from(certificate)?;
+        let blinded_public_key = certificate.signing_key.ok_or_else(|| {
+            Error::ParseError("descriptor signing key certificate has no signing key".into())
+        })?;
+        certificate.verify(&blinded_public_key)?;
+
+        let (signed, signature) = self
+            .0
+            .rfind("\nsignature ")
+            .map(|index| self.0.split_at(index + 1))
+            .ok_or_else(|| Error::ParseError("missing signature".into()))?;
+        let signature = decode(
+            signature
+                .trim_end()
+                .strip_prefix("signature ")
+                .unwrap_or_default(),
+        )?;
+        ed25519_dalek::VerifyingKey::from_bytes(&certificate.certified_key)
+            .and_then(|key| {
+                key.verify_strict(
+                    &[DESCRIPTOR_SIGNATURE_PREFIX, signed.as_bytes()].concat(),
+                    &ed25519_dalek::Signature::from_slice(&signature)?,
+                )
+            })
+            .map_err(Error::SignatureError)?;
+
+        let revision_counter = item(&document, "revision-counter")?
+            .arguments
+            .parse()
+            .map_err(|_| Error::ParseError("malformed revision-counter".into()))?;
+
+        let subcredential = subcredential(public_key, &blinded_public_key);
+
+        let superencrypted = decrypt_layer(
+            item_object(&document, "superencrypted")?,
+            &blinded_public_key,
+            &subcredential,
+            revision_counter,
+            SUPERENCRYPTED_DATA_CONSTANT,
+        )?;
+        let superencrypted = String::from_utf8(superencrypted)
+            .map_err(|_| Error::ParseError("superencrypted layer is not UTF-8".into()))?;
+
+        let superencrypted = items(superencrypted.trim_end_matches('\0'))?;
+
+        let encrypted = decrypt_layer(
+            item_object(&superencrypted, "encrypted")?,
+            &blinded_public_key,
+            &subcredential,
+            revision_counter,
+            ENCRYPTED_DATA_CONSTANT,
+        )?;
+        let encrypted = String::from_utf8(encrypted)
+            .map_err(|_| Error::ParseError("encrypted layer is not UTF-8".into()))?;
+
+        parse_introduction_points(&encrypted)
+    }
+}
+
+impl From<String> for Descriptor {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl std::fmt::Display for Descriptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[allow(clippy::module_name_repetitions)]
+pub struct DescriptorBuilder {
+    introduction_points: Vec<IntroductionPoint>,
+    revision_counter: u64,
+    time_period: u64,
+}
+
+impl DescriptorBuilder {
+    /// Signs the descriptor with the blinded key of the Onion Service.
+    #[must_use]
+    pub fn build(&self, secret_key: &ExpandedSecretKey) -> Descriptor {
+        let public_key = PublicKey::from(secret_key);
+        let blinded_secret_key = secret_key.blind(self.time_period);
+        let blinded_public_key = PublicKey::from(&blinded_secret_key).to_bytes();
+        let subcredential = subcredential(&public_key, &blinded_public_key);
+
+        let signing_key = ExpandedSecretKey::generate();
+        let expiration = expiration(SystemTime::now());
+
+        let mut encrypted = String::from("create2-formats 2\n");
+        for introduction_point in self
+            .introduction_points
+            .iter()
+            .take(MAX_INTRODUCTION_POINTS)
+        {
+            let _ = write!(
+                encrypted,
+                "introduction-point {}\nonion-key {}\nauth-key\n{}enc-key {}\nenc-key-cert\n{}",
+                introduction_point.link_specifiers,
+                introduction_point.onion_key,
+                pem(
+                    "ED25519 CERT",
+                    &certificate(
+                        CERTIFICATE_TYPE_INTRODUCTION_POINT_AUTHENTICATION,
+                        &introduction_point.auth_key,
+                        &signing_key,
+                        expiration,
+                    ),
+                ),
+                introduction_point.enc_key,
+                pem(
+                    "ED25519 CERT",
+                    &certificate(
+                        CERTIFICATE_TYPE_INTRODUCTION_POINT_ENCRYPTION,
+                        &introduction_point.enc_key_ed25519,
+                        &signing_key,
+                        expiration,
+                    ),
+                ),
+            );
+        }
+
+        let mut superencrypted = format!(
+            "desc-auth-type x25519\ndesc-auth-ephemeral-key {}\n",
+            general_purpose::STANDARD_NO_PAD.encode(random::<32>())
+        );
+        for _ in 0..FAKE_AUTH_CLIENTS {
+            let _ = writeln!(
+                superencrypted,
+                "auth-client {} {} {}",
+                BASE64.encode(random::<8>()),
+                BASE64.encode(random::<16>()),
+                BASE64.encode(random::<16>())
+            );
+        }
+        superencrypted.push_str("encrypted\n");
+        superencrypted.push_str(&pem(
+            "MESSAGE",
+            &encrypt_layer(
+                encrypted.as_bytes(),
+                &blinded_public_key,
+                &subcredential,
+                self.revision_counter,
+                ENCRYPTED_DATA_CONSTANT,
+            ),
+        ));
+        let mut superencrypted = superencrypted.into_bytes();
+        superencrypted.resize(
+            superencrypted
+                .len()
+                .next_multiple_of(SUPERENCRYPTED_PADDING),
+            0,
+        );
+
+        let document = format!(
+            "hs-descriptor 3\ndescriptor-lifetime {DESCRIPTOR_LIFETIME_MINUTES}\ndescriptor-signing-key-cert\n{}revision-counter {}\nsuperencrypted\n{}",
+            pem(
+                "ED25519 CERT",
+                &certificate(
+                    CERTIFICATE_TYPE_DESCRIPTOR_SIGNING,
+                    &PublicKey::from(&signing_key).to_bytes(),
+                    &blinded_secret_key,
+                    expiration,
+                ),
+            ),
+            self.revision_counter,
+            pem(
+                "MESSAGE",
+                &encrypt_layer(
+                    &superencrypted,
+                    &blinded_public_key,
+                    &subcredential,
+                    self.revision_counter,
+                    SUPERENCRYPTED_DATA_CONSTANT,
+                ),
+            ),
+        );
+
+        let signature =
+            signing_key.sign(&[DESCRIPTOR_SIGNATURE_PREFIX, document.as_bytes()].concat());
+
+        Descriptor(format!(
+            "{document}signature {}\n",
+            general_purpose::STANDARD_NO_PAD.encode(signature.to_bytes())
+        ))
+    }
+
+    #[must_use]
+    pub fn introduction_points(
+        mut self,
+        introduction_points: impl IntoIterator<Item = IntroductionPoint>,
+    ) -> Self {
+        self.introduction_points.extend(introduction_points);
+        self
+    }
+
+    /// Must increase with every upload of the time period, as hidden service
+    /// directories reject descriptors older than the one they hold.
+    #[must_use]
+    pub fn revision_counter(mut self, revision_counter: u64) -> Self {
+        self.revision_counter = revision_counter;
+        self
+    }
+
+    #[must_use]
+    pub fn time_period(mut self, time_period: u64) -> Self {
+        self.time_period = time_period;
+        self
+    }
+}
+
+/// An introduction point of an Onion Service, which can be moved between
+/// descriptors as its keys are certified by the signing key of the
+/// descriptor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntroductionPoint {
+    /// The ed25519 authentication key.
+    pub auth_key: [u8; 32],
+
+    /// `ntor <base64>`
+    pub enc_key: String,
+
+    /// The ed25519 form of the encryption key, certified by `enc-key-cert`.
+    pub enc_key_ed25519: [u8; 32],
+
+    /// The base64 encoded link specifiers of the introduction point relay.
+    pub link_specifiers: String,
+
+    /// `ntor <base64>`
+    pub onion_key: String,
+}
+
+/// The time period of the time, which changes every day at 12:00 UTC.
+#[must_use]
+pub fn time_period(now: SystemTime) -> u64 {
+    let minutes = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / 60;
+
+    minutes.saturating_sub(ROTATION_OFFSET_MINUTES) / TIME_PERIOD_LENGTH_MINUTES
+}
+
+/*
+ * ============================================================================
+ * Document
+ * ============================================================================
+ */
+struct Item<'a> {
+    arguments: &'a str,
+    keyword: &'a str,
+    object: Option<Vec<u8>>,
+}
+
+fn items(document: &str) -> Result<Vec<Item<'_>>> {
+    let mut items: Vec<Item> = Vec::new();
+    let mut lines = document.lines();
+
+    while let Some(line) = lines.next() {
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(label) = line
+            .strip_prefix("-----BEGIN ")
+            .and_then(|f| f.strip_suffix("-----"))
+        {
+            let end = format!("-----END {label}-----");
+            let mut body = String::new();
+            loop {
+                let line = lines
+                    .next()
+                    .ok_or_else(|| Error::ParseError(format!("unterminated {label}")))?;
+                if line == end {
+                    break;
+                }
+                body.push_str(line);
+            }
+
+            items
+                .last_mut()
+                .ok_or_else(|| Error::ParseError(format!("{label} without keyword")))?
+                .object = Some(decode(&body)?);
+            continue;
+        }
+
+        let (keyword, arguments) = line.split_once(' ').unwrap_or((line, ""));
+        items.push(Item {
+            arguments,
+            keyword,
+            object: None,
+        });
+    }
+
+    Ok(items)
+}
+
+fn item<'a, 'b>(items: &'a [Item<'b>], keyword: &str) -> Result<&'a Item<'b>> {
+    items
+        .iter()
+        .find(|item| item.keyword == keyword)
+        .ok_or_else(|| Error::ParseError(format!("missing {keyword}")))
+}
+
+fn item_object<'a>(items: &'a [Item<'_>], keyword: &str) -> Result<&'a [u8]> {
+    item(items, keyword)?
+        .object
+        .as_deref()
+        .ok_or_else(|| Error::ParseError(format!("missing {keyword} object")))
+}
+
+fn parse_introduction_points(document: &str) -> Result<Vec<IntroductionPoint>> {
+    let mut introduction_points: Vec<IntroductionPoint> = Vec::new();
+
+    for item in items(document)? {
+        if item.keyword == "introduction-point" {
+            introduction_points.push(IntroductionPoint {
+                auth_key: [0; 32],
+                enc_key: String::new(),
+                enc_key_ed25519: [0; 32],
+                link_specifiers: item.arguments.to_string(),
+                onion_key: String::new(),
+            });
+            continue;
+        }
+
+        let Some(introduction_point) = introduction_points.last_mut() else {
+            continue;
+        };
+
+        match (item.keyword, &item.object) {
+            ("auth-key", Some(object)) => {
+                introduction_point.auth_key =
+                    Certificate::try_from(object.as_slice())?.certified_key;
+            }
+            ("enc-key", _) => introduction_point.enc_key = item.arguments.to_string(),
+            ("enc-key-cert", Some(object)) => {
+                introduction_point.enc_key_ed25519 =
+                    Certificate::try_from(object.as_slice())?.certified_key;
+            }
+            ("onion-key", _) => introduction_point.onion_key = item.arguments.to_string(),
+            _ => {}
+        }
+    }
+
+    match introduction_points
+        .iter()
+        .find(|f| f.onion_key.is_empty() || f.enc_key.is_empty())
+    {
+        Some(introduction_point) => Err(Error::ParseError(format!(
+            "incomplete introduction point: {}",
+            introduction_point.link_specifiers
+        ))),
+        None => Ok(introduction_points),
+    }
+}
+
+fn pem(label: &str, data: &[u8]) -> String {
+    let encoded = BASE64.encode(data);
+    let mut pem = format!("-----BEGIN {label}-----\n");
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(&String::from_utf8_lossy(line));
+        pem.push('\n');
+    }
+    let _ = writeln!(pem, "-----END {label}-----");
+    pem
+}
+
+fn decode(data: &str) -> Result<Vec<u8>> {
+    BASE64
+        .decode(data)
+        .map_err(|e| Error::ParseError(format!("malformed base64: {e}")))
+}
+
+/*
+ * ============================================================================
+ * Certificate
+ * ============================================================================
+ */
+/// A tor ed25519 certificate of cert-spec section 2.1, whose only extension is
+/// the signing key.
+struct Certificate<'a> {
+    certified_key: [u8; 32],
+    message: &'a [u8],
+    signature: ed25519_dalek::Signature,
+    signing_key: Option<[u8; 32]>,
+}
+
+impl Certificate<'_> {
+    fn verify(&self, key: &[u8; 32]) -> Result<()> {
+        ed25519_dalek::VerifyingKey::from_bytes(key)
+            .and_then(|key| key.verify_strict(self.message, &self.signature))
+            .map_err(Error::SignatureError)
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Certificate<'a> {
+    type Error = Error;
+
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        let malformed = || Error::ParseError("malformed certificate".into());
+
+        if value.len() < 40 + 64 || value[0] != 1 {
+            return Err(malformed());
+        }
+        let (message, signature) = value.split_at(value.len() - 64);
+
+        let certified_key = message[7..39].try_into().map_err(|_| malformed())?;
+
+        let mut signing_key = None;
+        let mut rest = &message[40..];
+        for _ in 0..message[39] {
+            let [length_high, length_low, extension_type, _flags, data @ ..] = rest else {
+                return Err(malformed());
+            };
+            let length = usize::from(u16::from_be_bytes([*length_high, *length_low]));
+            if data.len() < length {
+                return Err(malformed());
+            }
+            if *extension_type == 0x04 {
+                signing_key = Some(data[..length].try_into().map_err(|_| malformed())?);
+            }
+            rest = &data[length..];
+        }
+        if !rest.is_empty() {
+            return Err(malformed());
+        }
+
+        Ok(Self {
+            certified_key,
+            message,
+            signature: ed25519_dalek::Signature::from_slice(signature)
+                .map_err(Error::SignatureError)?,
+            signing_key,
+        })
+    }
+}
+
+fn certificate(
+    certificate_type: u8,
+    certified_key: &[u8; 32],
+    signing_key: &ExpandedSecretKey,
+    expiration: u32,
+) -> Vec<u8> {
+    let mut certificate = vec![1, certificate_type];
+    certificate.extend(expiration.to_be_bytes());
+    certificate.push(1);
+    certificate.extend(certified_key);
+    certificate.push(1);
+    certificate.extend(32_u16.to_be_bytes());
+    certificate.extend([0x04, 0]);
+    certificate.extend(PublicKey::from(signing_key).to_bytes());
+
+    let signature = signing_key.sign(&certificate);
+    certificate.extend(signature.to_bytes());
+    certificate
+}
+
+/// Hours since the epoch.
+fn expiration(now: SystemTime) -> u32 {
+    let hours = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / 3600;
+
+    u32::try_from(hours + CERTIFICATE_LIFETIME_HOURS).unwrap_or(u32::MAX)
+}
+
+/*
+ * ============================================================================
+ * Encryption
+ * ============================================================================
+ */
+/// `N_hs_subcred` of rend-spec-v3 section 2.2.
+fn subcredential(public_key: &PublicKey, blinded_public_key: &[u8; 32]) -> [u8; 32] {
+    let credential = Sha3_256::new()
+        .chain_update(b"credential")
+        .chain_update(public_key.as_bytes())
+        .finalize();
+
+    Sha3_256::new()
+        .chain_update(b"subcredential")
+        .chain_update(credential)
+        .chain_update(blinded_public_key)
+        .finalize()
+        .into()
+}
+
+fn encrypt_layer(
+    plaintext: &[u8],
+    blinded_public_key: &[u8; 32],
+    subcredential: &[u8; 32],
+    revision_counter: u64,
+    constant: &[u8],
+) -> Vec<u8> {
+    let salt = random::<16>();
+    let (key, iv, mac_key) = layer_keys(
+        blinded_public_key,
+        subcredential,
+        revision_counter,
+        &salt,
+        constant,
+    );
+
+    let mut ciphertext = plaintext.to_vec();
+    aes_256_ctr(&key, iv, &mut ciphertext);
+    let mac = layer_mac(&mac_key, &salt, &ciphertext);
+
+    [&salt[..], &ciphertext, &mac].concat()
+}
+
+fn decrypt_layer(
+    blob: &[u8],
+    blinded_public_key: &[u8; 32],
+    subcredential: &[u8; 32],
+    revision_counter: u64,
+    constant: &[u8],
+) -> Result<Vec<u8>> {
+    if blob.len() < 16 + 32 {
+        return Err(Error::ParseError("encrypted layer too short".into()));
+    }
+    let (salt, rest) = blob.split_at(16);
+    let (ciphertext, mac) = rest.split_at(rest.len() - 32);
+
+    let (key, iv, mac_key) = layer_keys(
+        blinded_public_key,
+        subcredential,
+        revision_counter,
+        salt,
+        constant,
+    );
+    if layer_mac(&mac_key, salt, ciphertext) != mac {
+        return Err(Error::ParseError("encrypted layer MAC mismatch".into()));
+    }
+
+    let mut plaintext = ciphertext.to_vec();
+    aes_256_ctr(&key, iv, &mut plaintext);
+    Ok(plaintext)
+}
+
+fn layer_keys(
+    blinded_public_key: &[u8; 32],
+    subcredential: &[u8; 32],
+    revision_counter: u64,
+    salt: &[u8],
+    constant: &[u8],
+) -> ([u8; 32], [u8; 16], [u8; 32]) {
+    let mut keys = [0_u8; 80];
+    shake256(
+        &[
+            &blinded_public_key[..],
+            subcredential,
+            &revision_counter.to_be_bytes(),
+            salt,
+            constant,
+        ]
+        .concat(),
+        &mut keys,
+    );
+
+    let (key, rest) = keys.split_at(32);
+    let (iv, mac_key) = rest.split_at(16);
+    (
+        key.try_into()
+            .expect("slice of fixed size wasn't that size"),
+        iv.try_into().expect("slice of fixed size wasn't that size"),
+        mac_key
+            .try_into()
+            .expect("slice of fixed size wasn't that size"),
+    )
+}
+
+fn layer_mac(mac_key: &[u8; 32], salt: &[u8], ciphertext: &[u8]) -> [u8; 32] {
+    Sha3_256::new()
+        .chain_update(32_u64.to_be_bytes())
+        .chain_update(mac_key)
+        .chain_update(16_u64.to_be_bytes())
+        .chain_update(salt)
+        .chain_update(ciphertext)
+        .finalize()
+        .into()
+}
+
+fn aes_256_ctr(key: &[u8; 32], iv: [u8; 16], data: &mut [u8]) {
+    EncryptingKey::ctr(UnboundCipherKey::new(&AES_256, key).expect("AES-256 key is 32 bytes"))
+        .expect("AES-256-CTR is supported")
+        .less_safe_encrypt(data, EncryptionContext::Iv128(iv.into()))
+        .expect("AES-256-CTR accepts any length");
+}
+
+/// SHAKE256 of FIPS 202, which tor uses as its KDF.
+fn shake256(input: &[u8], output: &mut [u8]) {
+    const RATE: usize = 136;
+
+    let mut padded = input.to_vec();
+    padded.push(0x1F);
+    padded.resize(padded.len().next_multiple_of(RATE), 0);
+    *padded.last_mut().expect("padded input is never empty") |= 0x80;
+
+    let mut state = [0_u64; 25];
+    keccak::Keccak::new().with_f1600(|f1600| {
+        for block in padded.chunks(RATE) {
+            for (lane, bytes) in state.iter_mut().zip(block.chunks(8)) {
+                *lane ^= u64::from_le_bytes(bytes.try_into().expect("lanes are 8 bytes"));
+            }
+            f1600(&mut state);
+        }
+
+        for chunk in output.chunks_mut(RATE) {
+            let bytes = state
+                .iter()
+                .flat_map(|lane| lane.to_le_bytes())
+                .collect::<Vec<_>>();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+            f1600(&mut state);
+        }
+    });
+}
+
+/// # Panics
+///
+/// Panics if the system entropy source cannot be used to seed the RNG.
+fn random<const N: usize>() -> [u8; N] {
+    let mut csprng = rand::rngs::StdRng::try_from_rng(&mut rand::rngs::SysRng)
+        .expect("failed to seed StdRng from system entropy source");
+
+    let mut bytes = [0_u8; N];
+    csprng.fill_bytes(&mut bytes);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn introduction_point(index: u8) -> IntroductionPoint {
+        IntroductionPoint {
+            auth_key: PublicKey::from(&ExpandedSecretKey::generate()).to_bytes(),
+            enc_key: format!("ntor {}", BASE64.encode([index; 32])),
+            enc_key_ed25519: PublicKey::from(&ExpandedSecretKey::generate()).to_bytes(),
+            link_specifiers: BASE64.encode([index; 20]),
+            onion_key: format!("ntor {}", BASE64.encode([index + 1; 32])),
+        }
+    }
+
+    #[test]
+    fn shake256_empty() {
+        let mut output = [0_u8; 32];
+        shake256(b"", &mut output);
+
+        assert_eq!(
+            [
+                0x46, 0xb9, 0xdd, 0x2b, 0x0b, 0xa8, 0x8d, 0x13, 0x23, 0x3b, 0x3f, 0xeb, 0x74, 0x3e,
+                0xeb, 0x24, 0x3f, 0xcd, 0x52, 0xea, 0x62, 0xb8, 0x1b, 0x82, 0xb5, 0x0c, 0x27, 0x64,
+                0x6e, 0xd5, 0x76, 0x2f,
+            ],
+            output
+        );
+    }
+
+    #[test]
+    fn time_periods() {
+        assert_eq!(0, time_period(UNIX_EPOCH));
+        // rend-spec-v3 section 2.2.1
+        assert_eq!(
+            16903,
+            time_period(UNIX_EPOCH + std::time::Duration::from_secs(1_460_546_101))
+        );
+    }
+
+    #[test]
+    fn blind() {
+        let secret_key = ExpandedSecretKey::generate();
+        let public_key = PublicKey::from(&secret_key);
+
+        assert!(PublicKey::from(&secret_key.blind(16903)) == public_key.blind(16903));
+        assert!(public_key.blind(16903) != public_key.blind(16904));
+    }
+
+    #[test]
+    fn round_trip() {
+        let secret_key = ExpandedSecretKey::generate();
+        let introduction_points = (1..=3).map(introduction_point).collect::<Vec<_>>();
+
+        let descriptor = Descriptor::builder()
+            .introduction_points(introduction_points.clone())
+            .revision_counter(42)
+            .time_period(16903)
+            .build(&secret_key);
+
+        assert_eq!(
+            introduction_points,
+            descriptor
+                .introduction_points(&PublicKey::from(&secret_key))
+                .unwrap()
+        );
+
+        assert!(
+            descriptor
+                .introduction_points(&PublicKey::from(&ExpandedSecretKey::generate()))
+                .is_err()
+        );
+
+        let tampered = Descriptor::from(
+            descriptor
+                .to_string()
+                .replace("revision-counter 42", "revision-counter 43"),
+        );
+        assert!(
+            tampered
+                .introduction_points(&PublicKey::from(&secret_key))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn max_introduction_points() {
+        let secret_key = ExpandedSecretKey::generate();
+
+        let descriptor = Descriptor::builder()
+            .introduction_points((0..30).map(introduction_point))
+            .build(&secret_key);
+
+        assert_eq!(
+            MAX_INTRODUCTION_POINTS,
+            descriptor
+                .introduction_points(&PublicKey::from(&secret_key))
+                .unwrap()
+                .len()
+        );
+    }
+}
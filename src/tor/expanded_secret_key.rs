@@ -1,8 +1,13 @@
 use std::ops::Deref;
 
+use curve25519_dalek::Scalar;
 use rand::{Rng as _, SeedableRng as _};
+use sha2::{Digest as _, Sha512};
 
-use super::{Error, HiddenServiceSecretKey, Result, hidden_service_secret_key::Data};
+use super::{
+    Error, HiddenServiceSecretKey, PublicKey, Result, constants::BLIND_HASH_INPUT,
+    hidden_service_secret_key::Data,
+};
 
 pub struct ExpandedSecretKey(ed25519_dalek::hazmat::ExpandedSecretKey);
 
@@ -23,6 +28,30 @@ impl ExpandedSecretKey {
         Self(secret_key.as_bytes().into())
     }
 
+    /// Derives the blinded key of the time period, which signs the
+    /// descriptors of the Onion Service.
+    #[must_use]
+    pub fn blind(&self, time_period: u64) -> Self {
+        let factor = PublicKey::from(self).blinding_factor(time_period);
+        let mut hash_prefix = [0_u8; 32];
+        hash_prefix.copy_from_slice(
+            &Sha512::new()
+                .chain_update(self.hash_prefix)
+                .chain_update(BLIND_HASH_INPUT)
+                .finalize()[..32],
+        );
+
+        Self(ed25519_dalek::hazmat::ExpandedSecretKey {
+            hash_prefix,
+            scalar: factor * Scalar::from_bytes_mod_order(self.scalar.to_bytes()),
+        })
+    }
+
+    #[must_use]
+    pub fn sign(&self, message: &[u8]) -> ed25519_dalek::Signature {
+        ed25519_dalek::hazmat::raw_sign::<Sha512>(self, message, &PublicKey::from(self))
+    }
+
     #[must_use]
     pub fn to_bytes(&self) -> [u8; 64] {
         let mut output = [0_u8; 64];
@@ -1,5 +1,7 @@
 mod config_yaml;
 mod constants;
+mod control;
+mod descriptor;
 mod expanded_secret_key;
 mod hidden_service_public_key;
 mod hidden_service_secret_key;
@@ -9,29 +11,50 @@ mod public_key;
 mod torrc;
 
 pub use config_yaml::{ConfigYaml, ConfigYamlService, ConfigYamlServiceInstance};
+pub use control::{
+    ControlAuth, ControlClient, ControlEvent, ControlProtocolInfo, ControlReply, ControlReplyLine,
+    ControlSignal,
+};
+pub use descriptor::{Descriptor, DescriptorBuilder, IntroductionPoint, time_period};
 pub use expanded_secret_key::ExpandedSecretKey;
 pub use hidden_service_public_key::HiddenServicePublicKey;
 pub use hidden_service_secret_key::HiddenServiceSecretKey;
 pub use hostname::Hostname;
 pub use ob_config::{OBConfig, OBConfigBuilder};
 pub use public_key::PublicKey;
-pub use torrc::{Torrc, TorrcBuilder};
+pub use torrc::{Torrc, TorrcBuilder, TorrcRestart};
 
 #[derive(Debug)]
 pub enum Error {
+    Authentication(String),
+    ControlReply { status: u16, message: String },
+    Io(std::io::Error),
     ParseError(String),
     SignatureError(ed25519_dalek::SignatureError),
 }
 
+impl std::error::Error for Error {}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Error::Authentication(e) => write!(f, "authentication error: {e}"),
+            Error::ControlReply { status, message } => {
+                write!(f, "control reply error: {status} {message}")
+            }
+            Error::Io(e) => write!(f, "io error: {e}"),
             Error::ParseError(e) => write!(f, "parse error: {e}"),
             Error::SignatureError(e) => write!(f, "signature error: {e}"),
         }
     }
 }
 
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
 type Result<T, E = Error> = std::result::Result<T, E>;
 
 #[cfg(test)]
@@ -149,6 +172,11 @@ mod tests {
             Hostname::from(&public),
             "host names are different"
         );
+
+        assert!(
+            PublicKey::try_from(&hostname).unwrap() == public,
+            "public key from host name is different"
+        );
     }
 
     #[test]
@@ -1,12 +1,43 @@
 use std::ops::Deref;
 
+use curve25519_dalek::Scalar;
+use sha3::{Digest as _, Sha3_256};
+
 use super::{
-    Error, ExpandedSecretKey, HiddenServicePublicKey, Result, hidden_service_public_key::Data,
+    Error, ExpandedSecretKey, HiddenServicePublicKey, Hostname, Result,
+    constants::{BLIND_BASEPOINT, BLIND_NONCE, BLIND_STRING, TIME_PERIOD_LENGTH_MINUTES},
+    hidden_service_public_key::Data,
 };
 
 #[derive(PartialEq)]
 pub struct PublicKey(ed25519_dalek::VerifyingKey);
 
+impl PublicKey {
+    /// Derives the blinded public key of the time period, which identifies
+    /// the descriptors of the Onion Service.
+    #[must_use]
+    pub fn blind(&self, time_period: u64) -> Self {
+        Self((self.to_edwards() * self.blinding_factor(time_period)).into())
+    }
+
+    /// `h` of rend-spec-v3 appendix A.2, clamped.
+    pub(super) fn blinding_factor(&self, time_period: u64) -> Scalar {
+        let mut h: [u8; 32] = Sha3_256::new()
+            .chain_update(BLIND_STRING)
+            .chain_update(self.as_bytes())
+            .chain_update(BLIND_BASEPOINT)
+            .chain_update(BLIND_NONCE)
+            .chain_update(time_period.to_be_bytes())
+            .chain_update(TIME_PERIOD_LENGTH_MINUTES.to_be_bytes())
+            .finalize()
+            .into();
+        h[0] &= 0b1111_1000;
+        h[31] &= 0b0011_1111;
+        h[31] |= 0b0100_0000;
+        Scalar::from_bytes_mod_order(h)
+    }
+}
+
 impl Deref for PublicKey {
     type Target = ed25519_dalek::VerifyingKey;
 
@@ -21,6 +52,30 @@ impl From<&ExpandedSecretKey> for PublicKey {
     }
 }
 
+impl TryFrom<&Hostname> for PublicKey {
+    type Error = Error;
+
+    fn try_from(value: &Hostname) -> Result<Self, Self::Error> {
+        let hostname = value.to_string();
+        let domain = hostname.trim_end_matches(".onion");
+
+        let data = base32::decode(
+            base32::Alphabet::Rfc4648 { padding: false },
+            &domain.to_ascii_uppercase(),
+        )
+        .filter(|data| data.len() == 35)
+        .ok_or_else(|| Error::ParseError(format!("malformed onion address: {domain}")))?;
+
+        ed25519_dalek::VerifyingKey::from_bytes(
+            data[..32]
+                .try_into()
+                .expect("slice of fixed size wasn't that size"),
+        )
+        .map(Self)
+        .map_err(Error::SignatureError)
+    }
+}
+
 impl TryFrom<&HiddenServicePublicKey> for PublicKey {
     type Error = Error;
 
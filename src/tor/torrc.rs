@@ -1,5 +1,27 @@
 use crate::kubernetes::Annotation;
 
+/// Options Tor cannot change while running.
+const NON_RELOADABLE_OPTIONS: &[&str] = &[
+    "AccelDir",
+    "AccelName",
+    "CacheDirectory",
+    "CacheDirectoryGroupReadable",
+    "ControlPortFileGroupReadable",
+    "ControlPortWriteToFile",
+    "DataDirectory",
+    "DataDirectoryGroupReadable",
+    "DisableAllSwap",
+    "HardwareAccel",
+    "KeepBindCapabilities",
+    "KeyDirectory",
+    "KeyDirectoryGroupReadable",
+    "NoExec",
+    "RunAsDaemon",
+    "Sandbox",
+    "SyslogIdentityTag",
+    "User",
+];
+
 pub struct Torrc(String);
 
 impl Torrc {
@@ -7,6 +29,24 @@ impl Torrc {
     pub fn builder() -> TorrcBuilder {
         TorrcBuilder(Vec::new())
     }
+
+    /// The options whose change requires Tor to be restarted.
+    #[must_use]
+    pub fn restart(&self) -> TorrcRestart {
+        TorrcRestart(
+            self.0
+                .lines()
+                .filter(|line| {
+                    line.split_whitespace().next().is_some_and(|keyword| {
+                        NON_RELOADABLE_OPTIONS
+                            .iter()
+                            .any(|option| option.eq_ignore_ascii_case(keyword))
+                    })
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+    }
 }
 
 impl Annotation<'_> for Torrc {
@@ -25,6 +65,25 @@ impl std::fmt::Display for Torrc {
     }
 }
 
+#[allow(clippy::module_name_repetitions)]
+pub struct TorrcRestart(String);
+
+impl Annotation<'_> for TorrcRestart {
+    const NAME: &'static str = "torrc-restart";
+}
+
+impl<'a> From<&'a TorrcRestart> for std::borrow::Cow<'a, str> {
+    fn from(value: &'a TorrcRestart) -> Self {
+        std::borrow::Cow::Borrowed(&value.0)
+    }
+}
+
+impl std::fmt::Display for TorrcRestart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 #[allow(clippy::module_name_repetitions)]
 pub struct TorrcBuilder(Vec<String>);
 
@@ -41,6 +100,13 @@ impl TorrcBuilder {
         self
     }
 
+    #[must_use]
+    pub fn cookie_authentication(mut self, enabled: bool) -> Self {
+        self.0
+            .push(format!("CookieAuthentication {}", i32::from(enabled)));
+        self
+    }
+
     /// `~/.tor`
     #[must_use]
     pub fn data_dir(mut self, dir: &str) -> Self {
@@ -55,6 +121,14 @@ impl TorrcBuilder {
         self
     }
 
+    /// haproxy
+    #[must_use]
+    pub fn hidden_service_export_circuit_id(mut self, protocol: &str) -> Self {
+        self.0
+            .push(format!("HiddenServiceExportCircuitID {protocol}"));
+        self
+    }
+
     #[must_use]
     pub fn hidden_service_onion_balance_instance(mut self, enabled: bool) -> Self {
         self.0.push(format!(
@@ -79,6 +153,27 @@ impl TorrcBuilder {
         self
     }
 
+    /// 0.0.0.0:9035
+    #[must_use]
+    pub fn metrics_port(mut self, addr: &str) -> Self {
+        self.0.push(format!("MetricsPort {addr}"));
+        self
+    }
+
+    /// accept 10.0.0.0/8
+    #[must_use]
+    pub fn metrics_port_policy(mut self, policy: &str) -> Self {
+        self.0.push(format!("MetricsPortPolicy {policy}"));
+        self
+    }
+
+    /// 10
+    #[must_use]
+    pub fn shutdown_wait_length(mut self, seconds: i64) -> Self {
+        self.0.push(format!("ShutdownWaitLength {seconds}"));
+        self
+    }
+
     /// 9050
     /// 0.0.0.0:9050
     #[must_use]
@@ -93,3 +188,25 @@ impl TorrcBuilder {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restart() {
+        let torrc = Torrc::builder()
+            .template("Sandbox 1\nLog notice stdout")
+            .data_dir("${TOR_TMP_DIR}/home/.tor")
+            .control_port("127.0.0.1:6666")
+            .hidden_service_dir("${TOR_TMP_DIR}/var/lib/tor/hidden_service")
+            .hidden_service_port(80, "example:80")
+            .build();
+
+        assert_eq!(
+            r"Sandbox 1
+DataDirectory ${TOR_TMP_DIR}/home/.tor",
+            torrc.restart().to_string()
+        );
+    }
+}
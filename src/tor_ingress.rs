@@ -7,6 +7,7 @@ use std::{
 use futures::StreamExt;
 use k8s_openapi::{
     api::{
+        apps::v1::{Deployment, DeploymentStrategy, StatefulSet},
         autoscaling::v2::{
             CrossVersionObjectReference, HorizontalPodAutoscaler, HorizontalPodAutoscalerBehavior,
             HorizontalPodAutoscalerSpec, MetricSpec,
@@ -15,9 +16,13 @@ use k8s_openapi::{
             Affinity, Container, LocalObjectReference, PodSecurityContext, ResourceRequirements,
             Toleration, TopologySpreadConstraint, Volume,
         },
+        policy::v1::{PodDisruptionBudget, PodDisruptionBudgetSpec},
     },
     apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition,
-    apimachinery::pkg::apis::meta::v1::{Condition, Time},
+    apimachinery::pkg::{
+        apis::meta::v1::{Condition, LabelSelector, Time},
+        util::intstr::IntOrString,
+    },
     jiff::Timestamp,
 };
 use kube::{
@@ -30,22 +35,26 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     Error, Result,
+    collections::vec_get_or_insert,
     kubernetes::{
         self, Annotations, Api, ConditionsExt, ErrorBackoff, Labels, Object,
-        Resource as KubernetesResource, ResourceName, Torrc as KubernetesTorrc, error_policy,
-        pod_security_context,
+        PodDisruptionBudget as KubernetesPodDisruptionBudget, Resource as KubernetesResource,
+        ResourceName, Subset, TOR_AGABANI_CO_UK_TRACK_KEY, TorMetrics, Torrc as KubernetesTorrc,
+        error_policy, pod_security_context,
     },
     metrics::Metrics,
     onion_balance::{
         OnionBalance, OnionBalanceSpec, OnionBalanceSpecConfigMap, OnionBalanceSpecDeployment,
-        OnionBalanceSpecOnionKey, OnionBalanceSpecOnionService,
-        OnionBalanceSpecOnionServiceOnionKey,
+        OnionBalanceSpecHighAvailability, OnionBalanceSpecOnionBalance, OnionBalanceSpecOnionKey,
+        OnionBalanceSpecOnionService, OnionBalanceSpecOnionServiceOnionKey,
+        OnionBalanceStatusBackend,
     },
     onion_key::{OnionKey, OnionKeySpec, OnionKeySpecSecret},
     onion_service::{
         OnionService, OnionServiceSpec, OnionServiceSpecConfigMap, OnionServiceSpecDeployment,
-        OnionServiceSpecHiddenServicePort, OnionServiceSpecOnionBalance,
-        OnionServiceSpecOnionBalanceOnionKey, OnionServiceSpecOnionKey,
+        OnionServiceSpecHiddenServicePort, OnionServiceSpecHiddenServicePortFallback,
+        OnionServiceSpecOnionBalance, OnionServiceSpecOnionBalanceOnionKey,
+        OnionServiceSpecOnionKey, OnionServiceSpecPersistence,
     },
 };
 
@@ -130,17 +139,37 @@ pub struct TorIngressSpecOnionBalance {
     /// Deployment settings.
     pub deployment: Option<TorIngressSpecOnionBalanceDeployment>,
 
+    /// High availability settings of the `OnionBalance`.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub high_availability: Option<OnionBalanceSpecHighAvailability>,
+
     /// Map of string keys and values that can be used to organize and categorize (scope and select) objects. May match selectors of replication controllers and services. More info: <http://kubernetes.io/docs/user-guide/labels>
     pub labels: Option<BTreeMap<String, String>>,
 
+    /// Tor metrics settings.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub metrics: Option<TorMetrics>,
+
     /// Name of the `OnionBalance`.
     ///
     /// Default: name of the `TorIngress`
     pub name: Option<String>,
 
+    /// onionbalance settings of the `OnionBalance`.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub onion_balance: Option<OnionBalanceSpecOnionBalance>,
+
     /// `OnionKey` settings.
     pub onion_key: TorIngressSpecOnionBalanceOnionKey,
 
+    /// Pod Disruption Budget settings of the `OnionBalance`.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub pod_disruption_budget: Option<KubernetesPodDisruptionBudget>,
+
     /// Tor torrc settings.
     pub torrc: Option<KubernetesTorrc>,
 }
@@ -194,6 +223,11 @@ pub struct TorIngressSpecOnionBalanceDeployment {
     /// `SecurityContext` holds pod-level security attributes and common container settings. Optional: Defaults to empty.  See type description for default values of each field.
     pub security_context: Option<PodSecurityContext>,
 
+    /// The deployment strategy to use to replace existing pods with new ones.
+    ///
+    /// Default: Recreate
+    pub strategy: Option<DeploymentStrategy>,
+
     /// If specified, the pod's tolerations.
     pub tolerations: Option<Vec<Toleration>>,
 
@@ -252,6 +286,15 @@ pub struct TorIngressSpecOnionService {
     /// Annotations is an unstructured key value map stored with a resource that may be set by external tools to store and retrieve arbitrary metadata. They are not queryable and should be preserved when modifying objects. More info: <http://kubernetes.io/docs/user-guide/annotations>
     pub annotations: Option<BTreeMap<String, String>>,
 
+    /// Canary settings.
+    ///
+    /// When set, the first instances run a different Tor image or torrc
+    /// behind the same `OnionBalance`, and every `OnionService` is labelled
+    /// `tor.agabani.co.uk/track` with `canary` or `stable`.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub canary: Option<TorIngressSpecOnionServiceCanary>,
+
     /// Config Map settings.
     pub config_map: Option<TorIngressSpecOnionServiceConfigMap>,
 
@@ -261,6 +304,11 @@ pub struct TorIngressSpecOnionService {
     /// Map of string keys and values that can be used to organize and categorize (scope and select) objects. May match selectors of replication controllers and services. More info: <http://kubernetes.io/docs/user-guide/labels>
     pub labels: Option<BTreeMap<String, String>>,
 
+    /// Tor metrics settings, applied to every `OnionService`.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub metrics: Option<TorMetrics>,
+
     /// Name prefix of the `OnionService`.
     ///
     /// Default: name of the `TorIngress`
@@ -269,6 +317,24 @@ pub struct TorIngressSpecOnionService {
     /// `OnionKey` settings.
     pub onion_key: Option<TorIngressSpecOnionServiceOnionKey>,
 
+    /// Persistence settings.
+    ///
+    /// When set, each `OnionService` runs as a Stateful Set that keeps the Tor
+    /// data directory on a Persistent Volume Claim.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub persistence: Option<OnionServiceSpecPersistence>,
+
+    /// Pod Disruption Budget settings.
+    ///
+    /// When set, a single Pod Disruption Budget spans the Pods of every
+    /// `OnionService`, limiting how many instances a node drain can evict at
+    /// once. Its name defaults to the name of the `TorIngress` suffixed with
+    /// `-onion-service`.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub pod_disruption_budget: Option<KubernetesPodDisruptionBudget>,
+
     /// Onion Service Hidden Service ports.
     pub ports: Vec<TorIngressSpecOnionServicePort>,
 
@@ -276,6 +342,15 @@ pub struct TorIngressSpecOnionService {
     #[serde(default = "default_onion_service_replicas")]
     pub replicas: i32,
 
+    /// Rollout settings.
+    ///
+    /// When set, a change of the `OnionService` settings is rolled out to a
+    /// few instances at a time, waiting for them to be ready before moving on
+    /// to the next.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub rollout: Option<TorIngressSpecOnionServiceRollout>,
+
     /// Tor torrc settings.
     pub torrc: Option<KubernetesTorrc>,
 }
@@ -284,6 +359,28 @@ fn default_onion_service_replicas() -> i32 {
     3
 }
 
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TorIngressSpecOnionServiceCanary {
+    /// Tor image of the canary instances.
+    ///
+    /// The image must provide the `tor-operator` executable, like the Tor
+    /// image of the operator.
+    ///
+    /// Default: Tor image of the operator
+    pub image: Option<String>,
+
+    /// Number of instances, out of the replicas, running the canary.
+    #[schemars(range(min = 0))]
+    pub instances: i32,
+
+    /// Tor torrc settings of the canary instances.
+    ///
+    /// Default: torrc of the stable instances
+    pub torrc: Option<KubernetesTorrc>,
+}
+
 #[allow(clippy::module_name_repetitions)]
 #[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
@@ -333,6 +430,11 @@ pub struct TorIngressSpecOnionServiceDeployment {
     /// `SecurityContext` holds pod-level security attributes and common container settings. Optional: Defaults to empty.  See type description for default values of each field.
     pub security_context: Option<PodSecurityContext>,
 
+    /// The deployment strategy to use to replace existing pods with new ones.
+    ///
+    /// Default: Recreate
+    pub strategy: Option<DeploymentStrategy>,
+
     /// If specified, the pod's tolerations.
     pub tolerations: Option<Vec<Toleration>>,
 
@@ -374,10 +476,30 @@ pub struct TorIngressSpecOnionServiceOnionKey {
     /// Default: name of the `TorIngress`
     pub name_prefix: Option<String>,
 
+    /// What happens to the `OnionKeys` of the instances removed by a scale
+    /// down.
+    ///
+    /// Default: Delete
+    pub retention_policy: Option<TorIngressSpecOnionServiceOnionKeyRetentionPolicy>,
+
     /// Secret settings.
     pub secret: Option<TorIngressSpecOnionServiceOnionKeySecret>,
 }
 
+/// What happens to the `OnionKeys` of the instances removed by a scale down.
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq)]
+pub enum TorIngressSpecOnionServiceOnionKeyRetentionPolicy {
+    /// Delete the `OnionKeys`, so that a scale up generates new hostnames.
+    #[default]
+    Delete,
+
+    /// Keep the `OnionKeys` of removed instances, so that a scale up reuses
+    /// their hostnames in index order. With `horizontalPodAutoscaler`, those
+    /// of instances beyond `maxReplicas` are deleted.
+    Retain,
+}
+
 #[allow(clippy::module_name_repetitions)]
 #[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
@@ -398,6 +520,11 @@ pub struct TorIngressSpecOnionServiceOnionKeySecret {
 #[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct TorIngressSpecOnionServicePort {
+    /// Maintenance fallback settings.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub fallback: Option<OnionServiceSpecHiddenServicePortFallback>,
+
     /// The target any incoming traffic will be redirect to.
     pub target: String,
 
@@ -405,10 +532,38 @@ pub struct TorIngressSpecOnionServicePort {
     pub virtport: i32,
 }
 
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TorIngressSpecOnionServiceRollout {
+    /// Maximum number of instances that can be unavailable during the
+    /// rollout, counting the instances being updated.
+    ///
+    /// Default: 1
+    #[schemars(range(min = 1))]
+    pub max_unavailable_instances: Option<i32>,
+
+    /// Number of seconds to wait after updating instances before updating
+    /// the next ones.
+    ///
+    /// Default: 30
+    #[schemars(range(min = 0))]
+    pub pause_seconds: Option<i32>,
+}
+
 #[allow(clippy::module_name_repetitions)]
 #[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct TorIngressStatus {
+    /// `OnionServices` considered for the `OnionBalance` load balancing.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub backends: Vec<OnionBalanceStatusBackend>,
+
+    /// Canary observations.
+    ///
+    /// Only populated when canary is set.
+    pub canary: Option<TorIngressStatusCanary>,
+
     /// Represents the latest available observations of a deployment's current state.
     ///
     /// ### Initialized
@@ -418,6 +573,10 @@ pub struct TorIngressStatus {
     /// ### `OnionKey`
     ///
     /// `NotFound`, `HostnameNotFound`, `Ready`
+    ///
+    /// ### Rollout
+    ///
+    /// `Progressing`, `Complete`
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub conditions: Vec<Condition>,
 
@@ -433,11 +592,44 @@ pub struct TorIngressStatus {
     /// Number of replicas.
     pub replicas: i32,
 
+    /// Rollout observations.
+    ///
+    /// Only populated when rollout is set.
+    pub rollout: Option<TorIngressStatusRollout>,
+
     /// Represents the latest available observations of a deployment's current state.
     #[serde(default)]
     pub summary: BTreeMap<String, String>,
 }
 
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TorIngressStatusCanary {
+    /// Number of canary instances.
+    pub instances: i32,
+
+    /// Number of canary instances the `OnionBalance` considers ready.
+    pub ready_instances: i32,
+
+    /// Number of stable instances.
+    pub stable_instances: i32,
+
+    /// Number of stable instances the `OnionBalance` considers ready.
+    pub stable_ready_instances: i32,
+}
+
+#[allow(clippy::module_name_repetitions)]
+#[derive(JsonSchema, Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TorIngressStatusRollout {
+    /// Time instances were last updated.
+    pub last_update_time: Option<Time>,
+
+    /// Number of instances running the current `OnionService` settings.
+    pub updated_instances: i32,
+}
+
 impl TorIngress {
     #[must_use]
     fn default_name(&self) -> ResourceName {
@@ -606,6 +798,16 @@ impl TorIngress {
         )
     }
 
+    #[must_use]
+    pub fn onion_balance_deployment_strategy(&self) -> Option<DeploymentStrategy> {
+        self.spec
+            .onion_balance
+            .deployment
+            .as_ref()
+            .and_then(|f| f.strategy.as_ref())
+            .cloned()
+    }
+
     #[must_use]
     pub fn onion_balance_deployment_tolerations(&self) -> Option<Vec<Toleration>> {
         self.spec
@@ -638,6 +840,11 @@ impl TorIngress {
             .cloned()
     }
 
+    #[must_use]
+    pub fn onion_balance_high_availability(&self) -> Option<OnionBalanceSpecHighAvailability> {
+        self.spec.onion_balance.high_availability.clone()
+    }
+
     #[must_use]
     pub fn onion_balance_labels(&self) -> Option<Labels> {
         self.spec.onion_balance.labels.clone().map(Into::into)
@@ -657,6 +864,21 @@ impl TorIngress {
         ResourceName::from(&self.spec.onion_balance.onion_key.name)
     }
 
+    #[must_use]
+    pub fn onion_balance_metrics(&self) -> Option<TorMetrics> {
+        self.spec.onion_balance.metrics.clone()
+    }
+
+    #[must_use]
+    pub fn onion_balance_onion_balance(&self) -> Option<OnionBalanceSpecOnionBalance> {
+        self.spec.onion_balance.onion_balance.clone()
+    }
+
+    #[must_use]
+    pub fn onion_balance_pod_disruption_budget(&self) -> Option<KubernetesPodDisruptionBudget> {
+        self.spec.onion_balance.pod_disruption_budget.clone()
+    }
+
     #[must_use]
     pub fn onion_balance_torrc(&self) -> Option<KubernetesTorrc> {
         self.spec.onion_balance.torrc.clone()
@@ -667,6 +889,42 @@ impl TorIngress {
         self.spec.onion_service.annotations.clone().map(Into::into)
     }
 
+    #[must_use]
+    pub fn onion_service_canary_enabled(&self) -> bool {
+        self.spec.onion_service.canary.is_some()
+    }
+
+    #[must_use]
+    pub fn onion_service_canary_image(&self) -> Option<String> {
+        self.spec
+            .onion_service
+            .canary
+            .as_ref()
+            .and_then(|f| f.image.clone())
+    }
+
+    /// Number of canary instances, which are the first instances.
+    #[must_use]
+    pub fn onion_service_canary_instances(&self) -> i32 {
+        self.spec
+            .onion_service
+            .canary
+            .as_ref()
+            .map_or(0, |f| f.instances)
+            .min(self.onion_service_replicas())
+            .max(0)
+    }
+
+    #[must_use]
+    pub fn onion_service_canary_torrc(&self) -> Option<KubernetesTorrc> {
+        self.spec
+            .onion_service
+            .canary
+            .as_ref()
+            .and_then(|f| f.torrc.clone())
+            .or_else(|| self.onion_service_torrc())
+    }
+
     #[must_use]
     pub fn onion_service_config_map_annotations(&self) -> Option<Annotations> {
         self.spec
@@ -804,6 +1062,16 @@ impl TorIngress {
         )
     }
 
+    #[must_use]
+    pub fn onion_service_deployment_strategy(&self) -> Option<DeploymentStrategy> {
+        self.spec
+            .onion_service
+            .deployment
+            .as_ref()
+            .and_then(|f| f.strategy.as_ref())
+            .cloned()
+    }
+
     #[must_use]
     pub fn onion_service_deployment_tolerations(&self) -> Option<Vec<Toleration>> {
         self.spec
@@ -870,6 +1138,64 @@ impl TorIngress {
             .map_or_else(|| self.default_name(), Into::into)
     }
 
+    /// Instance of an `OnionService` `OnionKey`, parsed from its name.
+    #[must_use]
+    pub fn onion_service_onion_key_instance(&self, onion_key: &OnionKey) -> Option<i32> {
+        onion_key
+            .metadata
+            .name
+            .as_deref()?
+            .strip_prefix(self.onion_service_onion_key_name_prefix().as_ref())?
+            .strip_prefix('-')?
+            .parse()
+            .ok()
+            .filter(|instance| *instance >= 0)
+    }
+
+    /// Number of instances whose `OnionKeys` are kept, which exceeds the
+    /// replicas when the `OnionKeys` of removed instances are retained.
+    ///
+    /// Without `horizontalPodAutoscaler`, the highest instance of the existing
+    /// `OnionKeys` is kept, so that a manual scale down retains them too.
+    #[must_use]
+    pub fn onion_service_onion_key_pool_size(&self, onion_keys: &[OnionKey]) -> i32 {
+        match self.onion_service_onion_key_retention_policy() {
+            TorIngressSpecOnionServiceOnionKeyRetentionPolicy::Delete => {
+                self.onion_service_replicas()
+            }
+            TorIngressSpecOnionServiceOnionKeyRetentionPolicy::Retain => self
+                .spec
+                .horizontal_pod_autoscaler
+                .as_ref()
+                .map_or_else(
+                    || {
+                        onion_keys
+                            .iter()
+                            .filter_map(|onion_key| {
+                                self.onion_service_onion_key_instance(onion_key)
+                            })
+                            .map(|instance| instance.saturating_add(1))
+                            .max()
+                            .unwrap_or(0)
+                    },
+                    |f| f.max_replicas,
+                )
+                .max(self.onion_service_replicas()),
+        }
+    }
+
+    #[must_use]
+    pub fn onion_service_onion_key_retention_policy(
+        &self,
+    ) -> TorIngressSpecOnionServiceOnionKeyRetentionPolicy {
+        self.spec
+            .onion_service
+            .onion_key
+            .as_ref()
+            .and_then(|f| f.retention_policy)
+            .unwrap_or_default()
+    }
+
     #[must_use]
     pub fn onion_service_onion_key_secret_name_prefix(&self) -> ResourceName {
         self.spec
@@ -927,6 +1253,11 @@ impl TorIngress {
             .map(Into::into)
     }
 
+    #[must_use]
+    pub fn onion_service_metrics(&self) -> Option<TorMetrics> {
+        self.spec.onion_service.metrics.clone()
+    }
+
     #[must_use]
     pub fn onion_service_onion_key_secret_name(&self, instance: i32) -> ResourceName {
         format!(
@@ -936,11 +1267,95 @@ impl TorIngress {
         .into()
     }
 
+    #[must_use]
+    pub fn onion_service_persistence(&self) -> Option<OnionServiceSpecPersistence> {
+        self.spec.onion_service.persistence.clone()
+    }
+
+    #[must_use]
+    pub fn onion_service_pod_disruption_budget_annotations(&self) -> Option<Annotations> {
+        self.spec
+            .onion_service
+            .pod_disruption_budget
+            .as_ref()
+            .and_then(KubernetesPodDisruptionBudget::annotations)
+    }
+
+    #[must_use]
+    pub fn onion_service_pod_disruption_budget_enabled(&self) -> bool {
+        self.spec.onion_service.pod_disruption_budget.is_some()
+    }
+
+    #[must_use]
+    pub fn onion_service_pod_disruption_budget_labels(&self) -> Option<Labels> {
+        self.spec
+            .onion_service
+            .pod_disruption_budget
+            .as_ref()
+            .and_then(KubernetesPodDisruptionBudget::labels)
+    }
+
+    #[must_use]
+    pub fn onion_service_pod_disruption_budget_max_unavailable(&self) -> Option<IntOrString> {
+        self.spec
+            .onion_service
+            .pod_disruption_budget
+            .as_ref()
+            .and_then(KubernetesPodDisruptionBudget::max_unavailable)
+    }
+
+    #[must_use]
+    pub fn onion_service_pod_disruption_budget_min_available(&self) -> Option<IntOrString> {
+        self.spec
+            .onion_service
+            .pod_disruption_budget
+            .as_ref()
+            .and_then(|f| f.min_available.clone())
+    }
+
+    #[must_use]
+    pub fn onion_service_pod_disruption_budget_name(&self) -> ResourceName {
+        self.spec
+            .onion_service
+            .pod_disruption_budget
+            .as_ref()
+            .and_then(|f| f.name.as_ref())
+            .map_or_else(
+                || format!("{}-onion-service", self.default_name()).into(),
+                Into::into,
+            )
+    }
+
     #[must_use]
     pub fn onion_service_replicas(&self) -> i32 {
         self.spec.onion_service.replicas
     }
 
+    #[must_use]
+    pub fn onion_service_rollout_enabled(&self) -> bool {
+        self.spec.onion_service.rollout.is_some()
+    }
+
+    #[must_use]
+    pub fn onion_service_rollout_max_unavailable_instances(&self) -> i32 {
+        self.spec
+            .onion_service
+            .rollout
+            .as_ref()
+            .and_then(|f| f.max_unavailable_instances)
+            .unwrap_or(1)
+    }
+
+    #[must_use]
+    pub fn onion_service_rollout_pause_seconds(&self) -> i32 {
+        self.spec
+            .onion_service
+            .rollout
+            .as_ref()
+            .and_then(|f| f.pause_seconds)
+            .unwrap_or(30)
+    }
+
     #[must_use]
     pub fn onion_service_torrc(&self) -> Option<KubernetesTorrc> {
         self.spec.onion_service.torrc.clone()
@@ -950,6 +1365,11 @@ impl TorIngress {
     pub fn status_conditions(&self) -> Option<&Vec<Condition>> {
         self.status.as_ref().map(|f| f.conditions.as_ref())
     }
+
+    #[must_use]
+    pub fn status_rollout(&self) -> Option<&TorIngressStatusRollout> {
+        self.status.as_ref().and_then(|f| f.rollout.as_ref())
+    }
 }
 
 impl KubernetesResource for TorIngress {
@@ -993,6 +1413,7 @@ pub async fn run_controller(client: Client, config: Config, metrics: Metrics) {
     metrics.kubernetes_api_usage_count::<OnionBalance>("watch");
     metrics.kubernetes_api_usage_count::<OnionKey>("watch");
     metrics.kubernetes_api_usage_count::<OnionService>("watch");
+    metrics.kubernetes_api_usage_count::<PodDisruptionBudget>("watch");
     Controller::new(
         kube::Api::<TorIngress>::all(client.clone()),
         WatcherConfig::default(),
@@ -1013,6 +1434,10 @@ pub async fn run_controller(client: Client, config: Config, metrics: Metrics) {
         kube::Api::<OnionService>::all(client.clone()),
         WatcherConfig::default(),
     )
+    .owns(
+        kube::Api::<PodDisruptionBudget>::all(client.clone()),
+        WatcherConfig::default(),
+    )
     .shutdown_on_signal()
     .run(
         reconciler,
@@ -1111,6 +1536,14 @@ impl State {
     }
 }
 
+/// Observation of an instance during a rollout.
+enum RolloutInstance {
+    /// The `OnionService` does not exist yet.
+    New,
+    /// The `OnionService` exists, with settings that may be outdated.
+    Existing { outdated: bool, ready: bool },
+}
+
 /*
  * ============================================================================
  * Reconciler
@@ -1128,6 +1561,9 @@ async fn reconciler(object: Arc<TorIngress>, ctx: Arc<Context>) -> Result<Action
     let annotations = Annotations::new();
     let labels = object.try_labels()?;
 
+    let mut backends = Vec::new();
+    let mut rollout = None;
+
     // OnionKey
     let state = reconcile_onion_key(
         &Api::new(
@@ -1144,7 +1580,15 @@ async fn reconciler(object: Arc<TorIngress>, ctx: Arc<Context>) -> Result<Action
         let (onion_balance_onion_key, onion_service_onion_keys) = &**tuple;
 
         // OnionServices
-        reconcile_onion_services(
+        rollout = reconcile_onion_services(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
             &Api::new(
                 kube::Api::namespaced(ctx.client.clone(), &namespace),
                 ctx.metrics.clone(),
@@ -1157,7 +1601,7 @@ async fn reconciler(object: Arc<TorIngress>, ctx: Arc<Context>) -> Result<Action
         .await?;
 
         // OnionBalance
-        reconcile_onion_balance(
+        backends = reconcile_onion_balance(
             &Api::new(
                 kube::Api::namespaced(ctx.client.clone(), &namespace),
                 ctx.metrics.clone(),
@@ -1180,6 +1624,18 @@ async fn reconciler(object: Arc<TorIngress>, ctx: Arc<Context>) -> Result<Action
             &labels,
         )
         .await?;
+
+        // PodDisruptionBudget
+        reconcile_pod_disruption_budget(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &object,
+            &annotations,
+            &labels,
+        )
+        .await?;
     }
 
     // TorIngress
@@ -1190,6 +1646,8 @@ async fn reconciler(object: Arc<TorIngress>, ctx: Arc<Context>) -> Result<Action
         ),
         &object,
         &state,
+        backends,
+        rollout.as_ref(),
     )
     .await?;
 
@@ -1197,7 +1655,12 @@ async fn reconciler(object: Arc<TorIngress>, ctx: Arc<Context>) -> Result<Action
 
     tracing::info!("reconciled");
 
+    let rolling_out = rollout
+        .as_ref()
+        .is_some_and(|f| f.updated_instances < object.onion_service_replicas());
+
     match state {
+        State::Initialized(_) if rolling_out => Ok(Action::requeue(Duration::from_secs(5))),
         State::Initialized(_) => Ok(Action::requeue(Duration::from_hours(1))),
         _ => Ok(Action::requeue(Duration::from_secs(5))),
     }
@@ -1241,8 +1704,12 @@ async fn reconcile_onion_key(
         return Ok(State::OnionServiceOnionKeyHostnameNotFound);
     }
 
-    // OnionService: deletion
-    api.delete_many(object, deprecated).await?;
+    // OnionService: deletion, sparing the retained
+    api.delete_many(
+        object,
+        generate_deprecated_onion_service_onion_keys(object, deprecated),
+    )
+    .await?;
 
     Ok(State::Initialized(Box::new((
         onion_balance_onion_key,
@@ -1252,28 +1719,150 @@ async fn reconcile_onion_key(
 
 async fn reconcile_onion_services(
     api: &Api<OnionService>,
+    deployment_api: &Api<Deployment>,
+    stateful_set_api: &Api<StatefulSet>,
     object: &TorIngress,
     annotations: &Annotations,
     labels: &Labels,
     onion_balance_onion_key: &OnionKey,
-) -> Result<()> {
-    api.sync(
-        object,
-        (0..object.onion_service_replicas())
-            .map(|instance| {
-                generate_onion_service(
-                    object,
-                    annotations,
-                    labels,
-                    onion_balance_onion_key,
-                    instance,
-                )
-                .map(|svc| (instance, svc))
-            })
-            .collect::<Result<HashMap<_, _>>>()?,
-    )
-    .await
-    .map(|_| ())
+) -> Result<Option<TorIngressStatusRollout>> {
+    let mut onion_services = (0..object.onion_service_replicas())
+        .map(|instance| {
+            generate_onion_service(
+                object,
+                annotations,
+                labels,
+                onion_balance_onion_key,
+                instance,
+            )
+            .map(|svc| (instance, svc))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+
+    if !object.onion_service_rollout_enabled() {
+        return api.sync(object, onion_services).await.map(|_| None);
+    }
+
+    let specs = onion_services
+        .iter()
+        .map(|(instance, onion_service)| (*instance, onion_service.spec.clone()))
+        .collect::<HashMap<_, _>>();
+
+    let mut existing = api
+        .list(object)
+        .await?
+        .into_iter()
+        .map(|onion_service| onion_service.try_name().map(|name| (name, onion_service)))
+        .collect::<Result<HashMap<_, _>>>()?;
+
+    // instances: outdated and unavailable
+    let mut instances = specs.keys().copied().collect::<Vec<_>>();
+    instances.sort_unstable();
+
+    let mut rollout_instances = Vec::with_capacity(instances.len());
+    let mut outdated = HashMap::new();
+
+    for instance in instances {
+        let Some(onion_service) = existing.remove(&onion_services[&instance].try_name()?) else {
+            rollout_instances.push((instance, RolloutInstance::New));
+            continue;
+        };
+
+        let ready = instance_ready(deployment_api, stateful_set_api, &onion_service).await?;
+        let is_outdated = !specs[&instance].is_subset(&onion_service.spec);
+
+        rollout_instances.push((
+            instance,
+            RolloutInstance::Existing {
+                outdated: is_outdated,
+                ready,
+            },
+        ));
+
+        if is_outdated {
+            outdated.insert(instance, onion_service);
+        }
+    }
+
+    // instances: update within the budget, holding back the others
+    let now = Timestamp::now();
+
+    let mut updated = false;
+
+    for instance in generate_rollout_instances(object, &rollout_instances, now) {
+        updated |= outdated.remove(&instance).is_some();
+    }
+
+    onion_services.extend(outdated);
+
+    let onion_services = api.sync(object, onion_services).await?;
+
+    Ok(Some(TorIngressStatusRollout {
+        last_update_time: if updated {
+            Some(Time(now))
+        } else {
+            object
+                .status_rollout()
+                .and_then(|f| f.last_update_time.clone())
+        },
+        updated_instances: onion_services
+            .iter()
+            .filter(|(instance, onion_service)| specs[*instance].is_subset(&onion_service.spec))
+            .count()
+            .try_into()
+            .unwrap_or(i32::MAX),
+    }))
+}
+
+/// Whether the instance has rolled out its latest settings and is available.
+///
+/// The `OnionService` must have reconciled its latest settings first, or its
+/// workload would still look ready with the previous ones.
+async fn instance_ready(
+    deployment_api: &Api<Deployment>,
+    stateful_set_api: &Api<StatefulSet>,
+    onion_service: &OnionService,
+) -> Result<bool> {
+    if !onion_service.status_initialized() {
+        return Ok(false);
+    }
+
+    let name = onion_service.deployment_name();
+
+    if onion_service.persistence_enabled() {
+        let Some(stateful_set) = stateful_set_api.get_opt(&name).await? else {
+            return Ok(false);
+        };
+        let replicas = stateful_set
+            .spec
+            .as_ref()
+            .and_then(|f| f.replicas)
+            .unwrap_or(1);
+        return Ok(stateful_set.status.as_ref().is_some_and(|status| {
+            status.observed_generation >= stateful_set.metadata.generation
+                && status.updated_replicas.unwrap_or_default() >= replicas
+                && status.ready_replicas.unwrap_or_default() >= replicas
+        }));
+    }
+
+    let Some(deployment) = deployment_api.get_opt(&name).await? else {
+        return Ok(false);
+    };
+    let replicas = deployment
+        .spec
+        .as_ref()
+        .and_then(|f| f.replicas)
+        .unwrap_or(1);
+    Ok(deployment.status.as_ref().is_some_and(|status| {
+        status.observed_generation >= deployment.metadata.generation
+            && status.updated_replicas.unwrap_or_default() >= replicas
+            && status.available_replicas.unwrap_or_default() >= replicas
+            && status
+                .conditions
+                .iter()
+                .flatten()
+                .any(|f| f.type_ == "Available" && f.status == "True")
+    }))
 }
 
 async fn reconcile_onion_balance(
@@ -1282,17 +1871,23 @@ async fn reconcile_onion_balance(
     annotations: &Annotations,
     labels: &Labels,
     onion_service_onion_keys: &HashMap<i32, OnionKey>,
-) -> Result<()> {
-    api.sync(
-        object,
-        [(
-            (),
-            generate_onion_balance(object, annotations, labels, onion_service_onion_keys)?,
-        )]
-        .into(),
-    )
-    .await
-    .map(|_| ())
+) -> Result<Vec<OnionBalanceStatusBackend>> {
+    let mut onion_balances = api
+        .sync(
+            object,
+            [(
+                (),
+                generate_onion_balance(object, annotations, labels, onion_service_onion_keys)?,
+            )]
+            .into(),
+        )
+        .await?;
+
+    Ok(onion_balances
+        .remove(&())
+        .and_then(|f| f.status)
+        .map(|f| f.backends)
+        .unwrap_or_default())
 }
 
 async fn reconcile_horizontal_pod_autoscaler(
@@ -1314,15 +1909,35 @@ async fn reconcile_horizontal_pod_autoscaler(
     api.sync(object, resources).await.map(|_| ())
 }
 
+async fn reconcile_pod_disruption_budget(
+    api: &Api<PodDisruptionBudget>,
+    object: &TorIngress,
+    annotations: &Annotations,
+    labels: &Labels,
+) -> Result<()> {
+    let resources: HashMap<(), _> = generate_pod_disruption_budget(object, annotations, labels)?
+        .map(|pod_disruption_budget| [((), pod_disruption_budget)].into())
+        .unwrap_or_default();
+
+    api.sync(object, resources).await.map(|_| ())
+}
+
 async fn reconcile_tor_ingress(
     api: &Api<TorIngress>,
     object: &TorIngress,
     state: &State,
+    backends: Vec<OnionBalanceStatusBackend>,
+    rollout: Option<&TorIngressStatusRollout>,
 ) -> Result<()> {
+    let mut conditions = state.conditions(object.meta().generation);
+    if let Some(rollout) = rollout {
+        conditions.push(rollout_condition(object, rollout));
+    }
+
     let conditions = object
         .status_conditions()
         .unwrap_or(&Vec::new())
-        .merge_from(&state.conditions(object.meta().generation));
+        .merge_from(&conditions);
 
     let summary = conditions
         .iter()
@@ -1331,9 +1946,15 @@ async fn reconcile_tor_ingress(
             summary
         });
 
+    let canary = object
+        .onion_service_canary_enabled()
+        .then(|| generate_canary_status(object, &backends));
+
     api.update_status(
         object,
         TorIngressStatus {
+            backends,
+            canary,
             conditions,
             hostname: if let State::Initialized(tuple) = state {
                 let (onion_key, _) = &**tuple;
@@ -1343,12 +1964,71 @@ async fn reconcile_tor_ingress(
             },
             label_selector: object.try_label_selector::<OnionService>()?,
             replicas: object.onion_service_replicas(),
+            rollout: rollout.cloned(),
             summary,
         },
     )
     .await
 }
 
+fn generate_canary_status(
+    object: &TorIngress,
+    backends: &[OnionBalanceStatusBackend],
+) -> TorIngressStatusCanary {
+    let instances = object.onion_service_canary_instances();
+
+    let ready = |instances: std::ops::Range<i32>| {
+        let names = instances
+            .map(|instance| object.onion_service_name(instance))
+            .collect::<Vec<_>>();
+        backends
+            .iter()
+            .filter(|f| f.ready)
+            .filter(|f| {
+                names
+                    .iter()
+                    .any(|name| f.name.as_deref() == Some(name.as_ref()))
+            })
+            .count()
+            .try_into()
+            .unwrap_or(i32::MAX)
+    };
+
+    TorIngressStatusCanary {
+        instances,
+        ready_instances: ready(0..instances),
+        stable_instances: object.onion_service_replicas() - instances,
+        stable_ready_instances: ready(instances..object.onion_service_replicas()),
+    }
+}
+
+fn rollout_condition(object: &TorIngress, rollout: &TorIngressStatusRollout) -> Condition {
+    let replicas = object.onion_service_replicas();
+
+    if rollout.updated_instances < replicas {
+        Condition {
+            last_transition_time: Time(Timestamp::now()),
+            message: format!(
+                "{} of {replicas} instances are updated.",
+                rollout.updated_instances
+            ),
+            observed_generation: object.meta().generation,
+            reason: "Progressing".into(),
+            status: "False".into(),
+            type_: "Rollout".into(),
+        }
+    } else {
+        Condition {
+            last_transition_time: Time(Timestamp::now()),
+            message: "All instances are updated.".into(),
+            observed_generation: object.meta().generation,
+            reason: "Complete".into(),
+            status: "True".into(),
+            type_: "Rollout".into(),
+        }
+    }
+}
+
 fn generate_onion_balance(
     object: &TorIngress,
     annotations: &Annotations,
@@ -1413,18 +2093,23 @@ fn generate_onion_balance(
                 name: Some(object.onion_balance_deployment_name().into()),
                 node_selector: object.onion_balance_deployment_node_selector(),
                 security_context: Some(object.onion_balance_deployment_security_context()),
+                strategy: object.onion_balance_deployment_strategy(),
                 tolerations: object.onion_balance_deployment_tolerations(),
                 topology_spread_constraints: object
                     .onion_balance_deployment_topology_spread_constraints(),
                 volumes: object.onion_balance_deployment_volumes(),
             }),
+            high_availability: object.onion_balance_high_availability(),
+            metrics: object.onion_balance_metrics(),
+            onion_balance: object.onion_balance_onion_balance(),
             onion_key: OnionBalanceSpecOnionKey {
                 name: object.onion_balance_onion_key_name().into(),
             },
+            onion_service_selector: None,
             onion_services: (0..onion_service_onion_keys.len())
                 .map(|instance| -> Result<OnionBalanceSpecOnionService> {
                     Ok(OnionBalanceSpecOnionService {
-                        onion_key: OnionBalanceSpecOnionServiceOnionKey {
+                        onion_key: Some(OnionBalanceSpecOnionServiceOnionKey {
                             hostname: onion_service_onion_keys
                                 .get(
                                     &i32::try_from(instance).expect(
@@ -1434,10 +2119,13 @@ fn generate_onion_balance(
                                 .and_then(OnionKey::hostname)
                                 .ok_or(Error::MissingObjectKey(".status.hostname"))?
                                 .into(),
-                        },
+                        }),
+                        onion_service_ref: None,
                     })
                 })
                 .collect::<Result<Vec<_>>>()?,
+            pod_disruption_budget: object.onion_balance_pod_disruption_budget(),
+            services: Vec::new(),
             torrc: object.onion_balance_torrc(),
         },
         status: None,
@@ -1494,6 +2182,68 @@ fn generate_onion_service_onion_key(
     })
 }
 
+/// Instances to create or update with their latest settings during a rollout,
+/// holding back the outdated instances beyond the budget or within the pause.
+fn generate_rollout_instances(
+    object: &TorIngress,
+    instances: &[(i32, RolloutInstance)],
+    now: Timestamp,
+) -> Vec<i32> {
+    let paused = object
+        .status_rollout()
+        .and_then(|f| f.last_update_time.as_ref())
+        .is_some_and(|last_update_time| {
+            now.duration_since(last_update_time.0).as_secs()
+                < i64::from(object.onion_service_rollout_pause_seconds())
+        });
+
+    let unavailable = instances
+        .iter()
+        .filter(|(_, f)| matches!(f, RolloutInstance::Existing { ready: false, .. }))
+        .count();
+
+    let mut budget = object.onion_service_rollout_max_unavailable_instances()
+        - i32::try_from(unavailable).unwrap_or(i32::MAX);
+
+    instances
+        .iter()
+        .filter(|(_, f)| match f {
+            // new instances are created straight away
+            RolloutInstance::New => true,
+            RolloutInstance::Existing {
+                outdated: false, ..
+            } => false,
+            // an unavailable instance does not take from the budget
+            RolloutInstance::Existing { ready: false, .. } => !paused,
+            RolloutInstance::Existing { ready: true, .. } => {
+                if paused || budget <= 0 {
+                    return false;
+                }
+                budget -= 1;
+                true
+            }
+        })
+        .map(|(instance, _)| *instance)
+        .collect()
+}
+
+fn generate_deprecated_onion_service_onion_keys(
+    object: &TorIngress,
+    deprecated: Vec<OnionKey>,
+) -> Vec<OnionKey> {
+    let pool_size = object.onion_service_onion_key_pool_size(&deprecated);
+
+    deprecated
+        .into_iter()
+        .filter(|onion_key| {
+            object
+                .onion_service_onion_key_instance(onion_key)
+                .is_none_or(|instance| instance >= pool_size)
+        })
+        .collect()
+}
+
+#[allow(clippy::too_many_lines)]
 fn generate_onion_service(
     object: &TorIngress,
     annotations: &Annotations,
@@ -1501,6 +2251,30 @@ fn generate_onion_service(
     onion_balance_onion_key: &OnionKey,
     instance: i32,
 ) -> Result<OnionService> {
+    let canary = instance < object.onion_service_canary_instances();
+
+    let labels = &if object.onion_service_canary_enabled() {
+        let mut labels = BTreeMap::from(labels);
+        labels.insert(
+            TOR_AGABANI_CO_UK_TRACK_KEY.into(),
+            if canary { "canary" } else { "stable" }.into(),
+        );
+        labels.into()
+    } else {
+        labels.clone()
+    };
+
+    let mut containers = object.onion_service_deployment_containers();
+    if let Some(image) = canary
+        .then(|| object.onion_service_canary_image())
+        .flatten()
+    {
+        let container =
+            vec_get_or_insert(containers.get_or_insert_with(Vec::new), |f| f.name == "tor");
+        container.name = "tor".into();
+        container.image = Some(image);
+    }
+
     Ok(OnionService {
         metadata: ObjectMeta {
             name: Some(object.onion_service_name(instance).into()),
@@ -1547,7 +2321,7 @@ fn generate_onion_service(
                         .append_reverse(object.onion_service_deployment_annotations())
                         .into(),
                 ),
-                containers: object.onion_service_deployment_containers(),
+                containers,
                 image_pull_secrets: object.onion_service_deployment_image_pull_secrets(),
                 init_containers: object.onion_service_deployment_init_containers(),
                 labels: Some(
@@ -1559,11 +2333,18 @@ fn generate_onion_service(
                 name: Some(object.onion_service_deployment_name(instance).into()),
                 node_selector: object.onion_service_deployment_node_selector(),
                 security_context: Some(object.onion_service_deployment_security_context()),
+                strategy: object.onion_service_deployment_strategy(),
                 tolerations: object.onion_service_deployment_tolerations(),
                 topology_spread_constraints: object
                     .onion_service_deployment_topology_spread_constraints(),
                 volumes: object.onion_service_deployment_volumes(),
             }),
+            ephemeral: None,
+            export_circuit_id: None,
+            gateway: None,
+            high_availability: None,
+            injection: None,
+            metrics: object.onion_service_metrics(),
             onion_balance: Some(OnionServiceSpecOnionBalance {
                 onion_key: OnionServiceSpecOnionBalanceOnionKey {
                     hostname: onion_balance_onion_key
@@ -1572,20 +2353,29 @@ fn generate_onion_service(
                         .into(),
                 },
             }),
-            onion_key: OnionServiceSpecOnionKey {
+            onion_key: Some(OnionServiceSpecOnionKey {
                 name: object.onion_service_onion_key_name(instance).into(),
-            },
+            }),
+            persistence: object.onion_service_persistence(),
+            pod_disruption_budget: None,
             ports: object
                 .spec
                 .onion_service
                 .ports
                 .iter()
                 .map(|f| OnionServiceSpecHiddenServicePort {
+                    fallback: f.fallback.clone(),
                     target: f.target.clone(),
                     virtport: f.virtport,
                 })
                 .collect(),
-            torrc: object.onion_service_torrc(),
+            tls: None,
+            torrc: if canary {
+                object.onion_service_canary_torrc()
+            } else {
+                object.onion_service_torrc()
+            },
+            ttl: None,
         },
         status: None,
     })
@@ -1635,3 +2425,380 @@ fn generate_horizontal_pod_autoscaler(
         ..Default::default()
     }))
 }
+
+fn generate_pod_disruption_budget(
+    object: &TorIngress,
+    annotations: &Annotations,
+    labels: &Labels,
+) -> Result<Option<PodDisruptionBudget>> {
+    if !object.onion_service_pod_disruption_budget_enabled() {
+        return Ok(None);
+    }
+
+    Ok(Some(PodDisruptionBudget {
+        metadata: ObjectMeta {
+            name: Some(object.onion_service_pod_disruption_budget_name().into()),
+            annotations: Some(
+                annotations
+                    .clone()
+                    .append_reverse(object.onion_service_pod_disruption_budget_annotations())
+                    .into(),
+            ),
+            labels: Some(
+                labels
+                    .clone()
+                    .append_reverse(object.onion_service_pod_disruption_budget_labels())
+                    .into(),
+            ),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        spec: Some(PodDisruptionBudgetSpec {
+            max_unavailable: object.onion_service_pod_disruption_budget_max_unavailable(),
+            min_available: object.onion_service_pod_disruption_budget_min_available(),
+            selector: Some(LabelSelector {
+                match_labels: Some(object.try_part_of_selector_labels::<OnionService>()?.into()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::jiff::SignedDuration;
+
+    use crate::{kubernetes::PodDisruptionBudgetExt, onion_key::OnionKeyStatus};
+
+    use super::*;
+
+    fn onion_key(name: &str) -> OnionKey {
+        OnionKey {
+            metadata: ObjectMeta {
+                name: Some(name.into()),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn tor_ingress(
+        replicas: i32,
+        retention_policy: TorIngressSpecOnionServiceOnionKeyRetentionPolicy,
+        max_replicas: Option<i32>,
+    ) -> TorIngress {
+        let mut object = TorIngress::new("ingress", TorIngressSpec::default());
+        object.spec.onion_service.replicas = replicas;
+        object.spec.onion_service.onion_key = Some(TorIngressSpecOnionServiceOnionKey {
+            retention_policy: Some(retention_policy),
+            ..Default::default()
+        });
+        object.spec.horizontal_pod_autoscaler =
+            max_replicas.map(|max_replicas| TorIngressHorizontalPodAutoscaler {
+                max_replicas,
+                ..Default::default()
+            });
+        object
+    }
+
+    #[test]
+    fn onion_key_pool_size() {
+        use TorIngressSpecOnionServiceOnionKeyRetentionPolicy::{Delete, Retain};
+
+        let onion_keys = vec![
+            onion_key("ingress-3"),
+            onion_key("ingress-5"),
+            onion_key("ingress-balance"),
+            onion_key("other-9"),
+        ];
+
+        for (replicas, retention_policy, max_replicas, expected) in [
+            (2, Delete, None, 2),
+            (2, Delete, Some(8), 2),
+            (2, Retain, None, 6),
+            (7, Retain, None, 7),
+            (2, Retain, Some(4), 4),
+            (5, Retain, Some(4), 5),
+        ] {
+            assert_eq!(
+                tor_ingress(replicas, retention_policy, max_replicas)
+                    .onion_service_onion_key_pool_size(&onion_keys),
+                expected
+            );
+        }
+
+        assert_eq!(
+            tor_ingress(2, Retain, None).onion_service_onion_key_pool_size(&[]),
+            2
+        );
+    }
+
+    #[test]
+    fn deprecated_onion_keys() {
+        use TorIngressSpecOnionServiceOnionKeyRetentionPolicy::{Delete, Retain};
+
+        let deprecated = || {
+            [
+                "ingress-2",
+                "ingress-3",
+                "ingress-5",
+                "ingress-x",
+                "other-3",
+            ]
+            .into_iter()
+            .map(onion_key)
+            .collect::<Vec<_>>()
+        };
+        let names = |onion_keys: Vec<OnionKey>| {
+            onion_keys
+                .into_iter()
+                .filter_map(|onion_key| onion_key.metadata.name)
+                .collect::<Vec<_>>()
+        };
+
+        // delete
+        assert_eq!(
+            names(generate_deprecated_onion_service_onion_keys(
+                &tor_ingress(2, Delete, None),
+                deprecated()
+            )),
+            [
+                "ingress-2",
+                "ingress-3",
+                "ingress-5",
+                "ingress-x",
+                "other-3"
+            ]
+        );
+
+        // retain: manual scale down
+        assert_eq!(
+            names(generate_deprecated_onion_service_onion_keys(
+                &tor_ingress(2, Retain, None),
+                deprecated()
+            )),
+            ["ingress-x", "other-3"]
+        );
+
+        // retain: beyond the horizontal pod autoscaler
+        assert_eq!(
+            names(generate_deprecated_onion_service_onion_keys(
+                &tor_ingress(2, Retain, Some(4)),
+                deprecated()
+            )),
+            ["ingress-5", "ingress-x", "other-3"]
+        );
+    }
+
+    fn rollout(max_unavailable_instances: i32, last_update_time: Option<Timestamp>) -> TorIngress {
+        let mut object = TorIngress::new("ingress", TorIngressSpec::default());
+        object.spec.onion_service.rollout = Some(TorIngressSpecOnionServiceRollout {
+            max_unavailable_instances: Some(max_unavailable_instances),
+            pause_seconds: Some(30),
+        });
+        object.status = Some(TorIngressStatus {
+            rollout: Some(TorIngressStatusRollout {
+                last_update_time: last_update_time.map(Time),
+                updated_instances: 0,
+            }),
+            ..Default::default()
+        });
+        object
+    }
+
+    #[test]
+    fn rollout_instances() {
+        use RolloutInstance::{Existing, New};
+
+        let now = Timestamp::now();
+        let outdated = |ready| Existing {
+            outdated: true,
+            ready,
+        };
+        let updated = |ready| Existing {
+            outdated: false,
+            ready,
+        };
+
+        // max unavailable
+        assert_eq!(
+            generate_rollout_instances(
+                &rollout(2, None),
+                &[
+                    (0, outdated(true)),
+                    (1, outdated(true)),
+                    (2, outdated(true)),
+                    (3, updated(true)),
+                ],
+                now,
+            ),
+            [0, 1]
+        );
+
+        // max unavailable, already taken by an unavailable instance
+        assert_eq!(
+            generate_rollout_instances(
+                &rollout(1, None),
+                &[
+                    (0, updated(false)),
+                    (1, outdated(true)),
+                    (2, outdated(true))
+                ],
+                now,
+            ),
+            Vec::<i32>::new()
+        );
+
+        // unavailable instances, not counted against the budget
+        assert_eq!(
+            generate_rollout_instances(
+                &rollout(2, None),
+                &[
+                    (0, outdated(false)),
+                    (1, outdated(true)),
+                    (2, outdated(true)),
+                    (3, outdated(true)),
+                ],
+                now,
+            ),
+            [0, 1]
+        );
+
+        // pause window
+        assert_eq!(
+            generate_rollout_instances(
+                &rollout(2, Some(now - SignedDuration::from_secs(10))),
+                &[(0, outdated(false)), (1, outdated(true))],
+                now,
+            ),
+            Vec::<i32>::new()
+        );
+        assert_eq!(
+            generate_rollout_instances(
+                &rollout(2, Some(now - SignedDuration::from_secs(30))),
+                &[(0, outdated(false)), (1, outdated(true))],
+                now,
+            ),
+            [0, 1]
+        );
+
+        // new instances
+        assert_eq!(
+            generate_rollout_instances(
+                &rollout(1, Some(now)),
+                &[(0, outdated(true)), (1, New), (2, New)],
+                now,
+            ),
+            [1, 2]
+        );
+    }
+
+    #[test]
+    fn pod_disruption_budget_name() {
+        let mut object = TorIngress::new("ingress", TorIngressSpec::default());
+        object.metadata.uid = Some("00000000-0000-0000-0000-000000000000".into());
+        object.spec.onion_balance.pod_disruption_budget =
+            Some(KubernetesPodDisruptionBudget::default());
+        object.spec.onion_service.pod_disruption_budget =
+            Some(KubernetesPodDisruptionBudget::default());
+
+        let onion_balance = generate_onion_balance(
+            &object,
+            &Annotations::new(),
+            &Labels::default(),
+            &HashMap::new(),
+        )
+        .unwrap();
+
+        assert_ne!(
+            object.onion_service_pod_disruption_budget_name(),
+            onion_balance.pod_disruption_budget_name()
+        );
+        assert_eq!(
+            "ingress-onion-service",
+            object.onion_service_pod_disruption_budget_name().as_ref()
+        );
+    }
+
+    #[test]
+    fn canary() {
+        let mut object = TorIngress::new("ingress", TorIngressSpec::default());
+        object.metadata.uid = Some("00000000-0000-0000-0000-000000000000".into());
+        object.spec.onion_service.replicas = 3;
+        object.spec.onion_service.torrc = Some(KubernetesTorrc {
+            template: Some("stable".into()),
+            ..Default::default()
+        });
+        object.spec.onion_service.canary = Some(TorIngressSpecOnionServiceCanary {
+            image: Some("tor:canary".into()),
+            instances: 1,
+            torrc: Some(KubernetesTorrc {
+                template: Some("canary".into()),
+                ..Default::default()
+            }),
+        });
+
+        let onion_balance_onion_key = OnionKey {
+            status: Some(OnionKeyStatus {
+                hostname: Some(
+                    "2gzyxa5ihm7nsggfxnu52rck2vv4rvmdlkiu3zzui5du4xyclen53wid.onion".into(),
+                ),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let onion_services = (0..3)
+            .map(|instance| {
+                generate_onion_service(
+                    &object,
+                    &Annotations::new(),
+                    &Labels::default(),
+                    &onion_balance_onion_key,
+                    instance,
+                )
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        for (onion_service, (track, image, template)) in onion_services.iter().zip([
+            ("canary", Some("tor:canary"), "canary"),
+            ("stable", None, "stable"),
+            ("stable", None, "stable"),
+        ]) {
+            assert_eq!(
+                Some(track),
+                onion_service
+                    .metadata
+                    .labels
+                    .as_ref()
+                    .and_then(|f| f.get(TOR_AGABANI_CO_UK_TRACK_KEY))
+                    .map(String::as_str)
+            );
+            assert_eq!(
+                image,
+                onion_service
+                    .spec
+                    .deployment
+                    .as_ref()
+                    .and_then(|f| f.containers.as_ref())
+                    .and_then(|f| f.iter().find(|f| f.name == "tor"))
+                    .and_then(|f| f.image.as_deref())
+            );
+            assert_eq!(
+                Some(template),
+                onion_service
+                    .spec
+                    .torrc
+                    .as_ref()
+                    .and_then(|f| f.template.as_deref())
+            );
+        }
+    }
+}
@@ -7,17 +7,17 @@ use std::{
 use futures::StreamExt;
 use k8s_openapi::{
     api::{
-        apps::v1::{Deployment, DeploymentSpec},
+        apps::v1::{Deployment, DeploymentSpec, DeploymentStrategy},
         autoscaling::v2::{
             CrossVersionObjectReference, HorizontalPodAutoscaler, HorizontalPodAutoscalerBehavior,
             HorizontalPodAutoscalerSpec, MetricSpec,
         },
         core::v1::{
-            Affinity, Capabilities, ConfigMap, ConfigMapVolumeSource, Container, ExecAction,
-            KeyToPath, LocalObjectReference, PodSecurityContext, PodSpec, PodTemplateSpec, Probe,
-            SecurityContext, Service, ServicePort, ServiceSpec, Toleration,
-            TopologySpreadConstraint, Volume,
+            Affinity, ConfigMap, ConfigMapVolumeSource, Container, KeyToPath, LocalObjectReference,
+            PodSecurityContext, PodSpec, PodTemplateSpec, Service, ServicePort, ServiceSpec,
+            Toleration, TopologySpreadConstraint, Volume,
         },
+        policy::v1::{PodDisruptionBudget, PodDisruptionBudgetSpec},
     },
     apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition,
     apimachinery::pkg::{
@@ -39,8 +39,11 @@ use crate::{
     collections::vec_get_or_insert,
     kubernetes::{
         self, Annotations, Api, ConditionsExt, ErrorBackoff, Labels, Object,
-        Resource as KubernetesResource, ResourceName, SelectorLabels, Torrc as KubernetesTorrc,
-        error_policy, pod_security_context,
+        PodDisruptionBudget as KubernetesPodDisruptionBudget, PodDisruptionBudgetExt,
+        Resource as KubernetesResource, ResourceName, SelectorLabels, ServiceMonitor,
+        ServiceMonitorEndpoint, ServiceMonitorSpec, TorMetrics, TorMetricsExt,
+        Torrc as KubernetesTorrc, TorrcReloadStrategy, container_security_context, error_policy,
+        liveness_probe, pod_security_context, readiness_probe, startup_probe,
     },
     metrics::Metrics,
     tor::Torrc,
@@ -81,6 +84,16 @@ pub struct TorProxySpec {
     /// `HorizontalPodAutoscaler` settings.
     pub horizontal_pod_autoscaler: Option<TorProxyHorizontalPodAutoscaler>,
 
+    /// Tor metrics settings.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub metrics: Option<TorMetrics>,
+
+    /// Pod Disruption Budget settings.
+    ///
+    /// Default: nil / none / null / undefined.
+    pub pod_disruption_budget: Option<KubernetesPodDisruptionBudget>,
+
     /// Service settings.
     pub service: TorProxySpecService,
 
@@ -141,6 +154,11 @@ pub struct TorProxySpecDeployment {
     /// `SecurityContext` holds pod-level security attributes and common container settings. Optional: Defaults to empty.  See type description for default values of each field.
     pub security_context: Option<PodSecurityContext>,
 
+    /// The deployment strategy to use to replace existing pods with new ones.
+    ///
+    /// Default: `RollingUpdate`
+    pub strategy: Option<DeploymentStrategy>,
+
     /// If specified, the pod's tolerations.
     pub tolerations: Option<Vec<Toleration>>,
 
@@ -378,6 +396,20 @@ impl TorProxy {
         )
     }
 
+    #[must_use]
+    pub fn deployment_strategy(&self) -> Option<DeploymentStrategy> {
+        self.spec
+            .deployment
+            .as_ref()
+            .and_then(|f| f.strategy.as_ref())
+            .cloned()
+    }
+
+    #[must_use]
+    pub fn deployment_termination_grace_period_seconds(&self) -> i64 {
+        self.torrc_shutdown_wait_length_seconds() + 5
+    }
+
     #[must_use]
     pub fn deployment_tolerations(&self) -> Option<Vec<Toleration>> {
         self.spec
@@ -474,6 +506,24 @@ impl TorProxy {
             .collect()
     }
 
+    #[must_use]
+    pub fn torrc_reload_strategy(&self) -> TorrcReloadStrategy {
+        self.spec
+            .torrc
+            .as_ref()
+            .and_then(|f| f.reload_strategy)
+            .unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn torrc_shutdown_wait_length_seconds(&self) -> i64 {
+        self.spec
+            .torrc
+            .as_ref()
+            .and_then(|f| f.shutdown_wait_length_seconds)
+            .unwrap_or(10)
+    }
+
     #[must_use]
     pub fn torrc_template(&self) -> Option<&str> {
         self.spec
@@ -497,6 +547,18 @@ impl KubernetesResource for TorProxy {
     }
 }
 
+impl TorMetricsExt for TorProxy {
+    fn metrics(&self) -> Option<&TorMetrics> {
+        self.spec.metrics.as_ref()
+    }
+}
+
+impl PodDisruptionBudgetExt for TorProxy {
+    fn pod_disruption_budget(&self) -> Option<&KubernetesPodDisruptionBudget> {
+        self.spec.pod_disruption_budget.as_ref()
+    }
+}
+
 impl Object for TorProxy {
     const APP_KUBERNETES_IO_COMPONENT_VALUE: &'static str = "tor-proxy";
 
@@ -536,6 +598,7 @@ pub async fn run_controller(client: Client, config: Config, metrics: Metrics) {
     metrics.kubernetes_api_usage_count::<HorizontalPodAutoscaler>("watch");
     metrics.kubernetes_api_usage_count::<ConfigMap>("watch");
     metrics.kubernetes_api_usage_count::<Deployment>("watch");
+    metrics.kubernetes_api_usage_count::<PodDisruptionBudget>("watch");
     metrics.kubernetes_api_usage_count::<Service>("watch");
     Controller::new(
         kube::Api::<TorProxy>::all(client.clone()),
@@ -553,6 +616,10 @@ pub async fn run_controller(client: Client, config: Config, metrics: Metrics) {
         kube::Api::<Deployment>::all(client.clone()),
         WatcherConfig::default(),
     )
+    .owns(
+        kube::Api::<PodDisruptionBudget>::all(client.clone()),
+        WatcherConfig::default(),
+    )
     .owns(
         kube::Api::<Service>::all(client.clone()),
         WatcherConfig::default(),
@@ -641,6 +708,7 @@ impl State {
  * Reconciler
  * ============================================================================
  */
+#[allow(clippy::too_many_lines)]
 #[tracing::instrument(skip_all)]
 async fn reconciler(object: Arc<TorProxy>, ctx: Arc<Context>) -> Result<Action> {
     let _timer = ctx
@@ -653,6 +721,10 @@ async fn reconciler(object: Arc<TorProxy>, ctx: Arc<Context>) -> Result<Action>
     let torrc = generate_torrc(&object);
 
     let annotations = Annotations::new().add(&torrc);
+    let template_annotations = match object.torrc_reload_strategy() {
+        TorrcReloadStrategy::Restart => annotations.clone(),
+        TorrcReloadStrategy::Signal => Annotations::new().add(&torrc.restart()),
+    };
     let labels = object.try_labels()?;
     let selector_labels = object.try_selector_labels()?;
 
@@ -687,6 +759,7 @@ async fn reconciler(object: Arc<TorProxy>, ctx: Arc<Context>) -> Result<Action>
             &ctx.config,
             &object,
             &annotations,
+            &template_annotations,
             &labels,
             &selector_labels,
         )
@@ -704,6 +777,19 @@ async fn reconciler(object: Arc<TorProxy>, ctx: Arc<Context>) -> Result<Action>
         )
         .await?;
 
+        // PodDisruptionBudget
+        reconcile_pod_disruption_budget(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &object,
+            &annotations,
+            &labels,
+            &selector_labels,
+        )
+        .await?;
+
         // Service
         reconcile_service(
             &Api::new(
@@ -716,6 +802,19 @@ async fn reconciler(object: Arc<TorProxy>, ctx: Arc<Context>) -> Result<Action>
             &selector_labels,
         )
         .await?;
+
+        // ServiceMonitor
+        reconcile_service_monitor(
+            &Api::new(
+                kube::Api::namespaced(ctx.client.clone(), &namespace),
+                ctx.metrics.clone(),
+            ),
+            &object,
+            &annotations,
+            &labels,
+            &selector_labels,
+        )
+        .await?;
     }
 
     // TorProxy
@@ -760,6 +859,7 @@ async fn reconcile_deployment(
     config: &Config,
     object: &TorProxy,
     annotations: &Annotations,
+    template_annotations: &Annotations,
     labels: &Labels,
     selector_labels: &SelectorLabels,
 ) -> Result<()> {
@@ -767,7 +867,14 @@ async fn reconcile_deployment(
         object,
         [(
             (),
-            generate_deployment(object, config, annotations, labels, selector_labels)?,
+            generate_deployment(
+                object,
+                config,
+                annotations,
+                template_annotations,
+                labels,
+                selector_labels,
+            )?,
         )]
         .into(),
     )
@@ -794,6 +901,21 @@ async fn reconcile_horizontal_pod_autoscaler(
     api.sync(object, resources).await.map(|_| ())
 }
 
+async fn reconcile_pod_disruption_budget(
+    api: &Api<PodDisruptionBudget>,
+    object: &TorProxy,
+    annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+) -> Result<()> {
+    let resources: HashMap<(), _> =
+        generate_pod_disruption_budget(object, annotations, labels, selector_labels)?
+            .map(|pod_disruption_budget| [((), pod_disruption_budget)].into())
+            .unwrap_or_default();
+
+    api.sync(object, resources).await.map(|_| ())
+}
+
 async fn reconcile_service(
     api: &Api<Service>,
     object: &TorProxy,
@@ -801,16 +923,37 @@ async fn reconcile_service(
     labels: &Labels,
     selector_labels: &SelectorLabels,
 ) -> Result<()> {
-    api.sync(
-        object,
-        [(
-            (),
-            generate_service(object, annotations, labels, selector_labels)?,
-        )]
-        .into(),
-    )
-    .await
-    .map(|_| ())
+    let mut resources = HashMap::from([(
+        "service",
+        generate_service(object, annotations, labels, selector_labels)?,
+    )]);
+    if let Some(service) = generate_metrics_service(object, annotations, labels, selector_labels)? {
+        resources.insert("metrics", service);
+    }
+
+    api.sync(object, resources).await.map(|_| ())
+}
+
+async fn reconcile_service_monitor(
+    api: &Api<ServiceMonitor>,
+    object: &TorProxy,
+    annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+) -> Result<()> {
+    let resources: HashMap<(), _> =
+        generate_service_monitor(object, annotations, labels, selector_labels)?
+            .map(|service_monitor| [((), service_monitor)].into())
+            .unwrap_or_default();
+    let enabled = !resources.is_empty();
+
+    match api.sync(object, resources).await {
+        Ok(_) => Ok(()),
+        // The Prometheus Operator is optional, so there is nothing to clean up
+        // when its custom resource definitions are not installed.
+        Err(Error::Kube(kube::Error::Api(status))) if !enabled && status.is_not_found() => Ok(()),
+        Err(error) => Err(error),
+    }
 }
 
 async fn reconcile_tor_proxy(api: &Api<TorProxy>, object: &TorProxy, state: &State) -> Result<()> {
@@ -848,13 +991,24 @@ fn generate_torrc(object: &TorProxy) -> Torrc {
     if let Some(template) = object.torrc_template() {
         torrc = torrc.template(template);
     }
-    torrc = torrc.data_dir("${TOR_TMP_DIR}/home/.tor");
+    torrc = torrc
+        .data_dir("${TOR_TMP_DIR}/home/.tor")
+        .control_port("127.0.0.1:6666")
+        .cookie_authentication(true)
+        .shutdown_wait_length(object.torrc_shutdown_wait_length_seconds());
     if !object.service_ports_http_tunnel().is_empty() {
         torrc = torrc.http_tunnel_port("0.0.0.0:1080");
     }
     if !object.service_ports_socks().is_empty() {
         torrc = torrc.socks_port("0.0.0.0:9050");
     }
+    if let Some(metrics) = object.metrics() {
+        torrc = torrc.metrics_port(&metrics.listen());
+        torrc = metrics
+            .policy()
+            .iter()
+            .fold(torrc, |torrc, policy| torrc.metrics_port_policy(policy));
+    }
     torrc.build()
 }
 
@@ -896,6 +1050,7 @@ fn generate_deployment(
     object: &TorProxy,
     config: &Config,
     annotations: &Annotations,
+    template_annotations: &Annotations,
     labels: &Labels,
     selector_labels: &SelectorLabels,
 ) -> Result<Deployment> {
@@ -927,10 +1082,11 @@ fn generate_deployment(
                 match_labels: Some(selector_labels.into()),
                 ..Default::default()
             },
+            strategy: object.deployment_strategy(),
             template: PodTemplateSpec {
                 metadata: Some(ObjectMeta {
                     annotations: Some(
-                        annotations
+                        template_annotations
                             .clone()
                             .append_reverse(object.deployment_annotations())
                             .into(),
@@ -950,6 +1106,9 @@ fn generate_deployment(
                     init_containers: Some(generate_deployment_init_containers(object)),
                     node_selector: object.deployment_node_selector(),
                     security_context: Some(object.deployment_security_context()),
+                    termination_grace_period_seconds: Some(
+                        object.deployment_termination_grace_period_seconds(),
+                    ),
                     tolerations: object.deployment_tolerations(),
                     topology_spread_constraints: object.deployment_topology_spread_constraints(),
                     volumes: Some(generate_deployment_volumes(object)),
@@ -968,38 +1127,35 @@ fn generate_deployment_containers(object: &TorProxy, config: &Config) -> Vec<Con
     {
         let container = vec_get_or_insert(&mut containers, |f| f.name == "tor");
         container.name = "tor".to_string();
-        container.args = Some(vec![
-            "-c".into(),
-            [
-                "export TOR_TMP_DIR=${TOR_TMP_DIR:-$(mktemp -d --suffix=.tor -p /tmp)}",
-                // torrc
-                "mkdir -p $TOR_TMP_DIR/usr/local/etc/tor",
-                "envsubst < /etc/configs/torrc > $TOR_TMP_DIR/usr/local/etc/tor/torrc",
-                // data directory
-                "mkdir -p $TOR_TMP_DIR/home/.tor",
-                "chmod 700 $TOR_TMP_DIR/home/.tor",
-                // executable
-                "tor -f $TOR_TMP_DIR/usr/local/etc/tor/torrc",
-            ]
-            .join(" && "),
-        ]);
-        container.command = Some(vec!["/bin/bash".into()]);
+        container.args = Some({
+            let mut args = vec!["entrypoint", "run"];
+
+            // torrc
+            args.extend([
+                "--template",
+                "/etc/configs/torrc:${TOR_TMP_DIR}/usr/local/etc/tor/torrc",
+            ]);
+
+            // data directory
+            args.extend(["--directory", "${TOR_TMP_DIR}/home/.tor"]);
+
+            // reload
+            if object.torrc_reload_strategy() == TorrcReloadStrategy::Signal {
+                args.push("--reload");
+            }
+
+            // shutdown
+            args.push("--interrupt-on-terminate");
+
+            // executable
+            args.extend(["--", "tor", "-f", "${TOR_TMP_DIR}/usr/local/etc/tor/torrc"]);
+
+            args.into_iter().map(Into::into).collect()
+        });
+        container.command = Some(vec!["tor-operator".into()]);
         container.image = Some(config.tor_image.uri.clone());
         container.image_pull_policy = Some(config.tor_image.pull_policy.clone());
-        container.liveness_probe = Some(Probe {
-            exec: Some(ExecAction {
-                command: Some(vec![
-                    "/bin/bash".to_string(),
-                    "-c".to_string(),
-                    "echo > /dev/tcp/127.0.0.1/9050".to_string(),
-                ]),
-            }),
-            failure_threshold: Some(3),
-            period_seconds: Some(10),
-            success_threshold: Some(1),
-            timeout_seconds: Some(1),
-            ..Default::default()
-        });
+        container.liveness_probe = Some(liveness_probe(&[]));
 
         let ports = container.ports.get_or_insert_with(Default::default);
 
@@ -1023,20 +1179,18 @@ fn generate_deployment_containers(object: &TorProxy, config: &Config) -> Vec<Con
             port.protocol = Some("TCP".to_string());
         }
 
-        container.readiness_probe = Some(Probe {
-            exec: Some(ExecAction {
-                command: Some(vec![
-                    "/bin/bash".to_string(),
-                    "-c".to_string(),
-                    "echo > /dev/tcp/127.0.0.1/9050".to_string(),
-                ]),
-            }),
-            failure_threshold: Some(3),
-            period_seconds: Some(10),
-            success_threshold: Some(1),
-            timeout_seconds: Some(1),
-            ..Default::default()
-        });
+        if let Some(metrics) = object.metrics() {
+            let port = vec_get_or_insert(ports, |f| match &f.name {
+                Some(name) => name == "metrics",
+                None => false,
+            });
+            port.name = Some("metrics".to_string());
+            port.container_port = metrics.port();
+            port.protocol = Some("TCP".to_string());
+        }
+
+        container.readiness_probe = Some(readiness_probe(&[]));
+        container.startup_probe = Some(startup_probe(&[]));
 
         let volume_mounts = container.volume_mounts.get_or_insert_with(Default::default);
 
@@ -1049,13 +1203,7 @@ fn generate_deployment_containers(object: &TorProxy, config: &Config) -> Vec<Con
     }
 
     for container in &mut containers {
-        container.security_context = Some(SecurityContext {
-            capabilities: Some(Capabilities {
-                drop: Some(vec!["ALL".to_string()]),
-                ..Default::default()
-            }),
-            ..Default::default()
-        });
+        container.security_context = Some(container_security_context());
     }
 
     containers
@@ -1065,13 +1213,7 @@ fn generate_deployment_init_containers(object: &TorProxy) -> Vec<Container> {
     let mut containers = object.deployment_init_containers();
 
     for container in &mut containers {
-        container.security_context = Some(SecurityContext {
-            capabilities: Some(Capabilities {
-                drop: Some(vec!["ALL".to_string()]),
-                ..Default::default()
-            }),
-            ..Default::default()
-        });
+        container.security_context = Some(container_security_context());
     }
 
     containers
@@ -1210,6 +1352,148 @@ fn generate_service(
     })
 }
 
+fn generate_pod_disruption_budget(
+    object: &TorProxy,
+    annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+) -> Result<Option<PodDisruptionBudget>> {
+    if !object.pod_disruption_budget_enabled() {
+        return Ok(None);
+    }
+
+    Ok(Some(PodDisruptionBudget {
+        metadata: ObjectMeta {
+            name: Some(object.pod_disruption_budget_name().into()),
+            annotations: Some(
+                annotations
+                    .clone()
+                    .append_reverse(object.pod_disruption_budget_annotations())
+                    .into(),
+            ),
+            labels: Some(
+                labels
+                    .clone()
+                    .append_reverse(object.pod_disruption_budget_labels())
+                    .into(),
+            ),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        spec: Some(PodDisruptionBudgetSpec {
+            max_unavailable: object.pod_disruption_budget_max_unavailable(),
+            min_available: object.pod_disruption_budget_min_available(),
+            selector: Some(LabelSelector {
+                match_labels: Some(selector_labels.into()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }))
+}
+
+fn generate_metrics_service(
+    object: &TorProxy,
+    annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+) -> Result<Option<Service>> {
+    let Some(metrics) = object.metrics().filter(|f| f.service_enabled()) else {
+        return Ok(None);
+    };
+
+    Ok(Some(Service {
+        metadata: ObjectMeta {
+            name: Some(object.metrics_service_name().into()),
+            annotations: Some(
+                annotations
+                    .clone()
+                    .append_reverse(object.metrics_service_annotations())
+                    .into(),
+            ),
+            labels: Some(
+                labels
+                    .clone()
+                    .append_reverse(object.metrics_service_labels())
+                    .into(),
+            ),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        spec: Some(ServiceSpec {
+            ports: Some(vec![ServicePort {
+                name: Some("metrics".to_string()),
+                port: metrics.port(),
+                protocol: Some("TCP".to_string()),
+                target_port: Some(IntOrString::String("metrics".to_string())),
+                ..Default::default()
+            }]),
+            selector: Some(selector_labels.into()),
+            type_: Some("ClusterIP".to_string()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }))
+}
+
+fn generate_service_monitor(
+    object: &TorProxy,
+    annotations: &Annotations,
+    labels: &Labels,
+    selector_labels: &SelectorLabels,
+) -> Result<Option<ServiceMonitor>> {
+    if object
+        .metrics()
+        .and_then(|f| f.service_monitor.as_ref())
+        .is_none()
+    {
+        return Ok(None);
+    }
+
+    Ok(Some(ServiceMonitor {
+        metadata: ObjectMeta {
+            name: Some(object.metrics_service_monitor_name().into()),
+            annotations: Some(
+                annotations
+                    .clone()
+                    .append_reverse(object.metrics_service_monitor_annotations())
+                    .into(),
+            ),
+            labels: Some(
+                labels
+                    .clone()
+                    .append_reverse(object.metrics_service_monitor_labels())
+                    .into(),
+            ),
+            owner_references: Some(vec![
+                object
+                    .controller_owner_ref(&())
+                    .ok_or(Error::MissingObjectKey("uid"))?,
+            ]),
+            ..Default::default()
+        },
+        spec: ServiceMonitorSpec {
+            endpoints: vec![ServiceMonitorEndpoint {
+                interval: object.metrics_service_monitor_interval(),
+                port: "metrics".to_string(),
+            }],
+            selector: LabelSelector {
+                match_labels: Some(selector_labels.into()),
+                ..Default::default()
+            },
+        },
+    }))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1228,7 +1512,13 @@ mod tests {
 
         let torrc = generate_torrc(object);
 
-        assert_eq!(r"DataDirectory ${TOR_TMP_DIR}/home/.tor", torrc.to_string());
+        assert_eq!(
+            r"DataDirectory ${TOR_TMP_DIR}/home/.tor
+ControlPort 127.0.0.1:6666
+CookieAuthentication 1
+ShutdownWaitLength 10",
+            torrc.to_string()
+        );
     }
 
     #[test]
@@ -1252,11 +1542,72 @@ mod tests {
 
         assert_eq!(
             r"DataDirectory ${TOR_TMP_DIR}/home/.tor
+ControlPort 127.0.0.1:6666
+CookieAuthentication 1
+ShutdownWaitLength 10
 HTTPTunnelPort 0.0.0.0:1080",
             torrc.to_string()
         );
     }
 
+    #[test]
+    fn config_metrics_default() {
+        let object = &TorProxy {
+            spec: TorProxySpec {
+                metrics: Some(TorMetrics::default()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let torrc = generate_torrc(object);
+
+        assert_eq!(
+            r"DataDirectory ${TOR_TMP_DIR}/home/.tor
+ControlPort 127.0.0.1:6666
+CookieAuthentication 1
+ShutdownWaitLength 10
+MetricsPort 127.0.0.1:9035
+MetricsPortPolicy accept 127.0.0.1",
+            torrc.to_string()
+        );
+    }
+
+    #[test]
+    fn config_metrics() {
+        let object = &TorProxy {
+            spec: TorProxySpec {
+                metrics: Some(TorMetrics {
+                    policy: Some(vec!["accept 10.0.0.0/8".to_string()]),
+                    ..Default::default()
+                }),
+                service: TorProxySpecService {
+                    ports: vec![TorProxySpecServicePort {
+                        name: "socks".to_string(),
+                        port: 9050,
+                        protocol: "SOCKS".to_string(),
+                    }],
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let torrc = generate_torrc(object);
+
+        assert_eq!(
+            r"DataDirectory ${TOR_TMP_DIR}/home/.tor
+ControlPort 127.0.0.1:6666
+CookieAuthentication 1
+ShutdownWaitLength 10
+SocksPort 0.0.0.0:9050
+MetricsPort 0.0.0.0:9035
+MetricsPortPolicy accept 10.0.0.0/8",
+            torrc.to_string()
+        );
+    }
+
     #[test]
     fn config_socks() {
         let object = &TorProxy {
@@ -1278,6 +1629,9 @@ HTTPTunnelPort 0.0.0.0:1080",
 
         assert_eq!(
             r"DataDirectory ${TOR_TMP_DIR}/home/.tor
+ControlPort 127.0.0.1:6666
+CookieAuthentication 1
+ShutdownWaitLength 10
 SocksPort 0.0.0.0:9050",
             torrc.to_string()
         );
@@ -1311,6 +1665,9 @@ SocksPort 0.0.0.0:9050",
 
         assert_eq!(
             r"DataDirectory ${TOR_TMP_DIR}/home/.tor
+ControlPort 127.0.0.1:6666
+CookieAuthentication 1
+ShutdownWaitLength 10
 HTTPTunnelPort 0.0.0.0:1080
 SocksPort 0.0.0.0:9050",
             torrc.to_string()
@@ -0,0 +1,189 @@
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+
+use axum::{Json, Router, extract::State, routing::post};
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use k8s_openapi::api::core::v1::Pod;
+use kube::{
+    Client, ResourceExt as _,
+    core::{
+        DynamicObject,
+        admission::{AdmissionRequest, AdmissionResponse, AdmissionReview},
+    },
+};
+use tokio::net::TcpListener;
+use tower::ServiceExt as _;
+
+use crate::{
+    kubernetes::TOR_AGABANI_CO_UK_ONION_SERVICE_KEY,
+    onion_key::OnionKey,
+    onion_service::{self, OnionService},
+    tls,
+};
+
+/*
+ * ============================================================================
+ * Config
+ * ============================================================================
+ */
+pub struct Config {
+    /// Address to accept the admission requests on.
+    pub addr: SocketAddr,
+
+    /// PEM encoded certificate chain.
+    pub certificate: PathBuf,
+
+    /// PEM encoded private key.
+    pub key: PathBuf,
+
+    pub onion_service: onion_service::Config,
+}
+
+/*
+ * ============================================================================
+ * Context
+ * ============================================================================
+ */
+struct Context {
+    client: Client,
+    config: onion_service::Config,
+}
+
+/*
+ * ============================================================================
+ * Webhook
+ * ============================================================================
+ */
+/// Serves the mutating admission webhook injecting Tor into the Pods labelled,
+/// or annotated, with `tor.agabani.co.uk/onion-service`.
+///
+/// # Errors
+///
+/// Returns an error if the certificate cannot be loaded, or the listener
+/// cannot be bound or stops accepting connections.
+pub async fn run(client: Client, config: Config) -> std::io::Result<()> {
+    let acceptor = tls::acceptor(config.certificate, config.key)?;
+
+    let app = Router::new()
+        .route("/mutate/pods", post(mutate_pods))
+        .with_state(Arc::new(Context {
+            client,
+            config: config.onion_service,
+        }));
+
+    let listener = TcpListener::bind(&config.addr).await?;
+
+    tracing::info!(addr =% config.addr, "webhook started");
+
+    loop {
+        let (inbound, _) = listener.accept().await?;
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        tokio::spawn(async move {
+            let result = async {
+                let inbound = tls::accept(&acceptor, inbound).await?;
+                hyper::server::conn::http1::Builder::new()
+                    .serve_connection(
+                        TokioIo::new(inbound),
+                        service_fn(move |request| app.clone().oneshot(request)),
+                    )
+                    .await
+                    .map_err(std::io::Error::other)
+            };
+            if let Err(error) = result.await {
+                tracing::warn!(%error, "connection failed");
+            }
+        });
+    }
+}
+
+async fn mutate_pods(
+    State(ctx): State<Arc<Context>>,
+    Json(review): Json<AdmissionReview<Pod>>,
+) -> Json<AdmissionReview<DynamicObject>> {
+    let request: AdmissionRequest<Pod> = match review.try_into() {
+        Ok(request) => request,
+        Err(error) => return Json(AdmissionResponse::invalid(error).into_review()),
+    };
+
+    let response = AdmissionResponse::from(&request);
+
+    let response = match inject(&ctx, &request).await {
+        Ok(Some(patch)) => match response.clone().with_patch(patch) {
+            Ok(response) => response,
+            Err(error) => response.deny(error),
+        },
+        Ok(None) => response,
+        Err(error) => {
+            tracing::warn!(
+                name = request.name,
+                namespace = request.namespace,
+                error,
+                "injection failed"
+            );
+            response.deny(error)
+        }
+    };
+
+    Json(response.into_review())
+}
+
+/// Returns the patch injecting Tor into the Pod, or `None` when the Pod is
+/// neither labelled nor annotated.
+async fn inject(
+    ctx: &Context,
+    request: &AdmissionRequest<Pod>,
+) -> Result<Option<json_patch::Patch>, String> {
+    let Some(pod) = &request.object else {
+        return Ok(None);
+    };
+
+    // The label lets an objectSelector narrow down the Pods sent to the
+    // webhook, which cannot select on the annotation.
+    let Some(name) = pod
+        .labels()
+        .get(TOR_AGABANI_CO_UK_ONION_SERVICE_KEY)
+        .or_else(|| pod.annotations().get(TOR_AGABANI_CO_UK_ONION_SERVICE_KEY))
+    else {
+        return Ok(None);
+    };
+
+    let namespace = request
+        .namespace
+        .as_deref()
+        .or(pod.metadata.namespace.as_deref())
+        .ok_or("missing namespace")?;
+
+    let onion_service = kube::Api::<OnionService>::namespaced(ctx.client.clone(), namespace)
+        .get_opt(name)
+        .await
+        .map_err(|error| error.to_string())?
+        .ok_or_else(|| format!("OnionService {name} not found"))?;
+
+    if !onion_service.injection_enabled() {
+        return Err(format!(
+            "OnionService {name} does not have injection enabled"
+        ));
+    }
+
+    let onion_key_name = onion_service
+        .onion_key_name()
+        .ok_or_else(|| format!("OnionService {name} has no OnionKey"))?;
+
+    let onion_key = kube::Api::<OnionKey>::namespaced(ctx.client.clone(), namespace)
+        .get_opt(&onion_key_name)
+        .await
+        .map_err(|error| error.to_string())?
+        .ok_or_else(|| format!("OnionKey {onion_key_name} not found"))?;
+
+    if onion_key.hostname().is_none() {
+        return Err(format!("OnionKey {onion_key_name} has no hostname"));
+    }
+
+    let injected = onion_service::inject_pod(&onion_service, &ctx.config, &onion_key, pod.clone())?;
+
+    Ok(Some(json_patch::diff(
+        &serde_json::to_value(pod).map_err(|error| error.to_string())?,
+        &serde_json::to_value(&injected).map_err(|error| error.to_string())?,
+    )))
+}